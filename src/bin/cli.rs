@@ -1,9 +1,15 @@
 use clap::{Parser, Subcommand};
 use soundboard::{
-    types::socket::Request,
-    utils::daemon::{make_request, wait_for_daemon},
+    client::SoundboardClient,
+    types::{
+        payload::CONNECTION_EXIT_CODE, playlist::PlaylistMode, socket::Request, timeline::Timeline,
+    },
+    utils::{daemon::wait_for_daemon, render::render_timeline},
+};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
 };
-use std::{error::Error, path::PathBuf};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -14,12 +20,12 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    /// Perform an action (ping, pause, resume, toggle-pause, stop, play)
+    /// Perform an action (ping, pause, resume, toggle-pause, stop, play, add-app-route, remove-app-route, run-macro, cancel-macro, schedule, cancel-schedule)
     Action {
         #[clap(subcommand)]
         action: Actions,
     },
-    /// Get information from the player (is paused, volume, position, duration, state, current-file-path, input, inputs)
+    /// Get information from the player (is paused, volume, position, duration, state, current-file-path, input, inputs, app-streams, app-routes, running-macros, schedules)
     Get {
         #[clap(subcommand)]
         parameter: GetCommands,
@@ -29,6 +35,13 @@ enum Commands {
         #[clap(subcommand)]
         parameter: SetCommands,
     },
+    /// Mix a JSON timeline of sounds into a WAV or FLAC file (no daemon needed)
+    Render {
+        /// Timeline description (JSON)
+        timeline: PathBuf,
+        /// Output file (.wav or .flac)
+        output: PathBuf,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -44,11 +57,73 @@ enum Actions {
     /// Stop audio playback and clear the queue
     Stop,
     /// Play a file (through virtual mic)
-    Play { file_path: PathBuf },
+    Play {
+        file_path: PathBuf,
+        /// Voice priority (0-255, default: the sound's own priority)
+        #[clap(long)]
+        priority: Option<u8>,
+    },
+    /// Play a file on a layer, mixed with the main player
+    PlayOnLayer {
+        file_path: PathBuf,
+        /// Layer to play on (default: any free layer, stealing one if all are busy)
+        #[clap(long)]
+        layer: Option<usize>,
+        /// Voice priority (0-255, default: the sound's own priority)
+        #[clap(long)]
+        priority: Option<u8>,
+    },
     /// Preview a file (speakers only, not through virtual mic)
     Preview { file_path: PathBuf },
     /// Toggle loop
     ToggleLoop,
+    /// Route playback into matching applications' capture streams (Linux only)
+    AddAppRoute {
+        #[clap(long)]
+        application_name: Option<String>,
+        #[clap(long)]
+        process_binary: Option<String>,
+    },
+    /// Remove an application route
+    RemoveAppRoute {
+        #[clap(long)]
+        application_name: Option<String>,
+        #[clap(long)]
+        process_binary: Option<String>,
+    },
+    /// Run a macro defined in the GUI config
+    RunMacro { name: String },
+    /// Cancel a running macro
+    CancelMacro { name: String },
+    /// Play a file later or repeatedly (durations like 90, 45s, 30m, 1h30m)
+    Schedule {
+        file_path: PathBuf,
+        /// Layer to play on (default: main player)
+        #[clap(long)]
+        layer: Option<usize>,
+        /// Play after this long
+        #[clap(long, conflicts_with = "at")]
+        delay: Option<String>,
+        /// Play at a time: HH:MM[:SS], "YYYY-MM-DD HH:MM[:SS]" or RFC 3339
+        #[clap(long)]
+        at: Option<String>,
+        /// Repeat at this interval
+        #[clap(long)]
+        every: Option<String>,
+        /// Name shown in the schedule list
+        #[clap(long)]
+        label: Option<String>,
+    },
+    /// Cancel a scheduled play (see soundboard-cli get schedules)
+    CancelSchedule { id: u64 },
+    /// Play a playlist
+    PlayPlaylist {
+        name: String,
+        /// sequential, shuffle, repeat-all, repeat-one, round-robin or random
+        /// (default: the playlist's own mode)
+        #[clap(long)]
+        mode: Option<PlaylistMode>,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -75,6 +150,22 @@ enum GetCommands {
     Inputs,
     /// Is loop enabled (true or false)
     Loop,
+    /// Applications currently capturing audio (Linux only)
+    AppStreams,
+    /// Configured application routes
+    AppRoutes,
+    /// Macros that are currently running
+    RunningMacros,
+    /// Scheduled plays
+    Schedules,
+    /// Playlist being played, if any
+    Playlist,
+    /// Playlists and sounds as the GUI shows them
+    Library,
+    /// MIDI controller mappings
+    MidiMappings,
+    /// Daemon protocol version and supported commands
+    Hello,
 }
 
 #[derive(Subcommand, Debug)]
@@ -90,7 +181,20 @@ enum SetCommands {
     /// Audio input id (see soundboard-cli get inputs)
     Input { name: String },
     /// Enable or disable loop (true or false)
-    Loop { enabled: String },
+    Loop {
+        #[clap(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
+    /// Follow the system default microphone (true or false)
+    FollowDefaultInput {
+        #[clap(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
+    /// Follow the system default output device (true or false)
+    FollowDefaultOutput {
+        #[clap(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
 }
 
 #[tokio::main]
@@ -98,7 +202,21 @@ async fn main() -> Result<(), Box<dyn Error>> {
     soundboard::utils::logging::init();
     let cli = Cli::parse();
 
-    wait_for_daemon().await?;
+    // Rendering happens locally and doesn't talk to the daemon
+    if let Commands::Render { timeline, output } = &cli.command {
+        let base_dir = timeline.parent().unwrap_or_else(|| Path::new("."));
+        let rendered = render_timeline(&Timeline::load(timeline)?, base_dir, output)?;
+        println!(
+            "Rendered {:.2}s to {}",
+            rendered.as_secs_f64(),
+            output.display()
+        );
+        return Ok(());
+    }
+
+    if let Err(e) = wait_for_daemon().await {
+        exit_unreachable(e);
+    }
 
     let request = match cli.command {
         Commands::Action { action } => match action {
@@ -107,11 +225,24 @@ async fn main() -> Result<(), Box<dyn Error>> {
             Actions::Resume => Request::resume(),
             Actions::TogglePause => Request::toggle_pause(),
             Actions::Stop => Request::stop(),
-            Actions::Play { file_path } => {
+            Actions::Play {
+                file_path,
+                priority,
+            } => {
                 let path_str = file_path
                     .to_str()
                     .ok_or("File path contains invalid UTF-8 characters")?;
-                Request::play(path_str)
+                Request::play(path_str, priority)
+            }
+            Actions::PlayOnLayer {
+                file_path,
+                layer,
+                priority,
+            } => {
+                let path_str = file_path
+                    .to_str()
+                    .ok_or("File path contains invalid UTF-8 characters")?;
+                Request::play_on_layer(layer, path_str, priority)
             }
             Actions::Preview { file_path } => {
                 let path_str = file_path
@@ -120,6 +251,38 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 Request::preview(path_str)
             }
             Actions::ToggleLoop => Request::toggle_loop(),
+            Actions::AddAppRoute {
+                application_name,
+                process_binary,
+            } => Request::add_app_route(application_name.as_deref(), process_binary.as_deref()),
+            Actions::RemoveAppRoute {
+                application_name,
+                process_binary,
+            } => Request::remove_app_route(application_name.as_deref(), process_binary.as_deref()),
+            Actions::RunMacro { name } => Request::run_macro(&name),
+            Actions::CancelMacro { name } => Request::cancel_macro(&name),
+            Actions::Schedule {
+                file_path,
+                layer,
+                delay,
+                at,
+                every,
+                label,
+            } => {
+                let path_str = file_path
+                    .to_str()
+                    .ok_or("File path contains invalid UTF-8 characters")?;
+                Request::schedule_play(
+                    path_str,
+                    layer,
+                    delay.as_deref(),
+                    at.as_deref(),
+                    every.as_deref(),
+                    label.as_deref(),
+                )
+            }
+            Actions::CancelSchedule { id } => Request::cancel_schedule(id),
+            Actions::PlayPlaylist { name, mode } => Request::play_playlist(&name, mode),
         },
         Commands::Get { parameter } => match parameter {
             GetCommands::IsPaused => Request::get_is_paused(),
@@ -133,6 +296,14 @@ async fn main() -> Result<(), Box<dyn Error>> {
             GetCommands::Input => Request::get_input(),
             GetCommands::Inputs => Request::get_inputs(),
             GetCommands::Loop => Request::get_loop(),
+            GetCommands::AppStreams => Request::get_app_streams(),
+            GetCommands::AppRoutes => Request::get_app_routes(),
+            GetCommands::RunningMacros => Request::get_running_macros(),
+            GetCommands::Schedules => Request::list_schedules(),
+            GetCommands::Playlist => Request::get_playlist(),
+            GetCommands::Library => Request::get_library(),
+            GetCommands::MidiMappings => Request::get_midi_mappings(),
+            GetCommands::Hello => Request::hello("soundboard-cli"),
         },
         Commands::Set { parameter } => match parameter {
             SetCommands::Volume { volume } => Request::set_volume(volume),
@@ -140,14 +311,34 @@ async fn main() -> Result<(), Box<dyn Error>> {
             SetCommands::MicGain { mic_gain } => Request::set_mic_gain(mic_gain),
             SetCommands::Position { position } => Request::seek(position),
             SetCommands::Input { name } => Request::set_input(&name),
-            SetCommands::Loop { enabled } => Request::set_loop(&enabled),
+            SetCommands::Loop { enabled } => Request::set_loop(enabled),
+            SetCommands::FollowDefaultInput { enabled } => {
+                Request::set_follow_default_input(enabled)
+            }
+            SetCommands::FollowDefaultOutput { enabled } => {
+                Request::set_follow_default_output(enabled)
+            }
         },
+        Commands::Render { .. } => unreachable!("render is handled before connecting"),
     };
 
-    let response = make_request(request)
-        .await
-        .map_err(|e| e as Box<dyn Error>)?;
+    // Print the daemon's own message, whatever the command
+    let response = match SoundboardClient::new().request(request).await {
+        Ok(response) => response,
+        Err(e) => exit_unreachable(e),
+    };
     println!("{} : {}", response.status, response.message);
 
+    if let Some(error) = response.daemon_error() {
+        std::process::exit(error.code.exit_code());
+    }
+
     Ok(())
 }
+
+// Failing to reach the daemon gets an exit code of its own, so scripts can
+// tell it from the daemon turning the request down
+fn exit_unreachable(error: impl std::fmt::Display) -> ! {
+    eprintln!("Error: {}", error);
+    std::process::exit(CONNECTION_EXIT_CODE);
+}
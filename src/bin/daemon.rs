@@ -1,48 +1,117 @@
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+#[cfg(target_os = "linux")]
+use soundboard::utils::{
+    daemon::link_player_to_virtual_mic,
+    mpris::serve_mpris,
+    pipewire::{create_virtual_mic, shutdown_manager},
+    routing::app_routing_loop,
+};
 use soundboard::{
     types::{
         audio_player::PlayerState,
-        socket::{Request, Response},
+        config::AudioBackend,
+        payload::{DaemonError, ErrorCode},
+        socket::{Command, IncomingRequest, Response, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION},
     },
     utils::{
-        commands::parse_command,
+        commands::{command_executable, parse_command},
         daemon::{
-            create_runtime_dir, get_audio_player, get_daemon_config, get_runtime_dir,
-            init_audio_player, is_daemon_running,
+            create_runtime_dir, follow_default_devices_loop, get_audio_player, get_daemon_config,
+            get_runtime_dir, init_audio_player, is_daemon_running,
+        },
+        events::{
+            follow_events, publish_error, publish_loop_end, publish_player_state, publish_stop,
         },
+        hooks::hooks_loop,
+        irc::irc_loop,
+        midi::midi_loop,
+        mqtt::mqtt_loop,
+        obs::obs_loop,
+        osc::serve_osc,
+        playlist::continue_playlist,
+        remote::serve_remote,
+        schedule::schedule_loop,
     },
 };
-#[cfg(target_os = "linux")]
-use soundboard::utils::{
-    daemon::link_player_to_virtual_mic,
-    pipewire::create_virtual_mic,
+use std::{
+    error::Error,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Once},
+    time::Duration,
 };
-use std::{error::Error, fs, time::Duration};
 use tokio::{
     io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::{mpsc, Semaphore},
     time::{sleep, timeout},
 };
 
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Output backend (overrides audio_backend in daemon.json)
+    #[clap(long, value_enum)]
+    backend: Option<BackendKind>,
+    /// WAV file the file backend writes to
+    #[clap(long, required_if_eq("backend", "file"))]
+    output_file: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum BackendKind {
+    /// System output device
+    Device,
+    /// Discard output, no audio hardware needed
+    Null,
+    /// Write output to a WAV file
+    File,
+}
+
+impl Args {
+    fn audio_backend(&self) -> Option<AudioBackend> {
+        Some(match self.backend? {
+            BackendKind::Device => AudioBackend::Device,
+            BackendKind::Null => AudioBackend::Null,
+            BackendKind::File => AudioBackend::File {
+                path: self.output_file.clone()?,
+            },
+        })
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     soundboard::utils::logging::init();
+    let args = Args::parse();
     create_runtime_dir()?;
 
     if is_daemon_running()? {
         return Err("Another instance is already running.".into());
     }
 
-    get_daemon_config();
+    let backend = args
+        .audio_backend()
+        .unwrap_or(get_daemon_config().audio_backend);
+    // Headless backends skip PipeWire entirely so the daemon runs without it
+    let headless = backend.is_headless();
 
     #[cfg(target_os = "linux")]
-    create_virtual_mic()?;
+    if !headless {
+        create_virtual_mic().await?;
+    }
 
-    if let Err(e) = init_audio_player().await {
+    if let Err(e) = init_audio_player(&backend).await {
         tracing::error!("Failed to initialize audio player: {}", e);
         return Err(format!("Cannot start daemon: audio player initialization failed: {}", e).into());
     }
 
     #[cfg(target_os = "linux")]
-    link_player_to_virtual_mic().await?;
+    let _player_link = if headless {
+        None
+    } else {
+        link_player_to_virtual_mic().await?
+    };
 
     let runtime_dir = get_runtime_dir();
 
@@ -102,6 +171,59 @@ async fn main() -> Result<(), Box<dyn Error>> {
         player_loop().await;
     });
 
+    // Background tasks log their own failures; losing one must not take the daemon down
+    tokio::spawn(schedule_loop());
+    let remote = get_daemon_config().remote;
+    if remote.enabled {
+        tokio::spawn(async move {
+            if let Err(e) = serve_remote(remote).await {
+                tracing::error!("Remote control API stopped: {}", e);
+            }
+        });
+    }
+    let osc = get_daemon_config().osc;
+    if osc.enabled {
+        tokio::spawn(async move {
+            if let Err(e) = serve_osc(osc).await {
+                tracing::error!("OSC server stopped: {}", e);
+            }
+        });
+    }
+    let midi = get_daemon_config().midi;
+    if midi.enabled {
+        tokio::spawn(midi_loop(midi));
+    }
+    let mqtt = get_daemon_config().mqtt;
+    if mqtt.enabled {
+        tokio::spawn(mqtt_loop(mqtt));
+    }
+    let obs = get_daemon_config().obs;
+    if obs.enabled {
+        tokio::spawn(obs_loop(obs));
+    }
+    let irc = get_daemon_config().irc;
+    if irc.enabled {
+        tokio::spawn(irc_loop(irc));
+    }
+    let hooks = get_daemon_config().hooks;
+    if hooks.enabled {
+        tokio::spawn(hooks_loop(hooks));
+    }
+    if !headless {
+        #[cfg(target_os = "linux")]
+        tokio::spawn(app_routing_loop());
+        #[cfg(target_os = "linux")]
+        if get_daemon_config().mpris.enabled {
+            tokio::spawn(async {
+                // Expected without a session bus, e.g. as a system service
+                if let Err(e) = serve_mpris().await {
+                    tracing::warn!("MPRIS player unavailable: {}", e);
+                }
+            });
+        }
+        tokio::spawn(follow_default_devices_loop());
+    }
+
     tokio::select! {
         _ = commands_loop_handle => {
             tracing::error!("Commands loop was finished, stopping program...");
@@ -115,6 +237,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
     {
         let socket_path = get_runtime_dir().join("daemon.sock");
         let _ = fs::remove_file(&socket_path);
+        if !headless {
+            shutdown_manager();
+        }
     }
 
     Ok(())
@@ -129,17 +254,72 @@ const MAX_IPC_ARGS: usize = 32;
 // Whole-request deadline: guards against clients that write the length prefix
 // but then stall on the body.
 const IPC_READ_TIMEOUT: Duration = Duration::from_secs(5);
+// A client that stops reading its responses or events is dropped instead of
+// holding them back
+const IPC_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+// Connections stay open between requests, but not forever
+const IPC_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+// Requests a single connection may have in flight; reading stops until one
+// of them is answered
+const MAX_IN_FLIGHT: usize = 32;
+
+// Serve requests until the client hangs up. Requests run concurrently and are
+// answered as they finish, tagged with the request's id.
+async fn handle_connection(stream: impl AsyncRead + AsyncWrite + Send + 'static) {
+    let (mut reader, writer) = tokio::io::split(stream);
+    let (frames, outgoing) = mpsc::channel(MAX_IN_FLIGHT);
+    let writer = tokio::spawn(write_frames(writer, outgoing));
+    let in_flight = Arc::new(Semaphore::new(MAX_IN_FLIGHT));
+
+    while let Some(buffer) = read_request(&mut reader).await {
+        let incoming = IncomingRequest::from_slice(&buffer);
+        let (id, subscribing) = match &incoming {
+            Ok(IncomingRequest::Typed(request)) => (
+                request.id,
+                request.command == Command::Subscribe
+                    && (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&request.version),
+            ),
+            _ => (None, false),
+        };
+
+        // A subscription takes the connection over
+        if subscribing {
+            let response = Response::new(true, "Subscribed to events").with_id(id);
+            if send_frame(&frames, &response).await {
+                stream_events(reader, frames.clone()).await;
+            }
+            break;
+        }
 
-async fn handle_connection(mut stream: impl AsyncRead + AsyncWrite + Unpin) {
+        let Ok(permit) = in_flight.clone().acquire_owned().await else {
+            break;
+        };
+        let frames = frames.clone();
+        tokio::spawn(async move {
+            let response = respond(incoming).await.with_id(id);
+            send_frame(&frames, &response).await;
+            drop(permit);
+        });
+    }
+
+    // Let the writer flush the answers still on their way
+    drop(frames);
+    let _ = writer.await;
+}
+
+// The next request on the connection, or None once the client is gone
+async fn read_request(reader: &mut (impl AsyncRead + Unpin)) -> Option<Vec<u8>> {
     let mut len_bytes = [0u8; 4];
-    match timeout(IPC_READ_TIMEOUT, stream.read_exact(&mut len_bytes)).await {
+    match timeout(IPC_IDLE_TIMEOUT, reader.read_exact(&mut len_bytes)).await {
         Err(_) => {
-            tracing::error!("IPC: timed out reading message length");
-            return;
+            tracing::debug!("IPC: closing idle connection");
+            return None;
         }
+        // The client hung up between requests
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return None,
         Ok(Err(_)) => {
             tracing::error!("Failed to read message length from client!");
-            return;
+            return None;
         }
         Ok(Ok(_)) => {}
     }
@@ -148,61 +328,111 @@ async fn handle_connection(mut stream: impl AsyncRead + AsyncWrite + Unpin) {
 
     if request_len > MAX_IPC_MESSAGE_SIZE {
         tracing::error!("Rejected message: size {} exceeds maximum allowed {}", request_len, MAX_IPC_MESSAGE_SIZE);
-        return;
+        return None;
     }
 
     let mut buffer = vec![0u8; request_len];
-    match timeout(IPC_READ_TIMEOUT, stream.read_exact(&mut buffer)).await {
+    match timeout(IPC_READ_TIMEOUT, reader.read_exact(&mut buffer)).await {
         Err(_) => {
             tracing::error!("IPC: timed out reading message body");
-            return;
+            None
         }
         Ok(Err(_)) => {
             tracing::error!("Failed to read message from client!");
-            return;
+            None
         }
-        Ok(Ok(_)) => {}
+        Ok(Ok(_)) => Some(buffer),
     }
+}
+
+async fn respond(incoming: Result<IncomingRequest, serde_json::Error>) -> Response {
+    match incoming {
+        Ok(IncomingRequest::Typed(request)) => {
+            if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&request.version) {
+                Response::error(
+                    ErrorCode::InvalidRequest,
+                    format!(
+                        "Unsupported protocol version {} (daemon speaks {} to {})",
+                        request.version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+                    ),
+                )
+            } else {
+                command_executable(request.command).execute().await
+            }
+        }
+        Ok(IncomingRequest::Legacy(request)) => {
+            static LEGACY_WARNING: Once = Once::new();
+            LEGACY_WARNING.call_once(|| {
+                tracing::warn!(
+                    "A client sent a legacy {{name, args}} request; this shape will be removed in the next release"
+                );
+            });
+
+            if request.args.len() > MAX_IPC_ARGS {
+                tracing::error!(
+                    "Rejected request '{}': {} args exceeds limit of {}",
+                    request.name,
+                    request.args.len(),
+                    MAX_IPC_ARGS
+                );
+                return Response::error(ErrorCode::InvalidRequest, "Too many arguments");
+            }
 
-    let request: Request = match serde_json::from_slice(&buffer) {
-        Ok(req) => req,
+            match parse_command(&request) {
+                Some(cmd) => cmd.execute().await,
+                None => Response::error(ErrorCode::UnknownCommand, "Unknown command"),
+            }
+        }
         Err(e) => {
             tracing::error!("Failed to parse request JSON: {}", e);
-            return;
+            Response::error(ErrorCode::InvalidRequest, format!("Invalid request: {}", e))
         }
-    };
-
-    if request.args.len() > MAX_IPC_ARGS {
-        tracing::error!(
-            "Rejected request '{}': {} args exceeds limit of {}",
-            request.name,
-            request.args.len(),
-            MAX_IPC_ARGS
-        );
-        return;
     }
+}
 
-    let command = parse_command(&request);
-    let response: Response = match command {
-        Some(cmd) => cmd.execute().await,
-        None => Response::new(false, "Unknown command"),
-    };
-
-    let response_data = match serde_json::to_vec(&response) {
-        Ok(data) => data,
+// Queue a response or event for the writer; false once the client is gone
+async fn send_frame(frames: &mpsc::Sender<Vec<u8>>, value: &impl Serialize) -> bool {
+    match serde_json::to_vec(value) {
+        Ok(data) => frames.send(data).await.is_ok(),
         Err(e) => {
             tracing::error!("Failed to serialize response: {}", e);
-            return;
+            true
         }
-    };
-    let response_len = response_data.len() as u32;
+    }
+}
 
-    if stream.write_all(&response_len.to_le_bytes()).await.is_err() {
-        tracing::error!("Failed to write response length to client!");
-        return;
+async fn write_frames(mut writer: impl AsyncWrite + Unpin, mut outgoing: mpsc::Receiver<Vec<u8>>) {
+    while let Some(frame) = outgoing.recv().await {
+        let len = frame.len() as u32;
+        let written = timeout(IPC_WRITE_TIMEOUT, async {
+            writer.write_all(&len.to_le_bytes()).await?;
+            writer.write_all(&frame).await
+        })
+        .await;
+        if !matches!(written, Ok(Ok(()))) {
+            tracing::debug!("Failed to write to client, closing the connection");
+            return;
+        }
     }
-    if stream.write_all(&response_data).await.is_err() {
-        tracing::error!("Failed to write response to client!");
+}
+
+// Push events until the client disconnects. Anything the client sends ends
+// the subscription.
+async fn stream_events(mut reader: impl AsyncRead + Unpin, frames: mpsc::Sender<Vec<u8>>) {
+    let mut events = follow_events().await;
+    let mut byte = [0u8; 1];
+    loop {
+        let event = tokio::select! {
+            _ = reader.read(&mut byte) => return,
+            event = events.next() => match event {
+                Some(event) => event,
+                None => return,
+            },
+        };
+        if !send_frame(&frames, &event).await {
+            tracing::debug!("Event subscriber went away");
+            return;
+        }
     }
 }
 
@@ -226,13 +456,28 @@ async fn player_loop() {
     loop {
         let mut audio_player = get_audio_player().lock().await;
 
-        if audio_player.get_state() == PlayerState::Stopped && audio_player.looped {
+        let stopped = audio_player.get_state() == PlayerState::Stopped;
+        if stopped && continue_playlist(&mut audio_player).await {
+            // The playlist picked the next sound
+        } else if stopped && audio_player.looped {
             if let Some(ref file_path) = audio_player.current_file_path.clone() {
-                if let Err(e) = audio_player.play(file_path).await {
-                    tracing::error!("Failed to play looped file: {}", e);
+                match audio_player.play(file_path).await {
+                    Ok(()) => publish_loop_end(file_path),
+                    Err(e) => {
+                        tracing::error!("Failed to play looped file: {}", e);
+                        publish_error(DaemonError::new(
+                            ErrorCode::PlaybackError,
+                            format!("Failed to play looped file: {}", e),
+                        ));
+                    }
                 }
             }
+        } else if stopped {
+            // The sound ended, or something stopped it without saying so
+            publish_stop();
         }
+        // Position ticks, and whatever macros and schedules changed
+        publish_player_state(&mut audio_player);
 
         drop(audio_player);
         sleep(Duration::from_millis(100)).await;
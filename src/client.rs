@@ -0,0 +1,477 @@
+// A typed client for the daemon, for the CLI, the GUI and third-party tools.
+// Every command has a method that returns its `types::payload` data, so
+// callers never build `Request`s or read `Response`s by hand:
+//
+//     let client = SoundboardClient::new();
+//     client.set_volume(0.5).await?;
+//     let layers = client.get_layers_info().await?;
+
+#[cfg(target_os = "linux")]
+use crate::types::pipewire::AppStream;
+use crate::{
+    types::{
+        audio_player::{LayerInfo, PlayerState},
+        config::AppRouteRule,
+        events::Event,
+        library::Library,
+        midi::{MidiMapping, MidiTrigger},
+        payload::{
+            ActivePlaylist, CurrentDevice, DaemonError, Device, LayerVolume, MacroStarted,
+            MidiLearned, PlayStarted, PlaylistStarted, PreviewStarted,
+        },
+        playlist::PlaylistMode,
+        schedule::ScheduledPlay,
+        socket::{HelloInfo, Request, Response},
+    },
+    utils::daemon::{subscribe_events, DaemonConnection, EventStream, NotSent},
+};
+use serde::de::DeserializeOwned;
+use std::{error::Error, fmt, future::Future, path::PathBuf, sync::Arc};
+use tokio::{
+    runtime::{Handle, Runtime, RuntimeFlavor},
+    sync::Mutex,
+    time::Duration,
+};
+
+/// How long a request may wait for its response by default
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Why a client call failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientError {
+    /// The daemon couldn't be reached, hung up or didn't answer in time
+    Connection(String),
+    /// The daemon answered with an error
+    Daemon(DaemonError),
+    /// The response data didn't have the expected shape
+    UnexpectedData(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Connection(message) => write!(f, "Daemon connection failed: {}", message),
+            ClientError::Daemon(error) => write!(f, "{}", error),
+            ClientError::UnexpectedData(message) => {
+                write!(f, "Unexpected response data: {}", message)
+            }
+        }
+    }
+}
+
+impl Error for ClientError {}
+
+impl From<DaemonError> for ClientError {
+    fn from(error: DaemonError) -> Self {
+        ClientError::Daemon(error)
+    }
+}
+
+impl From<Box<dyn Error + Send + Sync>> for ClientError {
+    fn from(error: Box<dyn Error + Send + Sync>) -> Self {
+        match error.downcast::<DaemonError>() {
+            Ok(error) => ClientError::Daemon(*error),
+            Err(error) => ClientError::Connection(error.to_string()),
+        }
+    }
+}
+
+// The data of a response as `T`, or the daemon's error. Commands without
+// data decode as `()`.
+fn decode<T: DeserializeOwned>(response: Response) -> Result<T, ClientError> {
+    if let Some(error) = response.daemon_error() {
+        return Err(error.into());
+    }
+    serde_json::from_value(response.data.unwrap_or_default())
+        .map_err(|e| ClientError::UnexpectedData(e.to_string()))
+}
+
+/// Talks to the daemon over one connection, opened on first use and
+/// reopened when the daemon restarts. Clones share the connection, and
+/// requests from several tasks are sent over it concurrently.
+#[derive(Clone)]
+pub struct SoundboardClient {
+    connection: Arc<Mutex<Option<Arc<DaemonConnection>>>>,
+    timeout: Duration,
+}
+
+impl Default for SoundboardClient {
+    fn default() -> Self {
+        SoundboardClient::new()
+    }
+}
+
+impl SoundboardClient {
+    pub fn new() -> Self {
+        SoundboardClient {
+            connection: Arc::new(Mutex::new(None)),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// How long each request may wait for its response
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // The open connection, or a new one if there is none or `broken` is it
+    async fn connection(
+        &self,
+        broken: Option<&Arc<DaemonConnection>>,
+    ) -> Result<Arc<DaemonConnection>, ClientError> {
+        let mut current = self.connection.lock().await;
+        if let Some(connection) = current.as_ref() {
+            let is_broken = broken.is_some_and(|broken| Arc::ptr_eq(broken, connection));
+            if connection.is_open() && !is_broken {
+                return Ok(connection.clone());
+            }
+        }
+
+        let connection = Arc::new(DaemonConnection::open().await?);
+        *current = Some(connection.clone());
+        Ok(connection)
+    }
+
+    /// Send a request and return the daemon's response as is, failed or not
+    pub async fn request(&self, request: Request) -> Result<Response, ClientError> {
+        let connection = self.connection(None).await?;
+        match connection.request(request.clone(), self.timeout).await {
+            // The daemon restarted since the last request; try a new connection
+            Err(e) if e.is::<NotSent>() => {
+                let connection = self.connection(Some(&connection)).await?;
+                Ok(connection.request(request, self.timeout).await?)
+            }
+            result => Ok(result?),
+        }
+    }
+
+    async fn call<T: DeserializeOwned>(&self, request: Request) -> Result<T, ClientError> {
+        decode(self.request(request).await?)
+    }
+
+    /// Run requests in order with nothing else happening in between. Fails
+    /// with the error of the first request that failed; the ones after it
+    /// don't run.
+    pub async fn batch(&self, requests: Vec<Request>) -> Result<Vec<Response>, ClientError> {
+        self.call(Request::batch(requests)).await
+    }
+
+    /// Receive the daemon's events, starting with its current state. Uses a
+    /// connection of its own.
+    pub async fn subscribe(&self) -> Result<EventStream, ClientError> {
+        Ok(subscribe_events().await?)
+    }
+}
+
+/// A `SoundboardClient` for code that isn't async. Runs the client on a
+/// runtime of its own, so it works with or without a runtime around it.
+pub struct BlockingSoundboardClient {
+    client: SoundboardClient,
+    // Only None while dropping
+    runtime: Option<Runtime>,
+}
+
+impl BlockingSoundboardClient {
+    pub fn new() -> std::io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("soundboard-client")
+            .enable_all()
+            .build()?;
+        Ok(BlockingSoundboardClient {
+            client: SoundboardClient::new(),
+            runtime: Some(runtime),
+        })
+    }
+
+    /// How long each request may wait for its response
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client.timeout = timeout;
+        self
+    }
+
+    fn block_on<T: Send + 'static>(
+        &self,
+        future: impl Future<Output = Result<T, ClientError>> + Send + 'static,
+    ) -> Result<T, ClientError> {
+        let Some(runtime) = &self.runtime else {
+            return Err(ClientError::Connection("Client was shut down".to_string()));
+        };
+        let (sender, receiver) = std::sync::mpsc::channel();
+        runtime.spawn(async move {
+            let _ = sender.send(future.await);
+        });
+        wait(&receiver)
+            .unwrap_or_else(|| Err(ClientError::Connection("Client was shut down".to_string())))
+    }
+
+    /// Send a request and return the daemon's response as is, failed or not
+    pub fn request(&self, request: Request) -> Result<Response, ClientError> {
+        let client = self.client.clone();
+        self.block_on(async move { client.request(request).await })
+    }
+
+    fn call<T: DeserializeOwned + Send + 'static>(
+        &self,
+        request: Request,
+    ) -> Result<T, ClientError> {
+        let client = self.client.clone();
+        self.block_on(async move { client.call(request).await })
+    }
+
+    /// Run requests in order with nothing else happening in between. Fails
+    /// with the error of the first request that failed; the ones after it
+    /// don't run.
+    pub fn batch(&self, requests: Vec<Request>) -> Result<Vec<Response>, ClientError> {
+        self.call(Request::batch(requests))
+    }
+
+    /// Receive the daemon's events, starting with its current state
+    pub fn subscribe(&self) -> Result<BlockingEventStream, ClientError> {
+        let client = self.client.clone();
+        let mut events = self.block_on(async move { client.subscribe().await })?;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let Some(runtime) = &self.runtime else {
+            return Err(ClientError::Connection("Client was shut down".to_string()));
+        };
+        let reader = runtime.spawn(async move {
+            loop {
+                let event = events.next_event().await.map_err(ClientError::from);
+                let failed = event.is_err();
+                if sender.send(event).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Ok(BlockingEventStream { receiver, reader })
+    }
+}
+
+impl Drop for BlockingSoundboardClient {
+    fn drop(&mut self) {
+        // Dropping a runtime blocks, which panics inside async code
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Events from `BlockingSoundboardClient::subscribe`. Iterating blocks until
+/// the next event and ends after the first error.
+pub struct BlockingEventStream {
+    receiver: std::sync::mpsc::Receiver<Result<Event, ClientError>>,
+    reader: tokio::task::JoinHandle<()>,
+}
+
+impl BlockingEventStream {
+    /// Wait for the next event. Fails once the daemon goes away.
+    pub fn next_event(&mut self) -> Result<Event, ClientError> {
+        self.next()
+            .unwrap_or_else(|| Err(ClientError::Connection("Event stream closed".to_string())))
+    }
+}
+
+impl Iterator for BlockingEventStream {
+    type Item = Result<Event, ClientError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        wait(&self.receiver)
+    }
+}
+
+impl Drop for BlockingEventStream {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+// Block until the client's runtime sends a result. Blocking a worker of a
+// multi-threaded runtime is only allowed inside `block_in_place`.
+fn wait<T>(receiver: &std::sync::mpsc::Receiver<T>) -> Option<T> {
+    match Handle::try_current().map(|handle| handle.runtime_flavor()) {
+        Ok(RuntimeFlavor::MultiThread) => tokio::task::block_in_place(|| receiver.recv().ok()),
+        _ => receiver.recv().ok(),
+    }
+}
+
+// One method per command on both clients, named like the `Request`
+// constructors and returning the command's data
+macro_rules! commands {
+    ($(
+        $(#[$attr:meta])*
+        fn $name:ident($($arg:ident: $ty:ty),*) -> $data:ty = $request:expr;
+    )*) => {
+        impl SoundboardClient {
+            $(
+                $(#[$attr])*
+                pub async fn $name(&self, $($arg: $ty),*) -> Result<$data, ClientError> {
+                    self.call($request).await
+                }
+            )*
+        }
+
+        impl BlockingSoundboardClient {
+            $(
+                $(#[$attr])*
+                pub fn $name(&self, $($arg: $ty),*) -> Result<$data, ClientError> {
+                    self.call($request)
+                }
+            )*
+        }
+    };
+}
+
+commands! {
+    /// Handshake: the daemon's protocol version and the commands it knows
+    fn hello(client: &str) -> HelloInfo = Request::hello(client);
+    fn ping() -> () = Request::ping();
+    fn pause() -> PlayerState = Request::pause();
+    fn resume() -> PlayerState = Request::resume();
+    fn toggle_pause() -> PlayerState = Request::toggle_pause();
+    fn stop() -> PlayerState = Request::stop();
+    /// `priority` overrides the sound's own priority
+    fn play(file_path: &str, priority: Option<u8>) -> PlayStarted =
+        Request::play(file_path, priority);
+    fn preview(file_path: &str) -> PreviewStarted = Request::preview(file_path);
+    fn is_paused() -> bool = Request::get_is_paused();
+    fn get_state() -> PlayerState = Request::get_state();
+    /// Fails with `NotPlaying` when nothing is playing
+    fn get_current_file_path() -> PathBuf = Request::get_current_file_path();
+    fn get_volume() -> f32 = Request::get_volume();
+    fn set_volume(volume: f32) -> f32 = Request::set_volume(volume);
+    fn get_gain() -> f32 = Request::get_gain();
+    fn set_gain(gain: f32) -> f32 = Request::set_gain(gain);
+    fn get_mic_gain() -> f32 = Request::get_mic_gain();
+    fn set_mic_gain(mic_gain: f32) -> f32 = Request::set_mic_gain(mic_gain);
+    /// Seconds into the current sound
+    fn get_position() -> f32 = Request::get_position();
+    fn seek(position: f32) -> f32 = Request::seek(position);
+    /// Seconds
+    fn get_duration() -> f32 = Request::get_duration();
+    fn get_input() -> CurrentDevice = Request::get_input();
+    fn get_inputs() -> Vec<Device> = Request::get_inputs();
+    fn set_input(name: &str) -> String = Request::set_input(name);
+    fn get_output() -> CurrentDevice = Request::get_output();
+    fn get_outputs() -> Vec<Device> = Request::get_outputs();
+    fn set_output(name: &str) -> String = Request::set_output(name);
+    fn set_follow_default_input(enabled: bool) -> bool =
+        Request::set_follow_default_input(enabled);
+    fn set_follow_default_output(enabled: bool) -> bool =
+        Request::set_follow_default_output(enabled);
+    fn get_loop() -> bool = Request::get_loop();
+    fn set_loop(enabled: bool) -> bool = Request::set_loop(enabled);
+    fn toggle_loop() -> bool = Request::toggle_loop();
+    /// Play on `layer_index`, or on any layer if None
+    fn play_on_layer(layer_index: Option<usize>, file_path: &str, priority: Option<u8>)
+        -> PlayStarted = Request::play_on_layer(layer_index, file_path, priority);
+    fn stop_layer(layer_index: usize) -> usize = Request::stop_layer(layer_index);
+    fn stop_all_layers() -> () = Request::stop_all_layers();
+    fn set_layer_volume(layer_index: usize, volume: f32) -> LayerVolume =
+        Request::set_layer_volume(layer_index, volume);
+    fn get_layers_info() -> Vec<LayerInfo> = Request::get_layers_info();
+    #[cfg(target_os = "linux")]
+    fn get_app_streams() -> Vec<AppStream> = Request::get_app_streams();
+    fn get_app_routes() -> Vec<AppRouteRule> = Request::get_app_routes();
+    fn add_app_route(application_name: Option<&str>, process_binary: Option<&str>)
+        -> AppRouteRule = Request::add_app_route(application_name, process_binary);
+    fn remove_app_route(application_name: Option<&str>, process_binary: Option<&str>)
+        -> AppRouteRule = Request::remove_app_route(application_name, process_binary);
+    fn run_macro(name: &str) -> MacroStarted = Request::run_macro(name);
+    /// The name of the cancelled macro
+    fn cancel_macro(name: &str) -> String = Request::cancel_macro(name);
+    fn get_running_macros() -> Vec<String> = Request::get_running_macros();
+    fn schedule_play(
+        file_path: &str,
+        layer_index: Option<usize>,
+        delay: Option<&str>,
+        at: Option<&str>,
+        every: Option<&str>,
+        label: Option<&str>
+    ) -> ScheduledPlay = Request::schedule_play(file_path, layer_index, delay, at, every, label);
+    fn list_schedules() -> Vec<ScheduledPlay> = Request::list_schedules();
+    /// The id of the cancelled schedule
+    fn cancel_schedule(id: u64) -> u64 = Request::cancel_schedule(id);
+    /// `mode` overrides the playlist's own play mode
+    fn play_playlist(name: &str, mode: Option<PlaylistMode>) -> PlaylistStarted =
+        Request::play_playlist(name, mode);
+    /// None when no playlist is playing
+    fn get_playlist() -> Option<ActivePlaylist> = Request::get_playlist();
+    fn get_library() -> Library = Request::get_library();
+    fn get_midi_mappings() -> Vec<MidiMapping> = Request::get_midi_mappings();
+    /// Every mapping after the change
+    fn add_midi_mapping(mapping: MidiMapping) -> Vec<MidiMapping> =
+        Request::add_midi_mapping(mapping);
+    /// Every mapping after the change
+    fn remove_midi_mapping(channel: Option<u8>, trigger: MidiTrigger) -> Vec<MidiMapping> =
+        Request::remove_midi_mapping(channel, trigger);
+    /// Waits up to `utils::midi::LEARN_TIMEOUT`, so the client's timeout must
+    /// be longer
+    fn midi_learn() -> MidiLearned = Request::midi_learn();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::payload::ErrorCode;
+
+    #[test]
+    fn test_decode_data() {
+        let response = Response::new(true, "0.5").with_data(0.5f32);
+        assert_eq!(decode::<f32>(response), Ok(0.5));
+
+        // Commands without data
+        assert_eq!(decode::<()>(Response::new(true, "pong")), Ok(()));
+        assert_eq!(
+            decode::<Option<ActivePlaylist>>(Response::new(true, "null")),
+            Ok(None)
+        );
+
+        let response = Response::new(true, "Loop was set to true").with_data(true);
+        assert!(matches!(
+            decode::<f32>(response),
+            Err(ClientError::UnexpectedData(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_daemon_error() {
+        let response = Response::error(ErrorCode::NotPlaying, "Nothing is playing");
+        assert_eq!(
+            decode::<PathBuf>(response),
+            Err(ClientError::Daemon(DaemonError::new(
+                ErrorCode::NotPlaying,
+                "Nothing is playing"
+            )))
+        );
+
+        // Daemons that predate error codes only send a message
+        let response = Response::new(false, "Unknown command");
+        assert!(matches!(
+            decode::<()>(response),
+            Err(ClientError::Daemon(DaemonError {
+                code: ErrorCode::Internal,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_transport_errors() {
+        let error: Box<dyn Error + Send + Sync> =
+            DaemonError::new(ErrorCode::UnknownCommand, "Unknown command: subscribe").into();
+        assert!(matches!(
+            ClientError::from(error),
+            ClientError::Daemon(DaemonError {
+                code: ErrorCode::UnknownCommand,
+                ..
+            })
+        ));
+
+        let error: Box<dyn Error + Send + Sync> = "Read timeout".into();
+        assert_eq!(
+            ClientError::from(error),
+            ClientError::Connection("Read timeout".to_string())
+        );
+    }
+}
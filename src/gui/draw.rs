@@ -5,10 +5,12 @@ use egui::{
     ScrollArea, Slider, TextEdit, Ui, Vec2,
 };
 use egui_material_icons::icons;
-use soundboard::types::audio_player::PlayerState;
+use soundboard::types::audio_player::{PlayerState, NUM_AUDIO_LAYERS};
 use soundboard::types::config::HotkeyBinding;
-use soundboard::types::gui::{HotkeyRecording, UpdateStatus};
+use soundboard::types::gui::{HotkeyRecording, MidiLearnTarget, UpdateStatus};
+use soundboard::types::playlist::PlaylistMode;
 use soundboard::utils::gui::format_time_pair;
+use soundboard::utils::library::sound_display_name;
 use soundboard::utils::updater::get_current_version;
 
 // UI Constants
@@ -313,6 +315,77 @@ impl SoundpadGui {
             ui.separator();
             ui.add_space(10.0);
 
+            // --------- MIDI Controller Section ----------
+            ui.label(
+                RichText::new("MIDI Controller")
+                    .color(Color32::WHITE)
+                    .monospace(),
+            );
+            ui.add_space(5.0);
+
+            let mut removed_mapping = None;
+            if self.app_state.midi_mappings.is_empty() {
+                ui.label(RichText::new("No mappings").weak());
+            }
+            for mapping in &self.app_state.midi_mappings {
+                ui.horizontal(|ui| {
+                    let channel = mapping
+                        .channel
+                        .map(|channel| format!("ch {}", channel))
+                        .unwrap_or_else(|| "any ch".to_string());
+                    ui.label(format!(
+                        "{} ({}) → {}",
+                        mapping.trigger, channel, mapping.action
+                    ));
+                    if ui.small_button("Remove").clicked() {
+                        removed_mapping = Some(mapping.clone());
+                    }
+                });
+            }
+            if let Some(mapping) = removed_mapping {
+                self.remove_midi_mapping(&mapping);
+            }
+
+            ui.add_space(5.0);
+            ui.horizontal(|ui| {
+                let target = &mut self.app_state.midi_learn_target;
+                ComboBox::from_id_salt("midi_learn_target")
+                    .selected_text(target.label())
+                    .show_ui(ui, |ui| {
+                        for option in MidiLearnTarget::ALL {
+                            ui.selectable_value(target, option, option.label());
+                        }
+                    });
+                if target.uses_layer() {
+                    ui.label("Layer:");
+                    ui.add(
+                        egui::DragValue::new(&mut self.app_state.midi_learn_layer)
+                            .range(0..=NUM_AUDIO_LAYERS - 1),
+                    );
+                }
+
+                if self.midi_learn_receiver.is_some() {
+                    ui.spinner();
+                    ui.label(RichText::new("Press a pad or move a knob...").color(Color32::YELLOW));
+                } else if ui.button("Learn").clicked() {
+                    self.start_midi_learn();
+                }
+            });
+
+            if let Some(status) = &self.app_state.midi_status {
+                ui.label(RichText::new(status).weak().size(11.0));
+            }
+            ui.label(
+                RichText::new("Pick an action, click 'Learn', then touch a pad, knob or fader")
+                    .weak()
+                    .size(11.0),
+            );
+            // --------------------------------
+
+            ui.add_space(20.0);
+            ui.separator();
+            ui.add_space(10.0);
+
             // --------- Sounds Folder Section ----------
             ui.label(
                 RichText::new("Sounds Folder")
@@ -490,6 +563,8 @@ impl SoundpadGui {
             self.draw_controls(ui);
             // Layers panel (compact)
             self.draw_layers_panel(ui);
+            // Macro buttons
+            self.draw_macros_panel(ui);
             ui.separator();
         });
     }
@@ -587,6 +662,62 @@ impl SoundpadGui {
         }
     }
 
+    fn draw_macros_panel(&mut self, ui: &mut Ui) {
+        /// Action to perform on a macro
+        enum MacroAction {
+            Run(String),
+            Cancel(String),
+        }
+
+        if self.config.macros.is_empty() {
+            return;
+        }
+
+        let mut names: Vec<&String> = self.config.macros.keys().collect();
+        names.sort();
+
+        let mut macro_action: Option<MacroAction> = None;
+
+        ui.horizontal_wrapped(|ui| {
+            ui.label(
+                RichText::new(format!("{} Macros:", icons::ICON_QUEUE_MUSIC.codepoint))
+                    .size(12.0)
+                    .weak(),
+            );
+
+            for name in names {
+                let macro_btn = Button::new(
+                    RichText::new(format!(
+                        "{} {}",
+                        icons::ICON_PLAY_ARROW.codepoint,
+                        truncate_string(name, 16)
+                    ))
+                    .size(11.0),
+                )
+                .frame(false);
+                let macro_response = ui.add(macro_btn);
+
+                if macro_response.clicked() {
+                    macro_action = Some(MacroAction::Run(name.clone()));
+                }
+                if macro_response.secondary_clicked() {
+                    macro_action = Some(MacroAction::Cancel(name.clone()));
+                }
+                if macro_response.hovered() {
+                    macro_response
+                        .on_hover_text(format!("Click to run '{}', right-click to cancel", name));
+                }
+            }
+        });
+
+        // Execute actions after UI rendering (avoids borrow issues)
+        match macro_action {
+            Some(MacroAction::Run(name)) => self.run_macro(&name),
+            Some(MacroAction::Cancel(name)) => self.cancel_macro(&name),
+            None => {}
+        }
+    }
+
     fn draw_controls(&mut self, ui: &mut Ui) {
         ui.horizontal_top(|ui| {
             let control_size = [CONTROL_SIZE, CONTROL_SIZE];
@@ -1081,7 +1212,7 @@ impl SoundpadGui {
 
     /// Draw playlist header showing current playlist name
     fn draw_playlist_header(&mut self, ui: &mut Ui) {
-        if let Some(ref playlist_name) = self.app_state.current_playlist {
+        if let Some(playlist_name) = self.app_state.current_playlist.clone() {
             let is_all_sounds = playlist_name == "All Sounds";
             let icon = if is_all_sounds {
                 icons::ICON_LIBRARY_MUSIC
@@ -1094,14 +1225,48 @@ impl SoundpadGui {
                 Color32::LIGHT_BLUE
             };
 
+            // Virtual playlists (All Sounds, Favourites) have no play mode
+            let play_mode = self
+                .config
+                .categories
+                .get(&playlist_name)
+                .map(|p| p.play_mode);
+            let mut selected_mode = play_mode;
+            let mut play_clicked = false;
+
             ui.horizontal(|ui| {
                 ui.label(
                     RichText::new(format!("{} {}", icon.codepoint, playlist_name))
                         .color(color)
                         .monospace(),
                 );
+
+                if let Some(ref mut mode) = selected_mode {
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        play_clicked = ui
+                            .button(icons::ICON_PLAY_ARROW.codepoint)
+                            .on_hover_text("Play this playlist")
+                            .clicked();
+
+                        ComboBox::from_id_salt("playlist_mode")
+                            .selected_text(mode.display())
+                            .width(100.0)
+                            .show_ui(ui, |ui| {
+                                for option in PlaylistMode::ALL {
+                                    ui.selectable_value(mode, option, option.display());
+                                }
+                            });
+                    });
+                }
             });
             ui.add_space(4.0);
+
+            if let Some(mode) = selected_mode.filter(|m| Some(*m) != play_mode) {
+                self.set_playlist_mode(&playlist_name, mode);
+            }
+            if play_clicked {
+                self.play_playlist(&playlist_name);
+            }
         }
     }
 
@@ -1681,17 +1846,7 @@ impl SoundpadGui {
         is_being_dragged: bool,
     ) {
         // Get display name (custom name or filename)
-        let display_name = self
-            .config
-            .sound_metadata
-            .get(entry_path)
-            .and_then(|m| m.custom_name.clone())
-            .unwrap_or_else(|| {
-                entry_path
-                    .file_name()
-                    .map(|n| n.to_string_lossy().to_string())
-                    .unwrap_or_default()
-            });
+        let display_name = sound_display_name(&self.config, entry_path);
 
         let is_favorite = self.is_favorite(entry_path);
 
@@ -1848,17 +2003,7 @@ impl SoundpadGui {
         let is_favourites = playlist_name == "Favourites";
 
         // Get display name (custom name or filename)
-        let display_name = self
-            .config
-            .sound_metadata
-            .get(entry_path)
-            .and_then(|m| m.custom_name.clone())
-            .unwrap_or_else(|| {
-                entry_path
-                    .file_name()
-                    .map(|n| n.to_string_lossy().to_string())
-                    .unwrap_or_default()
-            });
+        let display_name = sound_display_name(&self.config, entry_path);
 
         let is_favorite = self.is_favorite(entry_path);
 
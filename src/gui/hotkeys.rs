@@ -2,12 +2,20 @@ use global_hotkey::{
     GlobalHotKeyEvent, GlobalHotKeyManager,
     hotkey::{Code, HotKey, Modifiers},
 };
-use soundboard::types::config::{HotkeyBinding, HotkeyConfig};
-use std::sync::{mpsc, Arc, RwLock};
+use soundboard::types::{
+    config::{HotkeyBinding, HotkeyConfig, SoundCategory},
+    macros::SoundMacro,
+};
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc, RwLock},
+};
 
 pub enum HotkeyAction {
     PlayPause,
     Stop,
+    RunMacro(String),
+    PlayPlaylist(String),
 }
 
 /// Shared state for hotkey IDs that can be updated at runtime
@@ -15,6 +23,10 @@ pub enum HotkeyAction {
 struct HotkeyIds {
     play_pause_id: Option<u32>,
     stop_id: Option<u32>,
+    /// Hotkey ID -> macro name
+    macro_ids: HashMap<u32, String>,
+    /// Hotkey ID -> playlist name
+    playlist_ids: HashMap<u32, String>,
 }
 
 pub struct HotkeyManager {
@@ -24,10 +36,16 @@ pub struct HotkeyManager {
     ids: Arc<RwLock<HotkeyIds>>,
     play_pause_binding: Option<HotkeyBinding>,
     stop_binding: Option<HotkeyBinding>,
+    macro_bindings: Vec<HotkeyBinding>,
+    playlist_bindings: Vec<HotkeyBinding>,
 }
 
 impl HotkeyManager {
-    pub fn new(config: &HotkeyConfig) -> Option<Self> {
+    pub fn new(
+        config: &HotkeyConfig,
+        macros: &HashMap<String, SoundMacro>,
+        playlists: &HashMap<String, SoundCategory>,
+    ) -> Option<Self> {
         let manager = match GlobalHotKeyManager::new() {
             Ok(m) => m,
             Err(e) => {
@@ -46,10 +64,12 @@ impl HotkeyManager {
             ids: ids.clone(),
             play_pause_binding: None,
             stop_binding: None,
+            macro_bindings: Vec::new(),
+            playlist_bindings: Vec::new(),
         };
 
         if config.enabled {
-            hotkey_manager.register_hotkeys(config);
+            hotkey_manager.register_hotkeys(config, macros, playlists);
         }
 
         // Start the event listener thread
@@ -73,6 +93,12 @@ impl HotkeyManager {
                             sender_clone.send(HotkeyAction::PlayPause).ok();
                         } else if Some(event.id) == ids.stop_id {
                             sender_clone.send(HotkeyAction::Stop).ok();
+                        } else if let Some(name) = ids.macro_ids.get(&event.id) {
+                            sender_clone.send(HotkeyAction::RunMacro(name.clone())).ok();
+                        } else if let Some(name) = ids.playlist_ids.get(&event.id) {
+                            sender_clone
+                                .send(HotkeyAction::PlayPlaylist(name.clone()))
+                                .ok();
                         }
                     }
                 }
@@ -82,7 +108,12 @@ impl HotkeyManager {
         Some(hotkey_manager)
     }
 
-    fn register_hotkeys(&mut self, config: &HotkeyConfig) {
+    fn register_hotkeys(
+        &mut self,
+        config: &HotkeyConfig,
+        macros: &HashMap<String, SoundMacro>,
+        playlists: &HashMap<String, SoundCategory>,
+    ) {
         let mut ids = match self.ids.write() {
             Ok(guard) => guard,
             Err(poisoned) => {
@@ -124,6 +155,50 @@ impl HotkeyManager {
                 }
             }
         }
+
+        // Register macro hotkeys
+        for (name, sound_macro) in macros {
+            let Some(ref binding) = sound_macro.hotkey else {
+                continue;
+            };
+            if let Some(hotkey) = binding_to_hotkey(binding) {
+                let id = hotkey.id();
+                match self.manager.register(hotkey) {
+                    Ok(()) => {
+                        ids.macro_ids.insert(id, name.clone());
+                        self.macro_bindings.push(binding.clone());
+                    }
+                    Err(e) => tracing::error!(
+                        "Failed to register hotkey for macro '{}' ({}): {}",
+                        name,
+                        binding.display(),
+                        e
+                    ),
+                }
+            }
+        }
+
+        // Register playlist hotkeys
+        for (name, playlist) in playlists {
+            let Some(ref binding) = playlist.hotkey else {
+                continue;
+            };
+            if let Some(hotkey) = binding_to_hotkey(binding) {
+                let id = hotkey.id();
+                match self.manager.register(hotkey) {
+                    Ok(()) => {
+                        ids.playlist_ids.insert(id, name.clone());
+                        self.playlist_bindings.push(binding.clone());
+                    }
+                    Err(e) => tracing::error!(
+                        "Failed to register hotkey for playlist '{}' ({}): {}",
+                        name,
+                        binding.display(),
+                        e
+                    ),
+                }
+            }
+        }
     }
 
     fn unregister_hotkeys(&mut self) {
@@ -150,15 +225,36 @@ impl HotkeyManager {
             }
         }
         ids.stop_id = None;
+
+        // Unregister macro hotkeys
+        for binding in self.macro_bindings.drain(..) {
+            if let Some(hotkey) = binding_to_hotkey(&binding) {
+                let _ = self.manager.unregister(hotkey);
+            }
+        }
+        ids.macro_ids.clear();
+
+        // Unregister playlist hotkeys
+        for binding in self.playlist_bindings.drain(..) {
+            if let Some(hotkey) = binding_to_hotkey(&binding) {
+                let _ = self.manager.unregister(hotkey);
+            }
+        }
+        ids.playlist_ids.clear();
     }
 
-    pub fn update_hotkeys(&mut self, config: &HotkeyConfig) {
+    pub fn update_hotkeys(
+        &mut self,
+        config: &HotkeyConfig,
+        macros: &HashMap<String, SoundMacro>,
+        playlists: &HashMap<String, SoundCategory>,
+    ) {
         // Unregister existing hotkeys
         self.unregister_hotkeys();
 
         // Re-register if enabled
         if config.enabled {
-            self.register_hotkeys(config);
+            self.register_hotkeys(config, macros, playlists);
         }
     }
 }
@@ -10,15 +10,20 @@ use eframe::{HardwareAcceleration, NativeOptions, icon_data::from_png_bytes, run
 use egui::{Context, Vec2, ViewportBuilder};
 use soundboard::{
     MutexExt,
+    client::{BlockingSoundboardClient, ClientError, SoundboardClient},
     types::{
         audio_player::PlayerState,
         config::GuiConfig,
         gui::{AppState, AudioPlayerState, UpdateStatus},
-        socket::Request,
+        midi::MidiMapping,
+        payload::MidiLearned,
+        playlist::PlaylistMode,
     },
     utils::{
         daemon::get_daemon_config,
-        gui::{get_gui_config, make_request_sync, start_app_state_thread},
+        gui::{get_gui_config, start_app_state_thread},
+        library::{SUPPORTED_EXTENSIONS, ordered_playlists, sounds_in_folder},
+        midi::LEARN_TIMEOUT,
         updater::{check_for_updates, download_update},
     },
 };
@@ -32,10 +37,6 @@ use std::{
     thread,
 };
 
-const SUPPORTED_EXTENSIONS: [&str; 13] = [
-    "mp3", "wav", "ogg", "flac", "mp4", "m4a", "aac", "mov", "mkv", "webm", "avi", "opus", "wma",
-];
-
 /// Validates that a path is safely within an allowed base directory.
 /// Returns the canonicalized path if valid, or None if the path escapes the base directory.
 fn validate_path_within(path: &Path, base_dir: &Path) -> Option<PathBuf> {
@@ -87,9 +88,12 @@ struct SoundpadGui {
     pub config: GuiConfig,
     pub audio_player_state: AudioPlayerState,
     pub audio_player_state_shared: Arc<Mutex<AudioPlayerState>>,
+    pub client: BlockingSoundboardClient,
     pub tray_handle: Option<TrayHandle>,
     pub hotkey_manager: Option<HotkeyManager>,
     pub update_receiver: Option<mpsc::Receiver<UpdateStatus>>,
+    /// Result of a running MIDI learn
+    pub midi_learn_receiver: Option<mpsc::Receiver<Result<MidiLearned, String>>>,
     /// Flag to track if startup update check has been performed
     pub startup_update_checked: bool,
     /// Cache of path-exists results, refreshed periodically to avoid per-frame syscalls
@@ -101,7 +105,8 @@ struct SoundpadGui {
 }
 
 impl SoundpadGui {
-    fn new(ctx: &Context) -> Self {
+    fn new(ctx: &Context) -> std::io::Result<Self> {
+        let client = BlockingSoundboardClient::new()?;
         let audio_player_state = Arc::new(Mutex::new(AudioPlayerState::default()));
         start_app_state_thread(audio_player_state.clone());
 
@@ -120,6 +125,7 @@ impl SoundpadGui {
             } else {
                 None
             },
+            midi_mappings: get_daemon_config().midi.mappings,
             ..Default::default()
         };
 
@@ -130,22 +136,24 @@ impl SoundpadGui {
         };
 
         let tray_handle = start_tray();
-        let hotkey_manager = HotkeyManager::new(&config.hotkeys);
+        let hotkey_manager = HotkeyManager::new(&config.hotkeys, &config.macros, &config.categories);
 
-        SoundpadGui {
+        Ok(SoundpadGui {
             app_state,
             config,
             audio_player_state: audio_player_state_local,
             audio_player_state_shared: audio_player_state,
+            client,
             tray_handle,
             hotkey_manager,
             update_receiver: None,
+            midi_learn_receiver: None,
             startup_update_checked: false,
             file_existence_cache: HashMap::new(),
             file_existence_checked_at: None,
             tags_cache: None,
             ordered_playlists_cache: None,
-        }
+        })
     }
 
     /// Returns true if `path` exists, refreshing the cache at most once per second.
@@ -210,6 +218,12 @@ impl SoundpadGui {
                 HotkeyAction::Stop => {
                     self.stop();
                 }
+                HotkeyAction::RunMacro(name) => {
+                    self.run_macro(&name);
+                }
+                HotkeyAction::PlayPlaylist(name) => {
+                    self.play_playlist(&name);
+                }
             }
         }
     }
@@ -234,6 +248,79 @@ impl SoundpadGui {
         }
     }
 
+    /// Wait in the background for a MIDI control to map to the chosen action
+    pub fn start_midi_learn(&mut self) {
+        let (sender, receiver) = mpsc::channel();
+        self.midi_learn_receiver = Some(receiver);
+        self.app_state.midi_status = None;
+
+        thread::spawn(move || {
+            let rt = match tokio::runtime::Runtime::new() {
+                Ok(rt) => rt,
+                Err(e) => {
+                    sender
+                        .send(Err(format!("Failed to create runtime: {}", e)))
+                        .ok();
+                    return;
+                }
+            };
+            let client = SoundboardClient::new().with_timeout(LEARN_TIMEOUT * 2);
+            let result = rt.block_on(client.midi_learn()).map_err(|e| e.to_string());
+            sender.send(result).ok();
+        });
+    }
+
+    fn poll_midi_learn(&mut self) {
+        let Some(ref receiver) = self.midi_learn_receiver else {
+            return;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err("MIDI learn stopped".to_string()),
+        };
+        self.midi_learn_receiver = None;
+
+        let learned = match result {
+            Ok(learned) => learned,
+            Err(e) => {
+                self.app_state.midi_status = Some(e);
+                return;
+            }
+        };
+        let action = self.app_state.midi_learn_target.action(
+            self.app_state.selected_file.as_ref(),
+            self.app_state.midi_learn_layer,
+        );
+        let Some(action) = action else {
+            self.app_state.midi_status = Some("Select a sound first".to_string());
+            return;
+        };
+        let mapping = MidiMapping {
+            channel: Some(learned.channel),
+            trigger: learned.trigger,
+            action,
+        };
+        let status = format!("{} → {}", mapping.trigger, mapping.action);
+        match self.client.add_midi_mapping(mapping) {
+            Ok(mappings) => {
+                self.app_state.midi_mappings = mappings;
+                self.app_state.midi_status = Some(status);
+            }
+            Err(e) => self.app_state.midi_status = Some(e.to_string()),
+        }
+    }
+
+    pub fn remove_midi_mapping(&mut self, mapping: &MidiMapping) {
+        match self
+            .client
+            .remove_midi_mapping(mapping.channel, mapping.trigger)
+        {
+            Ok(mappings) => self.app_state.midi_mappings = mappings,
+            Err(e) => self.app_state.midi_status = Some(e.to_string()),
+        }
+    }
+
     pub fn check_for_updates(&mut self) {
         self.app_state.update_status = UpdateStatus::Checking;
 
@@ -295,19 +382,13 @@ impl SoundpadGui {
     }
 
     pub fn play_toggle(&mut self) {
-        let (new_state, request) = {
-            let guard = self.audio_player_state_shared.lock_or_recover();
-            match guard.state {
-                PlayerState::Playing => (Some(PlayerState::Paused), Some(Request::pause())),
-                PlayerState::Paused => (Some(PlayerState::Playing), Some(Request::resume())),
-                PlayerState::Stopped => (None, None),
-            }
+        let state = self.audio_player_state_shared.lock_or_recover().state;
+        let new_state = match state {
+            PlayerState::Playing => self.client.pause().ok(),
+            PlayerState::Paused => self.client.resume().ok(),
+            PlayerState::Stopped => None,
         };
 
-        if let Some(req) = request {
-            make_request_sync(req).ok();
-        }
-
         if let Some(state) = new_state {
             let mut guard = self.audio_player_state_shared.lock_or_recover();
             guard.new_state = Some(state);
@@ -329,7 +410,7 @@ impl SoundpadGui {
     pub fn play_file(&mut self, path: &PathBuf) {
         // Apply per-sound volume if set
         if let Some(sound_volume) = self.get_sound_volume(path) {
-            make_request_sync(Request::set_volume(sound_volume)).ok();
+            self.client.set_volume(sound_volume).ok();
             // Update local state to reflect the volume change
             let mut guard = self.audio_player_state_shared.lock_or_recover();
             guard.volume = sound_volume;
@@ -337,8 +418,10 @@ impl SoundpadGui {
         }
 
         if let Some(path_str) = path.to_str() {
-            if let Err(e) = make_request_sync(Request::play(path_str)) {
-                tracing::error!("Failed to send play request: {}", e);
+            match self.client.play(path_str, None) {
+                Ok(_) => {}
+                Err(ClientError::Daemon(e)) => tracing::warn!("{}", e),
+                Err(e) => tracing::error!("Failed to send play request: {}", e),
             }
         } else {
             tracing::error!("Invalid file path encoding");
@@ -347,7 +430,7 @@ impl SoundpadGui {
 
     pub fn preview_file(&mut self, path: &Path) {
         if let Some(path_str) = path.to_str() {
-            if let Err(e) = make_request_sync(Request::preview(path_str)) {
+            if let Err(e) = self.client.preview(path_str) {
                 tracing::error!("Failed to send preview request: {}", e);
             }
         } else {
@@ -356,7 +439,7 @@ impl SoundpadGui {
     }
 
     pub fn set_input(&mut self, name: String) {
-        make_request_sync(Request::set_input(&name)).ok();
+        self.client.set_input(&name).ok();
 
         if self.config.save_input {
             let mut daemon_config = get_daemon_config();
@@ -368,7 +451,7 @@ impl SoundpadGui {
     }
 
     pub fn set_output(&mut self, name: String) {
-        make_request_sync(Request::set_output(&name)).ok();
+        self.client.set_output(&name).ok();
 
         // Save output preference to daemon config
         let mut daemon_config = get_daemon_config();
@@ -379,34 +462,70 @@ impl SoundpadGui {
     }
 
     pub fn toggle_loop(&mut self) {
-        make_request_sync(Request::toggle_loop()).ok();
+        self.client.toggle_loop().ok();
     }
 
     pub fn update_hotkeys(&mut self) {
         if let Some(ref mut hk) = self.hotkey_manager {
-            hk.update_hotkeys(&self.config.hotkeys);
+            hk.update_hotkeys(&self.config.hotkeys, &self.config.macros, &self.config.categories);
         }
     }
 
     pub fn stop(&mut self) {
-        make_request_sync(Request::stop()).ok();
+        self.client.stop().ok();
         let mut guard = self.audio_player_state_shared.lock_or_recover();
         guard.new_state = Some(PlayerState::Stopped);
         guard.state = PlayerState::Stopped;
     }
 
     pub fn stop_layer(&mut self, layer_index: usize) {
-        if let Err(e) = make_request_sync(Request::stop_layer(layer_index)) {
+        if let Err(e) = self.client.stop_layer(layer_index) {
             tracing::error!("Failed to stop layer {}: {}", layer_index, e);
         }
     }
 
     pub fn stop_all_layers(&mut self) {
-        if let Err(e) = make_request_sync(Request::stop_all_layers()) {
+        if let Err(e) = self.client.stop_all_layers() {
             tracing::error!("Failed to stop all layers: {}", e);
         }
     }
 
+    pub fn run_macro(&mut self, name: &str) {
+        match self.client.run_macro(name) {
+            Ok(_) => {}
+            Err(ClientError::Daemon(e)) => {
+                tracing::error!("Failed to run macro '{}': {}", name, e);
+            }
+            Err(e) => tracing::error!("Failed to send run macro request: {}", e),
+        }
+    }
+
+    pub fn cancel_macro(&mut self, name: &str) {
+        if let Err(e) = self.client.cancel_macro(name) {
+            tracing::error!("Failed to cancel macro '{}': {}", name, e);
+        }
+    }
+
+    pub fn play_playlist(&mut self, name: &str) {
+        match self.client.play_playlist(name, None) {
+            Ok(_) => {}
+            Err(ClientError::Daemon(e)) => {
+                tracing::error!("Failed to play playlist '{}': {}", name, e);
+            }
+            Err(e) => tracing::error!("Failed to send play playlist request: {}", e),
+        }
+    }
+
+    /// Set how a playlist is played
+    pub fn set_playlist_mode(&mut self, name: &str, mode: PlaylistMode) {
+        if let Some(playlist) = self.config.categories.get_mut(name) {
+            playlist.play_mode = mode;
+            if let Err(e) = self.config.save_to_file() {
+                tracing::error!("Failed to save config: {}", e);
+            }
+        }
+    }
+
     pub fn toggle_favorite(&mut self, path: &PathBuf) {
         let was_favorite = self.config.favorites.contains(path);
         if was_favorite {
@@ -496,19 +615,7 @@ impl SoundpadGui {
     fn load_all_sounds(&mut self) {
         self.app_state.files.clear();
         if let Some(ref sounds_folder) = self.config.sounds_folder {
-            if let Ok(entries) = sounds_folder.read_dir() {
-                for entry in entries.filter_map(|e| e.ok()) {
-                    let path = entry.path();
-                    if path.is_file() {
-                        // Check if it's a supported audio file
-                        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                            if SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str()) {
-                                self.app_state.files.insert(path);
-                            }
-                        }
-                    }
-                }
-            }
+            self.app_state.files.extend(sounds_in_folder(sounds_folder));
         }
         self.invalidate_files_cache();
     }
@@ -571,29 +678,7 @@ impl SoundpadGui {
             }
         }
 
-        let mut ordered = Vec::new();
-
-        // First, add playlists in the configured order
-        let ordered_set: std::collections::HashSet<&String> =
-            self.config.playlist_order.iter().collect();
-        for name in &self.config.playlist_order {
-            if self.config.categories.contains_key(name) {
-                ordered.push(name.clone());
-            }
-        }
-
-        // Then, add any playlists not in the order list (sorted alphabetically).
-        // O(1) contains check via set instead of O(n) Vec::contains.
-        let mut remaining: Vec<_> = self
-            .config
-            .categories
-            .keys()
-            .filter(|k| !ordered_set.contains(*k))
-            .cloned()
-            .collect();
-        remaining.sort();
-        ordered.extend(remaining);
-
+        let ordered = ordered_playlists(&self.config);
         self.ordered_playlists_cache = Some((now, ordered.clone()));
         ordered
     }
@@ -1002,13 +1087,13 @@ pub async fn run() -> Result<(), Box<dyn Error>> {
         options,
         Box::new(|cc| {
             egui_material_icons::initialize(&cc.egui_ctx);
-            Ok(Box::new(SoundpadGui::new(&cc.egui_ctx)))
+            Ok(Box::new(SoundpadGui::new(&cc.egui_ctx)?))
         }),
     ) {
         Ok(_) => {
             let config = get_gui_config();
             if config.pause_on_exit {
-                make_request_sync(Request::pause()).ok();
+                SoundboardClient::new().pause().await.ok();
             }
             Ok(())
         }
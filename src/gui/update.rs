@@ -1,13 +1,7 @@
 use crate::gui::{MutexExt, SoundpadGui};
 use eframe::{App, Frame as EFrame};
 use egui::Ui;
-use soundboard::{
-    types::socket::Request,
-    utils::{
-        daemon::{get_daemon_config, is_daemon_running},
-        gui::make_request_sync,
-    },
-};
+use soundboard::utils::daemon::{get_daemon_config, is_daemon_running};
 
 impl App for SoundpadGui {
     fn logic(&mut self, ctx: &egui::Context, _frame: &mut EFrame) {
@@ -23,6 +17,8 @@ impl App for SoundpadGui {
         self.poll_hotkey_messages();
         // Poll for update check results
         self.poll_update_status();
+        // Poll for a learned MIDI control
+        self.poll_midi_learn();
 
         {
             let guard = self.audio_player_state_shared.lock_or_recover();
@@ -69,7 +65,7 @@ impl App for SoundpadGui {
         }
 
         if self.app_state.position_dragged {
-            make_request_sync(Request::seek(self.app_state.position_slider_value)).ok();
+            self.client.seek(self.app_state.position_slider_value).ok();
             let mut guard = self.audio_player_state_shared.lock_or_recover();
             guard.new_position = Some(self.app_state.position_slider_value);
             guard.position = self.app_state.position_slider_value;
@@ -81,7 +77,7 @@ impl App for SoundpadGui {
         if self.app_state.volume_dragged {
             let new_volume = self.app_state.volume_slider_value;
 
-            make_request_sync(Request::set_volume(new_volume)).ok();
+            self.client.set_volume(new_volume).ok();
 
             let mut guard = self.audio_player_state_shared.lock_or_recover();
             guard.new_volume = Some(self.app_state.volume_slider_value);
@@ -101,7 +97,7 @@ impl App for SoundpadGui {
         if self.app_state.gain_dragged {
             let new_gain = self.app_state.gain_slider_value;
 
-            make_request_sync(Request::set_gain(new_gain)).ok();
+            self.client.set_gain(new_gain).ok();
 
             let mut guard = self.audio_player_state_shared.lock_or_recover();
             guard.new_gain = Some(self.app_state.gain_slider_value);
@@ -121,7 +117,7 @@ impl App for SoundpadGui {
         if self.app_state.mic_gain_dragged {
             let new_mic_gain = self.app_state.mic_gain_slider_value;
 
-            make_request_sync(Request::set_mic_gain(new_mic_gain)).ok();
+            self.client.set_mic_gain(new_mic_gain).ok();
 
             let mut guard = self.audio_player_state_shared.lock_or_recover();
             guard.new_mic_gain = Some(self.app_state.mic_gain_slider_value);
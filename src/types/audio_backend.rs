@@ -0,0 +1,284 @@
+use crate::utils::wav::{create_wav_file, WavWriter};
+use rodio::mixer::{Mixer, MixerSource};
+use rodio::stream::MixerDeviceSink;
+#[cfg(test)]
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    num::NonZero,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Channel count of the headless mixer
+pub const HEADLESS_CHANNELS: u16 = 2;
+/// Sample rate of the headless mixer
+pub const HEADLESS_SAMPLE_RATE: u32 = 48000;
+/// In real time, samples are pulled from the mixer in 10 ms chunks
+const CHUNKS_PER_SECOND: u32 = 100;
+#[cfg(test)]
+const CHUNK_DURATION: Duration = Duration::from_millis(1000 / CHUNKS_PER_SECOND as u64);
+
+/// The stream the AudioPlayer's sinks are mixed into
+pub enum AudioOutput {
+    /// A real output device
+    Device(MixerDeviceSink),
+    /// No device; a thread consumes the mix in real time
+    Headless(HeadlessOutput),
+}
+
+impl AudioOutput {
+    pub fn mixer(&self) -> &Mixer {
+        match self {
+            AudioOutput::Device(stream) => stream.mixer(),
+            AudioOutput::Headless(output) => output.mixer(),
+        }
+    }
+
+    pub fn is_headless(&self) -> bool {
+        matches!(self, AudioOutput::Headless(_))
+    }
+}
+
+/// Mixer that is drained by a background thread, either discarding the
+/// samples or writing them to a WAV file. The thread runs at real-time speed
+/// unless a test created it with a `StepClock`.
+pub struct HeadlessOutput {
+    mixer: Mixer,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Drives a stepped headless output: the mixer is only pulled as far as the
+/// clock is advanced, so what ends up in the file doesn't depend on timing
+#[cfg(test)]
+pub struct StepClock(Sender<u64>);
+
+#[cfg(test)]
+impl StepClock {
+    /// Mix and write the next `frames` sample frames
+    pub fn advance(&self, frames: u64) {
+        // The output thread only goes away with the output itself
+        let _ = self.0.send(frames);
+    }
+}
+
+impl HeadlessOutput {
+    /// Consume and discard the mixed output
+    pub fn null() -> Self {
+        Self::start(None, Pacing::real_time())
+    }
+
+    /// Write the mixed output to a 32-bit float WAV file
+    pub fn file(path: &Path) -> io::Result<Self> {
+        let writer = create_wav_file(path, HEADLESS_CHANNELS, HEADLESS_SAMPLE_RATE)?;
+        tracing::info!("Writing audio output to {}", path.display());
+        Ok(Self::start(Some(writer), Pacing::real_time()))
+    }
+
+    /// Write the mixed output to a WAV file as `StepClock::advance` asks
+    #[cfg(test)]
+    pub fn file_stepped(path: &Path) -> io::Result<(Self, StepClock)> {
+        let writer = create_wav_file(path, HEADLESS_CHANNELS, HEADLESS_SAMPLE_RATE)?;
+        let (steps, receiver) = mpsc::channel();
+        Ok((
+            Self::start(Some(writer), Pacing::Stepped(receiver)),
+            StepClock(steps),
+        ))
+    }
+
+    pub fn mixer(&self) -> &Mixer {
+        &self.mixer
+    }
+
+    fn start(writer: Option<WavWriter<BufWriter<File>>>, pacing: Pacing) -> Self {
+        let channels = NonZero::new(HEADLESS_CHANNELS).expect("channel count is non-zero");
+        let sample_rate = NonZero::new(HEADLESS_SAMPLE_RATE).expect("sample rate is non-zero");
+        let (mixer, source) = rodio::mixer::mixer(channels, sample_rate);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = thread::Builder::new()
+            .name("headless-output".to_string())
+            .spawn({
+                let stop = stop.clone();
+                move || drain_mixer(source, writer, pacing, &stop)
+            })
+            .map_err(|e| tracing::error!("Failed to spawn headless output thread: {}", e))
+            .ok();
+
+        Self {
+            mixer,
+            stop,
+            thread,
+        }
+    }
+}
+
+impl Drop for HeadlessOutput {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                tracing::error!("Headless output thread panicked");
+            }
+        }
+    }
+}
+
+/// How the output thread decides how much to pull next
+enum Pacing {
+    /// 10 ms chunks at the rate a sound card would ask for them
+    RealTime { started: Instant, chunks: u64 },
+    /// Whatever the `StepClock` asks for
+    #[cfg(test)]
+    Stepped(Receiver<u64>),
+}
+
+impl Pacing {
+    fn real_time() -> Self {
+        Pacing::RealTime {
+            started: Instant::now(),
+            chunks: 0,
+        }
+    }
+
+    /// Frames to pull next, or None once the output is stopped
+    fn next_frames(&mut self, stop: &AtomicBool) -> Option<u64> {
+        match self {
+            Pacing::RealTime { started, chunks } => {
+                // Sleep until the chunk is due; measuring from `started`
+                // keeps the rate from drifting with scheduling jitter
+                let due =
+                    *started + Duration::from_millis(*chunks * 1000 / CHUNKS_PER_SECOND as u64);
+                if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                    thread::sleep(wait);
+                }
+                *chunks += 1;
+                (!stop.load(Ordering::Relaxed))
+                    .then_some((HEADLESS_SAMPLE_RATE / CHUNKS_PER_SECOND) as u64)
+            }
+            // Steps sent before the output was dropped are still written
+            #[cfg(test)]
+            Pacing::Stepped(steps) => loop {
+                match steps.recv_timeout(CHUNK_DURATION) {
+                    Ok(frames) => return Some(frames),
+                    Err(RecvTimeoutError::Timeout) if !stop.load(Ordering::Relaxed) => {}
+                    Err(_) => return None,
+                }
+            },
+        }
+    }
+}
+
+/// Pull samples from the mixer as `pacing` allows, until it stops. An idle
+/// mixer yields `None`, which is written as silence so the file stays
+/// aligned with the clock.
+fn drain_mixer(
+    mut source: MixerSource,
+    mut writer: Option<WavWriter<BufWriter<File>>>,
+    mut pacing: Pacing,
+    stop: &AtomicBool,
+) {
+    let mut frames_pulled: u64 = 0;
+
+    while let Some(frames) = pacing.next_frames(stop) {
+        for _ in 0..frames * HEADLESS_CHANNELS as u64 {
+            let sample = source.next().unwrap_or(0.0);
+            if let Some(wav) = writer.as_mut() {
+                if let Err(e) = wav.write_sample(sample) {
+                    tracing::error!("Failed to write audio output file: {}", e);
+                    writer = None;
+                }
+            }
+        }
+
+        // The daemon is usually stopped by a signal and never drops the
+        // player, so keep the header valid as the file grows
+        let seconds_before = frames_pulled / HEADLESS_SAMPLE_RATE as u64;
+        frames_pulled += frames;
+        if frames_pulled / HEADLESS_SAMPLE_RATE as u64 > seconds_before {
+            if let Some(wav) = writer.as_mut() {
+                if let Err(e) = wav.update_header() {
+                    tracing::error!("Failed to update audio output file header: {}", e);
+                    writer = None;
+                }
+            }
+        }
+    }
+
+    if let Some(wav) = writer {
+        if let Err(e) = wav.finalize() {
+            tracing::error!("Failed to finalize audio output file: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::{Decoder, Player};
+    use std::fs;
+
+    // Writes `samples` the way the file backend does, for comparison
+    fn wav_bytes(path: &Path, samples: &[f32]) -> Vec<u8> {
+        let mut writer = create_wav_file(path, HEADLESS_CHANNELS, HEADLESS_SAMPLE_RATE).unwrap();
+        for sample in samples {
+            writer.write_sample(*sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        fs::read(path).unwrap()
+    }
+
+    #[test]
+    fn test_file_output_matches_played_wav() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // 1000 frames with different left and right ramps
+        let sound: Vec<f32> = (0..1000)
+            .flat_map(|i| [i as f32 / 1000.0, (500 - i) as f32 / 2000.0])
+            .collect();
+        let sound_path = dir.path().join("sound.wav");
+        wav_bytes(&sound_path, &sound);
+
+        let output_path = dir.path().join("out.wav");
+        let (output, clock) = HeadlessOutput::file_stepped(&output_path).unwrap();
+        // Queued before the mixer sees it, so the sound starts on the first
+        // frame instead of after the empty queue's silence
+        let (player, queue) = Player::new();
+        player.append(Decoder::try_from(File::open(&sound_path).unwrap()).unwrap());
+        output.mixer().add(queue);
+
+        // Uneven steps that run past the end of the sound
+        clock.advance(333);
+        clock.advance(667);
+        clock.advance(500);
+        drop(output);
+        drop(player);
+
+        let mut expected = sound.clone();
+        expected.resize(1500 * HEADLESS_CHANNELS as usize, 0.0);
+        assert_eq!(
+            fs::read(&output_path).unwrap(),
+            wav_bytes(&dir.path().join("expected.wav"), &expected)
+        );
+    }
+
+    #[test]
+    fn test_stepped_output_without_steps_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_path = dir.path().join("out.wav");
+        let (output, _clock) = HeadlessOutput::file_stepped(&output_path).unwrap();
+        drop(output);
+
+        assert_eq!(
+            fs::read(&output_path).unwrap(),
+            wav_bytes(&dir.path().join("expected.wav"), &[])
+        );
+    }
+}
@@ -1,11 +1,16 @@
 #[cfg(target_os = "linux")]
 use crate::{
-    types::pipewire::{AudioDevice, DeviceType, Terminate},
-    utils::{
-        pipewire::{create_link, get_all_devices, get_device, invalidate_device_cache},
+    types::pipewire::{AudioDevice, DeviceType, LinkHandle},
+    utils::pipewire::{create_link, get_all_devices, get_default_devices, get_device},
+    VIRTUAL_MIC_NAME,
+};
+use crate::{
+    types::{
+        audio_backend::{AudioOutput, HeadlessOutput},
+        config::{AudioBackend, DaemonConfig},
     },
+    utils::daemon::get_daemon_config,
 };
-use crate::utils::daemon::get_daemon_config;
 use rodio::{cpal, Decoder, Player, Source};
 use rodio::cpal::traits::{DeviceTrait, HostTrait};
 use rodio::stream::{DeviceSinkBuilder, MixerDeviceSink};
@@ -17,7 +22,7 @@ use std::{
     error::Error,
     fs,
     path::{Path, PathBuf},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 #[derive(Debug, Eq, PartialEq, Default, Clone, Copy, Serialize, Deserialize)]
@@ -28,6 +33,17 @@ pub enum PlayerState {
     Playing,
 }
 
+impl PlayerState {
+    /// Lowercase name, as published to OSC and MQTT
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PlayerState::Playing => "playing",
+            PlayerState::Paused => "paused",
+            PlayerState::Stopped => "stopped",
+        }
+    }
+}
+
 /// Get all available output devices
 pub fn get_output_devices() -> HashMap<String, String> {
     let mut devices = HashMap::new();
@@ -53,6 +69,59 @@ pub fn get_default_output_device() -> Option<String> {
         .map(|desc| desc.name().to_string())
 }
 
+/// Get the system default output name. On Linux this is the PipeWire
+/// description of the default sink rather than the ALSA device name.
+async fn get_system_default_output_name() -> Option<String> {
+    #[cfg(target_os = "linux")]
+    if let Ok(defaults) = get_default_devices().await {
+        if let Some(name) = defaults.sink_description.or(defaults.sink) {
+            return Some(name);
+        }
+    }
+
+    get_default_output_device()
+}
+
+/// Get the system default input device name, ignoring the virtual mic
+#[cfg(target_os = "linux")]
+async fn get_system_default_input_name() -> Option<String> {
+    get_default_devices()
+        .await
+        .ok()?
+        .source
+        .filter(|name| name != VIRTUAL_MIC_NAME)
+}
+
+/// Get the default input device name
+#[cfg(target_os = "windows")]
+pub fn get_default_input_device() -> Option<String> {
+    let host = cpal::default_host();
+    host.default_input_device()
+        .and_then(|d| d.description().ok())
+        .map(|desc| desc.name().to_string())
+}
+
+/// System default input and output names, as following them would pick them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemDefaults {
+    pub input: Option<String>,
+    pub output: Option<String>,
+}
+
+/// Query the system default devices. Doesn't need the audio player, so
+/// callers can compare defaults without holding its lock.
+pub async fn get_system_defaults() -> SystemDefaults {
+    #[cfg(target_os = "linux")]
+    let input = get_system_default_input_name().await;
+    #[cfg(target_os = "windows")]
+    let input = get_default_input_device();
+
+    SystemDefaults {
+        input,
+        output: get_system_default_output_name().await,
+    }
+}
+
 /// Get all available input devices (for mic selection on Windows)
 #[cfg(target_os = "windows")]
 pub fn get_input_devices() -> HashMap<String, String> {
@@ -75,6 +144,10 @@ pub struct AudioLayer {
     pub volume: f32,
     pub current_file_path: Option<PathBuf>,
     pub duration: Option<f32>,
+    /// Priority of the sound on this layer (higher wins voice stealing)
+    pub priority: u8,
+    /// When the current sound started, used to find the oldest voice
+    pub started_at: Option<Instant>,
 }
 
 impl AudioLayer {
@@ -86,6 +159,8 @@ impl AudioLayer {
             volume: 1.0,
             current_file_path: None,
             duration: None,
+            priority: 0,
+            started_at: None,
         }
     }
 
@@ -150,12 +225,12 @@ impl Source for MicSource {
 }
 
 pub struct AudioPlayer {
-    _stream_handle: MixerDeviceSink,
+    output: AudioOutput,
     sink: Player, // Main player for primary playback
     layers: Vec<AudioLayer>, // Additional layers for mixing
 
     #[cfg(target_os = "linux")]
-    input_link_sender: Option<pipewire::channel::Sender<Terminate>>,
+    input_link: Option<LinkHandle>,
     #[cfg(target_os = "linux")]
     pub current_input_device: Option<AudioDevice>,
 
@@ -168,27 +243,44 @@ pub struct AudioPlayer {
 
     pub current_output_device: Option<String>,
 
+    /// Track the system default input instead of a fixed device
+    pub follow_default_input: bool,
+    /// Track the system default output instead of a fixed device
+    pub follow_default_output: bool,
+
     pub volume: f32,
     pub gain: f32,
     pub mic_gain: f32,
     pub duration: Option<f32>,
 
     pub current_file_path: Option<PathBuf>,
+    /// Priority of the sound on the main player
+    pub priority: u8,
 
     pub looped: bool,
 }
 
 impl AudioPlayer {
-    pub async fn new() -> Result<Self, Box<dyn Error>> {
+    pub async fn new(backend: &AudioBackend) -> Result<Self, Box<dyn Error>> {
         let daemon_config = get_daemon_config();
+        // Headless backends run without audio hardware, so there is no mic to pass through
+        let headless = backend.is_headless();
         let default_volume = daemon_config.default_volume.unwrap_or(1.0);
         let default_gain = daemon_config.default_gain.unwrap_or(1.0);
         let default_mic_gain = daemon_config.default_mic_gain.unwrap_or(1.0);
 
         #[cfg(target_os = "linux")]
         let default_input_device = {
+            let input_name = if headless {
+                None
+            } else if daemon_config.follow_default_input {
+                get_system_default_input_name().await
+            } else {
+                daemon_config.default_input_name.clone()
+            };
+
             let mut device: Option<AudioDevice> = None;
-            if let Some(name) = daemon_config.default_input_name {
+            if let Some(name) = input_name {
                 if let Ok(d) = get_device(&name).await {
                     if d.device_type == DeviceType::Input {
                         device = Some(d);
@@ -199,54 +291,30 @@ impl AudioPlayer {
         };
 
         #[cfg(target_os = "windows")]
-        let default_input_name = daemon_config.default_input_name;
+        let default_input_name = if headless {
+            None
+        } else if daemon_config.follow_default_input {
+            get_default_input_device()
+        } else {
+            daemon_config.default_input_name.clone()
+        };
 
-        // Try to use configured output device, fall back to default
-        let (stream_handle, current_output_device) =
-            if let Some(ref output_name) = daemon_config.default_output_name {
-                match Self::create_stream_for_device(output_name) {
-                    Ok(stream) => (stream, Some(output_name.clone())),
-                    Err(_) => {
-                        tracing::error!(
-                            "Failed to use output device '{}', falling back to default",
-                            output_name
-                        );
-                        (
-                            DeviceSinkBuilder::open_default_sink()?,
-                            get_default_output_device(),
-                        )
-                    }
-                }
-            } else {
-                // On Windows, try to auto-detect VB-Audio Virtual Cable
-                #[cfg(target_os = "windows")]
-                {
-                    let vb_cable = Self::find_virtual_cable();
-                    if let Some(ref cable_name) = vb_cable {
-                        match Self::create_stream_for_device(cable_name) {
-                            Ok(stream) => (stream, Some(cable_name.clone())),
-                            Err(_) => (
-                                DeviceSinkBuilder::open_default_sink()?,
-                                get_default_output_device(),
-                            ),
-                        }
-                    } else {
-                        (
-                            DeviceSinkBuilder::open_default_sink()?,
-                            get_default_output_device(),
-                        )
-                    }
-                }
-                #[cfg(not(target_os = "windows"))]
-                {
-                    (
-                        DeviceSinkBuilder::open_default_sink()?,
-                        get_default_output_device(),
-                    )
-                }
-            };
+        let (output, current_output_device) = match backend {
+            AudioBackend::Device => {
+                let (stream, name) = Self::open_device_output(&daemon_config).await?;
+                (AudioOutput::Device(stream), name)
+            }
+            AudioBackend::Null => (
+                AudioOutput::Headless(HeadlessOutput::null()),
+                Some("Null output".to_string()),
+            ),
+            AudioBackend::File { path } => (
+                AudioOutput::Headless(HeadlessOutput::file(path)?),
+                Some(path.display().to_string()),
+            ),
+        };
 
-        let mixer: &rodio::mixer::Mixer = stream_handle.mixer();
+        let mixer: &rodio::mixer::Mixer = output.mixer();
         let sink = Player::connect_new(mixer);
         sink.set_volume(default_volume * default_gain);
 
@@ -268,12 +336,12 @@ impl AudioPlayer {
         let has_input_device = default_input_device.is_some();
 
         let mut audio_player = AudioPlayer {
-            _stream_handle: stream_handle,
+            output,
             sink,
             layers,
 
             #[cfg(target_os = "linux")]
-            input_link_sender: None,
+            input_link: None,
             #[cfg(target_os = "linux")]
             current_input_device: default_input_device,
 
@@ -286,12 +354,16 @@ impl AudioPlayer {
 
             current_output_device,
 
+            follow_default_input: !headless && daemon_config.follow_default_input,
+            follow_default_output: !headless && daemon_config.follow_default_output,
+
             volume: default_volume,
             gain: default_gain,
             mic_gain: default_mic_gain,
             duration: None,
 
             current_file_path: None,
+            priority: 0,
 
             looped: false,
         };
@@ -313,6 +385,60 @@ impl AudioPlayer {
         Ok(audio_player)
     }
 
+    /// Open the system output device the config asks for
+    async fn open_device_output(
+        daemon_config: &DaemonConfig,
+    ) -> Result<(MixerDeviceSink, Option<String>), Box<dyn Error>> {
+        // Try to use configured output device, fall back to default
+        let output = if daemon_config.follow_default_output {
+            let output_name = get_system_default_output_name().await;
+            (DeviceSinkBuilder::open_default_sink()?, output_name)
+        } else if let Some(ref output_name) = daemon_config.default_output_name {
+            match Self::create_stream_for_device(output_name) {
+                Ok(stream) => (stream, Some(output_name.clone())),
+                Err(_) => {
+                    tracing::error!(
+                        "Failed to use output device '{}', falling back to default",
+                        output_name
+                    );
+                    (
+                        DeviceSinkBuilder::open_default_sink()?,
+                        get_default_output_device(),
+                    )
+                }
+            }
+        } else {
+            // On Windows, try to auto-detect VB-Audio Virtual Cable
+            #[cfg(target_os = "windows")]
+            {
+                let vb_cable = Self::find_virtual_cable();
+                if let Some(ref cable_name) = vb_cable {
+                    match Self::create_stream_for_device(cable_name) {
+                        Ok(stream) => (stream, Some(cable_name.clone())),
+                        Err(_) => (
+                            DeviceSinkBuilder::open_default_sink()?,
+                            get_default_output_device(),
+                        ),
+                    }
+                } else {
+                    (
+                        DeviceSinkBuilder::open_default_sink()?,
+                        get_default_output_device(),
+                    )
+                }
+            }
+            #[cfg(not(target_os = "windows"))]
+            {
+                (
+                    DeviceSinkBuilder::open_default_sink()?,
+                    get_default_output_device(),
+                )
+            }
+        };
+
+        Ok(output)
+    }
+
     fn create_stream_for_device(device_name: &str) -> Result<MixerDeviceSink, Box<dyn Error>> {
         let host = cpal::default_host();
         let devices = host.output_devices()?;
@@ -347,6 +473,11 @@ impl AudioPlayer {
         None
     }
 
+    /// True when running on the null or file backend
+    pub fn is_headless(&self) -> bool {
+        self.output.is_headless()
+    }
+
     pub fn get_current_output_device(&self) -> Option<&String> {
         self.current_output_device.as_ref()
     }
@@ -355,18 +486,119 @@ impl AudioPlayer {
         get_output_devices()
     }
 
+    /// Follow the current system defaults. Returns whether anything changed.
+    pub async fn sync_default_devices(&mut self) -> Result<bool, Box<dyn Error>> {
+        let defaults = get_system_defaults().await;
+        self.apply_system_defaults(defaults).await
+    }
+
+    /// Move the mic passthrough to the default input if it changed.
+    /// PipeWire moves the playback stream to a new default sink on its own,
+    /// so only the reported output name is updated. Returns whether anything
+    /// changed.
     #[cfg(target_os = "linux")]
-    fn abort_link_thread(&mut self) {
-        if let Some(sender) = &self.input_link_sender {
-            if sender.send(Terminate {}).is_err() {
-                tracing::error!("Failed to send terminate signal to link thread");
+    pub async fn apply_system_defaults(
+        &mut self,
+        defaults: SystemDefaults,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut changed = false;
+        if self.follow_default_input {
+            if let Some(source) = defaults.input {
+                let current = self.current_input_device.as_ref().map(|d| d.name.as_str());
+                if current != Some(source.as_str()) {
+                    tracing::info!("Following system default input '{}'", source);
+                    self.set_current_input_device(&source).await?;
+                    self.apply_mic_gain();
+                    changed = true;
+                }
+            }
+        }
+
+        if self.follow_default_output {
+            if let Some(name) = defaults.output {
+                changed |= self.current_output_device.as_ref() != Some(&name);
+                self.current_output_device = Some(name);
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Move the mic passthrough and the output stream to the defaults if
+    /// they changed. Returns whether anything changed.
+    #[cfg(target_os = "windows")]
+    pub async fn apply_system_defaults(
+        &mut self,
+        defaults: SystemDefaults,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut changed = false;
+        if self.follow_default_input {
+            if let Some(name) = defaults.input {
+                if self.current_input_device.as_ref() != Some(&name) {
+                    tracing::info!("Following system default input '{}'", name);
+                    self.set_current_input_device(&name).await?;
+                    changed = true;
+                }
             }
         }
+
+        if self.follow_default_output {
+            if let Some(name) = defaults.output {
+                if self.current_output_device.as_ref() != Some(&name) {
+                    tracing::info!("Following system default output '{}'", name);
+                    self.reopen_output(&name)?;
+                    changed = true;
+                }
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Reopen the output stream on another device. WASAPI streams stay on
+    /// the device they were opened on, so the sinks are rebuilt and anything
+    /// playing is stopped.
+    #[cfg(target_os = "windows")]
+    fn reopen_output(&mut self, device_name: &str) -> Result<(), Box<dyn Error>> {
+        let stream_handle = Self::create_stream_for_device(device_name)?;
+        let mixer = stream_handle.mixer();
+
+        self.stop_mic_passthrough();
+        self.sink.stop();
+        self.sink = Player::connect_new(mixer);
+        for layer in &mut self.layers {
+            layer.sink.stop();
+            let volume = layer.volume;
+            *layer = AudioLayer::new(mixer);
+            layer.volume = volume;
+            layer.sink.set_volume(volume * self.gain);
+        }
+        self.mic_sink = Player::connect_new(mixer);
+        self.mic_sink.stop();
+
+        self.output = AudioOutput::Device(stream_handle);
+        self.current_output_device = Some(device_name.to_string());
+        self.current_file_path = None;
+        self.duration = None;
+        self.update_sink_volume();
+
+        if self.current_input_device.is_some() {
+            self.start_mic_passthrough()?;
+            self.apply_mic_gain();
+        }
+
+        Ok(())
+    }
+
+    /// Drop the mic -> virtual mic link; the manager destroys it on the server
+    #[cfg(target_os = "linux")]
+    fn unlink_input_device(&mut self) {
+        self.input_link = None;
     }
 
     #[cfg(target_os = "linux")]
     async fn link_devices(&mut self) -> Result<(), Box<dyn Error>> {
-        self.abort_link_thread();
+        self.unlink_input_device();
 
         let current_input_name = match &self.current_input_device {
             Some(device) => device.name.clone(),
@@ -381,10 +613,6 @@ impl AudioPlayer {
         const RETRY_DELAY_MS: u64 = 100;
 
         for attempt in 1..=MAX_RETRIES {
-            if attempt > 1 {
-                // Retrying because the expected device wasn't visible yet; force fresh enumeration.
-                invalidate_device_cache().await;
-            }
             let (input_devices, _) = get_all_devices().await?;
 
             // Find the virtual mic
@@ -430,12 +658,15 @@ impl AudioPlayer {
             ) {
                 (Some(output_fl), Some(output_fr), Some(input_fl), Some(input_fr)) => {
                     // All ports available, create the link
-                    self.input_link_sender = Some(create_link(
-                        output_fl.clone(),
-                        output_fr.clone(),
-                        input_fl.clone(),
-                        input_fr.clone(),
-                    )?);
+                    self.input_link = Some(
+                        create_link(
+                            output_fl.clone(),
+                            output_fr.clone(),
+                            input_fl.clone(),
+                            input_fr.clone(),
+                        )
+                        .await?,
+                    );
                     return Ok(());
                 }
                 (out_fl, out_fr, in_fl, in_fr) => {
@@ -601,6 +832,7 @@ impl AudioPlayer {
         match Decoder::try_from(file) {
             Ok(source) => {
                 self.current_file_path = Some(file_path.to_path_buf());
+                self.priority = 0;
 
                 if let Some(duration) = source.total_duration() {
                     self.duration = Some(duration.as_secs_f32());
@@ -639,6 +871,7 @@ impl AudioPlayer {
         match Decoder::try_from(file) {
             Ok(source) => {
                 self.current_file_path = Some(file_path.to_path_buf());
+                self.priority = 0;
 
                 if let Some(duration) = source.total_duration() {
                     self.duration = Some(duration.as_secs_f32());
@@ -649,7 +882,7 @@ impl AudioPlayer {
                 // Stop current playback and abort virtual mic link
                 self.sink.stop();
                 #[cfg(target_os = "linux")]
-                self.abort_link_thread();
+                self.unlink_input_device();
 
                 self.sink.append(source);
                 self.sink.play();
@@ -829,6 +1062,8 @@ impl AudioPlayer {
             Ok(source) => {
                 let layer = &mut self.layers[layer_index];
                 layer.current_file_path = Some(file_path.to_path_buf());
+                layer.priority = 0;
+                layer.started_at = Some(Instant::now());
 
                 if let Some(duration) = source.total_duration() {
                     layer.duration = Some(duration.as_secs_f32());
@@ -864,6 +1099,7 @@ impl AudioPlayer {
         self.layers[layer_index].sink.stop();
         self.layers[layer_index].current_file_path = None;
         self.layers[layer_index].duration = None;
+        self.layers[layer_index].started_at = None;
         Ok(())
     }
 
@@ -873,6 +1109,7 @@ impl AudioPlayer {
             layer.sink.stop();
             layer.current_file_path = None;
             layer.duration = None;
+            layer.started_at = None;
         }
     }
 
@@ -917,6 +1154,16 @@ impl AudioPlayer {
         Ok(self.layers[layer_index].volume)
     }
 
+    /// Set the priority of the sound playing on a layer
+    pub fn set_layer_priority(&mut self, layer_index: usize, priority: u8) -> Result<(), Box<dyn Error>> {
+        if layer_index >= self.layers.len() {
+            return Err(format!("Invalid layer index: {}", layer_index).into());
+        }
+
+        self.layers[layer_index].priority = priority;
+        Ok(())
+    }
+
     /// Check if a layer is playing
     pub fn is_layer_playing(&self, layer_index: usize) -> bool {
         if layer_index >= self.layers.len() {
@@ -942,6 +1189,8 @@ impl AudioPlayer {
             current_file: layer.current_file_path.clone(),
             position: layer.sink.get_pos().as_secs_f32(),
             duration: layer.duration,
+            priority: layer.priority,
+            age: layer.started_at.map(|t| t.elapsed().as_secs_f32()),
         })
     }
 
@@ -954,7 +1203,7 @@ impl AudioPlayer {
 }
 
 /// Information about an audio layer
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct LayerInfo {
     pub index: usize,
     pub is_playing: bool,
@@ -964,4 +1213,9 @@ pub struct LayerInfo {
     pub current_file: Option<PathBuf>,
     pub position: f32,
     pub duration: Option<f32>,
+    #[serde(default)]
+    pub priority: u8,
+    /// Seconds since the current sound started
+    #[serde(default)]
+    pub age: Option<f32>,
 }
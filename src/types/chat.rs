@@ -0,0 +1,69 @@
+// Chat commands, stored in daemon.json under `irc`: what `!name` in a channel
+// plays
+
+use crate::types::{playlist::PlaylistMode, socket::Command};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// What a chat command does
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ChatAction {
+    /// Play on `layer_index`, or on any free layer
+    Play {
+        file_path: PathBuf,
+        #[serde(default)]
+        layer_index: Option<usize>,
+    },
+    Playlist {
+        name: String,
+        #[serde(default)]
+        mode: Option<PlaylistMode>,
+    },
+}
+
+impl ChatAction {
+    pub fn command(&self) -> Command {
+        match self {
+            ChatAction::Play {
+                file_path,
+                layer_index,
+            } => Command::PlayOnLayer {
+                layer_index: *layer_index,
+                file_path: file_path.to_string_lossy().into_owned(),
+                priority: None,
+            },
+            ChatAction::Playlist { name, mode } => Command::PlayPlaylist {
+                name: name.clone(),
+                mode: *mode,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatCommand {
+    /// Typed after the prefix, e.g. `airhorn` for `!airhorn`; case-insensitive
+    pub name: String,
+    pub action: ChatAction,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chat_command_json_shape() {
+        let command: ChatCommand = serde_json::from_str(
+            r#"{"name": "meme", "action": {"action": "playlist", "name": "Memes", "mode": "random"}}"#,
+        )
+        .expect("deserialize");
+        assert_eq!(
+            command.action.command(),
+            Command::PlayPlaylist {
+                name: "Memes".to_string(),
+                mode: Some(PlaylistMode::Random),
+            }
+        );
+    }
+}
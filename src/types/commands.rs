@@ -1,19 +1,70 @@
+#[cfg(target_os = "linux")]
+use crate::utils::{
+    pipewire::{get_all_devices, get_app_streams, get_default_devices, set_stream_target},
+    routing::notify_app_routes_changed,
+};
 use crate::{
-    types::{audio_player::PlayerState, socket::Response},
-    utils::daemon::get_audio_player,
+    types::{
+        audio_player::{PlayerState, NUM_AUDIO_LAYERS},
+        config::{AppRouteRule, GuiConfig},
+        midi::{MidiMapping, MidiTrigger},
+        payload::{
+            ActivePlaylist, BatchFailed, CurrentDevice, DaemonError, Device, ErrorCode,
+            LayerVolume, MacroStarted, MidiLearned, PlayStarted, PlaylistStarted, PreviewStarted,
+        },
+        playlist::PlaylistMode,
+        schedule::ScheduledPlay,
+        socket::{Command, HelloInfo, Response},
+    },
+    utils::{
+        commands::command_executable,
+        daemon::{
+            get_daemon_config, get_sound_metadata, lock_audio_player, update_daemon_config,
+            with_audio_player_held,
+        },
+        events::{publish_devices, publish_player_state, publish_stop},
+        library::build_library,
+        limits::play_limited,
+        macros::{cancel_macro, get_running_macros, run_macro},
+        midi::{learn_next_message, midi_running, reload_midi_mappings, LEARN_TIMEOUT},
+        playlist::{get_active_playlist, play_playlist, stop_playlist},
+        schedule::{next_schedule_id, notify_schedules_changed, unix_now},
+        voices::{check_main_priority, place_on_layer},
+    },
 };
 #[cfg(target_os = "linux")]
-use crate::VIRTUAL_MIC_NAME;
-#[cfg(target_os = "linux")]
-use crate::utils::pipewire::get_all_devices;
+use crate::{DAEMON_OUTPUT_NAME, VIRTUAL_MIC_NAME};
 use async_trait::async_trait;
+#[cfg(target_os = "linux")]
+use std::error::Error;
 use std::path::PathBuf;
 
+/// Commands a single batch may run
+const MAX_BATCH_COMMANDS: usize = 64;
+
 #[async_trait]
 pub trait Executable {
     async fn execute(&self) -> Response;
 }
 
+/// Runs a command that changes the player, then publishes the change to
+/// event subscribers
+pub struct PublishPlayerState<E>(pub E);
+
+/// Runs a command that changes the selected devices, then publishes them to
+/// event subscribers
+pub struct PublishDevices<E>(pub E);
+
+pub struct HelloCommand {
+    pub client: Option<String>,
+}
+
+pub struct SubscribeCommand {}
+
+pub struct BatchCommand {
+    pub commands: Vec<Command>,
+}
+
 pub struct PingCommand {}
 
 pub struct PauseCommand {}
@@ -55,11 +106,13 @@ pub struct SeekCommand {
 pub struct GetDurationCommand {}
 
 pub struct PlayCommand {
-    pub file_path: Option<PathBuf>,
+    pub file_path: Result<PathBuf, DaemonError>,
+    /// None if the priority is invalid, Some(None) for the sound's own priority
+    pub priority: Option<Option<u8>>,
 }
 
 pub struct PreviewCommand {
-    pub file_path: Option<PathBuf>,
+    pub file_path: Result<PathBuf, DaemonError>,
 }
 
 pub struct GetCurrentFilePathCommand {}
@@ -88,10 +141,21 @@ pub struct SetLoopCommand {
 
 pub struct ToggleLoopCommand {}
 
+pub struct SetFollowDefaultInputCommand {
+    pub enabled: Option<bool>,
+}
+
+pub struct SetFollowDefaultOutputCommand {
+    pub enabled: Option<bool>,
+}
+
 // Layer commands
 pub struct PlayOnLayerCommand {
-    pub layer_index: Option<usize>,
-    pub file_path: Option<PathBuf>,
+    /// None if the layer index is invalid, Some(None) for any layer
+    pub layer_index: Option<Option<usize>>,
+    pub file_path: Result<PathBuf, DaemonError>,
+    /// None if the priority is invalid, Some(None) for the sound's own priority
+    pub priority: Option<Option<u8>>,
 }
 
 pub struct StopLayerCommand {
@@ -107,6 +171,180 @@ pub struct SetLayerVolumeCommand {
 
 pub struct GetLayersInfoCommand {}
 
+// App routing commands
+pub struct GetAppStreamsCommand {}
+
+pub struct GetAppRoutesCommand {}
+
+pub struct AddAppRouteCommand {
+    pub rule: Option<AppRouteRule>,
+}
+
+pub struct RemoveAppRouteCommand {
+    pub rule: Option<AppRouteRule>,
+}
+
+// Macro commands
+pub struct RunMacroCommand {
+    pub name: Option<String>,
+}
+
+pub struct CancelMacroCommand {
+    pub name: Option<String>,
+}
+
+pub struct GetRunningMacrosCommand {}
+
+// Schedule commands
+pub struct SchedulePlayCommand {
+    pub file_path: Result<PathBuf, DaemonError>,
+    /// None if the layer index is invalid, Some(None) for the main player
+    pub layer_index: Option<Option<usize>>,
+    /// Unix time of the first play and the repeat interval in seconds
+    pub timing: Option<(u64, Option<u64>)>,
+    pub label: Option<String>,
+}
+
+pub struct ListSchedulesCommand {}
+
+pub struct CancelScheduleCommand {
+    pub id: Option<u64>,
+}
+
+// Playlist commands
+pub struct PlayPlaylistCommand {
+    pub name: Option<String>,
+    /// None if the mode is invalid, Some(None) for the playlist's own mode
+    pub mode: Option<Option<PlaylistMode>>,
+}
+
+pub struct GetActivePlaylistCommand {}
+
+pub struct GetLibraryCommand {}
+
+pub struct GetMidiMappingsCommand {}
+
+pub struct AddMidiMappingCommand {
+    pub mapping: MidiMapping,
+}
+
+pub struct RemoveMidiMappingCommand {
+    pub channel: Option<u8>,
+    pub trigger: MidiTrigger,
+}
+
+pub struct MidiLearnCommand {}
+
+#[async_trait]
+impl Executable for HelloCommand {
+    async fn execute(&self) -> Response {
+        if let Some(client) = &self.client {
+            tracing::debug!("Hello from {}", client);
+        }
+        let hello = HelloInfo::current();
+        Response::new(
+            true,
+            format!(
+                "Protocol {} (daemon {}), {} commands",
+                hello.protocol_version,
+                hello.daemon_version,
+                hello.capabilities.len()
+            ),
+        )
+        .with_data(hello)
+    }
+}
+
+// The daemon answers subscribe on the connection itself, and batches refuse
+// it, so this doesn't normally run
+#[async_trait]
+impl Executable for SubscribeCommand {
+    async fn execute(&self) -> Response {
+        Response::error(
+            ErrorCode::InvalidRequest,
+            "subscribe can't be part of a batch",
+        )
+    }
+}
+
+#[async_trait]
+impl<E: Executable + Send + Sync> Executable for PublishPlayerState<E> {
+    async fn execute(&self) -> Response {
+        let response = self.0.execute().await;
+        publish_player_state(&mut *lock_audio_player().await);
+        response
+    }
+}
+
+#[async_trait]
+impl<E: Executable + Send + Sync> Executable for PublishDevices<E> {
+    async fn execute(&self) -> Response {
+        let response = self.0.execute().await;
+        publish_devices().await;
+        response
+    }
+}
+
+#[async_trait]
+impl Executable for BatchCommand {
+    async fn execute(&self) -> Response {
+        if self.commands.len() > MAX_BATCH_COMMANDS {
+            return Response::error(
+                ErrorCode::InvalidArg,
+                format!("A batch may hold at most {} commands", MAX_BATCH_COMMANDS),
+            );
+        }
+
+        // Checked up front, so nothing runs
+        let unbatchable = self
+            .commands
+            .iter()
+            .enumerate()
+            .find_map(|(i, command)| unbatchable_name(command).map(|name| (i, name)));
+        if let Some((index, name)) = unbatchable {
+            let error = DaemonError::new(
+                ErrorCode::InvalidRequest,
+                format!("{} can't be part of a batch", name),
+            );
+            return batch_failed(index, Vec::new(), error);
+        }
+
+        with_audio_player_held(async {
+            let mut responses = Vec::with_capacity(self.commands.len());
+            for (i, command) in self.commands.iter().enumerate() {
+                let response = command_executable(command.clone()).execute().await;
+                if let Some(error) = response.daemon_error() {
+                    return batch_failed(i, responses, error);
+                }
+                responses.push(response);
+            }
+            Response::new(true, format!("Ran {} commands", responses.len())).with_data(responses)
+        })
+        .await
+    }
+}
+
+/// Commands a batch refuses: subscribe takes over the connection, and
+/// midi_learn would hold the player while it waits for a control
+fn unbatchable_name(command: &Command) -> Option<&'static str> {
+    match command {
+        Command::Subscribe => Some("subscribe"),
+        Command::MidiLearn => Some("midi_learn"),
+        _ => None,
+    }
+}
+
+/// Error response for a batch that stopped at command `index`, carrying the
+/// responses of the commands that ran before it
+fn batch_failed(index: usize, responses: Vec<Response>, error: DaemonError) -> Response {
+    let message = format!("Batch stopped at command {}: {}", index + 1, error);
+    Response::new(false, message).with_data(BatchFailed {
+        error,
+        index,
+        responses,
+    })
+}
+
 #[async_trait]
 impl Executable for PingCommand {
     async fn execute(&self) -> Response {
@@ -117,66 +355,69 @@ impl Executable for PingCommand {
 #[async_trait]
 impl Executable for PauseCommand {
     async fn execute(&self) -> Response {
-        let mut audio_player = get_audio_player().lock().await;
+        let mut audio_player = lock_audio_player().await;
         audio_player.pause();
-        Response::new(true, "Audio was paused")
+        Response::new(true, "Audio was paused").with_data(audio_player.get_state())
     }
 }
 
 #[async_trait]
 impl Executable for ResumeCommand {
     async fn execute(&self) -> Response {
-        let mut audio_player = get_audio_player().lock().await;
+        let mut audio_player = lock_audio_player().await;
         audio_player.resume();
-        Response::new(true, "Audio was resumed")
+        Response::new(true, "Audio was resumed").with_data(audio_player.get_state())
     }
 }
 
 #[async_trait]
 impl Executable for TogglePauseCommand {
     async fn execute(&self) -> Response {
-        let mut audio_player = get_audio_player().lock().await;
+        let mut audio_player = lock_audio_player().await;
 
         if audio_player.get_state() == PlayerState::Stopped {
-            return Response::new(false, "Audio is not playing");
+            return Response::error(ErrorCode::NotPlaying, "Audio is not playing");
         }
 
-        if audio_player.is_paused() {
+        let message = if audio_player.is_paused() {
             audio_player.resume();
-            Response::new(true, "Audio was resumed")
+            "Audio was resumed"
         } else {
             audio_player.pause();
-            Response::new(true, "Audio was paused")
-        }
+            "Audio was paused"
+        };
+        Response::new(true, message).with_data(audio_player.get_state())
     }
 }
 
 #[async_trait]
 impl Executable for StopCommand {
     async fn execute(&self) -> Response {
-        let mut audio_player = get_audio_player().lock().await;
+        let mut audio_player = lock_audio_player().await;
+        stop_playlist();
         audio_player.stop();
-        Response::new(true, "Audio was stopped")
+        publish_stop();
+        Response::new(true, "Audio was stopped").with_data(audio_player.get_state())
     }
 }
 
 #[async_trait]
 impl Executable for IsPausedCommand {
     async fn execute(&self) -> Response {
-        let audio_player = get_audio_player().lock().await;
-        let is_paused = audio_player.is_paused().to_string();
-        Response::new(true, is_paused)
+        let audio_player = lock_audio_player().await;
+        let is_paused = audio_player.is_paused();
+        Response::new(true, is_paused.to_string()).with_data(is_paused)
     }
 }
 
 #[async_trait]
 impl Executable for GetStateCommand {
     async fn execute(&self) -> Response {
-        let audio_player = get_audio_player().lock().await;
+        let audio_player = lock_audio_player().await;
         let state = audio_player.get_state();
         match serde_json::to_string(&state) {
-            Ok(json) => Response::new(true, json),
-            Err(_) => Response::new(false, "Failed to serialize player state"),
+            Ok(json) => Response::new(true, json).with_data(state),
+            Err(_) => Response::error(ErrorCode::Internal, "Failed to serialize player state"),
         }
     }
 }
@@ -184,9 +425,9 @@ impl Executable for GetStateCommand {
 #[async_trait]
 impl Executable for GetVolumeCommand {
     async fn execute(&self) -> Response {
-        let audio_player = get_audio_player().lock().await;
+        let audio_player = lock_audio_player().await;
         let volume = audio_player.volume;
-        Response::new(true, volume.to_string())
+        Response::new(true, volume.to_string()).with_data(volume)
     }
 }
 
@@ -194,11 +435,11 @@ impl Executable for GetVolumeCommand {
 impl Executable for SetVolumeCommand {
     async fn execute(&self) -> Response {
         if let Some(volume) = self.volume {
-            let mut audio_player = get_audio_player().lock().await;
+            let mut audio_player = lock_audio_player().await;
             audio_player.set_volume(volume);
-            Response::new(true, format!("Audio volume was set to {}", volume))
+            Response::new(true, format!("Audio volume was set to {}", volume)).with_data(volume)
         } else {
-            Response::new(false, "Invalid volume value")
+            Response::error(ErrorCode::InvalidArg, "Invalid volume value")
         }
     }
 }
@@ -206,9 +447,9 @@ impl Executable for SetVolumeCommand {
 #[async_trait]
 impl Executable for GetGainCommand {
     async fn execute(&self) -> Response {
-        let audio_player = get_audio_player().lock().await;
+        let audio_player = lock_audio_player().await;
         let gain = audio_player.get_gain();
-        Response::new(true, gain.to_string())
+        Response::new(true, gain.to_string()).with_data(gain)
     }
 }
 
@@ -216,11 +457,11 @@ impl Executable for GetGainCommand {
 impl Executable for SetGainCommand {
     async fn execute(&self) -> Response {
         if let Some(gain) = self.gain {
-            let mut audio_player = get_audio_player().lock().await;
+            let mut audio_player = lock_audio_player().await;
             audio_player.set_gain(gain);
-            Response::new(true, format!("Audio gain was set to {}", gain))
+            Response::new(true, format!("Audio gain was set to {}", gain)).with_data(gain)
         } else {
-            Response::new(false, "Invalid gain value")
+            Response::error(ErrorCode::InvalidArg, "Invalid gain value")
         }
     }
 }
@@ -228,9 +469,9 @@ impl Executable for SetGainCommand {
 #[async_trait]
 impl Executable for GetMicGainCommand {
     async fn execute(&self) -> Response {
-        let audio_player = get_audio_player().lock().await;
+        let audio_player = lock_audio_player().await;
         let mic_gain = audio_player.get_mic_gain();
-        Response::new(true, mic_gain.to_string())
+        Response::new(true, mic_gain.to_string()).with_data(mic_gain)
     }
 }
 
@@ -238,11 +479,11 @@ impl Executable for GetMicGainCommand {
 impl Executable for SetMicGainCommand {
     async fn execute(&self) -> Response {
         if let Some(mic_gain) = self.mic_gain {
-            let mut audio_player = get_audio_player().lock().await;
+            let mut audio_player = lock_audio_player().await;
             audio_player.set_mic_gain(mic_gain);
-            Response::new(true, format!("Mic gain was set to {}", mic_gain))
+            Response::new(true, format!("Mic gain was set to {}", mic_gain)).with_data(mic_gain)
         } else {
-            Response::new(false, "Invalid mic gain value")
+            Response::error(ErrorCode::InvalidArg, "Invalid mic gain value")
         }
     }
 }
@@ -250,9 +491,9 @@ impl Executable for SetMicGainCommand {
 #[async_trait]
 impl Executable for GetPositionCommand {
     async fn execute(&self) -> Response {
-        let audio_player = get_audio_player().lock().await;
+        let audio_player = lock_audio_player().await;
         let position = audio_player.get_position();
-        Response::new(true, position.to_string())
+        Response::new(true, position.to_string()).with_data(position)
     }
 }
 
@@ -260,13 +501,17 @@ impl Executable for GetPositionCommand {
 impl Executable for SeekCommand {
     async fn execute(&self) -> Response {
         if let Some(position) = self.position {
-            let mut audio_player = get_audio_player().lock().await;
+            let mut audio_player = lock_audio_player().await;
+            if audio_player.get_state() == PlayerState::Stopped {
+                return Response::error(ErrorCode::NotPlaying, "Nothing is playing right now");
+            }
             match audio_player.seek(position) {
-                Ok(_) => Response::new(true, format!("Audio position was set to {}", position)),
-                Err(err) => Response::new(false, err.to_string()),
+                Ok(_) => Response::new(true, format!("Audio position was set to {}", position))
+                    .with_data(position),
+                Err(err) => Response::error(ErrorCode::PlaybackError, err.to_string()),
             }
         } else {
-            Response::new(false, "Invalid position value")
+            Response::error(ErrorCode::InvalidArg, "Invalid position value")
         }
     }
 }
@@ -274,10 +519,13 @@ impl Executable for SeekCommand {
 #[async_trait]
 impl Executable for GetDurationCommand {
     async fn execute(&self) -> Response {
-        let mut audio_player = get_audio_player().lock().await;
+        let mut audio_player = lock_audio_player().await;
         match audio_player.get_duration() {
-            Ok(duration) => Response::new(true, duration.to_string()),
-            Err(err) => Response::new(false, err.to_string()),
+            Ok(duration) => Response::new(true, duration.to_string()).with_data(duration),
+            Err(err) if audio_player.get_state() == PlayerState::Stopped => {
+                Response::error(ErrorCode::NotPlaying, err.to_string())
+            }
+            Err(err) => Response::error(ErrorCode::PlaybackError, err.to_string()),
         }
     }
 }
@@ -285,14 +533,34 @@ impl Executable for GetDurationCommand {
 #[async_trait]
 impl Executable for PlayCommand {
     async fn execute(&self) -> Response {
-        if let Some(file_path) = &self.file_path {
-            let mut audio_player = get_audio_player().lock().await;
-            match audio_player.play(file_path).await {
-                Ok(_) => Response::new(true, format!("Now playing {}", file_path.display())),
-                Err(err) => Response::new(false, err.to_string()),
+        let file_path = match &self.file_path {
+            Ok(file_path) => file_path,
+            Err(e) => return e.clone().into(),
+        };
+        let Some(priority) = self.priority else {
+            return Response::error(ErrorCode::InvalidArg, "Invalid priority");
+        };
+
+        let metadata = get_sound_metadata(file_path);
+        let priority = priority.or(metadata.priority).unwrap_or_default();
+
+        let mut audio_player = lock_audio_player().await;
+        if let Err(e) = check_main_priority(&audio_player, priority) {
+            return Response::error(e.code, format!("Dropped {}: {}", file_path.display(), e));
+        }
+        match play_limited(&mut audio_player, None, file_path, &metadata).await {
+            Ok(_) => {
+                audio_player.priority = priority;
+                Response::new(true, format!("Now playing {}", file_path.display())).with_data(
+                    PlayStarted {
+                        file_path: file_path.clone(),
+                        layer_index: None,
+                        priority,
+                        replaced: None,
+                    },
+                )
             }
-        } else {
-            Response::new(false, "Invalid file path")
+            Err(err) => err.into(),
         }
     }
 }
@@ -300,14 +568,22 @@ impl Executable for PlayCommand {
 #[async_trait]
 impl Executable for PreviewCommand {
     async fn execute(&self) -> Response {
-        if let Some(file_path) = &self.file_path {
-            let mut audio_player = get_audio_player().lock().await;
-            match audio_player.preview(file_path) {
-                Ok(_) => Response::new(true, format!("Previewing {}", file_path.display())),
-                Err(err) => Response::new(false, err.to_string()),
+        match &self.file_path {
+            Ok(file_path) => {
+                let mut audio_player = lock_audio_player().await;
+                match audio_player.preview(file_path) {
+                    // The preview replaced whatever was playing
+                    Ok(_) => {
+                        publish_stop();
+                        Response::new(true, format!("Previewing {}", file_path.display()))
+                            .with_data(PreviewStarted {
+                                file_path: file_path.clone(),
+                            })
+                    }
+                    Err(err) => Response::error(ErrorCode::PlaybackError, err.to_string()),
+                }
             }
-        } else {
-            Response::new(false, "Invalid file path")
+            Err(e) => e.clone().into(),
         }
     }
 }
@@ -315,15 +591,15 @@ impl Executable for PreviewCommand {
 #[async_trait]
 impl Executable for GetCurrentFilePathCommand {
     async fn execute(&self) -> Response {
-        let mut audio_player = get_audio_player().lock().await;
+        let mut audio_player = lock_audio_player().await;
         let current_file_path = audio_player.get_current_file_path();
         if let Some(current_file_path) = current_file_path {
             match current_file_path.to_str() {
-                Some(path_str) => Response::new(true, path_str),
-                None => Response::new(false, "File path contains invalid UTF-8"),
+                Some(path_str) => Response::new(true, path_str).with_data(path_str),
+                None => Response::error(ErrorCode::Internal, "File path contains invalid UTF-8"),
             }
         } else {
-            Response::new(false, "No file is playing")
+            Response::error(ErrorCode::NotPlaying, "No file is playing")
         }
     }
 }
@@ -333,23 +609,46 @@ impl Executable for GetCurrentInputCommand {
     async fn execute(&self) -> Response {
         #[cfg(target_os = "linux")]
         {
-            let audio_player = get_audio_player().lock().await;
+            let audio_player = lock_audio_player().await;
             if let Some(input_device) = &audio_player.current_input_device {
                 Response::new(
                     true,
-                    format!("{} - {}", input_device.name, input_device.nick),
+                    format!(
+                        "{} - {}{}",
+                        input_device.name,
+                        input_device.nick,
+                        follow_default_suffix(audio_player.follow_default_input)
+                    ),
                 )
+                .with_data(CurrentDevice {
+                    id: input_device.name.clone(),
+                    name: input_device.nick.clone(),
+                    follows_default: audio_player.follow_default_input,
+                })
             } else {
-                Response::new(false, "No input device selected")
+                Response::error(ErrorCode::NotFound, "No input device selected")
             }
         }
         #[cfg(target_os = "windows")]
         {
-            let audio_player = get_audio_player().lock().await;
+            let audio_player = lock_audio_player().await;
             if let Some(ref device_name) = audio_player.current_input_device {
-                Response::new(true, format!("{} - {}", device_name, device_name))
+                Response::new(
+                    true,
+                    format!(
+                        "{} - {}{}",
+                        device_name,
+                        device_name,
+                        follow_default_suffix(audio_player.follow_default_input)
+                    ),
+                )
+                .with_data(CurrentDevice {
+                    id: device_name.clone(),
+                    name: device_name.clone(),
+                    follows_default: audio_player.follow_default_input,
+                })
             } else {
-                Response::new(false, "No input device selected")
+                Response::error(ErrorCode::NotFound, "No input device selected")
             }
         }
     }
@@ -362,30 +661,43 @@ impl Executable for GetAllInputsCommand {
         {
             match get_all_devices().await {
                 Ok((input_devices, _output_devices)) => {
-                    let mut input_devices_strings = vec![];
-                    for device in input_devices {
-                        if device.name == VIRTUAL_MIC_NAME {
-                            continue;
-                        }
-
-                        let string = format!("{} - {}", device.name, device.nick);
-                        input_devices_strings.push(string);
-                    }
-                    let response_message = input_devices_strings.join("; ");
-                    Response::new(true, response_message)
+                    let devices: Vec<Device> = input_devices
+                        .into_iter()
+                        .filter(|device| device.name != VIRTUAL_MIC_NAME)
+                        .map(|device| Device {
+                            id: device.name,
+                            name: device.nick,
+                        })
+                        .collect();
+                    let response_message = devices
+                        .iter()
+                        .map(|device| format!("{} - {}", device.id, device.name))
+                        .collect::<Vec<_>>()
+                        .join("; ");
+                    Response::new(true, response_message).with_data(devices)
                 }
-                Err(e) => Response::new(false, format!("Failed to get input devices: {}", e)),
+                Err(e) => Response::error(
+                    ErrorCode::DeviceError,
+                    format!("Failed to get input devices: {}", e),
+                ),
             }
         }
         #[cfg(target_os = "windows")]
         {
-            let devices = crate::types::audio_player::get_input_devices();
-            let mut device_strings: Vec<String> = devices.keys()
-                .map(|name| format!("{} - {}", name, name))
+            let mut devices: Vec<Device> = crate::types::audio_player::get_input_devices()
+                .into_keys()
+                .map(|name| Device {
+                    id: name.clone(),
+                    name,
+                })
                 .collect();
-            device_strings.sort();
-            let response_message = device_strings.join("; ");
-            Response::new(true, response_message)
+            devices.sort_by(|a, b| a.id.cmp(&b.id));
+            let response_message = devices
+                .iter()
+                .map(|device| format!("{} - {}", device.id, device.name))
+                .collect::<Vec<_>>()
+                .join("; ");
+            Response::new(true, response_message).with_data(devices)
         }
     }
 }
@@ -394,13 +706,26 @@ impl Executable for GetAllInputsCommand {
 impl Executable for SetCurrentInputCommand {
     async fn execute(&self) -> Response {
         if let Some(name) = &self.name {
-            let mut audio_player = get_audio_player().lock().await;
+            let mut audio_player = lock_audio_player().await;
+
+            // An explicit choice stops following the system default
+            if audio_player.follow_default_input {
+                audio_player.follow_default_input = false;
+                let saved = update_daemon_config(|config| {
+                    config.follow_default_input = false;
+                    Ok(())
+                });
+                if let Err(e) = saved {
+                    tracing::error!("{}", e);
+                }
+            }
+
             match audio_player.set_current_input_device(name).await {
-                Ok(_) => Response::new(true, "Input device was set"),
-                Err(err) => Response::new(false, err.to_string()),
+                Ok(_) => Response::new(true, "Input device was set").with_data(name),
+                Err(err) => Response::error(ErrorCode::DeviceError, err.to_string()),
             }
         } else {
-            Response::new(false, "Invalid index value")
+            Response::error(ErrorCode::InvalidArg, "Invalid index value")
         }
     }
 }
@@ -408,11 +733,23 @@ impl Executable for SetCurrentInputCommand {
 #[async_trait]
 impl Executable for GetCurrentOutputCommand {
     async fn execute(&self) -> Response {
-        let audio_player = get_audio_player().lock().await;
+        let audio_player = lock_audio_player().await;
         if let Some(output_device) = audio_player.get_current_output_device() {
-            Response::new(true, output_device.clone())
+            Response::new(
+                true,
+                format!(
+                    "{}{}",
+                    output_device,
+                    follow_default_suffix(audio_player.follow_default_output)
+                ),
+            )
+            .with_data(CurrentDevice {
+                id: output_device.clone(),
+                name: output_device.clone(),
+                follows_default: audio_player.follow_default_output,
+            })
         } else {
-            Response::new(false, "No output device selected")
+            Response::error(ErrorCode::NotFound, "No output device selected")
         }
     }
 }
@@ -420,11 +757,22 @@ impl Executable for GetCurrentOutputCommand {
 #[async_trait]
 impl Executable for GetAllOutputsCommand {
     async fn execute(&self) -> Response {
-        let audio_player = get_audio_player().lock().await;
-        let output_devices = audio_player.get_all_output_devices();
-        let output_devices_strings: Vec<String> = output_devices.keys().cloned().collect();
-        let response_message = output_devices_strings.join("; ");
-        Response::new(true, response_message)
+        let audio_player = lock_audio_player().await;
+        let mut devices: Vec<Device> = audio_player
+            .get_all_output_devices()
+            .into_keys()
+            .map(|name| Device {
+                id: name.clone(),
+                name,
+            })
+            .collect();
+        devices.sort_by(|a, b| a.id.cmp(&b.id));
+        let response_message = devices
+            .iter()
+            .map(|device| device.id.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+        Response::new(true, response_message).with_data(devices)
     }
 }
 
@@ -432,40 +780,154 @@ impl Executable for GetAllOutputsCommand {
 impl Executable for SetCurrentOutputCommand {
     async fn execute(&self) -> Response {
         let Some(name) = self.name.clone() else {
-            return Response::new(false, "Invalid output device name");
+            return Response::error(ErrorCode::InvalidArg, "Invalid output device name");
         };
 
-        let mut config = crate::utils::daemon::get_daemon_config();
-        config.default_output_name = Some(name);
-        match config.save_to_file() {
+        let saved = update_daemon_config(|config| {
+            config.default_output_name = Some(name.clone());
+            config.follow_default_output = false;
+            Ok(())
+        });
+        match saved {
             Ok(()) => Response::new(
                 true,
                 "Output device preference saved (restart daemon to apply)",
-            ),
-            Err(e) => Response::new(false, format!("Failed to save preference: {}", e)),
+            )
+            .with_data(name),
+            Err(error) => error.into(),
         }
     }
 }
 
+/// Marks devices that were picked by following the system default
+fn follow_default_suffix(following: bool) -> &'static str {
+    if following {
+        " (system default)"
+    } else {
+        ""
+    }
+}
+
+#[async_trait]
+impl Executable for SetFollowDefaultInputCommand {
+    async fn execute(&self) -> Response {
+        let Some(enabled) = self.enabled else {
+            return Response::error(ErrorCode::InvalidArg, "Invalid enabled value");
+        };
+
+        let mut audio_player = lock_audio_player().await;
+        if audio_player.is_headless() {
+            return Response::error(
+                ErrorCode::Unsupported,
+                "Not available with a headless audio backend",
+            );
+        }
+
+        let saved = update_daemon_config(|config| {
+            config.follow_default_input = enabled;
+            Ok(())
+        });
+        if let Err(error) = saved {
+            return error.into();
+        }
+
+        audio_player.follow_default_input = enabled;
+        if enabled {
+            if let Err(e) = audio_player.sync_default_devices().await {
+                return Response::error(
+                    ErrorCode::DeviceError,
+                    format!("Failed to switch to default input: {}", e),
+                );
+            }
+        }
+
+        Response::new(true, format!("Follow default input was set to {}", enabled))
+            .with_data(enabled)
+    }
+}
+
+#[async_trait]
+impl Executable for SetFollowDefaultOutputCommand {
+    async fn execute(&self) -> Response {
+        let Some(enabled) = self.enabled else {
+            return Response::error(ErrorCode::InvalidArg, "Invalid enabled value");
+        };
+
+        let mut audio_player = lock_audio_player().await;
+        if audio_player.is_headless() {
+            return Response::error(
+                ErrorCode::Unsupported,
+                "Not available with a headless audio backend",
+            );
+        }
+
+        let saved = update_daemon_config(|config| {
+            config.follow_default_output = enabled;
+            Ok(())
+        });
+        if let Err(error) = saved {
+            return error.into();
+        }
+
+        // Windows reopens the output stream on the next sync
+        #[cfg(target_os = "linux")]
+        if let Err(e) = set_output_stream_following(enabled).await {
+            return Response::error(
+                ErrorCode::DeviceError,
+                format!("Failed to move the output stream: {}", e),
+            );
+        }
+
+        audio_player.follow_default_output = enabled;
+        if enabled {
+            if let Err(e) = audio_player.sync_default_devices().await {
+                return Response::error(
+                    ErrorCode::DeviceError,
+                    format!("Failed to switch to default output: {}", e),
+                );
+            }
+        }
+
+        Response::new(
+            true,
+            format!("Follow default output was set to {}", enabled),
+        )
+        .with_data(enabled)
+    }
+}
+
+/// PipeWire moves a stream with the default sink unless it has a target, so
+/// following clears the target and not following pins the stream to the sink
+/// it plays on now
+#[cfg(target_os = "linux")]
+async fn set_output_stream_following(following: bool) -> Result<(), Box<dyn Error>> {
+    let target = if following {
+        None
+    } else {
+        get_default_devices().await?.sink
+    };
+    set_stream_target(DAEMON_OUTPUT_NAME, target.as_deref()).await
+}
+
 #[async_trait]
 impl Executable for GetLoopCommand {
     async fn execute(&self) -> Response {
-        let audio_player = get_audio_player().lock().await;
-        Response::new(true, audio_player.looped.to_string())
+        let audio_player = lock_audio_player().await;
+        Response::new(true, audio_player.looped.to_string()).with_data(audio_player.looped)
     }
 }
 
 #[async_trait]
 impl Executable for SetLoopCommand {
     async fn execute(&self) -> Response {
-        let mut audio_player = get_audio_player().lock().await;
+        let mut audio_player = lock_audio_player().await;
 
         match self.enabled {
             Some(enabled) => {
                 audio_player.looped = enabled;
-                Response::new(true, format!("Loop was set to {}", enabled))
+                Response::new(true, format!("Loop was set to {}", enabled)).with_data(enabled)
             }
-            None => Response::new(false, "Invalid enabled value"),
+            None => Response::error(ErrorCode::InvalidArg, "Invalid enabled value"),
         }
     }
 }
@@ -473,9 +935,10 @@ impl Executable for SetLoopCommand {
 #[async_trait]
 impl Executable for ToggleLoopCommand {
     async fn execute(&self) -> Response {
-        let mut audio_player = get_audio_player().lock().await;
+        let mut audio_player = lock_audio_player().await;
         audio_player.looped = !audio_player.looped;
         Response::new(true, format!("Loop was set to {}", audio_player.looped))
+            .with_data(audio_player.looped)
     }
 }
 
@@ -484,19 +947,58 @@ impl Executable for ToggleLoopCommand {
 #[async_trait]
 impl Executable for PlayOnLayerCommand {
     async fn execute(&self) -> Response {
-        match (&self.layer_index, &self.file_path) {
-            (Some(layer_index), Some(file_path)) => {
-                let mut audio_player = get_audio_player().lock().await;
-                match audio_player.play_on_layer(*layer_index, file_path).await {
-                    Ok(_) => Response::new(
-                        true,
-                        format!("Playing {} on layer {}", file_path.display(), layer_index),
-                    ),
-                    Err(err) => Response::new(false, err.to_string()),
+        let Some(layer_index) = self.layer_index else {
+            return Response::error(ErrorCode::InvalidArg, "Invalid layer index");
+        };
+        let file_path = match &self.file_path {
+            Ok(file_path) => file_path,
+            Err(e) => return e.clone().into(),
+        };
+        let Some(priority) = self.priority else {
+            return Response::error(ErrorCode::InvalidArg, "Invalid priority");
+        };
+
+        let metadata = get_sound_metadata(file_path);
+        let priority = priority.or(metadata.priority).unwrap_or_default();
+        let policy = get_daemon_config().voice_stealing;
+
+        let mut audio_player = lock_audio_player().await;
+        let (layer_index, stolen) =
+            match place_on_layer(&audio_player, layer_index, priority, policy) {
+                Ok(placement) => placement,
+                Err(e) => {
+                    return Response::error(
+                        e.code,
+                        format!("Dropped {}: {}", file_path.display(), e),
+                    )
                 }
-            }
-            _ => Response::new(false, "Invalid layer index or file path"),
+            };
+
+        if let Err(err) =
+            play_limited(&mut audio_player, Some(layer_index), file_path, &metadata).await
+        {
+            return err.into();
         }
+        audio_player.set_layer_priority(layer_index, priority).ok();
+
+        let mut message = format!("Playing {} on layer {}", file_path.display(), layer_index);
+        if let Some(stolen) = &stolen {
+            let replaced = stolen
+                .file_path
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "unknown sound".to_string());
+            message.push_str(&format!(
+                " (replaced {}, priority {})",
+                replaced, stolen.priority
+            ));
+        }
+        Response::new(true, message).with_data(PlayStarted {
+            file_path: file_path.clone(),
+            layer_index: Some(layer_index),
+            priority,
+            replaced: stolen,
+        })
     }
 }
 
@@ -504,13 +1006,14 @@ impl Executable for PlayOnLayerCommand {
 impl Executable for StopLayerCommand {
     async fn execute(&self) -> Response {
         if let Some(layer_index) = self.layer_index {
-            let mut audio_player = get_audio_player().lock().await;
+            let mut audio_player = lock_audio_player().await;
             match audio_player.stop_layer(layer_index) {
-                Ok(_) => Response::new(true, format!("Stopped layer {}", layer_index)),
-                Err(err) => Response::new(false, err.to_string()),
+                Ok(_) => Response::new(true, format!("Stopped layer {}", layer_index))
+                    .with_data(layer_index),
+                Err(err) => Response::error(ErrorCode::InvalidArg, err.to_string()),
             }
         } else {
-            Response::new(false, "Invalid layer index")
+            Response::error(ErrorCode::InvalidArg, "Invalid layer index")
         }
     }
 }
@@ -518,7 +1021,7 @@ impl Executable for StopLayerCommand {
 #[async_trait]
 impl Executable for StopAllLayersCommand {
     async fn execute(&self) -> Response {
-        let mut audio_player = get_audio_player().lock().await;
+        let mut audio_player = lock_audio_player().await;
         audio_player.stop_all_layers();
         Response::new(true, "All layers stopped")
     }
@@ -529,16 +1032,20 @@ impl Executable for SetLayerVolumeCommand {
     async fn execute(&self) -> Response {
         match (self.layer_index, self.volume) {
             (Some(layer_index), Some(volume)) => {
-                let mut audio_player = get_audio_player().lock().await;
+                let mut audio_player = lock_audio_player().await;
                 match audio_player.set_layer_volume(layer_index, volume) {
                     Ok(_) => Response::new(
                         true,
                         format!("Layer {} volume set to {}", layer_index, volume),
-                    ),
-                    Err(err) => Response::new(false, err.to_string()),
+                    )
+                    .with_data(LayerVolume {
+                        layer_index,
+                        volume,
+                    }),
+                    Err(err) => Response::error(ErrorCode::InvalidArg, err.to_string()),
                 }
             }
-            _ => Response::new(false, "Invalid layer index or volume"),
+            _ => Response::error(ErrorCode::InvalidArg, "Invalid layer index or volume"),
         }
     }
 }
@@ -546,11 +1053,448 @@ impl Executable for SetLayerVolumeCommand {
 #[async_trait]
 impl Executable for GetLayersInfoCommand {
     async fn execute(&self) -> Response {
-        let audio_player = get_audio_player().lock().await;
+        let audio_player = lock_audio_player().await;
         let layers_info = audio_player.get_all_layers_info();
         match serde_json::to_string(&layers_info) {
-            Ok(json) => Response::new(true, json),
-            Err(_) => Response::new(false, "Failed to serialize layers info"),
+            Ok(json) => Response::new(true, json).with_data(layers_info),
+            Err(_) => Response::error(ErrorCode::Internal, "Failed to serialize layers info"),
+        }
+    }
+}
+
+// ============= App Routing Command Implementations =============
+
+#[async_trait]
+impl Executable for GetAppStreamsCommand {
+    async fn execute(&self) -> Response {
+        #[cfg(target_os = "linux")]
+        {
+            match get_app_streams().await {
+                Ok(streams) => match serde_json::to_string(&streams) {
+                    Ok(json) => Response::new(true, json).with_data(streams),
+                    Err(_) => Response::error(
+                        ErrorCode::Internal,
+                        "Failed to serialize application streams",
+                    ),
+                },
+                Err(e) => Response::error(
+                    ErrorCode::DeviceError,
+                    format!("Failed to get application streams: {}", e),
+                ),
+            }
+        }
+        #[cfg(target_os = "windows")]
+        {
+            Response::error(
+                ErrorCode::Unsupported,
+                "Application routing is only supported on Linux (PipeWire)",
+            )
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for GetAppRoutesCommand {
+    async fn execute(&self) -> Response {
+        let config = get_daemon_config();
+        match serde_json::to_string(&config.app_routes) {
+            Ok(json) => Response::new(true, json).with_data(config.app_routes),
+            Err(_) => Response::error(
+                ErrorCode::Internal,
+                "Failed to serialize application routes",
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for AddAppRouteCommand {
+    async fn execute(&self) -> Response {
+        let Some(rule) = self.rule.clone() else {
+            return Response::error(
+                ErrorCode::InvalidArg,
+                "Invalid application route: set application_name or process_binary",
+            );
+        };
+
+        let added = update_daemon_config(|config| {
+            if config.app_routes.contains(&rule) {
+                return Ok(false);
+            }
+            config.app_routes.push(rule.clone());
+            Ok(true)
+        });
+
+        match added {
+            Ok(false) => Response::new(
+                true,
+                format!("Application route already exists: {}", rule.display()),
+            )
+            .with_data(rule),
+            Ok(true) => {
+                #[cfg(target_os = "linux")]
+                notify_app_routes_changed();
+                Response::new(true, format!("Application route added: {}", rule.display()))
+                    .with_data(rule)
+            }
+            Err(error) => error.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for RemoveAppRouteCommand {
+    async fn execute(&self) -> Response {
+        let Some(rule) = &self.rule else {
+            return Response::error(
+                ErrorCode::InvalidArg,
+                "Invalid application route: set application_name or process_binary",
+            );
+        };
+
+        let removed = update_daemon_config(|config| {
+            let routes_before = config.app_routes.len();
+            config.app_routes.retain(|r| r != rule);
+            if config.app_routes.len() == routes_before {
+                return Err(DaemonError::new(
+                    ErrorCode::NotFound,
+                    format!("No such application route: {}", rule.display()),
+                ));
+            }
+            Ok(())
+        });
+
+        match removed {
+            Ok(()) => {
+                #[cfg(target_os = "linux")]
+                notify_app_routes_changed();
+                Response::new(
+                    true,
+                    format!("Application route removed: {}", rule.display()),
+                )
+                .with_data(rule)
+            }
+            Err(error) => error.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for RunMacroCommand {
+    async fn execute(&self) -> Response {
+        let Some(name) = &self.name else {
+            return Response::error(ErrorCode::InvalidArg, "Invalid macro name");
+        };
+
+        // Macros are edited in the GUI, so read them fresh on every run
+        let sound_macro = match GuiConfig::load_from_file() {
+            Ok(config) => config.macros.get(name).cloned(),
+            Err(e) => {
+                return Response::error(
+                    ErrorCode::ConfigError,
+                    format!("Failed to load macros: {}", e),
+                )
+            }
+        };
+        let Some(sound_macro) = sound_macro else {
+            return Response::error(ErrorCode::NotFound, format!("Macro '{}' not found", name));
+        };
+        if let Err(e) = sound_macro.validate() {
+            return Response::error(
+                ErrorCode::InvalidArg,
+                format!("Invalid macro '{}': {}", name, e),
+            );
+        }
+
+        let steps = sound_macro.steps.len();
+        let restarted = run_macro(name, sound_macro);
+        let message = if restarted {
+            format!("Restarted macro '{}' ({} steps)", name, steps)
+        } else {
+            format!("Running macro '{}' ({} steps)", name, steps)
+        };
+        Response::new(true, message).with_data(MacroStarted {
+            name: name.clone(),
+            steps,
+            restarted,
+        })
+    }
+}
+
+#[async_trait]
+impl Executable for CancelMacroCommand {
+    async fn execute(&self) -> Response {
+        let Some(name) = &self.name else {
+            return Response::error(ErrorCode::InvalidArg, "Invalid macro name");
+        };
+
+        if cancel_macro(name) {
+            Response::new(true, format!("Cancelled macro '{}'", name)).with_data(name)
+        } else {
+            Response::error(
+                ErrorCode::NotFound,
+                format!("Macro '{}' is not running", name),
+            )
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for GetRunningMacrosCommand {
+    async fn execute(&self) -> Response {
+        let running = get_running_macros();
+        match serde_json::to_string(&running) {
+            Ok(json) => Response::new(true, json).with_data(running),
+            Err(_) => Response::error(ErrorCode::Internal, "Failed to serialize running macros"),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for SchedulePlayCommand {
+    async fn execute(&self) -> Response {
+        let file_path = match &self.file_path {
+            Ok(file_path) => file_path,
+            Err(e) => return e.clone().into(),
+        };
+        let layer_index = match self.layer_index {
+            Some(Some(layer_index)) if layer_index >= NUM_AUDIO_LAYERS => None,
+            layer_index => layer_index,
+        };
+        let Some(layer_index) = layer_index else {
+            return Response::error(ErrorCode::InvalidArg, "Invalid layer index");
+        };
+        let Some((next_run, interval_secs)) = self.timing else {
+            return Response::error(
+                ErrorCode::InvalidArg,
+                "Invalid schedule: set one of delay or at (in the future), and/or every",
+            );
+        };
+
+        let added = update_daemon_config(|config| {
+            let schedule = ScheduledPlay {
+                id: next_schedule_id(&config.schedules),
+                file_path: file_path.clone(),
+                layer_index,
+                next_run,
+                interval_secs,
+                label: self.label.clone(),
+            };
+            config.schedules.push(schedule.clone());
+            Ok(schedule)
+        });
+        let schedule = match added {
+            Ok(schedule) => schedule,
+            Err(error) => return error.into(),
+        };
+        notify_schedules_changed();
+
+        let mut message = format!(
+            "Scheduled #{}: {} in {}s",
+            schedule.id,
+            file_path.display(),
+            next_run.saturating_sub(unix_now())
+        );
+        if let Some(interval_secs) = interval_secs {
+            message.push_str(&format!(", then every {}s", interval_secs));
+        }
+        Response::new(true, message).with_data(schedule)
+    }
+}
+
+#[async_trait]
+impl Executable for ListSchedulesCommand {
+    async fn execute(&self) -> Response {
+        let config = get_daemon_config();
+        match serde_json::to_string(&config.schedules) {
+            Ok(json) => Response::new(true, json).with_data(config.schedules),
+            Err(_) => Response::error(ErrorCode::Internal, "Failed to serialize schedules"),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for CancelScheduleCommand {
+    async fn execute(&self) -> Response {
+        let Some(id) = self.id else {
+            return Response::error(ErrorCode::InvalidArg, "Invalid schedule id");
+        };
+
+        let cancelled = update_daemon_config(|config| {
+            let schedules_before = config.schedules.len();
+            config.schedules.retain(|s| s.id != id);
+            if config.schedules.len() == schedules_before {
+                return Err(DaemonError::new(
+                    ErrorCode::NotFound,
+                    format!("No schedule with id {}", id),
+                ));
+            }
+            Ok(())
+        });
+
+        match cancelled {
+            Ok(()) => {
+                notify_schedules_changed();
+                Response::new(true, format!("Cancelled schedule #{}", id)).with_data(id)
+            }
+            Err(error) => error.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for PlayPlaylistCommand {
+    async fn execute(&self) -> Response {
+        let Some(name) = &self.name else {
+            return Response::error(ErrorCode::InvalidArg, "Invalid playlist name");
+        };
+        let Some(mode) = self.mode else {
+            return Response::error(ErrorCode::InvalidArg, "Invalid playlist mode");
+        };
+
+        match play_playlist(name, mode).await {
+            Ok((sound, mode)) => Response::new(
+                true,
+                format!(
+                    "Playing playlist '{}' ({}): {}",
+                    name,
+                    mode.display(),
+                    sound.display()
+                ),
+            )
+            .with_data(PlaylistStarted {
+                name: name.clone(),
+                mode,
+                file_path: sound,
+            }),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for GetActivePlaylistCommand {
+    async fn execute(&self) -> Response {
+        let active = get_active_playlist().map(|(name, mode)| ActivePlaylist { name, mode });
+        match serde_json::to_string(&active) {
+            Ok(json) => Response::new(true, json).with_data(active),
+            Err(_) => Response::error(ErrorCode::Internal, "Failed to serialize active playlist"),
+        }
+    }
+}
+
+#[async_trait]
+impl Executable for GetLibraryCommand {
+    async fn execute(&self) -> Response {
+        // Playlists are edited in the GUI, so read them fresh every time
+        match GuiConfig::load_from_file() {
+            Ok(config) => {
+                let library = build_library(&config);
+                Response::new(true, format!("{} playlists", library.playlists.len()))
+                    .with_data(library)
+            }
+            Err(e) => Response::error(
+                ErrorCode::ConfigError,
+                format!("Failed to load the library: {}", e),
+            ),
+        }
+    }
+}
+
+// Edit the saved MIDI mappings and have the MIDI loop pick them up
+fn update_midi_mappings(
+    update: impl FnOnce(&mut Vec<MidiMapping>) -> Result<(), DaemonError>,
+    message: String,
+) -> Response {
+    let updated = update_daemon_config(|config| {
+        update(&mut config.midi.mappings)?;
+        Ok(config.midi.mappings.clone())
+    });
+    match updated {
+        Ok(mappings) => {
+            reload_midi_mappings();
+            Response::new(true, message).with_data(mappings)
+        }
+        Err(error) => error.into(),
+    }
+}
+
+#[async_trait]
+impl Executable for GetMidiMappingsCommand {
+    async fn execute(&self) -> Response {
+        let mappings = get_daemon_config().midi.mappings;
+        Response::new(true, format!("{} MIDI mappings", mappings.len())).with_data(mappings)
+    }
+}
+
+#[async_trait]
+impl Executable for AddMidiMappingCommand {
+    async fn execute(&self) -> Response {
+        if self.mapping.channel.is_some_and(|channel| channel > 15) {
+            return Response::error(ErrorCode::InvalidArg, "MIDI channels go from 0 to 15");
+        }
+
+        update_midi_mappings(
+            |mappings| {
+                mappings.retain(|mapping| !mapping.same_trigger(&self.mapping));
+                mappings.push(self.mapping.clone());
+                Ok(())
+            },
+            format!(
+                "MIDI mapping added: {} -> {}",
+                self.mapping.trigger, self.mapping.action
+            ),
+        )
+    }
+}
+
+#[async_trait]
+impl Executable for RemoveMidiMappingCommand {
+    async fn execute(&self) -> Response {
+        update_midi_mappings(
+            |mappings| {
+                let count = mappings.len();
+                mappings.retain(|mapping| {
+                    mapping.channel != self.channel || mapping.trigger != self.trigger
+                });
+                if mappings.len() == count {
+                    return Err(DaemonError::new(
+                        ErrorCode::NotFound,
+                        format!("No MIDI mapping for {}", self.trigger),
+                    ));
+                }
+                Ok(())
+            },
+            format!("MIDI mapping removed: {}", self.trigger),
+        )
+    }
+}
+
+#[async_trait]
+impl Executable for MidiLearnCommand {
+    async fn execute(&self) -> Response {
+        if !midi_running() {
+            return Response::error(
+                ErrorCode::Unsupported,
+                "MIDI is off; set midi.enabled in daemon.json and restart the daemon",
+            );
+        }
+        match learn_next_message().await {
+            Some(message) => {
+                let learned = MidiLearned {
+                    channel: message.channel,
+                    trigger: message.trigger(),
+                };
+                Response::new(
+                    true,
+                    format!("{} on channel {}", learned.trigger, learned.channel),
+                )
+                .with_data(learned)
+            }
+            None => Response::error(
+                ErrorCode::NotFound,
+                format!("No MIDI message within {} seconds", LEARN_TIMEOUT.as_secs()),
+            ),
         }
     }
 }
@@ -1,4 +1,10 @@
-use crate::utils::config::get_config_path;
+use crate::{
+    types::{
+        chat::ChatCommand, hooks::Hook, macros::SoundMacro, midi::MidiMapping, obs::ObsRule,
+        playlist::PlaylistMode, schedule::ScheduledPlay,
+    },
+    utils::config::get_config_path,
+};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
@@ -78,6 +84,12 @@ pub struct SoundCategory {
     pub name: String,
     /// Ordered list of sound file paths in this category
     pub sounds: Vec<PathBuf>,
+    /// How the daemon plays this playlist
+    #[serde(default)]
+    pub play_mode: PlaylistMode,
+    /// Global hotkey that plays this playlist
+    #[serde(default)]
+    pub hotkey: Option<HotkeyBinding>,
 }
 
 impl SoundCategory {
@@ -85,6 +97,8 @@ impl SoundCategory {
         Self {
             name: name.to_string(),
             sounds: Vec::new(),
+            play_mode: PlaylistMode::default(),
+            hotkey: None,
         }
     }
 
@@ -118,6 +132,12 @@ pub struct SoundMetadata {
     /// Individual volume for this sound (0.0 to 1.0, None = use global volume)
     #[serde(default)]
     pub volume: Option<f32>,
+    /// Seconds before this sound can be played again (None = no cooldown)
+    #[serde(default)]
+    pub cooldown_secs: Option<f32>,
+    /// Voice priority when layers run out (None = 0, the lowest)
+    #[serde(default)]
+    pub priority: Option<u8>,
 }
 
 impl SoundMetadata {
@@ -145,6 +165,408 @@ impl SoundMetadata {
             && self.description.is_none()
             && self.tags.is_empty()
             && self.volume.is_none()
+            && self.cooldown_secs.is_none()
+            && self.priority.is_none()
+    }
+}
+
+/// Rule for linking the daemon's output straight into an application's
+/// capture stream. A rule matches when every field it sets matches
+/// (case-insensitive); a rule with no fields set matches nothing.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct AppRouteRule {
+    /// PipeWire `application.name` (e.g. "Discord")
+    #[serde(default)]
+    pub application_name: Option<String>,
+    /// PipeWire `application.process.binary` (e.g. "Discord", "firefox")
+    #[serde(default)]
+    pub process_binary: Option<String>,
+}
+
+impl AppRouteRule {
+    pub fn matches(&self, application_name: Option<&str>, process_binary: Option<&str>) -> bool {
+        fn field_matches(rule: &Option<String>, value: Option<&str>) -> bool {
+            match (rule, value) {
+                (None, _) => true,
+                (Some(rule), Some(value)) => rule.eq_ignore_ascii_case(value),
+                (Some(_), None) => false,
+            }
+        }
+
+        if self.application_name.is_none() && self.process_binary.is_none() {
+            return false;
+        }
+
+        field_matches(&self.application_name, application_name)
+            && field_matches(&self.process_binary, process_binary)
+    }
+
+    /// Format as human-readable string
+    pub fn display(&self) -> String {
+        match (&self.application_name, &self.process_binary) {
+            (Some(name), Some(binary)) => format!("{} ({})", name, binary),
+            (Some(name), None) => name.clone(),
+            (None, Some(binary)) => format!("({})", binary),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// Where the daemon sends its mixed output
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AudioBackend {
+    /// The system output device
+    #[default]
+    Device,
+    /// Discard the output (consumed in real time, no device needed)
+    Null,
+    /// Write the mixed output to a WAV file
+    File { path: PathBuf },
+}
+
+impl AudioBackend {
+    /// Null and file backends need no audio hardware or PipeWire
+    pub fn is_headless(&self) -> bool {
+        !matches!(self, AudioBackend::Device)
+    }
+}
+
+/// What to do when a sound needs a layer and all of them are busy. Sounds
+/// with a higher priority than the new one are never stolen.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum VoiceStealing {
+    /// Drop the new sound
+    Drop,
+    /// Replace the lowest-priority sound, the oldest among equals
+    #[default]
+    LowestPriority,
+    /// Replace the sound that has been playing longest
+    Oldest,
+    /// Replace the sound on the quietest layer
+    Quietest,
+}
+
+/// Global limits on how often sounds can be triggered
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RateLimitConfig {
+    /// Plays allowed per window (None = unlimited)
+    #[serde(default)]
+    pub max_plays: Option<u32>,
+    /// Length of the window in seconds
+    #[serde(default = "default_rate_limit_window")]
+    pub window_secs: f32,
+    /// Sounds allowed to play at once across the main player and layers
+    /// (None = unlimited)
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+}
+
+fn default_rate_limit_window() -> f32 {
+    10.0
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_plays: None,
+            window_secs: default_rate_limit_window(),
+            max_concurrent: None,
+        }
+    }
+}
+
+/// The daemon's HTTP and WebSocket API, for phones, stream decks and overlays
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RemoteConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Address to listen on; use 0.0.0.0 to accept other devices on the LAN
+    #[serde(default = "default_remote_bind")]
+    pub bind: String,
+    /// Bearer token every request must carry; the API won't start without one
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+fn default_remote_bind() -> String {
+    "127.0.0.1:19736".to_string()
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: default_remote_bind(),
+            token: None,
+        }
+    }
+}
+
+/// UDP Open Sound Control server, for TouchOSC and other OSC controllers
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OscConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// UDP address for incoming messages. The default only hears this
+    /// machine; a controller app on a tablet needs 0.0.0.0 or a LAN address.
+    #[serde(default = "default_osc_bind")]
+    pub bind: String,
+    /// `host:port`s that always get feedback, on top of clients that send
+    /// `/soundboard/register`
+    #[serde(default)]
+    pub feedback: Vec<String>,
+}
+
+fn default_osc_bind() -> String {
+    "127.0.0.1:8000".to_string()
+}
+
+impl Default for OscConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: default_osc_bind(),
+            feedback: Vec::new(),
+        }
+    }
+}
+
+/// The MPRIS player on the session bus, for media keys and desktop widgets
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct MprisConfig {
+    /// Off by default, so media keys keep reaching music players
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// MIDI pad controllers, off by default
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MidiConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Only use input ports whose name contains this; all controllers if None
+    #[serde(default)]
+    pub port: Option<String>,
+    /// Light the pads of sounds that are playing
+    #[serde(default = "default_midi_feedback")]
+    pub feedback: bool,
+    #[serde(default)]
+    pub mappings: Vec<MidiMapping>,
+}
+
+fn default_midi_feedback() -> bool {
+    true
+}
+
+impl Default for MidiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: None,
+            feedback: default_midi_feedback(),
+            mappings: Vec::new(),
+        }
+    }
+}
+
+/// MQTT client for home automation, off by default
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MqttConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Broker host name or address
+    #[serde(default = "default_mqtt_broker")]
+    pub broker: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Must be unique on the broker
+    #[serde(default = "default_mqtt_topic_prefix")]
+    pub client_id: String,
+    /// Topics are `<prefix>/command/<command>`, `<prefix>/state`, ...
+    #[serde(default = "default_mqtt_topic_prefix")]
+    pub topic_prefix: String,
+}
+
+fn default_mqtt_broker() -> String {
+    "localhost".to_string()
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_topic_prefix() -> String {
+    "soundboard".to_string()
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            broker: default_mqtt_broker(),
+            port: default_mqtt_port(),
+            username: None,
+            password: None,
+            client_id: default_mqtt_topic_prefix(),
+            topic_prefix: default_mqtt_topic_prefix(),
+        }
+    }
+}
+
+/// OBS WebSocket (v5) client, off by default
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ObsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_obs_host")]
+    pub host: String,
+    #[serde(default = "default_obs_port")]
+    pub port: u16,
+    /// Needed when authentication is on in OBS's WebSocket settings
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub rules: Vec<ObsRule>,
+    /// Text source that shows the sound playing on the main player
+    #[serde(default)]
+    pub now_playing_source: Option<String>,
+}
+
+fn default_obs_host() -> String {
+    "localhost".to_string()
+}
+
+fn default_obs_port() -> u16 {
+    4455
+}
+
+impl Default for ObsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: default_obs_host(),
+            port: default_obs_port(),
+            password: None,
+            rules: Vec::new(),
+            now_playing_source: None,
+        }
+    }
+}
+
+/// IRC chat commands (Twitch works too), off by default
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IrcConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_irc_server")]
+    pub server: String,
+    #[serde(default = "default_irc_port")]
+    pub port: u16,
+    #[serde(default = "default_irc_nick")]
+    pub nick: String,
+    /// Sent as PASS; an `oauth:` token on Twitch
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub channels: Vec<String>,
+    #[serde(default = "default_irc_prefix")]
+    pub prefix: String,
+    #[serde(default)]
+    pub commands: Vec<ChatCommand>,
+    /// Seconds before the same user can trigger another sound
+    #[serde(default = "default_irc_user_cooldown")]
+    pub user_cooldown: u64,
+    /// Seconds before anyone can trigger another sound
+    #[serde(default = "default_irc_global_cooldown")]
+    pub global_cooldown: u64,
+    /// Only these users may trigger sounds; everyone if empty
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Users who may never trigger sounds, even if in `allow`
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+fn default_irc_server() -> String {
+    "irc.chat.twitch.tv".to_string()
+}
+
+fn default_irc_port() -> u16 {
+    6667
+}
+
+fn default_irc_nick() -> String {
+    "soundboard".to_string()
+}
+
+fn default_irc_prefix() -> String {
+    "!".to_string()
+}
+
+fn default_irc_user_cooldown() -> u64 {
+    30
+}
+
+fn default_irc_global_cooldown() -> u64 {
+    5
+}
+
+impl Default for IrcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            server: default_irc_server(),
+            port: default_irc_port(),
+            nick: default_irc_nick(),
+            password: None,
+            channels: Vec::new(),
+            prefix: default_irc_prefix(),
+            commands: Vec::new(),
+            user_cooldown: default_irc_user_cooldown(),
+            global_cooldown: default_irc_global_cooldown(),
+            allow: Vec::new(),
+            deny: Vec::new(),
+        }
+    }
+}
+
+/// Shell commands run on daemon events, off by default
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HooksConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub commands: Vec<Hook>,
+    /// Seconds before a hook is killed
+    #[serde(default = "default_hook_timeout")]
+    pub timeout: u64,
+    /// Hooks running at once; hooks over the limit are skipped
+    #[serde(default = "default_hook_max_concurrent")]
+    pub max_concurrent: usize,
+}
+
+fn default_hook_timeout() -> u64 {
+    10
+}
+
+fn default_hook_max_concurrent() -> usize {
+    4
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            commands: Vec::new(),
+            timeout: default_hook_timeout(),
+            max_concurrent: default_hook_max_concurrent(),
+        }
     }
 }
 
@@ -155,6 +577,46 @@ pub struct DaemonConfig {
     pub default_volume: Option<f32>,
     pub default_gain: Option<f32>,
     pub default_mic_gain: Option<f32>,
+    /// Applications whose capture streams receive the daemon's output directly
+    #[serde(default)]
+    pub app_routes: Vec<AppRouteRule>,
+    /// Use the system default microphone instead of `default_input_name`
+    #[serde(default)]
+    pub follow_default_input: bool,
+    /// Use the system default output instead of `default_output_name`
+    #[serde(default)]
+    pub follow_default_output: bool,
+    /// Output backend; `--backend` on the daemon command line overrides it
+    #[serde(default)]
+    pub audio_backend: AudioBackend,
+    /// Scheduled and recurring plays
+    #[serde(default)]
+    pub schedules: Vec<ScheduledPlay>,
+    /// Anti-spam limits for play requests
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// Policy for playing on a free layer when none is free
+    #[serde(default)]
+    pub voice_stealing: VoiceStealing,
+    /// HTTP and WebSocket remote control, off by default
+    #[serde(default)]
+    pub remote: RemoteConfig,
+    /// OSC control surface server, off by default
+    #[serde(default)]
+    pub osc: OscConfig,
+    /// Linux only
+    #[serde(default)]
+    pub mpris: MprisConfig,
+    #[serde(default)]
+    pub midi: MidiConfig,
+    #[serde(default)]
+    pub mqtt: MqttConfig,
+    #[serde(default)]
+    pub obs: ObsConfig,
+    #[serde(default)]
+    pub irc: IrcConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
 }
 
 impl DaemonConfig {
@@ -215,6 +677,9 @@ pub struct GuiConfig {
     /// Whether to automatically check for updates on startup
     #[serde(default = "default_auto_check_updates")]
     pub auto_check_updates: bool,
+    /// Named sequences of actions, run by the daemon
+    #[serde(default)]
+    pub macros: HashMap<String, SoundMacro>,
 }
 
 fn default_auto_check_updates() -> bool {
@@ -246,6 +711,7 @@ impl Default for GuiConfig {
             sidebar_width: default_sidebar_width(),
             playlist_order: Vec::new(),
             auto_check_updates: default_auto_check_updates(),
+            macros: HashMap::default(),
         }
     }
 }
@@ -284,6 +750,12 @@ impl GuiConfig {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::{
+        chat::ChatAction,
+        hooks::HookEvent,
+        midi::{MidiAction, MidiTrigger},
+        obs::{ObsAction, ObsEvent},
+    };
 
     // --- SoundCategory tests (TEST-04) ---
 
@@ -398,6 +870,104 @@ mod tests {
             default_volume: Some(0.75),
             default_gain: Some(1.5),
             default_mic_gain: Some(2.0),
+            app_routes: vec![AppRouteRule {
+                application_name: Some("Discord".to_string()),
+                process_binary: None,
+            }],
+            follow_default_input: true,
+            follow_default_output: false,
+            audio_backend: AudioBackend::File {
+                path: PathBuf::from("/tmp/out.wav"),
+            },
+            schedules: vec![ScheduledPlay {
+                id: 1,
+                file_path: PathBuf::from("/sounds/water.ogg"),
+                layer_index: Some(2),
+                next_run: 1_700_000_000,
+                interval_secs: Some(1800),
+                label: Some("hydration".to_string()),
+            }],
+            rate_limit: RateLimitConfig {
+                max_plays: Some(5),
+                window_secs: 30.0,
+                max_concurrent: Some(3),
+            },
+            voice_stealing: VoiceStealing::Quietest,
+            remote: RemoteConfig {
+                enabled: true,
+                bind: "0.0.0.0:19736".to_string(),
+                token: Some("s3cret".to_string()),
+            },
+            osc: OscConfig {
+                enabled: true,
+                bind: "0.0.0.0:8000".to_string(),
+                feedback: vec!["192.168.1.20:9000".to_string()],
+            },
+            mpris: MprisConfig { enabled: true },
+            midi: MidiConfig {
+                enabled: true,
+                port: Some("LPD8".to_string()),
+                feedback: false,
+                mappings: vec![MidiMapping {
+                    channel: Some(9),
+                    trigger: MidiTrigger::Control { controller: 7 },
+                    action: MidiAction::Volume,
+                }],
+            },
+            mqtt: MqttConfig {
+                enabled: true,
+                broker: "192.168.1.5".to_string(),
+                port: 1884,
+                username: Some("soundboard".to_string()),
+                password: Some("s3cret".to_string()),
+                client_id: "soundboard-desk".to_string(),
+                topic_prefix: "home/soundboard".to_string(),
+            },
+            obs: ObsConfig {
+                enabled: true,
+                host: "192.168.1.10".to_string(),
+                port: 4456,
+                password: Some("hunter2".to_string()),
+                rules: vec![ObsRule {
+                    on: ObsEvent::SceneChanged {
+                        scene: Some("BRB".to_string()),
+                    },
+                    action: ObsAction::Play {
+                        file_path: PathBuf::from("/sounds/stinger.ogg"),
+                        layer_index: Some(3),
+                    },
+                }],
+                now_playing_source: Some("Now Playing".to_string()),
+            },
+            irc: IrcConfig {
+                enabled: true,
+                server: "127.0.0.1".to_string(),
+                port: 6668,
+                nick: "soundbot".to_string(),
+                password: Some("oauth:abc".to_string()),
+                channels: vec!["#stream".to_string()],
+                prefix: "?".to_string(),
+                commands: vec![ChatCommand {
+                    name: "airhorn".to_string(),
+                    action: ChatAction::Play {
+                        file_path: PathBuf::from("/sounds/airhorn.ogg"),
+                        layer_index: None,
+                    },
+                }],
+                user_cooldown: 60,
+                global_cooldown: 0,
+                allow: Vec::new(),
+                deny: vec!["spammer".to_string()],
+            },
+            hooks: HooksConfig {
+                enabled: true,
+                commands: vec![Hook {
+                    on: HookEvent::Play,
+                    command: "notify-send \"$SOUNDBOARD_FILE_PATH\"".to_string(),
+                }],
+                timeout: 3,
+                max_concurrent: 1,
+            },
         };
 
         let json = serde_json::to_string(&config).expect("serialize");
@@ -408,6 +978,21 @@ mod tests {
         assert_eq!(loaded.default_volume, config.default_volume);
         assert_eq!(loaded.default_gain, config.default_gain);
         assert_eq!(loaded.default_mic_gain, config.default_mic_gain);
+        assert_eq!(loaded.app_routes, config.app_routes);
+        assert!(loaded.follow_default_input);
+        assert!(!loaded.follow_default_output);
+        assert_eq!(loaded.audio_backend, config.audio_backend);
+        assert_eq!(loaded.schedules, config.schedules);
+        assert_eq!(loaded.rate_limit, config.rate_limit);
+        assert_eq!(loaded.voice_stealing, VoiceStealing::Quietest);
+        assert_eq!(loaded.remote, config.remote);
+        assert_eq!(loaded.osc, config.osc);
+        assert_eq!(loaded.mpris, config.mpris);
+        assert_eq!(loaded.midi, config.midi);
+        assert_eq!(loaded.mqtt, config.mqtt);
+        assert_eq!(loaded.obs, config.obs);
+        assert_eq!(loaded.irc, config.irc);
+        assert_eq!(loaded.hooks, config.hooks);
     }
 
     #[test]
@@ -416,6 +1001,36 @@ mod tests {
         let config: DaemonConfig = serde_json::from_str(json).expect("deserialize empty");
         assert!(config.default_input_name.is_none());
         assert!(config.default_volume.is_none());
+        assert!(config.app_routes.is_empty());
+        assert!(!config.follow_default_input);
+        assert!(!config.follow_default_output);
+        assert_eq!(config.audio_backend, AudioBackend::Device);
+        assert!(config.schedules.is_empty());
+        assert_eq!(config.rate_limit, RateLimitConfig::default());
+        assert_eq!(config.voice_stealing, VoiceStealing::LowestPriority);
+        assert!(!config.remote.enabled);
+        assert_eq!(config.remote.bind, "127.0.0.1:19736");
+        assert_eq!(config.osc, OscConfig::default());
+        assert!(!config.mpris.enabled);
+        assert_eq!(config.midi, MidiConfig::default());
+        assert!(config.midi.feedback);
+        assert_eq!(config.mqtt, MqttConfig::default());
+        assert_eq!(config.mqtt.port, 1883);
+        assert_eq!(config.obs, ObsConfig::default());
+        assert_eq!(config.obs.port, 4455);
+        assert_eq!(config.irc, IrcConfig::default());
+        assert_eq!(config.irc.prefix, "!");
+        assert_eq!(config.hooks, HooksConfig::default());
+        assert_eq!(config.hooks.timeout, 10);
+    }
+
+    #[test]
+    fn test_rate_limit_partial_config() {
+        let json = r#"{"rate_limit": {"max_plays": 3}}"#;
+        let config: DaemonConfig = serde_json::from_str(json).expect("deserialize");
+        assert_eq!(config.rate_limit.max_plays, Some(3));
+        assert_eq!(config.rate_limit.window_secs, 10.0);
+        assert_eq!(config.rate_limit.max_concurrent, None);
     }
 
     #[test]
@@ -449,6 +1064,7 @@ mod tests {
         assert_eq!(config.auto_check_updates, default_auto_check_updates());
         assert!(config.categories.is_empty());
         assert!(config.sounds_folder.is_none());
+        assert!(config.macros.is_empty());
     }
 
     #[test]
@@ -491,6 +1107,15 @@ mod tests {
         assert_eq!(loaded.sounds[0], PathBuf::from("/sounds/a.mp3"));
     }
 
+    #[test]
+    fn test_sound_category_play_mode_defaults() {
+        // Playlists saved before play modes existed
+        let json = r#"{"name": "Old", "sounds": ["/sounds/a.mp3"]}"#;
+        let loaded: SoundCategory = serde_json::from_str(json).expect("deserialize");
+        assert_eq!(loaded.play_mode, PlaylistMode::Sequential);
+        assert!(loaded.hotkey.is_none());
+    }
+
     // --- Config validation tests (ARCH-20) ---
 
     #[test]
@@ -529,6 +1154,13 @@ mod tests {
         assert_eq!(loaded.volume, Some(0.75));
     }
 
+    #[test]
+    fn test_sound_metadata_cooldown_not_empty() {
+        let mut meta = SoundMetadata::new();
+        meta.cooldown_secs = Some(5.0);
+        assert!(!meta.is_empty());
+    }
+
     #[test]
     fn test_hotkey_binding_serialization_roundtrip() {
         let binding = HotkeyBinding::new("KeyP", true, true, false, false);
@@ -549,4 +1181,54 @@ mod tests {
         assert!(loaded.play_pause.is_none());
         assert!(!loaded.enabled);
     }
+
+    // --- AppRouteRule tests ---
+
+    #[test]
+    fn test_app_route_rule_matches_case_insensitive() {
+        let rule = AppRouteRule {
+            application_name: Some("discord".to_string()),
+            process_binary: None,
+        };
+        assert!(rule.matches(Some("Discord"), Some("Discord")));
+        assert!(rule.matches(Some("DISCORD"), None));
+        assert!(!rule.matches(Some("Firefox"), Some("firefox")));
+        assert!(!rule.matches(None, Some("discord")));
+    }
+
+    #[test]
+    fn test_app_route_rule_requires_all_set_fields() {
+        let rule = AppRouteRule {
+            application_name: Some("WEBRTC VoiceEngine".to_string()),
+            process_binary: Some("firefox".to_string()),
+        };
+        assert!(rule.matches(Some("WEBRTC VoiceEngine"), Some("firefox")));
+        assert!(!rule.matches(Some("WEBRTC VoiceEngine"), Some("chromium")));
+    }
+
+    #[test]
+    fn test_app_route_rule_empty_matches_nothing() {
+        let rule = AppRouteRule::default();
+        assert!(!rule.matches(Some("Discord"), Some("Discord")));
+        assert!(!rule.matches(None, None));
+    }
+
+    // --- AudioBackend tests ---
+
+    #[test]
+    fn test_audio_backend_json_shape() {
+        let backend: AudioBackend =
+            serde_json::from_str(r#"{"type": "file", "path": "/tmp/out.wav"}"#).unwrap();
+        assert_eq!(
+            backend,
+            AudioBackend::File {
+                path: PathBuf::from("/tmp/out.wav")
+            }
+        );
+        assert!(backend.is_headless());
+
+        let backend: AudioBackend = serde_json::from_str(r#"{"type": "null"}"#).unwrap();
+        assert_eq!(backend, AudioBackend::Null);
+        assert!(!AudioBackend::Device.is_headless());
+    }
 }
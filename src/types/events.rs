@@ -0,0 +1,156 @@
+// Events pushed to `subscribe` connections. After the daemon acknowledges
+// the subscription it sends one length-prefixed `Event` per frame: first the
+// full current state, then only what changed.
+
+use crate::types::{
+    audio_player::{LayerInfo, PlayerState},
+    payload::{CurrentDevice, DaemonError, Device},
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Something changed in the daemon, tagged by its `event` field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// Playback started, stopped, paused or resumed, or loop was toggled
+    State {
+        state: PlayerState,
+        file_path: Option<PathBuf>,
+        /// Seconds, 0 when nothing is playing
+        duration: f32,
+        looped: bool,
+    },
+    /// Position tick of the main player, in seconds
+    Position {
+        position: f32,
+    },
+    Volume {
+        volume: f32,
+        gain: f32,
+        mic_gain: f32,
+    },
+    Layers {
+        layers: Vec<LayerInfo>,
+    },
+    Devices {
+        input: Option<CurrentDevice>,
+        inputs: Vec<Device>,
+        output: Option<CurrentDevice>,
+        outputs: Vec<Device>,
+    },
+    /// The main player started a sound; resuming from pause doesn't count
+    Play {
+        file_path: PathBuf,
+        duration: f32,
+        looped: bool,
+    },
+    /// The main player stopped, or its sound ended
+    Stop {
+        file_path: PathBuf,
+    },
+    /// A layer started a sound
+    LayerPlay {
+        layer_index: usize,
+        file_path: PathBuf,
+    },
+    /// A looping sound on the main player started over
+    LoopEnd {
+        file_path: PathBuf,
+    },
+    /// Something failed in the background (a scheduled play, a macro step...)
+    Error {
+        error: DaemonError,
+    },
+}
+
+/// Daemon state mirrored by subscribers. Each field holds the event that
+/// describes it, so diffing two snapshots yields the events to send.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub state: Event,
+    pub position: Event,
+    pub volume: Event,
+    pub layers: Event,
+    pub devices: Event,
+}
+
+impl Snapshot {
+    /// Events that turn `previous` into this snapshot; everything if there
+    /// is no previous snapshot
+    pub fn events_since(&self, previous: Option<&Snapshot>) -> Vec<Event> {
+        [
+            (&self.state, previous.map(|p| &p.state)),
+            (&self.position, previous.map(|p| &p.position)),
+            (&self.volume, previous.map(|p| &p.volume)),
+            (&self.layers, previous.map(|p| &p.layers)),
+            (&self.devices, previous.map(|p| &p.devices)),
+        ]
+        .into_iter()
+        .filter(|(current, previous)| Some(*current) != *previous)
+        .map(|(current, _)| current.clone())
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::payload::ErrorCode;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            state: Event::State {
+                state: PlayerState::Playing,
+                file_path: Some(PathBuf::from("/sounds/rain.ogg")),
+                duration: 12.5,
+                looped: false,
+            },
+            position: Event::Position { position: 1.0 },
+            volume: Event::Volume {
+                volume: 1.0,
+                gain: 1.0,
+                mic_gain: 1.0,
+            },
+            layers: Event::Layers { layers: vec![] },
+            devices: Event::Devices {
+                input: None,
+                inputs: vec![],
+                output: None,
+                outputs: vec![],
+            },
+        }
+    }
+
+    #[test]
+    fn test_first_snapshot_sends_everything() {
+        assert_eq!(snapshot().events_since(None).len(), 5);
+    }
+
+    #[test]
+    fn test_only_changes_are_sent() {
+        let previous = snapshot();
+        assert!(previous.events_since(Some(&previous)).is_empty());
+
+        let mut current = previous.clone();
+        current.position = Event::Position { position: 1.5 };
+        assert_eq!(
+            current.events_since(Some(&previous)),
+            vec![Event::Position { position: 1.5 }]
+        );
+    }
+
+    #[test]
+    fn test_event_schema() {
+        let event = Event::Error {
+            error: DaemonError::new(ErrorCode::PlaybackError, "Failed to play rain.ogg"),
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["event"], "error");
+        assert_eq!(json["error"]["code"], "PLAYBACK_ERROR");
+
+        let json = serde_json::to_value(Event::Position { position: 2.0 }).unwrap();
+        assert_eq!(json["event"], "position");
+        assert_eq!(json["position"], 2.0);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::types::audio_player::{LayerInfo, PlayerState};
+use crate::types::{
+    audio_player::{LayerInfo, PlayerState},
+    midi::{MidiAction, MidiMapping},
+};
 
 use egui::Id;
 
@@ -34,6 +37,75 @@ pub enum HotkeyRecording {
     Stop,
 }
 
+/// What the next learned MIDI control will do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MidiLearnTarget {
+    #[default]
+    PlaySelected,
+    ToggleSelected,
+    StopLayer,
+    StopAll,
+    LayerVolume,
+    Volume,
+    Gain,
+    MicGain,
+}
+
+impl MidiLearnTarget {
+    pub const ALL: [MidiLearnTarget; 8] = [
+        MidiLearnTarget::PlaySelected,
+        MidiLearnTarget::ToggleSelected,
+        MidiLearnTarget::StopLayer,
+        MidiLearnTarget::StopAll,
+        MidiLearnTarget::LayerVolume,
+        MidiLearnTarget::Volume,
+        MidiLearnTarget::Gain,
+        MidiLearnTarget::MicGain,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MidiLearnTarget::PlaySelected => "Play selected sound",
+            MidiLearnTarget::ToggleSelected => "Play/stop selected sound",
+            MidiLearnTarget::StopLayer => "Stop layer",
+            MidiLearnTarget::StopAll => "Stop all",
+            MidiLearnTarget::LayerVolume => "Layer volume",
+            MidiLearnTarget::Volume => "Volume",
+            MidiLearnTarget::Gain => "Gain",
+            MidiLearnTarget::MicGain => "Mic gain",
+        }
+    }
+
+    /// Whether the target needs a layer index
+    pub fn uses_layer(self) -> bool {
+        matches!(
+            self,
+            MidiLearnTarget::StopLayer | MidiLearnTarget::LayerVolume
+        )
+    }
+
+    /// The mapping's action; None for sound targets without a selected sound
+    pub fn action(self, selected_file: Option<&PathBuf>, layer_index: usize) -> Option<MidiAction> {
+        let play = |toggle| {
+            selected_file.map(|file_path| MidiAction::Play {
+                file_path: file_path.clone(),
+                layer_index: None,
+                toggle,
+            })
+        };
+        match self {
+            MidiLearnTarget::PlaySelected => play(false),
+            MidiLearnTarget::ToggleSelected => play(true),
+            MidiLearnTarget::StopLayer => Some(MidiAction::StopLayer { layer_index }),
+            MidiLearnTarget::StopAll => Some(MidiAction::StopAll),
+            MidiLearnTarget::LayerVolume => Some(MidiAction::LayerVolume { layer_index }),
+            MidiLearnTarget::Volume => Some(MidiAction::Volume),
+            MidiLearnTarget::Gain => Some(MidiAction::Gain),
+            MidiLearnTarget::MicGain => Some(MidiAction::MicGain),
+        }
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct AppState {
     pub search_query: String,
@@ -64,6 +136,15 @@ pub struct AppState {
     /// Currently recording hotkey (if any)
     pub recording_hotkey: Option<HotkeyRecording>,
 
+    /// MIDI mappings from the daemon config
+    pub midi_mappings: Vec<MidiMapping>,
+    /// Action for the next learned MIDI control
+    pub midi_learn_target: MidiLearnTarget,
+    /// Layer for layer targets
+    pub midi_learn_layer: usize,
+    /// Outcome of the last learn or mapping change
+    pub midi_status: Option<String>,
+
     /// Currently selected category (if viewing a category instead of a directory)
     pub current_category: Option<String>,
     /// Whether the "new category" dialog is open
@@ -0,0 +1,74 @@
+// Shell hooks, stored in daemon.json under `hooks`: commands run when
+// something happens in the daemon
+
+use crate::types::payload::ErrorCode;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// When a hook runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    /// The main player started a sound; resuming from pause doesn't count
+    Play,
+    /// The main player stopped
+    Stop,
+    /// A layer started a sound
+    LayerPlay,
+    /// A looping sound on the main player wrapped around
+    LoopEnd,
+    /// The input or output device changed
+    DeviceChanged,
+    /// Something failed in the background
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hook {
+    pub on: HookEvent,
+    /// Run by `sh -c`, or `cmd /C` on Windows
+    pub command: String,
+}
+
+/// What happened, passed to hooks as JSON on stdin and as `SOUNDBOARD_*`
+/// environment variables named after the fields
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum HookData {
+    Play {
+        file_path: PathBuf,
+        duration: f32,
+        looped: bool,
+    },
+    Stop {
+        file_path: Option<PathBuf>,
+    },
+    LayerPlay {
+        layer_index: usize,
+        file_path: PathBuf,
+    },
+    LoopEnd {
+        file_path: PathBuf,
+    },
+    DeviceChanged {
+        input: Option<String>,
+        output: Option<String>,
+    },
+    Error {
+        error_code: ErrorCode,
+        error_message: String,
+    },
+}
+
+impl HookData {
+    pub fn event(&self) -> HookEvent {
+        match self {
+            HookData::Play { .. } => HookEvent::Play,
+            HookData::Stop { .. } => HookEvent::Stop,
+            HookData::LayerPlay { .. } => HookEvent::LayerPlay,
+            HookData::LoopEnd { .. } => HookEvent::LoopEnd,
+            HookData::DeviceChanged { .. } => HookEvent::DeviceChanged,
+            HookData::Error { .. } => HookEvent::Error,
+        }
+    }
+}
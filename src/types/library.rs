@@ -0,0 +1,28 @@
+// The sound library as the GUI shows it, for clients that can't read gui.json
+// themselves, like the web control panel
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// A sound, named as the GUI names it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibrarySound {
+    pub file_path: PathBuf,
+    /// The sound's `custom_name`, or its file name
+    pub name: String,
+    pub favorite: bool,
+}
+
+/// A playlist and its sounds, in order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LibraryPlaylist {
+    pub name: String,
+    pub sounds: Vec<LibrarySound>,
+}
+
+/// "All Sounds" and "Favourites" first, then the user's playlists in the
+/// GUI's order. Sounds whose files are gone are left out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Library {
+    pub playlists: Vec<LibraryPlaylist>,
+}
@@ -0,0 +1,134 @@
+use crate::types::{audio_player::NUM_AUDIO_LAYERS, config::HotkeyBinding};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, path::PathBuf};
+
+/// A single action in a macro. `layer_index` of None targets the main player.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum MacroStep {
+    /// Play a file on the main player
+    Play { file_path: PathBuf },
+    /// Play a file on a layer
+    PlayOnLayer {
+        layer_index: usize,
+        file_path: PathBuf,
+    },
+    /// Pause the macro
+    Wait { seconds: f32 },
+    /// Stop the main player or a layer
+    Stop {
+        #[serde(default)]
+        layer_index: Option<usize>,
+    },
+    /// Set the volume (0.0 to 1.0) of the main player or a layer
+    SetVolume {
+        #[serde(default)]
+        layer_index: Option<usize>,
+        volume: f32,
+    },
+    /// Ramp the volume of the main player or a layer to `volume` over `seconds`
+    Fade {
+        #[serde(default)]
+        layer_index: Option<usize>,
+        volume: f32,
+        seconds: f32,
+    },
+}
+
+impl MacroStep {
+    fn layer_index(&self) -> Option<usize> {
+        match self {
+            MacroStep::PlayOnLayer { layer_index, .. } => Some(*layer_index),
+            MacroStep::Stop { layer_index }
+            | MacroStep::SetVolume { layer_index, .. }
+            | MacroStep::Fade { layer_index, .. } => *layer_index,
+            MacroStep::Play { .. } | MacroStep::Wait { .. } => None,
+        }
+    }
+
+    fn seconds(&self) -> Option<f32> {
+        match self {
+            MacroStep::Wait { seconds } | MacroStep::Fade { seconds, .. } => Some(*seconds),
+            _ => None,
+        }
+    }
+}
+
+/// A scripted sequence of steps triggered as one sound
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct SoundMacro {
+    pub steps: Vec<MacroStep>,
+    /// Global hotkey that runs the macro from the GUI
+    #[serde(default)]
+    pub hotkey: Option<HotkeyBinding>,
+}
+
+impl SoundMacro {
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.steps.is_empty() {
+            return Err("Macro has no steps".into());
+        }
+
+        for (i, step) in self.steps.iter().enumerate() {
+            if let Some(layer) = step.layer_index().filter(|l| *l >= NUM_AUDIO_LAYERS) {
+                return Err(format!("Step {}: invalid layer index: {}", i + 1, layer).into());
+            }
+            if let Some(seconds) = step.seconds().filter(|s| !s.is_finite() || *s < 0.0) {
+                return Err(format!("Step {}: invalid duration: {}", i + 1, seconds).into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_macro_json_shape() {
+        let json = r#"{
+            "steps": [
+                {"action": "play_on_layer", "layer_index": 1, "file_path": "/sounds/drumroll.ogg"},
+                {"action": "wait", "seconds": 2.0},
+                {"action": "play", "file_path": "/sounds/rimshot.ogg"},
+                {"action": "fade", "layer_index": 0, "volume": 0.2, "seconds": 0.3},
+                {"action": "stop"}
+            ]
+        }"#;
+        let sound_macro: SoundMacro = serde_json::from_str(json).unwrap();
+
+        assert_eq!(sound_macro.steps.len(), 5);
+        assert_eq!(sound_macro.hotkey, None);
+        assert_eq!(
+            sound_macro.steps[0],
+            MacroStep::PlayOnLayer {
+                layer_index: 1,
+                file_path: PathBuf::from("/sounds/drumroll.ogg"),
+            }
+        );
+        assert_eq!(sound_macro.steps[4], MacroStep::Stop { layer_index: None });
+        assert!(sound_macro.validate().is_ok());
+    }
+
+    #[test]
+    fn test_macro_validation() {
+        assert!(SoundMacro::default().validate().is_err());
+
+        let invalid_layer = SoundMacro {
+            steps: vec![MacroStep::SetVolume {
+                layer_index: Some(NUM_AUDIO_LAYERS),
+                volume: 0.5,
+            }],
+            hotkey: None,
+        };
+        assert!(invalid_layer.validate().is_err());
+
+        let negative_wait = SoundMacro {
+            steps: vec![MacroStep::Wait { seconds: -1.0 }],
+            hotkey: None,
+        };
+        assert!(negative_wait.validate().is_err());
+    }
+}
@@ -0,0 +1,246 @@
+// MIDI controller mappings, stored in daemon.json under `midi`, and the
+// channel messages they react to
+
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::PathBuf};
+
+/// Which message a mapping reacts to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MidiTrigger {
+    /// A pad or key
+    Note { note: u8 },
+    /// A knob, fader or CC button
+    Control { controller: u8 },
+}
+
+impl fmt::Display for MidiTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiTrigger::Note { note } => write!(f, "Note {}", note),
+            MidiTrigger::Control { controller } => write!(f, "CC {}", controller),
+        }
+    }
+}
+
+/// What a mapping does
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum MidiAction {
+    /// Play on `layer_index`, or on any free layer. With `toggle`, pressing
+    /// again while the sound plays stops it.
+    Play {
+        file_path: PathBuf,
+        #[serde(default)]
+        layer_index: Option<usize>,
+        #[serde(default)]
+        toggle: bool,
+    },
+    StopLayer {
+        layer_index: usize,
+    },
+    /// The main player and every layer
+    StopAll,
+    /// The message's value sets the level
+    LayerVolume {
+        layer_index: usize,
+    },
+    Volume,
+    Gain,
+    MicGain,
+}
+
+impl MidiAction {
+    /// Whether the action follows the message's value instead of firing once
+    pub fn is_level(&self) -> bool {
+        matches!(
+            self,
+            MidiAction::LayerVolume { .. }
+                | MidiAction::Volume
+                | MidiAction::Gain
+                | MidiAction::MicGain
+        )
+    }
+}
+
+impl fmt::Display for MidiAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiAction::Play {
+                file_path, toggle, ..
+            } => {
+                let name = file_path
+                    .file_name()
+                    .map(|name| name.to_string_lossy())
+                    .unwrap_or_default();
+                let verb = if *toggle { "Play/stop" } else { "Play" };
+                write!(f, "{} {}", verb, name)
+            }
+            MidiAction::StopLayer { layer_index } => write!(f, "Stop layer {}", layer_index),
+            MidiAction::StopAll => write!(f, "Stop all"),
+            MidiAction::LayerVolume { layer_index } => {
+                write!(f, "Layer {} volume", layer_index)
+            }
+            MidiAction::Volume => write!(f, "Volume"),
+            MidiAction::Gain => write!(f, "Gain"),
+            MidiAction::MicGain => write!(f, "Mic gain"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MidiMapping {
+    /// 0-15; None matches every channel
+    #[serde(default)]
+    pub channel: Option<u8>,
+    pub trigger: MidiTrigger,
+    pub action: MidiAction,
+}
+
+impl MidiMapping {
+    pub fn matches(&self, message: &MidiMessage) -> bool {
+        self.channel
+            .is_none_or(|channel| channel == message.channel)
+            && message.trigger() == self.trigger
+    }
+
+    /// Whether `other` reacts to the same messages, so one replaces the other
+    pub fn same_trigger(&self, other: &MidiMapping) -> bool {
+        self.channel == other.channel && self.trigger == other.trigger
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiMessageKind {
+    NoteOn { note: u8, velocity: u8 },
+    NoteOff { note: u8 },
+    Control { controller: u8, value: u8 },
+}
+
+/// A channel message from a controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MidiMessage {
+    /// 0-15
+    pub channel: u8,
+    pub kind: MidiMessageKind,
+}
+
+impl MidiMessage {
+    /// Note and control change messages; everything else is None
+    pub fn parse(bytes: &[u8]) -> Option<MidiMessage> {
+        let (&status, data) = bytes.split_first()?;
+        let channel = status & 0x0F;
+        let kind = match (status & 0xF0, data) {
+            // Note on with velocity 0 is how many controllers send note off
+            (0x90, &[note, 0, ..]) | (0x80, &[note, _, ..]) => MidiMessageKind::NoteOff { note },
+            (0x90, &[note, velocity, ..]) => MidiMessageKind::NoteOn { note, velocity },
+            (0xB0, &[controller, value, ..]) => MidiMessageKind::Control { controller, value },
+            _ => return None,
+        };
+        Some(MidiMessage { channel, kind })
+    }
+
+    pub fn trigger(&self) -> MidiTrigger {
+        match self.kind {
+            MidiMessageKind::NoteOn { note, .. } | MidiMessageKind::NoteOff { note } => {
+                MidiTrigger::Note { note }
+            }
+            MidiMessageKind::Control { controller, .. } => MidiTrigger::Control { controller },
+        }
+    }
+
+    /// Velocity or controller value, 0-127
+    pub fn value(&self) -> u8 {
+        match self.kind {
+            MidiMessageKind::NoteOn { velocity, .. } => velocity,
+            MidiMessageKind::NoteOff { .. } => 0,
+            MidiMessageKind::Control { value, .. } => value,
+        }
+    }
+
+    /// Note on with `velocity` 0 turns a pad's LED off
+    pub fn note_on(channel: u8, note: u8, velocity: u8) -> [u8; 3] {
+        [0x90 | (channel & 0x0F), note & 0x7F, velocity & 0x7F]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_messages() {
+        assert_eq!(
+            MidiMessage::parse(&[0x91, 36, 100]),
+            Some(MidiMessage {
+                channel: 1,
+                kind: MidiMessageKind::NoteOn {
+                    note: 36,
+                    velocity: 100
+                },
+            })
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0x90, 36, 0]).map(|m| m.kind),
+            Some(MidiMessageKind::NoteOff { note: 36 })
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0x80, 36, 64]).map(|m| m.kind),
+            Some(MidiMessageKind::NoteOff { note: 36 })
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0xBF, 7, 127]),
+            Some(MidiMessage {
+                channel: 15,
+                kind: MidiMessageKind::Control {
+                    controller: 7,
+                    value: 127
+                },
+            })
+        );
+        // Pitch bend, clock and truncated messages are ignored
+        assert_eq!(MidiMessage::parse(&[0xE0, 0, 64]), None);
+        assert_eq!(MidiMessage::parse(&[0xF8]), None);
+        assert_eq!(MidiMessage::parse(&[0x90, 36]), None);
+    }
+
+    #[test]
+    fn test_mapping_matches_channel_and_trigger() {
+        let mapping = MidiMapping {
+            channel: Some(0),
+            trigger: MidiTrigger::Note { note: 36 },
+            action: MidiAction::StopAll,
+        };
+        let pad = |channel, note| MidiMessage {
+            channel,
+            kind: MidiMessageKind::NoteOn { note, velocity: 90 },
+        };
+        assert!(mapping.matches(&pad(0, 36)));
+        assert!(!mapping.matches(&pad(1, 36)));
+        assert!(!mapping.matches(&pad(0, 37)));
+
+        let any_channel = MidiMapping {
+            channel: None,
+            ..mapping
+        };
+        assert!(any_channel.matches(&pad(9, 36)));
+    }
+
+    #[test]
+    fn test_mapping_json_shape() {
+        let mapping: MidiMapping = serde_json::from_str(
+            r#"{"channel": 9, "trigger": {"type": "note", "note": 36},
+                "action": {"action": "play", "file_path": "/sounds/a.ogg", "toggle": true}}"#,
+        )
+        .expect("deserialize");
+        assert_eq!(
+            mapping.action,
+            MidiAction::Play {
+                file_path: PathBuf::from("/sounds/a.ogg"),
+                layer_index: None,
+                toggle: true,
+            }
+        );
+        assert_eq!(mapping.trigger.to_string(), "Note 36");
+    }
+}
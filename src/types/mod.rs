@@ -1,7 +1,20 @@
+pub mod audio_backend;
 pub mod audio_player;
+pub mod chat;
 pub mod commands;
 pub mod config;
+pub mod events;
 pub mod gui;
+pub mod hooks;
+pub mod library;
+pub mod macros;
+pub mod midi;
+pub mod obs;
+pub mod osc;
+pub mod payload;
 #[cfg(target_os = "linux")]
 pub mod pipewire;
+pub mod playlist;
+pub mod schedule;
 pub mod socket;
+pub mod timeline;
@@ -0,0 +1,111 @@
+// OBS rules, stored in daemon.json under `obs`: which OBS events play a sound
+// or run a macro
+
+use crate::types::socket::Command;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Something that happened in OBS
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ObsEvent {
+    /// The program scene changed. In a rule, no `scene` matches every scene.
+    SceneChanged {
+        #[serde(default)]
+        scene: Option<String>,
+    },
+    StreamStarted,
+    StreamStopped,
+    RecordingStarted,
+    RecordingStopped,
+}
+
+/// What a rule does
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ObsAction {
+    /// Play on `layer_index`, or on any free layer, so it mixes over music
+    Play {
+        file_path: PathBuf,
+        #[serde(default)]
+        layer_index: Option<usize>,
+    },
+    Macro {
+        name: String,
+    },
+}
+
+impl ObsAction {
+    pub fn command(&self) -> Command {
+        match self {
+            ObsAction::Play {
+                file_path,
+                layer_index,
+            } => Command::PlayOnLayer {
+                layer_index: *layer_index,
+                file_path: file_path.to_string_lossy().into_owned(),
+                priority: None,
+            },
+            ObsAction::Macro { name } => Command::RunMacro { name: name.clone() },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObsRule {
+    pub on: ObsEvent,
+    pub action: ObsAction,
+}
+
+impl ObsRule {
+    pub fn matches(&self, event: &ObsEvent) -> bool {
+        match (&self.on, event) {
+            (ObsEvent::SceneChanged { scene: None }, ObsEvent::SceneChanged { .. }) => true,
+            (on, event) => on == event,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(name: &str) -> ObsEvent {
+        ObsEvent::SceneChanged {
+            scene: Some(name.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_rule_matches() {
+        let rule: ObsRule = serde_json::from_str(
+            r#"{"on": {"event": "scene_changed", "scene": "BRB"},
+                "action": {"action": "play", "file_path": "/sounds/stinger.ogg"}}"#,
+        )
+        .expect("deserialize");
+        assert!(rule.matches(&scene("BRB")));
+        assert!(!rule.matches(&scene("Gameplay")));
+        assert!(!rule.matches(&ObsEvent::StreamStarted));
+
+        let any_scene = ObsRule {
+            on: ObsEvent::SceneChanged { scene: None },
+            ..rule
+        };
+        assert!(any_scene.matches(&scene("Gameplay")));
+
+        let stream = ObsRule {
+            on: ObsEvent::StreamStarted,
+            action: ObsAction::Macro {
+                name: "intro".to_string(),
+            },
+        };
+        assert!(stream.matches(&ObsEvent::StreamStarted));
+        assert!(!stream.matches(&ObsEvent::RecordingStarted));
+        assert_eq!(
+            stream.action.command(),
+            Command::RunMacro {
+                name: "intro".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,276 @@
+// Open Sound Control 1.0 packets, and their binary encoding. Bundle time
+// tags are read but not kept: the daemon runs everything as it arrives.
+
+use std::error::Error;
+
+const BUNDLE_TAG: &[u8] = b"#bundle\0";
+
+/// An OSC argument
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscType {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    /// Strings and symbols
+    String(String),
+    Blob(Vec<u8>),
+    Bool(bool),
+    /// Nil and impulse, which carry no data
+    Nil,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub addr: String,
+    pub args: Vec<OscType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscPacket {
+    Message(OscMessage),
+    Bundle(Vec<OscPacket>),
+}
+
+impl OscPacket {
+    /// Decode one datagram
+    pub fn decode(bytes: &[u8]) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if bytes.starts_with(BUNDLE_TAG) {
+            let mut reader = Reader {
+                bytes,
+                pos: BUNDLE_TAG.len(),
+            };
+            // Time tag
+            reader.take(8)?;
+            let mut content = Vec::new();
+            while !reader.is_empty() {
+                let size = usize::try_from(reader.int()?).map_err(|_| "Negative element size")?;
+                content.push(OscPacket::decode(reader.take(size)?)?);
+            }
+            return Ok(OscPacket::Bundle(content));
+        }
+
+        let mut reader = Reader { bytes, pos: 0 };
+        let addr = reader.string()?;
+        if !addr.starts_with('/') {
+            return Err(format!("Invalid OSC address '{}'", addr).into());
+        }
+        // Very old senders leave out the type tags when there are no arguments
+        let tags = if reader.is_empty() {
+            ",".to_string()
+        } else {
+            reader.string()?
+        };
+        let tags = tags.strip_prefix(',').ok_or("Missing OSC type tags")?;
+
+        let mut args = Vec::with_capacity(tags.len());
+        for tag in tags.chars() {
+            args.push(match tag {
+                'i' => OscType::Int(reader.int()?),
+                'h' => OscType::Long(i64::from_be_bytes(reader.array()?)),
+                'f' => OscType::Float(f32::from_be_bytes(reader.array()?)),
+                'd' => OscType::Double(f64::from_be_bytes(reader.array()?)),
+                's' | 'S' => OscType::String(reader.string()?),
+                'b' => {
+                    let size = usize::try_from(reader.int()?).map_err(|_| "Negative blob size")?;
+                    let blob = reader.take(size)?.to_vec();
+                    reader.take(padding(size))?;
+                    OscType::Blob(blob)
+                }
+                'T' => OscType::Bool(true),
+                'F' => OscType::Bool(false),
+                'N' | 'I' => OscType::Nil,
+                _ => return Err(format!("Unsupported OSC type tag '{}'", tag).into()),
+            });
+        }
+
+        Ok(OscPacket::Message(OscMessage { addr, args }))
+    }
+}
+
+impl OscMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_string(&mut bytes, &self.addr);
+
+        let mut tags = String::from(",");
+        for arg in &self.args {
+            tags.push(match arg {
+                OscType::Int(_) => 'i',
+                OscType::Long(_) => 'h',
+                OscType::Float(_) => 'f',
+                OscType::Double(_) => 'd',
+                OscType::String(_) => 's',
+                OscType::Blob(_) => 'b',
+                OscType::Bool(true) => 'T',
+                OscType::Bool(false) => 'F',
+                OscType::Nil => 'N',
+            });
+        }
+        write_string(&mut bytes, &tags);
+
+        for arg in &self.args {
+            match arg {
+                OscType::Int(value) => bytes.extend(value.to_be_bytes()),
+                OscType::Long(value) => bytes.extend(value.to_be_bytes()),
+                OscType::Float(value) => bytes.extend(value.to_be_bytes()),
+                OscType::Double(value) => bytes.extend(value.to_be_bytes()),
+                OscType::String(value) => write_string(&mut bytes, value),
+                OscType::Blob(blob) => {
+                    bytes.extend((blob.len() as i32).to_be_bytes());
+                    bytes.extend(blob);
+                    bytes.resize(bytes.len() + padding(blob.len()), 0);
+                }
+                OscType::Bool(_) | OscType::Nil => {}
+            }
+        }
+        bytes
+    }
+}
+
+/// Zero bytes after `len` bytes of data to reach a multiple of four
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+// Strings end with at least one null byte, then pad to four bytes
+fn write_string(bytes: &mut Vec<u8>, value: &str) {
+    bytes.extend(value.as_bytes());
+    bytes.resize(bytes.len() + 1 + padding(value.len() + 1), 0);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Box<dyn Error + Send + Sync>> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or("Truncated OSC packet")?;
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Box<dyn Error + Send + Sync>> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn int(&mut self) -> Result<i32, Box<dyn Error + Send + Sync>> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    fn string(&mut self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let rest = &self.bytes[self.pos.min(self.bytes.len())..];
+        let len = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or("Unterminated OSC string")?;
+        let value = std::str::from_utf8(&rest[..len])?.to_string();
+        self.take(len + 1 + padding(len + 1))?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(addr: &str, args: Vec<OscType>) -> OscMessage {
+        OscMessage {
+            addr: addr.to_string(),
+            args,
+        }
+    }
+
+    #[test]
+    fn test_message_encoding() {
+        // The example from the OSC 1.0 specification
+        let foo = message(
+            "/foo",
+            vec![
+                OscType::Int(1000),
+                OscType::Int(-1),
+                OscType::String("hello".into()),
+                OscType::Float(1.234),
+                OscType::Float(5.678),
+            ],
+        );
+        let bytes = foo.encode();
+        assert_eq!(&bytes[..16], b"/foo\0\0\0\0,iisff\0\0");
+        assert_eq!(bytes.len(), 40);
+        assert_eq!(&bytes[20..24], &[0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(&bytes[24..32], b"hello\0\0\0");
+        assert_eq!(OscPacket::decode(&bytes).unwrap(), OscPacket::Message(foo));
+    }
+
+    #[test]
+    fn test_roundtrip_all_types() {
+        let all = message(
+            "/soundboard/test",
+            vec![
+                OscType::Long(-5),
+                OscType::Double(0.25),
+                OscType::String(String::new()),
+                OscType::Blob(vec![1, 2, 3, 4, 5]),
+                OscType::Bool(true),
+                OscType::Bool(false),
+                OscType::Nil,
+            ],
+        );
+        let bytes = all.encode();
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(OscPacket::decode(&bytes).unwrap(), OscPacket::Message(all));
+    }
+
+    #[test]
+    fn test_decode_nested_bundle() {
+        let stop = message("/soundboard/stop", vec![]).encode();
+        let pause = message("/soundboard/pause", vec![]).encode();
+
+        let bundle = |elements: &[&[u8]]| {
+            let mut bytes = BUNDLE_TAG.to_vec();
+            bytes.extend([0, 0, 0, 0, 0, 0, 0, 1]);
+            for element in elements {
+                bytes.extend((element.len() as i32).to_be_bytes());
+                bytes.extend(*element);
+            }
+            bytes
+        };
+        let inner = bundle(&[&pause]);
+        let outer = bundle(&[&stop, &inner]);
+
+        assert_eq!(
+            OscPacket::decode(&outer).unwrap(),
+            OscPacket::Bundle(vec![
+                OscPacket::Message(message("/soundboard/stop", vec![])),
+                OscPacket::Bundle(vec![OscPacket::Message(message(
+                    "/soundboard/pause",
+                    vec![]
+                ))]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_decode_malformed() {
+        assert!(OscPacket::decode(b"").is_err());
+        assert!(OscPacket::decode(b"/foo").is_err());
+        assert!(OscPacket::decode(b"foo\0,\0\0\0").is_err());
+        assert!(OscPacket::decode(b"/foo\0\0\0\0,i\0\0").is_err());
+        assert!(OscPacket::decode(b"/foo\0\0\0\0,x\0\0").is_err());
+        // No type tags at all
+        assert_eq!(
+            OscPacket::decode(b"/foo\0\0\0\0").unwrap(),
+            OscPacket::Message(message("/foo", vec![]))
+        );
+    }
+}
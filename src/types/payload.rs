@@ -0,0 +1,304 @@
+// Schemas of the structured `data` carried by responses. Scalar getters
+// (volume, gain, position, duration, loop, paused) answer with a bare JSON
+// value, player state with a `PlayerState` and layers with `LayerInfo`s.
+// Failed requests carry a `DaemonError`, failed batches a `BatchFailed`.
+
+use crate::{
+    types::{midi::MidiTrigger, playlist::PlaylistMode, socket::Response},
+    utils::voices::StolenVoice,
+};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt, path::PathBuf};
+
+/// An audio input or output device
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Device {
+    /// Name used to select the device
+    pub id: String,
+    /// Human readable name
+    pub name: String,
+}
+
+/// The device the daemon is using
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CurrentDevice {
+    pub id: String,
+    pub name: String,
+    /// The device was picked by following the system default
+    pub follows_default: bool,
+}
+
+/// A sound that started playing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayStarted {
+    pub file_path: PathBuf,
+    /// None for the main player
+    pub layer_index: Option<usize>,
+    pub priority: u8,
+    /// The sound that was cut off to make room, if any
+    pub replaced: Option<StolenVoice>,
+}
+
+/// A sound that started playing on the preview output
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PreviewStarted {
+    pub file_path: PathBuf,
+}
+
+/// A layer's new volume
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerVolume {
+    pub layer_index: usize,
+    pub volume: f32,
+}
+
+/// A macro that started running
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MacroStarted {
+    pub name: String,
+    pub steps: usize,
+    /// The macro was already running and started over
+    pub restarted: bool,
+}
+
+/// The playlist being played
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActivePlaylist {
+    pub name: String,
+    pub mode: PlaylistMode,
+}
+
+/// A playlist that started playing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlaylistStarted {
+    pub name: String,
+    pub mode: PlaylistMode,
+    /// The first sound
+    pub file_path: PathBuf,
+}
+
+/// The control a `midi_learn` caught
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MidiLearned {
+    pub channel: u8,
+    pub trigger: MidiTrigger,
+}
+
+/// Why a request failed. The codes are stable; scripts can rely on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// A bad or missing argument
+    InvalidArg,
+    /// The file, device, macro, playlist, route or schedule doesn't exist
+    NotFound,
+    /// The request needs a sound to be playing
+    NotPlaying,
+    /// An audio device or PipeWire failed
+    DeviceError,
+    /// A cooldown or rate limit blocked the play
+    RateLimited,
+    /// No layer is free, or a more important sound is playing
+    Busy,
+    /// The sound couldn't be decoded or played
+    PlaybackError,
+    /// Not available on this platform or audio backend
+    Unsupported,
+    /// Reading or saving a config file failed
+    ConfigError,
+    /// The daemon doesn't know the command
+    UnknownCommand,
+    /// The request isn't valid JSON or uses an unsupported protocol version
+    InvalidRequest,
+    /// Anything else
+    #[default]
+    Internal,
+}
+
+/// CLI exit code when the daemon couldn't be reached. 1 is left to other
+/// failures and 2 to usage errors, as clap reports them.
+pub const CONNECTION_EXIT_CODE: i32 = 3;
+
+impl ErrorCode {
+    /// Process exit code used by the CLI
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorCode::Internal => 4,
+            ErrorCode::InvalidArg => 5,
+            ErrorCode::NotFound => 6,
+            ErrorCode::NotPlaying => 7,
+            ErrorCode::DeviceError => 8,
+            ErrorCode::RateLimited => 9,
+            ErrorCode::Busy => 10,
+            ErrorCode::PlaybackError => 11,
+            ErrorCode::Unsupported => 12,
+            ErrorCode::ConfigError => 13,
+            ErrorCode::UnknownCommand => 14,
+            ErrorCode::InvalidRequest => 15,
+        }
+    }
+}
+
+/// A failed request: a stable code for programs and a message for humans
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DaemonError {
+    /// Missing from daemons that predate error codes
+    #[serde(default)]
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl DaemonError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        DaemonError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for DaemonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for DaemonError {}
+
+/// A batch that stopped at a failing command. The error fields sit at the
+/// top level, so it also reads as a `DaemonError`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchFailed {
+    #[serde(flatten)]
+    pub error: DaemonError,
+    /// Index of the failing command, counting from 0
+    pub index: usize,
+    /// Responses of the commands before it, which stay applied
+    pub responses: Vec<Response>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_names_survive_separators() {
+        // Names that broke the old "name - nick; ..." string format
+        let devices = vec![
+            Device {
+                id: "alsa_input.usb-Mic_-_Pro".to_string(),
+                name: "Mic - Pro; USB".to_string(),
+            },
+            Device {
+                id: "hdmi;1".to_string(),
+                name: "HDMI - 1".to_string(),
+            },
+        ];
+        let json = serde_json::to_string(&devices).unwrap();
+        let parsed: Vec<Device> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, devices);
+    }
+
+    #[test]
+    fn test_error_codes_are_stable() {
+        let error = DaemonError::new(ErrorCode::NotFound, "Macro 'x' not found");
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["code"], "NOT_FOUND");
+        assert_eq!(
+            serde_json::to_value(ErrorCode::RateLimited).unwrap(),
+            "RATE_LIMITED"
+        );
+
+        // Errors from daemons without codes
+        let error: DaemonError = serde_json::from_str(r#"{"message":"oops"}"#).unwrap();
+        assert_eq!(error.code, ErrorCode::Internal);
+    }
+
+    #[test]
+    fn test_started_payloads_keep_their_shape() {
+        let started = PlaylistStarted {
+            name: "Ambience".to_string(),
+            mode: PlaylistMode::Shuffle,
+            file_path: PathBuf::from("/sounds/rain.ogg"),
+        };
+        let json = serde_json::to_value(&started).unwrap();
+        assert_eq!(json["name"], "Ambience");
+        assert_eq!(json["file_path"], "/sounds/rain.ogg");
+        assert_eq!(
+            json["mode"],
+            serde_json::to_value(PlaylistMode::Shuffle).unwrap()
+        );
+
+        let json = serde_json::to_value(MacroStarted {
+            name: "intro".to_string(),
+            steps: 3,
+            restarted: false,
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"name": "intro", "steps": 3, "restarted": false})
+        );
+    }
+
+    #[test]
+    fn test_batch_failed_reads_as_daemon_error() {
+        let failed = BatchFailed {
+            error: DaemonError::new(ErrorCode::NotFound, "File not found"),
+            index: 1,
+            responses: vec![Response::new(true, "Audio was stopped")],
+        };
+        let response =
+            Response::new(false, "Batch stopped at command 2: File not found").with_data(&failed);
+
+        let error = response.daemon_error().unwrap();
+        assert_eq!(error.code, ErrorCode::NotFound);
+        assert_eq!(error.message, "File not found");
+
+        let parsed: BatchFailed = serde_json::from_value(response.data.unwrap()).unwrap();
+        assert_eq!(parsed.index, 1);
+        assert_eq!(parsed.responses.len(), 1);
+        assert!(parsed.responses[0].status);
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let codes = [
+            ErrorCode::InvalidArg,
+            ErrorCode::NotFound,
+            ErrorCode::NotPlaying,
+            ErrorCode::DeviceError,
+            ErrorCode::RateLimited,
+            ErrorCode::Busy,
+            ErrorCode::PlaybackError,
+            ErrorCode::Unsupported,
+            ErrorCode::ConfigError,
+            ErrorCode::UnknownCommand,
+            ErrorCode::InvalidRequest,
+            ErrorCode::Internal,
+        ];
+        let mut exit_codes: Vec<i32> = codes.iter().map(|code| code.exit_code()).collect();
+        exit_codes.sort();
+        exit_codes.dedup();
+        assert_eq!(exit_codes.len(), codes.len());
+        // 0 is success, 1 a CLI failure and 2 a usage error
+        assert!(exit_codes.iter().all(|&code| code > 2));
+        assert!(!exit_codes.contains(&CONNECTION_EXIT_CODE));
+    }
+
+    #[test]
+    fn test_play_started_schema() {
+        let started = PlayStarted {
+            file_path: PathBuf::from("/sounds/alert.ogg"),
+            layer_index: Some(2),
+            priority: 10,
+            replaced: Some(StolenVoice {
+                file_path: Some(PathBuf::from("/sounds/rain.ogg")),
+                priority: 0,
+            }),
+        };
+        let json = serde_json::to_value(&started).unwrap();
+        assert_eq!(json["layer_index"], 2);
+        assert_eq!(json["replaced"]["file_path"], "/sounds/rain.ogg");
+    }
+}
@@ -1,5 +1,57 @@
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+/// Identifier of a link pair owned by the PipeWire manager thread
+pub type LinkId = u64;
+
+/// Reply channel for manager commands. Errors are sent as strings because
+/// `Box<dyn Error>` is not `Send`.
+pub type PwReply<T> = oneshot::Sender<Result<T, String>>;
+
+/// Commands accepted by the long-lived PipeWire manager thread
 #[derive(Debug)]
-pub struct Terminate {}
+pub enum PwCommand {
+    /// Create the soundboard virtual microphone node
+    CreateVirtualMic { reply: PwReply<()> },
+    /// Link two stereo port pairs (FL -> FL, FR -> FR)
+    CreateLink {
+        id: LinkId,
+        output_fl: Port,
+        output_fr: Port,
+        input_fl: Port,
+        input_fr: Port,
+        reply: PwReply<()>,
+    },
+    /// Destroy a link pair previously created with `CreateLink`
+    DestroyLink(LinkId),
+    /// Keep a stream on the node named `target` through the `default`
+    /// metadata, or let the session manager move it with the default again
+    /// for `None`
+    SetStreamTarget {
+        node_id: u32,
+        target: Option<String>,
+        reply: PwReply<()>,
+    },
+    /// Round-trip with the server so that every object created before this
+    /// command is visible in the registry mirror
+    Sync { reply: PwReply<()> },
+    /// Quit the manager's main loop
+    Terminate,
+}
+
+/// Owned link pair. The links are destroyed when the handle is dropped.
+pub struct LinkHandle {
+    pub(crate) id: LinkId,
+    pub(crate) sender: pipewire::channel::Sender<PwCommand>,
+}
+
+impl Drop for LinkHandle {
+    fn drop(&mut self) {
+        if self.sender.send(PwCommand::DestroyLink(self.id)).is_err() {
+            tracing::error!("Failed to send destroy signal for link {}", self.id);
+        }
+    }
+}
 
 #[derive(Debug, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct Port {
@@ -29,3 +81,28 @@ pub struct AudioDevice {
     pub output_fl: Option<Port>,
     pub output_fr: Option<Port>,
 }
+
+/// An application's capture stream (`Stream/Input/Audio`), e.g. Discord's mic input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppStream {
+    pub id: u32,
+    pub node_name: String,
+    pub application_name: Option<String>,
+    pub process_binary: Option<String>,
+
+    #[serde(skip)]
+    pub input_fl: Option<Port>,
+    #[serde(skip)]
+    pub input_fr: Option<Port>,
+}
+
+/// System default devices from the PipeWire `default` metadata
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DefaultDevices {
+    /// `node.name` of the default source (`default.audio.source`)
+    pub source: Option<String>,
+    /// `node.name` of the default sink (`default.audio.sink`)
+    pub sink: Option<String>,
+    /// Description of the default sink, if the sink is in the registry
+    pub sink_description: Option<String>,
+}
@@ -0,0 +1,242 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// How a playlist is played
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaylistMode {
+    /// Play the whole list once, in order
+    #[default]
+    Sequential,
+    /// Play the whole list once, in random order
+    Shuffle,
+    /// Play the list in order, forever
+    RepeatAll,
+    /// Keep repeating the first sound
+    RepeatOne,
+    /// Play the next sound on each trigger
+    RoundRobin,
+    /// Play a random sound on each trigger
+    Random,
+}
+
+impl PlaylistMode {
+    pub const ALL: [PlaylistMode; 6] = [
+        PlaylistMode::Sequential,
+        PlaylistMode::Shuffle,
+        PlaylistMode::RepeatAll,
+        PlaylistMode::RepeatOne,
+        PlaylistMode::RoundRobin,
+        PlaylistMode::Random,
+    ];
+
+    /// Continuous modes move on to the next sound when one finishes; the
+    /// others play a single sound per trigger
+    pub fn is_continuous(self) -> bool {
+        !matches!(self, PlaylistMode::RoundRobin | PlaylistMode::Random)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PlaylistMode::Sequential => "sequential",
+            PlaylistMode::Shuffle => "shuffle",
+            PlaylistMode::RepeatAll => "repeat_all",
+            PlaylistMode::RepeatOne => "repeat_one",
+            PlaylistMode::RoundRobin => "round_robin",
+            PlaylistMode::Random => "random",
+        }
+    }
+
+    /// Format as human-readable string
+    pub fn display(self) -> &'static str {
+        match self {
+            PlaylistMode::Sequential => "Sequential",
+            PlaylistMode::Shuffle => "Shuffle",
+            PlaylistMode::RepeatAll => "Repeat all",
+            PlaylistMode::RepeatOne => "Repeat one",
+            PlaylistMode::RoundRobin => "Round-robin",
+            PlaylistMode::Random => "Random",
+        }
+    }
+}
+
+impl FromStr for PlaylistMode {
+    type Err = String;
+
+    /// Accepts "repeat_all", "repeat-all" and "RepeatAll" alike
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .trim()
+            .chars()
+            .filter(|c| *c != '_' && *c != '-')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+
+        PlaylistMode::ALL
+            .into_iter()
+            .find(|mode| mode.as_str().replace('_', "") == normalized)
+            .ok_or_else(|| format!("Unknown playlist mode: {}", s))
+    }
+}
+
+/// Position of the daemon in a playlist
+#[derive(Debug, Clone, Default)]
+pub struct PlaylistCursor {
+    /// Play order, as indices into the playlist
+    order: Vec<usize>,
+    /// Position in `order` of the next sound
+    position: usize,
+    /// Index of the sound picked last
+    last: Option<usize>,
+}
+
+impl PlaylistCursor {
+    /// Start a new pass over a playlist of `len` sounds
+    pub fn restart(&mut self, mode: PlaylistMode, len: usize, rng: &mut fastrand::Rng) {
+        self.order = (0..len).collect();
+        if mode == PlaylistMode::Shuffle {
+            rng.shuffle(&mut self.order);
+        }
+        self.position = 0;
+        self.last = None;
+    }
+
+    /// Index of the next sound to play, or None once a pass of a
+    /// non-repeating mode is over
+    pub fn advance(
+        &mut self,
+        mode: PlaylistMode,
+        len: usize,
+        rng: &mut fastrand::Rng,
+    ) -> Option<usize> {
+        if len == 0 {
+            return None;
+        }
+        // The playlist was edited since the cursor was set up
+        if self.order.len() != len {
+            self.restart(mode, len, rng);
+        }
+
+        let index = match mode {
+            PlaylistMode::Sequential | PlaylistMode::Shuffle => {
+                let index = *self.order.get(self.position)?;
+                self.position += 1;
+                index
+            }
+            PlaylistMode::RepeatAll | PlaylistMode::RoundRobin => {
+                if self.position >= len {
+                    self.position = 0;
+                }
+                let index = self.order[self.position];
+                self.position += 1;
+                index
+            }
+            PlaylistMode::RepeatOne => self.last.filter(|last| *last < len).unwrap_or(0),
+            // Never the same sound twice in a row
+            PlaylistMode::Random => match self.last {
+                Some(last) if len > 1 && last < len => {
+                    let index = rng.usize(..len - 1);
+                    if index >= last {
+                        index + 1
+                    } else {
+                        index
+                    }
+                }
+                _ => rng.usize(..len),
+            },
+        };
+
+        self.last = Some(index);
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn picks(mode: PlaylistMode, len: usize, count: usize) -> Vec<Option<usize>> {
+        let mut rng = fastrand::Rng::with_seed(7);
+        let mut cursor = PlaylistCursor::default();
+        cursor.restart(mode, len, &mut rng);
+        (0..count)
+            .map(|_| cursor.advance(mode, len, &mut rng))
+            .collect()
+    }
+
+    #[test]
+    fn test_playlist_mode_from_str() {
+        for mode in PlaylistMode::ALL {
+            assert_eq!(mode.as_str().parse::<PlaylistMode>(), Ok(mode));
+        }
+        assert_eq!("round-robin".parse(), Ok(PlaylistMode::RoundRobin));
+        assert_eq!("RepeatAll".parse(), Ok(PlaylistMode::RepeatAll));
+        assert!("backwards".parse::<PlaylistMode>().is_err());
+    }
+
+    #[test]
+    fn test_playlist_mode_json_shape() {
+        let json = serde_json::to_string(&PlaylistMode::RoundRobin).unwrap();
+        assert_eq!(json, "\"round_robin\"");
+    }
+
+    #[test]
+    fn test_sequential_plays_once() {
+        assert_eq!(
+            picks(PlaylistMode::Sequential, 3, 4),
+            vec![Some(0), Some(1), Some(2), None]
+        );
+    }
+
+    #[test]
+    fn test_repeat_all_and_round_robin_wrap() {
+        let expected = vec![Some(0), Some(1), Some(0), Some(1), Some(0)];
+        assert_eq!(picks(PlaylistMode::RepeatAll, 2, 5), expected);
+        assert_eq!(picks(PlaylistMode::RoundRobin, 2, 5), expected);
+    }
+
+    #[test]
+    fn test_repeat_one_stays_on_first() {
+        assert_eq!(picks(PlaylistMode::RepeatOne, 3, 3), vec![Some(0); 3]);
+    }
+
+    #[test]
+    fn test_shuffle_plays_each_sound_once() {
+        let mut played: Vec<usize> = picks(PlaylistMode::Shuffle, 5, 5)
+            .into_iter()
+            .map(Option::unwrap)
+            .collect();
+        played.sort();
+        assert_eq!(played, vec![0, 1, 2, 3, 4]);
+        assert_eq!(picks(PlaylistMode::Shuffle, 5, 6)[5], None);
+    }
+
+    #[test]
+    fn test_random_never_repeats_back_to_back() {
+        let played = picks(PlaylistMode::Random, 3, 50);
+        for pair in played.windows(2) {
+            assert_ne!(pair[0], pair[1]);
+        }
+        assert!(played.iter().all(|p| p.is_some_and(|i| i < 3)));
+        assert_eq!(picks(PlaylistMode::Random, 1, 2), vec![Some(0), Some(0)]);
+    }
+
+    #[test]
+    fn test_cursor_restarts_when_playlist_changes() {
+        let mut rng = fastrand::Rng::with_seed(1);
+        let mut cursor = PlaylistCursor::default();
+        assert_eq!(
+            cursor.advance(PlaylistMode::RoundRobin, 3, &mut rng),
+            Some(0)
+        );
+        assert_eq!(
+            cursor.advance(PlaylistMode::RoundRobin, 3, &mut rng),
+            Some(1)
+        );
+        assert_eq!(
+            cursor.advance(PlaylistMode::RoundRobin, 4, &mut rng),
+            Some(0)
+        );
+        assert_eq!(cursor.advance(PlaylistMode::RoundRobin, 0, &mut rng), None);
+    }
+}
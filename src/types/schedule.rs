@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// A sound the daemon plays at a set time, optionally repeating
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ScheduledPlay {
+    pub id: u64,
+    pub file_path: PathBuf,
+    /// Layer to play on (None = main player)
+    #[serde(default)]
+    pub layer_index: Option<usize>,
+    /// Unix time (seconds) of the next play
+    pub next_run: u64,
+    /// Repeat every this many seconds (None = play once)
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+    /// Optional name shown when listing schedules (e.g. "hydration")
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+impl ScheduledPlay {
+    pub fn is_due(&self, now: u64) -> bool {
+        self.next_run <= now
+    }
+
+    /// Move a recurring schedule to its first run after `now`, skipping any
+    /// runs that were missed. Returns false for one-shot schedules, which
+    /// are finished once they have played.
+    pub fn reschedule(&mut self, now: u64) -> bool {
+        match self.interval_secs.filter(|i| *i > 0) {
+            Some(interval) => {
+                if self.next_run <= now {
+                    let missed = (now - self.next_run) / interval + 1;
+                    self.next_run += missed * interval;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(next_run: u64, interval_secs: Option<u64>) -> ScheduledPlay {
+        ScheduledPlay {
+            id: 1,
+            file_path: PathBuf::from("/sounds/water.ogg"),
+            layer_index: None,
+            next_run,
+            interval_secs,
+            label: None,
+        }
+    }
+
+    #[test]
+    fn test_schedule_is_due() {
+        let s = schedule(100, None);
+        assert!(!s.is_due(99));
+        assert!(s.is_due(100));
+        assert!(s.is_due(101));
+    }
+
+    #[test]
+    fn test_one_shot_is_not_rescheduled() {
+        let mut s = schedule(100, None);
+        assert!(!s.reschedule(100));
+    }
+
+    #[test]
+    fn test_recurring_reschedule_skips_missed_runs() {
+        let mut s = schedule(100, Some(30));
+        assert!(s.reschedule(100));
+        assert_eq!(s.next_run, 130);
+
+        // Daemon was down for a while
+        assert!(s.reschedule(200));
+        assert_eq!(s.next_run, 220);
+
+        // Not due yet, nothing changes
+        assert!(s.reschedule(210));
+        assert_eq!(s.next_run, 220);
+    }
+
+    #[test]
+    fn test_schedule_json_defaults() {
+        let json = r#"{"id": 3, "file_path": "/sounds/a.ogg", "next_run": 1700000000}"#;
+        let s: ScheduledPlay = serde_json::from_str(json).unwrap();
+        assert_eq!(s.layer_index, None);
+        assert_eq!(s.interval_secs, None);
+        assert_eq!(s.label, None);
+    }
+}
@@ -1,171 +1,583 @@
-use serde::{Deserialize, Serialize};
+use crate::types::{
+    midi::{MidiMapping, MidiTrigger},
+    payload::{DaemonError, ErrorCode},
+    playlist::PlaylistMode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+/// Version of the typed protocol spoken by this build
+pub const PROTOCOL_VERSION: u32 = 2;
+/// Oldest protocol version the daemon still accepts. Version 1 is the
+/// legacy `{name, args}` shape, accepted for one more release.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// A command sent to the daemon, tagged by its `command` field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    /// Handshake: the daemon answers with its protocol version and capabilities
+    Hello {
+        client: Option<String>,
+    },
+    /// Keep the connection open and push `types::events::Event`s
+    Subscribe,
+    /// Run commands in order while holding the player, so nothing else
+    /// happens in between. Stops at the first failure, answering with a
+    /// `BatchFailed`.
+    Batch {
+        commands: Vec<Command>,
+    },
+    Ping,
+    Pause,
+    Resume,
+    TogglePause,
+    Stop,
+    Play {
+        file_path: String,
+        priority: Option<u8>,
+    },
+    Preview {
+        file_path: String,
+    },
+    IsPaused,
+    GetVolume,
+    SetVolume {
+        volume: f32,
+    },
+    GetGain,
+    SetGain {
+        gain: f32,
+    },
+    GetMicGain,
+    SetMicGain {
+        mic_gain: f32,
+    },
+    GetPosition,
+    Seek {
+        position: f32,
+    },
+    GetDuration,
+    GetState,
+    GetCurrentFilePath,
+    GetInput,
+    GetInputs,
+    SetInput {
+        input_name: String,
+    },
+    GetOutput,
+    GetOutputs,
+    SetOutput {
+        output_name: String,
+    },
+    GetLoop,
+    SetLoop {
+        enabled: bool,
+    },
+    ToggleLoop,
+    SetFollowDefaultInput {
+        enabled: bool,
+    },
+    SetFollowDefaultOutput {
+        enabled: bool,
+    },
+    /// No `layer_index` means any layer
+    PlayOnLayer {
+        layer_index: Option<usize>,
+        file_path: String,
+        priority: Option<u8>,
+    },
+    StopLayer {
+        layer_index: usize,
+    },
+    StopAllLayers,
+    SetLayerVolume {
+        layer_index: usize,
+        volume: f32,
+    },
+    GetLayersInfo,
+    GetAppStreams,
+    GetAppRoutes,
+    AddAppRoute {
+        application_name: Option<String>,
+        process_binary: Option<String>,
+    },
+    RemoveAppRoute {
+        application_name: Option<String>,
+        process_binary: Option<String>,
+    },
+    RunMacro {
+        name: String,
+    },
+    CancelMacro {
+        name: String,
+    },
+    GetRunningMacros,
+    SchedulePlay {
+        file_path: String,
+        layer_index: Option<usize>,
+        delay: Option<String>,
+        at: Option<String>,
+        every: Option<String>,
+        label: Option<String>,
+    },
+    ListSchedules,
+    CancelSchedule {
+        id: u64,
+    },
+    PlayPlaylist {
+        name: String,
+        mode: Option<PlaylistMode>,
+    },
+    GetPlaylist,
+    /// Playlists, favorites and sound names from the GUI's config
+    GetLibrary,
+    GetMidiMappings,
+    /// Replaces the mapping with the same channel and trigger, if any
+    AddMidiMapping {
+        mapping: MidiMapping,
+    },
+    RemoveMidiMapping {
+        channel: Option<u8>,
+        trigger: MidiTrigger,
+    },
+    /// Wait for the next pad press or knob turn and answer with it instead
+    /// of running its mapping
+    MidiLearn,
+}
+
+impl Command {
+    /// Every command name, reported as capabilities in the hello handshake
+    pub const NAMES: &'static [&'static str] = &[
+        "hello",
+        "subscribe",
+        "batch",
+        "ping",
+        "pause",
+        "resume",
+        "toggle_pause",
+        "stop",
+        "play",
+        "preview",
+        "is_paused",
+        "get_volume",
+        "set_volume",
+        "get_gain",
+        "set_gain",
+        "get_mic_gain",
+        "set_mic_gain",
+        "get_position",
+        "seek",
+        "get_duration",
+        "get_state",
+        "get_current_file_path",
+        "get_input",
+        "get_inputs",
+        "set_input",
+        "get_output",
+        "get_outputs",
+        "set_output",
+        "get_loop",
+        "set_loop",
+        "toggle_loop",
+        "set_follow_default_input",
+        "set_follow_default_output",
+        "play_on_layer",
+        "stop_layer",
+        "stop_all_layers",
+        "set_layer_volume",
+        "get_layers_info",
+        "get_app_streams",
+        "get_app_routes",
+        "add_app_route",
+        "remove_app_route",
+        "run_macro",
+        "cancel_macro",
+        "get_running_macros",
+        "schedule_play",
+        "list_schedules",
+        "cancel_schedule",
+        "play_playlist",
+        "get_playlist",
+        "get_library",
+        "get_midi_mappings",
+        "add_midi_mapping",
+        "remove_midi_mapping",
+        "midi_learn",
+    ];
+}
+
+/// A typed request: the protocol version and the command's fields, e.g.
+/// `{"version":2,"command":"set_volume","volume":0.5}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Request {
-    pub name: String,
-    pub args: HashMap<String, String>,
+    pub version: u32,
+    /// Echoed in the response, so a connection can have several requests in
+    /// flight
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    #[serde(flatten)]
+    pub command: Command,
 }
 
 impl Request {
-    pub fn new<T: AsRef<str>>(function_name: T, data: Vec<(T, T)>) -> Self {
-        let hashmap_data: HashMap<String, String> = data
-            .into_iter()
-            .map(|(key, value)| (key.as_ref().to_string(), value.as_ref().to_string()))
-            .collect();
-
+    pub fn new(command: Command) -> Self {
         Request {
-            name: function_name.as_ref().to_string(),
-            args: hashmap_data,
+            version: PROTOCOL_VERSION,
+            id: None,
+            command,
         }
     }
 
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn batch(requests: Vec<Request>) -> Self {
+        Request::new(Command::Batch {
+            commands: requests
+                .into_iter()
+                .map(|request| request.command)
+                .collect(),
+        })
+    }
+
+    pub fn hello(client: &str) -> Self {
+        Request::new(Command::Hello {
+            client: Some(client.to_string()),
+        })
+    }
+
+    pub fn subscribe() -> Self {
+        Request::new(Command::Subscribe)
+    }
+
     pub fn ping() -> Self {
-        Request::new("ping", vec![])
+        Request::new(Command::Ping)
     }
 
     pub fn pause() -> Self {
-        Request::new("pause", vec![])
+        Request::new(Command::Pause)
     }
 
     pub fn resume() -> Self {
-        Request::new("resume", vec![])
+        Request::new(Command::Resume)
     }
 
     pub fn toggle_pause() -> Self {
-        Request::new("toggle_pause", vec![])
+        Request::new(Command::TogglePause)
     }
 
     pub fn stop() -> Self {
-        Request::new("stop", vec![])
+        Request::new(Command::Stop)
     }
 
-    pub fn play(file_path: &str) -> Self {
-        Request::new("play", vec![("file_path", file_path)])
+    /// `priority` overrides the sound's own priority
+    pub fn play(file_path: &str, priority: Option<u8>) -> Self {
+        Request::new(Command::Play {
+            file_path: file_path.to_string(),
+            priority,
+        })
     }
 
     pub fn preview(file_path: &str) -> Self {
-        Request::new("preview", vec![("file_path", file_path)])
+        Request::new(Command::Preview {
+            file_path: file_path.to_string(),
+        })
     }
 
     pub fn get_is_paused() -> Self {
-        Request::new("is_paused", vec![])
+        Request::new(Command::IsPaused)
     }
 
     pub fn get_volume() -> Self {
-        Request::new("get_volume", vec![])
+        Request::new(Command::GetVolume)
     }
 
     pub fn get_position() -> Self {
-        Request::new("get_position", vec![])
+        Request::new(Command::GetPosition)
     }
 
     pub fn get_duration() -> Self {
-        Request::new("get_duration", vec![])
+        Request::new(Command::GetDuration)
     }
 
     pub fn get_state() -> Self {
-        Request::new("get_state", vec![])
+        Request::new(Command::GetState)
     }
 
     pub fn get_current_file_path() -> Self {
-        Request::new("get_current_file_path", vec![])
+        Request::new(Command::GetCurrentFilePath)
     }
 
     pub fn get_input() -> Self {
-        Request::new("get_input", vec![])
+        Request::new(Command::GetInput)
     }
 
     pub fn get_inputs() -> Self {
-        Request::new("get_inputs", vec![])
+        Request::new(Command::GetInputs)
     }
 
     pub fn set_volume(volume: f32) -> Self {
-        Request::new("set_volume", vec![("volume", &volume.to_string())])
+        Request::new(Command::SetVolume { volume })
     }
 
     pub fn get_gain() -> Self {
-        Request::new("get_gain", vec![])
+        Request::new(Command::GetGain)
     }
 
     pub fn set_gain(gain: f32) -> Self {
-        Request::new("set_gain", vec![("gain", &gain.to_string())])
+        Request::new(Command::SetGain { gain })
     }
 
     pub fn get_mic_gain() -> Self {
-        Request::new("get_mic_gain", vec![])
+        Request::new(Command::GetMicGain)
     }
 
     pub fn set_mic_gain(mic_gain: f32) -> Self {
-        Request::new("set_mic_gain", vec![("mic_gain", &mic_gain.to_string())])
+        Request::new(Command::SetMicGain { mic_gain })
     }
 
     pub fn seek(position: f32) -> Self {
-        Request::new("seek", vec![("position", &position.to_string())])
+        Request::new(Command::Seek { position })
     }
 
     pub fn set_input(name: &str) -> Self {
-        Request::new("set_input", vec![("input_name", name)])
+        Request::new(Command::SetInput {
+            input_name: name.to_string(),
+        })
     }
 
     pub fn get_output() -> Self {
-        Request::new("get_output", vec![])
+        Request::new(Command::GetOutput)
     }
 
     pub fn get_outputs() -> Self {
-        Request::new("get_outputs", vec![])
+        Request::new(Command::GetOutputs)
     }
 
     pub fn set_output(name: &str) -> Self {
-        Request::new("set_output", vec![("output_name", name)])
+        Request::new(Command::SetOutput {
+            output_name: name.to_string(),
+        })
     }
 
     pub fn get_loop() -> Self {
-        Request::new("get_loop", vec![])
+        Request::new(Command::GetLoop)
     }
 
-    pub fn set_loop(enabled: &str) -> Self {
-        Request::new("set_loop", vec![("enabled", enabled)])
+    pub fn set_loop(enabled: bool) -> Self {
+        Request::new(Command::SetLoop { enabled })
     }
 
     pub fn toggle_loop() -> Self {
-        Request::new("toggle_loop", vec![])
+        Request::new(Command::ToggleLoop)
+    }
+
+    pub fn set_follow_default_input(enabled: bool) -> Self {
+        Request::new(Command::SetFollowDefaultInput { enabled })
+    }
+
+    pub fn set_follow_default_output(enabled: bool) -> Self {
+        Request::new(Command::SetFollowDefaultOutput { enabled })
     }
 
     // Layer commands
-    pub fn play_on_layer(layer_index: usize, file_path: &str) -> Self {
-        Request::new(
-            "play_on_layer",
-            vec![
-                ("layer_index", &layer_index.to_string()),
-                ("file_path", file_path),
-            ],
-        )
+    /// Play on `layer_index`, or on any layer if None (stealing one if all
+    /// are busy, per the daemon's voice stealing policy)
+    pub fn play_on_layer(
+        layer_index: Option<usize>,
+        file_path: &str,
+        priority: Option<u8>,
+    ) -> Self {
+        Request::new(Command::PlayOnLayer {
+            layer_index,
+            file_path: file_path.to_string(),
+            priority,
+        })
     }
 
     pub fn stop_layer(layer_index: usize) -> Self {
-        Request::new(
-            "stop_layer",
-            vec![("layer_index", &layer_index.to_string())],
-        )
+        Request::new(Command::StopLayer { layer_index })
     }
 
     pub fn stop_all_layers() -> Self {
-        Request::new("stop_all_layers", vec![])
+        Request::new(Command::StopAllLayers)
     }
 
     pub fn set_layer_volume(layer_index: usize, volume: f32) -> Self {
-        Request::new(
-            "set_layer_volume",
-            vec![
-                ("layer_index", &layer_index.to_string()),
-                ("volume", &volume.to_string()),
-            ],
-        )
+        Request::new(Command::SetLayerVolume {
+            layer_index,
+            volume,
+        })
     }
 
     pub fn get_layers_info() -> Self {
-        Request::new("get_layers_info", vec![])
+        Request::new(Command::GetLayersInfo)
+    }
+
+    // App routing commands
+    pub fn get_app_streams() -> Self {
+        Request::new(Command::GetAppStreams)
+    }
+
+    pub fn get_app_routes() -> Self {
+        Request::new(Command::GetAppRoutes)
+    }
+
+    pub fn add_app_route(application_name: Option<&str>, process_binary: Option<&str>) -> Self {
+        Request::new(Command::AddAppRoute {
+            application_name: application_name.map(str::to_string),
+            process_binary: process_binary.map(str::to_string),
+        })
+    }
+
+    pub fn remove_app_route(application_name: Option<&str>, process_binary: Option<&str>) -> Self {
+        Request::new(Command::RemoveAppRoute {
+            application_name: application_name.map(str::to_string),
+            process_binary: process_binary.map(str::to_string),
+        })
+    }
+
+    // Macro commands
+    pub fn run_macro(name: &str) -> Self {
+        Request::new(Command::RunMacro {
+            name: name.to_string(),
+        })
+    }
+
+    pub fn cancel_macro(name: &str) -> Self {
+        Request::new(Command::CancelMacro {
+            name: name.to_string(),
+        })
+    }
+
+    pub fn get_running_macros() -> Self {
+        Request::new(Command::GetRunningMacros)
+    }
+
+    // Schedule commands
+    pub fn schedule_play(
+        file_path: &str,
+        layer_index: Option<usize>,
+        delay: Option<&str>,
+        at: Option<&str>,
+        every: Option<&str>,
+        label: Option<&str>,
+    ) -> Self {
+        Request::new(Command::SchedulePlay {
+            file_path: file_path.to_string(),
+            layer_index,
+            delay: delay.map(str::to_string),
+            at: at.map(str::to_string),
+            every: every.map(str::to_string),
+            label: label.map(str::to_string),
+        })
+    }
+
+    pub fn list_schedules() -> Self {
+        Request::new(Command::ListSchedules)
+    }
+
+    pub fn cancel_schedule(id: u64) -> Self {
+        Request::new(Command::CancelSchedule { id })
+    }
+
+    // Playlist commands
+    pub fn play_playlist(name: &str, mode: Option<PlaylistMode>) -> Self {
+        Request::new(Command::PlayPlaylist {
+            name: name.to_string(),
+            mode,
+        })
+    }
+
+    pub fn get_playlist() -> Self {
+        Request::new(Command::GetPlaylist)
+    }
+
+    pub fn get_library() -> Self {
+        Request::new(Command::GetLibrary)
+    }
+
+    pub fn get_midi_mappings() -> Self {
+        Request::new(Command::GetMidiMappings)
+    }
+
+    pub fn add_midi_mapping(mapping: MidiMapping) -> Self {
+        Request::new(Command::AddMidiMapping { mapping })
+    }
+
+    pub fn remove_midi_mapping(channel: Option<u8>, trigger: MidiTrigger) -> Self {
+        Request::new(Command::RemoveMidiMapping { channel, trigger })
+    }
+
+    /// Needs a response timeout longer than `utils::midi::LEARN_TIMEOUT`
+    pub fn midi_learn() -> Self {
+        Request::new(Command::MidiLearn)
+    }
+}
+
+/// The pre-versioned request shape, where every argument is a string.
+/// Still accepted by the daemon for one transitional release.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct LegacyRequest {
+    pub name: String,
+    pub args: HashMap<String, String>,
+}
+
+impl LegacyRequest {
+    pub fn new<T: AsRef<str>>(function_name: T, data: Vec<(T, T)>) -> Self {
+        let hashmap_data: HashMap<String, String> = data
+            .into_iter()
+            .map(|(key, value)| (key.as_ref().to_string(), value.as_ref().to_string()))
+            .collect();
+
+        LegacyRequest {
+            name: function_name.as_ref().to_string(),
+            args: hashmap_data,
+        }
+    }
+}
+
+/// A request as read off the socket, in either shape
+#[derive(Debug, Clone)]
+pub enum IncomingRequest {
+    Typed(Request),
+    Legacy(LegacyRequest),
+}
+
+impl IncomingRequest {
+    /// Requests with a `name` and no `version` use the legacy shape
+    pub fn from_slice(data: &[u8]) -> Result<Self, serde_json::Error> {
+        let value: serde_json::Value = serde_json::from_slice(data)?;
+        let is_legacy = value.get("name").is_some() && value.get("version").is_none();
+        if is_legacy {
+            serde_json::from_value(value).map(IncomingRequest::Legacy)
+        } else {
+            serde_json::from_value(value).map(IncomingRequest::Typed)
+        }
+    }
+}
+
+/// Reply to the hello handshake
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HelloInfo {
+    pub protocol_version: u32,
+    pub min_protocol_version: u32,
+    pub daemon_version: String,
+    /// Commands the daemon understands
+    pub capabilities: Vec<String>,
+}
+
+impl HelloInfo {
+    pub fn current() -> Self {
+        HelloInfo {
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            daemon_version: env!("CARGO_PKG_VERSION").to_string(),
+            capabilities: Command::NAMES.iter().map(|name| name.to_string()).collect(),
+        }
     }
 }
 
@@ -173,6 +585,17 @@ impl Request {
 pub struct Response {
     pub status: bool,
     pub message: String,
+    /// Protocol version of the daemon that answered; 0 for daemons that
+    /// predate versioning
+    #[serde(default)]
+    pub version: u32,
+    /// The id of the request this answers, if it had one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    /// Structured result for programs, see `types::payload`. `message` is
+    /// for humans.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
 }
 
 impl Response {
@@ -180,25 +603,76 @@ impl Response {
         Response {
             status,
             message: message.as_ref().to_string(),
+            version: PROTOCOL_VERSION,
+            id: None,
+            data: None,
         }
     }
+
+    /// Answer the request with this id
+    pub fn with_id(mut self, id: Option<u64>) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// A failed request, with a `DaemonError` as data
+    pub fn error<T: AsRef<str>>(code: ErrorCode, message: T) -> Self {
+        Response::from(DaemonError::new(code, message.as_ref()))
+    }
+
+    /// Attach structured data to the response
+    pub fn with_data<T: Serialize>(mut self, data: T) -> Self {
+        match serde_json::to_value(data) {
+            Ok(data) => self.data = Some(data),
+            Err(e) => tracing::error!("Failed to serialize response data: {}", e),
+        }
+        self
+    }
+
+    /// The data of a successful response as `T`
+    pub fn data_as<T: DeserializeOwned>(&self) -> Option<T> {
+        if !self.status {
+            return None;
+        }
+        serde_json::from_value(self.data.clone()?).ok()
+    }
+
+    /// Why the request failed, or None if it succeeded. Daemons that predate
+    /// error codes report every error as `Internal`.
+    pub fn daemon_error(&self) -> Option<DaemonError> {
+        if self.status {
+            return None;
+        }
+        let error = self
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value::<DaemonError>(data).ok());
+        Some(error.unwrap_or_else(|| DaemonError::new(ErrorCode::Internal, &self.message)))
+    }
+}
+
+impl From<DaemonError> for Response {
+    fn from(error: DaemonError) -> Self {
+        Response::new(false, &error.message).with_data(error)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    // Request::new tests
+    // LegacyRequest::new tests
     #[test]
     fn test_request_new_empty_args() {
-        let request = Request::new("test_command", vec![]);
+        let request = LegacyRequest::new("test_command", vec![]);
         assert_eq!(request.name, "test_command");
         assert!(request.args.is_empty());
     }
 
     #[test]
     fn test_request_new_with_args() {
-        let request = Request::new("test_command", vec![("key1", "value1"), ("key2", "value2")]);
+        let request =
+            LegacyRequest::new("test_command", vec![("key1", "value1"), ("key2", "value2")]);
         assert_eq!(request.name, "test_command");
         assert_eq!(request.args.get("key1"), Some(&"value1".to_string()));
         assert_eq!(request.args.get("key2"), Some(&"value2".to_string()));
@@ -206,7 +680,7 @@ mod tests {
 
     #[test]
     fn test_request_new_converts_to_string() {
-        let request = Request::new(
+        let request = LegacyRequest::new(
             String::from("cmd"),
             vec![(String::from("k"), String::from("v"))],
         );
@@ -216,285 +690,450 @@ mod tests {
 
     // Request helper method tests
     #[test]
-    fn test_request_ping() {
+    fn test_request_uses_current_version() {
         let request = Request::ping();
-        assert_eq!(request.name, "ping");
-        assert!(request.args.is_empty());
+        assert_eq!(request.version, PROTOCOL_VERSION);
+        assert_eq!(request.command, Command::Ping);
     }
 
     #[test]
-    fn test_request_pause() {
-        let request = Request::pause();
-        assert_eq!(request.name, "pause");
-        assert!(request.args.is_empty());
-    }
-
-    #[test]
-    fn test_request_resume() {
-        let request = Request::resume();
-        assert_eq!(request.name, "resume");
-        assert!(request.args.is_empty());
-    }
-
-    #[test]
-    fn test_request_toggle_pause() {
-        let request = Request::toggle_pause();
-        assert_eq!(request.name, "toggle_pause");
-        assert!(request.args.is_empty());
+    fn test_request_hello() {
+        let request = Request::hello("soundboard-cli");
+        assert_eq!(
+            request.command,
+            Command::Hello {
+                client: Some("soundboard-cli".to_string())
+            }
+        );
     }
 
     #[test]
-    fn test_request_stop() {
-        let request = Request::stop();
-        assert_eq!(request.name, "stop");
-        assert!(request.args.is_empty());
+    fn test_request_playback_controls() {
+        assert_eq!(Request::pause().command, Command::Pause);
+        assert_eq!(Request::resume().command, Command::Resume);
+        assert_eq!(Request::toggle_pause().command, Command::TogglePause);
+        assert_eq!(Request::stop().command, Command::Stop);
     }
 
     #[test]
     fn test_request_play() {
-        let request = Request::play("/path/to/file.mp3");
-        assert_eq!(request.name, "play");
+        let request = Request::play("/path/to/file.mp3", None);
         assert_eq!(
-            request.args.get("file_path"),
-            Some(&"/path/to/file.mp3".to_string())
+            request.command,
+            Command::Play {
+                file_path: "/path/to/file.mp3".to_string(),
+                priority: None
+            }
         );
+
+        let request = Request::play("/path/to/file.mp3", Some(3));
+        assert!(matches!(
+            request.command,
+            Command::Play {
+                priority: Some(3),
+                ..
+            }
+        ));
     }
 
     #[test]
     fn test_request_preview() {
         let request = Request::preview("/path/to/file.wav");
-        assert_eq!(request.name, "preview");
         assert_eq!(
-            request.args.get("file_path"),
-            Some(&"/path/to/file.wav".to_string())
+            request.command,
+            Command::Preview {
+                file_path: "/path/to/file.wav".to_string()
+            }
         );
     }
 
     #[test]
-    fn test_request_get_is_paused() {
-        let request = Request::get_is_paused();
-        assert_eq!(request.name, "is_paused");
-    }
-
-    #[test]
-    fn test_request_get_volume() {
-        let request = Request::get_volume();
-        assert_eq!(request.name, "get_volume");
+    fn test_request_getters() {
+        assert_eq!(Request::get_is_paused().command, Command::IsPaused);
+        assert_eq!(Request::get_volume().command, Command::GetVolume);
+        assert_eq!(Request::get_gain().command, Command::GetGain);
+        assert_eq!(Request::get_mic_gain().command, Command::GetMicGain);
+        assert_eq!(Request::get_position().command, Command::GetPosition);
+        assert_eq!(Request::get_duration().command, Command::GetDuration);
+        assert_eq!(Request::get_state().command, Command::GetState);
+        assert_eq!(
+            Request::get_current_file_path().command,
+            Command::GetCurrentFilePath
+        );
+        assert_eq!(Request::get_input().command, Command::GetInput);
+        assert_eq!(Request::get_inputs().command, Command::GetInputs);
+        assert_eq!(Request::get_output().command, Command::GetOutput);
+        assert_eq!(Request::get_outputs().command, Command::GetOutputs);
+        assert_eq!(Request::get_loop().command, Command::GetLoop);
+        assert_eq!(Request::get_layers_info().command, Command::GetLayersInfo);
     }
 
     #[test]
     fn test_request_set_volume() {
         let request = Request::set_volume(0.75);
-        assert_eq!(request.name, "set_volume");
-        assert_eq!(request.args.get("volume"), Some(&"0.75".to_string()));
+        assert_eq!(request.command, Command::SetVolume { volume: 0.75 });
     }
 
     #[test]
-    fn test_request_get_gain() {
-        let request = Request::get_gain();
-        assert_eq!(request.name, "get_gain");
+    fn test_request_set_gain() {
+        assert_eq!(
+            Request::set_gain(1.5).command,
+            Command::SetGain { gain: 1.5 }
+        );
+        assert_eq!(
+            Request::set_mic_gain(2.0).command,
+            Command::SetMicGain { mic_gain: 2.0 }
+        );
     }
 
     #[test]
-    fn test_request_set_gain() {
-        let request = Request::set_gain(1.5);
-        assert_eq!(request.name, "set_gain");
-        assert_eq!(request.args.get("gain"), Some(&"1.5".to_string()));
+    fn test_request_seek() {
+        let request = Request::seek(30.5);
+        assert_eq!(request.command, Command::Seek { position: 30.5 });
     }
 
     #[test]
-    fn test_request_get_mic_gain() {
-        let request = Request::get_mic_gain();
-        assert_eq!(request.name, "get_mic_gain");
+    fn test_request_set_devices() {
+        assert_eq!(
+            Request::set_input("mic1").command,
+            Command::SetInput {
+                input_name: "mic1".to_string()
+            }
+        );
+        assert_eq!(
+            Request::set_output("speaker1").command,
+            Command::SetOutput {
+                output_name: "speaker1".to_string()
+            }
+        );
     }
 
     #[test]
-    fn test_request_set_mic_gain() {
-        let request = Request::set_mic_gain(2.0);
-        assert_eq!(request.name, "set_mic_gain");
-        assert_eq!(request.args.get("mic_gain"), Some(&"2".to_string()));
+    fn test_request_loop() {
+        assert_eq!(
+            Request::set_loop(true).command,
+            Command::SetLoop { enabled: true }
+        );
+        assert_eq!(Request::toggle_loop().command, Command::ToggleLoop);
     }
 
+    // Layer command tests
     #[test]
-    fn test_request_get_position() {
-        let request = Request::get_position();
-        assert_eq!(request.name, "get_position");
+    fn test_request_play_on_layer() {
+        let request = Request::play_on_layer(Some(0), "/path/to/file.ogg", None);
+        assert_eq!(
+            request.command,
+            Command::PlayOnLayer {
+                layer_index: Some(0),
+                file_path: "/path/to/file.ogg".to_string(),
+                priority: None
+            }
+        );
+
+        let request = Request::play_on_layer(None, "/path/to/file.ogg", Some(9));
+        assert!(matches!(
+            request.command,
+            Command::PlayOnLayer {
+                layer_index: None,
+                priority: Some(9),
+                ..
+            }
+        ));
     }
 
     #[test]
-    fn test_request_seek() {
-        let request = Request::seek(30.5);
-        assert_eq!(request.name, "seek");
-        assert_eq!(request.args.get("position"), Some(&"30.5".to_string()));
+    fn test_request_layers() {
+        assert_eq!(
+            Request::stop_layer(2).command,
+            Command::StopLayer { layer_index: 2 }
+        );
+        assert_eq!(Request::stop_all_layers().command, Command::StopAllLayers);
+        assert_eq!(
+            Request::set_layer_volume(1, 0.8).command,
+            Command::SetLayerVolume {
+                layer_index: 1,
+                volume: 0.8
+            }
+        );
     }
 
     #[test]
-    fn test_request_get_duration() {
-        let request = Request::get_duration();
-        assert_eq!(request.name, "get_duration");
+    fn test_request_set_follow_default() {
+        assert_eq!(
+            Request::set_follow_default_input(true).command,
+            Command::SetFollowDefaultInput { enabled: true }
+        );
+        assert_eq!(
+            Request::set_follow_default_output(false).command,
+            Command::SetFollowDefaultOutput { enabled: false }
+        );
     }
 
+    // App routing command tests
     #[test]
-    fn test_request_get_state() {
-        let request = Request::get_state();
-        assert_eq!(request.name, "get_state");
+    fn test_request_app_routes() {
+        assert_eq!(Request::get_app_streams().command, Command::GetAppStreams);
+        assert_eq!(
+            Request::add_app_route(Some("Discord"), None).command,
+            Command::AddAppRoute {
+                application_name: Some("Discord".to_string()),
+                process_binary: None
+            }
+        );
+        assert_eq!(
+            Request::remove_app_route(None, Some("firefox")).command,
+            Command::RemoveAppRoute {
+                application_name: None,
+                process_binary: Some("firefox".to_string())
+            }
+        );
     }
 
+    // Macro command tests
     #[test]
-    fn test_request_get_current_file_path() {
-        let request = Request::get_current_file_path();
-        assert_eq!(request.name, "get_current_file_path");
+    fn test_request_macros() {
+        assert_eq!(
+            Request::run_macro("rimshot").command,
+            Command::RunMacro {
+                name: "rimshot".to_string()
+            }
+        );
+        assert_eq!(
+            Request::cancel_macro("rimshot").command,
+            Command::CancelMacro {
+                name: "rimshot".to_string()
+            }
+        );
     }
 
     #[test]
-    fn test_request_get_input() {
-        let request = Request::get_input();
-        assert_eq!(request.name, "get_input");
+    fn test_request_schedule_play() {
+        let request =
+            Request::schedule_play("/sounds/water.ogg", Some(1), None, None, Some("30m"), None);
+        assert_eq!(
+            request.command,
+            Command::SchedulePlay {
+                file_path: "/sounds/water.ogg".to_string(),
+                layer_index: Some(1),
+                delay: None,
+                at: None,
+                every: Some("30m".to_string()),
+                label: None
+            }
+        );
+        assert_eq!(
+            Request::cancel_schedule(4).command,
+            Command::CancelSchedule { id: 4 }
+        );
     }
 
     #[test]
-    fn test_request_get_inputs() {
-        let request = Request::get_inputs();
-        assert_eq!(request.name, "get_inputs");
+    fn test_request_play_playlist() {
+        let request = Request::play_playlist("Intros", Some(PlaylistMode::RoundRobin));
+        assert_eq!(
+            request.command,
+            Command::PlayPlaylist {
+                name: "Intros".to_string(),
+                mode: Some(PlaylistMode::RoundRobin)
+            }
+        );
     }
 
+    // Response tests
     #[test]
-    fn test_request_set_input() {
-        let request = Request::set_input("mic1");
-        assert_eq!(request.name, "set_input");
-        assert_eq!(request.args.get("input_name"), Some(&"mic1".to_string()));
+    fn test_response_new_success() {
+        let response = Response::new(true, "Success message");
+        assert!(response.status);
+        assert_eq!(response.message, "Success message");
+        assert_eq!(response.version, PROTOCOL_VERSION);
     }
 
     #[test]
-    fn test_request_get_output() {
-        let request = Request::get_output();
-        assert_eq!(request.name, "get_output");
+    fn test_response_new_failure() {
+        let response = Response::new(false, "Error message");
+        assert!(!response.status);
+        assert_eq!(response.message, "Error message");
     }
 
     #[test]
-    fn test_request_get_outputs() {
-        let request = Request::get_outputs();
-        assert_eq!(request.name, "get_outputs");
+    fn test_response_new_with_string() {
+        let response = Response::new(true, String::from("Test"));
+        assert!(response.status);
+        assert_eq!(response.message, "Test");
     }
 
     #[test]
-    fn test_request_set_output() {
-        let request = Request::set_output("speaker1");
-        assert_eq!(request.name, "set_output");
+    fn test_response_error_has_code() {
+        let response = Response::error(ErrorCode::InvalidArg, "Invalid volume value");
+        assert!(!response.status);
+        assert_eq!(response.message, "Invalid volume value");
+        assert_eq!(
+            response.data,
+            Some(serde_json::json!({"code": "INVALID_ARG", "message": "Invalid volume value"}))
+        );
         assert_eq!(
-            request.args.get("output_name"),
-            Some(&"speaker1".to_string())
+            response.daemon_error().map(|e| e.code),
+            Some(ErrorCode::InvalidArg)
         );
     }
 
     #[test]
-    fn test_request_get_loop() {
-        let request = Request::get_loop();
-        assert_eq!(request.name, "get_loop");
-    }
+    fn test_daemon_error_from_old_daemon() {
+        let response: Response =
+            serde_json::from_str(r#"{"status":false,"message":"Invalid file path"}"#).unwrap();
+        let error = response.daemon_error().unwrap();
+        assert_eq!(error.code, ErrorCode::Internal);
+        assert_eq!(error.message, "Invalid file path");
 
-    #[test]
-    fn test_request_set_loop() {
-        let request = Request::set_loop("true");
-        assert_eq!(request.name, "set_loop");
-        assert_eq!(request.args.get("enabled"), Some(&"true".to_string()));
+        assert!(Response::new(true, "pong").daemon_error().is_none());
     }
 
     #[test]
-    fn test_request_toggle_loop() {
-        let request = Request::toggle_loop();
-        assert_eq!(request.name, "toggle_loop");
+    fn test_response_data() {
+        let response = Response::new(true, "0.5").with_data(0.5f32);
+        assert_eq!(response.data_as::<f32>(), Some(0.5));
+        assert_eq!(response.data_as::<bool>(), None);
+
+        let json = serde_json::to_string(&Response::new(true, "pong")).unwrap();
+        assert!(!json.contains("data"), "empty data should be left out");
     }
 
-    // Layer command tests
     #[test]
-    fn test_request_play_on_layer() {
-        let request = Request::play_on_layer(0, "/path/to/file.ogg");
-        assert_eq!(request.name, "play_on_layer");
-        assert_eq!(request.args.get("layer_index"), Some(&"0".to_string()));
-        assert_eq!(
-            request.args.get("file_path"),
-            Some(&"/path/to/file.ogg".to_string())
-        );
+    fn test_response_default() {
+        let response = Response::default();
+        assert!(!response.status);
+        assert!(response.message.is_empty());
     }
 
     #[test]
-    fn test_request_stop_layer() {
-        let request = Request::stop_layer(2);
-        assert_eq!(request.name, "stop_layer");
-        assert_eq!(request.args.get("layer_index"), Some(&"2".to_string()));
+    fn test_request_default() {
+        let request = LegacyRequest::default();
+        assert!(request.name.is_empty());
+        assert!(request.args.is_empty());
     }
 
+    // Serialization tests
     #[test]
-    fn test_request_stop_all_layers() {
-        let request = Request::stop_all_layers();
-        assert_eq!(request.name, "stop_all_layers");
+    fn test_request_serialization() {
+        let json = serde_json::to_value(Request::set_volume(0.5)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"version": 2, "command": "set_volume", "volume": 0.5})
+        );
+
+        let json = serde_json::to_value(Request::ping()).unwrap();
+        assert_eq!(json, serde_json::json!({"version": 2, "command": "ping"}));
     }
 
     #[test]
-    fn test_request_set_layer_volume() {
-        let request = Request::set_layer_volume(1, 0.8);
-        assert_eq!(request.name, "set_layer_volume");
-        assert_eq!(request.args.get("layer_index"), Some(&"1".to_string()));
-        assert_eq!(request.args.get("volume"), Some(&"0.8".to_string()));
+    fn test_request_deserialization() {
+        let json = r#"{"version":2,"command":"play_on_layer","file_path":"/a.ogg"}"#;
+        let request: Request = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            request.command,
+            Command::PlayOnLayer {
+                layer_index: None,
+                file_path: "/a.ogg".to_string(),
+                priority: None
+            }
+        );
     }
 
     #[test]
-    fn test_request_get_layers_info() {
-        let request = Request::get_layers_info();
-        assert_eq!(request.name, "get_layers_info");
+    fn test_request_round_trip() {
+        let requests = [
+            Request::hello("gui"),
+            Request::set_loop(false),
+            Request::schedule_play("/a.ogg", None, Some("90"), None, None, Some("tea")),
+            Request::play_playlist("Intros", None),
+        ];
+        for request in requests {
+            let json = serde_json::to_string(&request).unwrap();
+            assert_eq!(serde_json::from_str::<Request>(&json).unwrap(), request);
+        }
     }
 
-    // Response tests
     #[test]
-    fn test_response_new_success() {
-        let response = Response::new(true, "Success message");
-        assert!(response.status);
-        assert_eq!(response.message, "Success message");
+    fn test_request_id_and_batch() {
+        let request = Request::batch(vec![
+            Request::stop_all_layers(),
+            Request::play("/a.ogg", None),
+            Request::set_volume(0.5),
+        ])
+        .with_id(7);
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["id"], 7);
+        assert_eq!(json["command"], "batch");
+        assert_eq!(json["commands"][1]["command"], "play");
+        assert_eq!(serde_json::from_value::<Request>(json).unwrap(), request);
+
+        // Requests without an id don't send one
+        let json = serde_json::to_value(Request::ping()).unwrap();
+        assert!(json.get("id").is_none());
     }
 
     #[test]
-    fn test_response_new_failure() {
-        let response = Response::new(false, "Error message");
-        assert!(!response.status);
-        assert_eq!(response.message, "Error message");
+    fn test_request_wrong_field_type_rejected() {
+        let json = r#"{"version":2,"command":"set_volume","volume":"loud"}"#;
+        assert!(serde_json::from_str::<Request>(json).is_err());
     }
 
     #[test]
-    fn test_response_new_with_string() {
-        let response = Response::new(true, String::from("Test"));
-        assert!(response.status);
-        assert_eq!(response.message, "Test");
+    fn test_incoming_legacy_shape() {
+        let json = br#"{"name":"set_volume","args":{"volume":"0.5"}}"#;
+        match IncomingRequest::from_slice(json).unwrap() {
+            IncomingRequest::Legacy(request) => {
+                assert_eq!(request.name, "set_volume");
+                assert_eq!(request.args.get("volume"), Some(&"0.5".to_string()));
+            }
+            other => panic!("expected a legacy request, got {:?}", other),
+        }
     }
 
     #[test]
-    fn test_response_default() {
-        let response = Response::default();
-        assert!(!response.status);
-        assert!(response.message.is_empty());
+    fn test_incoming_typed_shape() {
+        // Typed commands may have a `name` field of their own
+        let json = br#"{"version":2,"command":"run_macro","name":"rimshot"}"#;
+        match IncomingRequest::from_slice(json).unwrap() {
+            IncomingRequest::Typed(request) => assert_eq!(
+                request.command,
+                Command::RunMacro {
+                    name: "rimshot".to_string()
+                }
+            ),
+            other => panic!("expected a typed request, got {:?}", other),
+        }
     }
 
     #[test]
-    fn test_request_default() {
-        let request = Request::default();
-        assert!(request.name.is_empty());
-        assert!(request.args.is_empty());
+    fn test_incoming_unknown_command_rejected() {
+        let json = br#"{"version":2,"command":"explode"}"#;
+        assert!(IncomingRequest::from_slice(json).is_err());
     }
 
-    // Serialization tests
     #[test]
-    fn test_request_serialization() {
-        let request = Request::ping();
-        let json = serde_json::to_string(&request).unwrap();
-        assert!(json.contains("\"name\":\"ping\""));
+    fn test_hello_info_lists_every_command() {
+        let hello = HelloInfo::current();
+        assert_eq!(hello.protocol_version, PROTOCOL_VERSION);
+        assert!(hello.capabilities.iter().any(|c| c == "hello"));
+        assert_eq!(hello.capabilities.len(), Command::NAMES.len());
     }
 
     #[test]
-    fn test_request_deserialization() {
-        let json = r#"{"name":"ping","args":{}}"#;
-        let request: Request = serde_json::from_str(json).unwrap();
-        assert_eq!(request.name, "ping");
-        assert!(request.args.is_empty());
+    fn test_command_names_are_tags() {
+        // Every unit command's tag must be listed
+        for request in [
+            Request::subscribe(),
+            Request::ping(),
+            Request::get_is_paused(),
+            Request::get_current_file_path(),
+            Request::get_playlist(),
+            Request::midi_learn(),
+        ] {
+            let json = serde_json::to_value(&request).unwrap();
+            let tag = json["command"].as_str().unwrap();
+            assert!(Command::NAMES.contains(&tag), "{} is not listed", tag);
+        }
     }
 
     #[test]
@@ -503,23 +1142,31 @@ mod tests {
         let json = serde_json::to_string(&response).unwrap();
         assert!(json.contains("\"status\":true"));
         assert!(json.contains("\"message\":\"pong\""));
+        assert!(json.contains("\"version\":2"));
     }
 
     #[test]
     fn test_response_deserialization() {
+        // Responses from daemons that predate versioning
         let json = r#"{"status":true,"message":"pong"}"#;
         let response: Response = serde_json::from_str(json).unwrap();
         assert!(response.status);
         assert_eq!(response.message, "pong");
+        assert_eq!(response.version, 0);
     }
 
     // --- Edge case tests (TEST-13) ---
 
     #[test]
     fn test_request_with_unicode_args() {
-        let request = Request::new("play", vec![("file_path", "/sounds/日本語.mp3")]);
+        let request = Request::play("/sounds/日本語.mp3", None);
         let json = serde_json::to_string(&request).unwrap();
         let deserialized: Request = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, request);
+
+        let request = LegacyRequest::new("play", vec![("file_path", "/sounds/日本語.mp3")]);
+        let json = serde_json::to_string(&request).unwrap();
+        let deserialized: LegacyRequest = serde_json::from_str(&json).unwrap();
         assert_eq!(
             deserialized.args.get("file_path"),
             Some(&"/sounds/日本語.mp3".to_string())
@@ -528,12 +1175,12 @@ mod tests {
 
     #[test]
     fn test_request_with_special_chars_in_args() {
-        let request = Request::new(
+        let request = LegacyRequest::new(
             "play",
             vec![("file_path", "/path/with spaces/file (1).mp3")],
         );
         let json = serde_json::to_string(&request).unwrap();
-        let deserialized: Request = serde_json::from_str(&json).unwrap();
+        let deserialized: LegacyRequest = serde_json::from_str(&json).unwrap();
         assert_eq!(
             deserialized.args.get("file_path"),
             Some(&"/path/with spaces/file (1).mp3".to_string())
@@ -542,7 +1189,7 @@ mod tests {
 
     #[test]
     fn test_request_with_empty_arg_value() {
-        let request = Request::new("set_input", vec![("input_name", "")]);
+        let request = LegacyRequest::new("set_input", vec![("input_name", "")]);
         assert_eq!(request.args.get("input_name"), Some(&String::new()));
     }
 
@@ -557,8 +1204,8 @@ mod tests {
 
     #[test]
     fn test_request_invalid_json_rejected() {
-        let result = serde_json::from_str::<Request>("not valid json");
-        assert!(result.is_err());
+        assert!(serde_json::from_str::<Request>("not valid json").is_err());
+        assert!(IncomingRequest::from_slice(b"not valid json").is_err());
     }
 
     #[test]
@@ -573,7 +1220,11 @@ mod tests {
     #[test]
     fn test_request_extra_fields_ignored() {
         let json = r#"{"name":"ping","args":{},"extra":"field"}"#;
-        let request: Request = serde_json::from_str(json).unwrap();
+        let request: LegacyRequest = serde_json::from_str(json).unwrap();
         assert_eq!(request.name, "ping");
+
+        let json = r#"{"version":2,"command":"ping","extra":"field"}"#;
+        let request: Request = serde_json::from_str(json).unwrap();
+        assert_eq!(request.command, Command::Ping);
     }
 }
@@ -0,0 +1,201 @@
+use crate::types::audio_player::{MAX_GAIN, MIN_GAIN, NUM_AUDIO_LAYERS};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, error::Error, fs, path::Path, path::PathBuf};
+
+/// A sequence of sounds to be mixed offline, loaded from JSON
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Timeline {
+    /// Output sample rate in Hz
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: u32,
+    /// Output channel count
+    #[serde(default = "default_channels")]
+    pub channels: u16,
+    /// Volume of the main player (0.0 to 1.0)
+    #[serde(default = "default_one")]
+    pub volume: f32,
+    /// Output gain/boost applied to the main player and every layer
+    #[serde(default = "default_one")]
+    pub gain: f32,
+    /// Per-layer volume (0.0 to 1.0), layers not listed play at 1.0
+    #[serde(default)]
+    pub layer_volumes: HashMap<usize, f32>,
+    pub clips: Vec<TimelineClip>,
+}
+
+/// A single sound placed on the timeline
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TimelineClip {
+    /// Audio file, relative paths are resolved against the timeline file
+    pub file: PathBuf,
+    /// Start time in seconds
+    #[serde(default)]
+    pub start: f32,
+    /// Layer to play on (None = main player). Like in the player, a new
+    /// clip on the same layer cuts off the previous one.
+    #[serde(default)]
+    pub layer: Option<usize>,
+    /// Individual volume for this clip (0.0 to 1.0)
+    #[serde(default = "default_one")]
+    pub volume: f32,
+    /// Fade-in length in seconds
+    #[serde(default)]
+    pub fade_in: f32,
+    /// Fade-out length in seconds, ending where the clip stops
+    #[serde(default)]
+    pub fade_out: f32,
+    /// Play at most this many seconds of the file
+    #[serde(default)]
+    pub duration: Option<f32>,
+}
+
+fn default_sample_rate() -> u32 {
+    48000
+}
+
+fn default_channels() -> u16 {
+    2
+}
+
+fn default_one() -> f32 {
+    1.0
+}
+
+impl Timeline {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read timeline '{}': {}", path.display(), e))?;
+        let timeline: Timeline = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse timeline '{}': {}", path.display(), e))?;
+        timeline.validate()?;
+        Ok(timeline)
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.sample_rate == 0 {
+            return Err("Sample rate must be greater than 0".into());
+        }
+        if self.channels == 0 {
+            return Err("Channel count must be greater than 0".into());
+        }
+        if let Some(layer) = self.layer_volumes.keys().find(|l| **l >= NUM_AUDIO_LAYERS) {
+            return Err(format!("Invalid layer index: {}", layer).into());
+        }
+
+        for (i, clip) in self.clips.iter().enumerate() {
+            if let Some(layer) = clip.layer.filter(|l| *l >= NUM_AUDIO_LAYERS) {
+                return Err(format!("Clip {}: invalid layer index: {}", i, layer).into());
+            }
+            let times = [
+                clip.start,
+                clip.fade_in,
+                clip.fade_out,
+                clip.duration.unwrap_or(0.0),
+            ];
+            if times.iter().any(|t| !t.is_finite() || *t < 0.0) {
+                return Err(
+                    format!("Clip {}: times must be non-negative numbers of seconds", i).into(),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Amplitude a clip on `layer` is played at, before its own volume.
+    /// Mirrors the sink volumes of the AudioPlayer: `volume * gain` for the
+    /// main player and `layer volume * gain` for layers.
+    pub fn track_volume(&self, layer: Option<usize>) -> f32 {
+        let gain = self.gain.clamp(MIN_GAIN, MAX_GAIN);
+        let volume = match layer {
+            Some(layer) => self.layer_volumes.get(&layer).copied().unwrap_or(1.0),
+            None => self.volume,
+        };
+        volume.clamp(0.0, 1.0) * gain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline_defaults() {
+        let timeline: Timeline = serde_json::from_str(r#"{"clips": [{"file": "a.wav"}]}"#).unwrap();
+
+        assert_eq!(timeline.sample_rate, 48000);
+        assert_eq!(timeline.channels, 2);
+        assert_eq!(timeline.volume, 1.0);
+        assert_eq!(timeline.gain, 1.0);
+        assert!(timeline.layer_volumes.is_empty());
+
+        let clip = &timeline.clips[0];
+        assert_eq!(clip.file, PathBuf::from("a.wav"));
+        assert_eq!(clip.start, 0.0);
+        assert_eq!(clip.layer, None);
+        assert_eq!(clip.volume, 1.0);
+        assert_eq!(clip.fade_in, 0.0);
+        assert_eq!(clip.fade_out, 0.0);
+        assert_eq!(clip.duration, None);
+    }
+
+    #[test]
+    fn test_timeline_full() {
+        let json = r#"{
+            "sample_rate": 44100,
+            "channels": 1,
+            "gain": 2.0,
+            "layer_volumes": {"1": 0.5},
+            "clips": [
+                {"file": "/tmp/a.ogg", "start": 1.5, "layer": 1, "volume": 0.8,
+                 "fade_in": 0.2, "fade_out": 0.5, "duration": 3.0}
+            ]
+        }"#;
+        let timeline: Timeline = serde_json::from_str(json).unwrap();
+        assert!(timeline.validate().is_ok());
+
+        assert_eq!(timeline.sample_rate, 44100);
+        assert_eq!(timeline.channels, 1);
+        assert_eq!(timeline.layer_volumes.get(&1), Some(&0.5));
+
+        let clip = &timeline.clips[0];
+        assert_eq!(clip.start, 1.5);
+        assert_eq!(clip.layer, Some(1));
+        assert_eq!(clip.duration, Some(3.0));
+    }
+
+    #[test]
+    fn test_timeline_validation() {
+        let mut timeline: Timeline =
+            serde_json::from_str(r#"{"clips": [{"file": "a.wav", "layer": 3}]}"#).unwrap();
+        assert!(timeline.validate().is_ok());
+
+        timeline.clips[0].layer = Some(NUM_AUDIO_LAYERS);
+        assert!(timeline.validate().is_err());
+
+        timeline.clips[0].layer = None;
+        timeline.clips[0].start = -1.0;
+        assert!(timeline.validate().is_err());
+
+        timeline.clips[0].start = 0.0;
+        timeline.channels = 0;
+        assert!(timeline.validate().is_err());
+    }
+
+    #[test]
+    fn test_track_volume_matches_player() {
+        let mut timeline: Timeline = serde_json::from_str(r#"{"clips": []}"#).unwrap();
+        timeline.volume = 0.5;
+        timeline.gain = 2.0;
+        timeline.layer_volumes.insert(0, 0.25);
+
+        assert_eq!(timeline.track_volume(None), 1.0);
+        assert_eq!(timeline.track_volume(Some(0)), 0.5);
+        assert_eq!(timeline.track_volume(Some(1)), 2.0);
+
+        // Out-of-range values are clamped like the player's setters
+        timeline.volume = 3.0;
+        timeline.gain = 10.0;
+        assert_eq!(timeline.track_volume(None), MAX_GAIN);
+    }
+}
@@ -1,20 +1,33 @@
-use crate::types::{commands::*, socket::Request};
-
+use crate::{
+    types::{
+        commands::*,
+        config::AppRouteRule,
+        payload::{DaemonError, ErrorCode},
+        playlist::PlaylistMode,
+        socket::{Command, LegacyRequest},
+    },
+    utils::schedule::{parse_duration_secs, parse_wall_clock},
+};
+
+use chrono::{DateTime, Local};
 use std::path::PathBuf;
 
 /// Supported audio file extensions
 const SUPPORTED_AUDIO_EXTENSIONS: &[&str] = &["mp3", "wav", "ogg", "flac", "m4a", "aac", "opus"];
 
 /// Validates that a file path is safe and points to a valid audio file.
-/// Returns None if the path is invalid or potentially malicious.
-fn validate_audio_path(path_str: &str) -> Option<PathBuf> {
-    if path_str.is_empty() {
-        return None;
+/// Fails with `NotFound` if the file can't be reached and `InvalidArg` if the
+/// path is invalid or potentially malicious.
+fn validate_audio_path(path_str: &str) -> Result<PathBuf, DaemonError> {
+    let invalid = || DaemonError::new(ErrorCode::InvalidArg, "Invalid file path");
+
+    if path_str.trim().is_empty() {
+        return Err(DaemonError::new(ErrorCode::InvalidArg, "Missing file path"));
     }
 
     // Security: Reject paths with null bytes
     if path_str.contains('\0') {
-        return None;
+        return Err(invalid());
     }
 
     let path = PathBuf::from(path_str);
@@ -22,7 +35,13 @@ fn validate_audio_path(path_str: &str) -> Option<PathBuf> {
     // Security: Canonicalize to resolve symlinks and ../ sequences
     let canonical = match path.canonicalize() {
         Ok(p) => p,
-        Err(_) => return None, // File doesn't exist or permission denied
+        // File doesn't exist or permission denied
+        Err(_) => {
+            return Err(DaemonError::new(
+                ErrorCode::NotFound,
+                format!("File not found: {}", path_str),
+            ))
+        }
     };
 
     // Security (Windows): reject NTFS Alternate Data Streams (foo.wav:evil.exe).
@@ -34,33 +53,271 @@ fn validate_audio_path(path_str: &str) -> Option<PathBuf> {
         // Skip the drive letter colon (index 1), if present.
         let tail_has_colon = s.char_indices().skip(2).any(|(_, c)| c == ':');
         if tail_has_colon {
-            return None;
+            return Err(invalid());
         }
     }
 
     // Verify it's a file, not a directory
     if !canonical.is_file() {
-        return None;
+        return Err(invalid());
     }
 
     // Verify it has a supported audio extension
     if let Some(ext) = canonical.extension() {
         let ext_lower = ext.to_string_lossy().to_lowercase();
         if SUPPORTED_AUDIO_EXTENSIONS.contains(&ext_lower.as_str()) {
-            return Some(canonical);
+            return Ok(canonical);
         }
     }
 
-    None
+    Err(DaemonError::new(
+        ErrorCode::InvalidArg,
+        "Unsupported audio file type",
+    ))
+}
+
+/// Validate the `file_path` arg of a legacy request
+fn parse_audio_path(request: &LegacyRequest) -> Result<PathBuf, DaemonError> {
+    validate_audio_path(request.args.get("file_path").map_or("", String::as_str))
+}
+
+/// Build an app route rule from an application name and/or process binary.
+/// Returns None if neither is set.
+fn app_route_rule(
+    application_name: Option<&str>,
+    process_binary: Option<&str>,
+) -> Option<AppRouteRule> {
+    let non_empty = |value: Option<&str>| {
+        value
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+
+    let rule = AppRouteRule {
+        application_name: non_empty(application_name),
+        process_binary: non_empty(process_binary),
+    };
+
+    if rule.application_name.is_none() && rule.process_binary.is_none() {
+        return None;
+    }
+    Some(rule)
+}
+
+/// Build an app route rule from `application_name` / `process_binary` args.
+/// Returns None if neither is set.
+fn parse_app_route_rule(request: &LegacyRequest) -> Option<AppRouteRule> {
+    app_route_rule(
+        request.args.get("application_name").map(String::as_str),
+        request.args.get("process_binary").map(String::as_str),
+    )
+}
+
+/// Optional `priority` arg: None if invalid, Some(None) if not given
+fn parse_priority(request: &LegacyRequest) -> Option<Option<u8>> {
+    match request.args.get("priority").map(|s| s.trim()) {
+        None | Some("") => Some(None),
+        Some(s) => s.parse::<u8>().ok().map(Some),
+    }
+}
+
+/// Work out when a scheduled play first runs and how often it repeats.
+/// `delay` and `at` are mutually exclusive; with neither, the first play is
+/// one interval from now.
+fn schedule_timing(
+    delay: Option<&str>,
+    at: Option<&str>,
+    every: Option<&str>,
+    now: DateTime<Local>,
+) -> Option<(u64, Option<u64>)> {
+    let interval = match non_empty(every) {
+        Some(every) => Some(parse_duration_secs(every).filter(|secs| *secs > 0)?),
+        None => None,
+    };
+    let now_secs = u64::try_from(now.timestamp()).ok()?;
+
+    let start_at = match (non_empty(delay), non_empty(at)) {
+        (Some(_), Some(_)) => return None,
+        (Some(delay), None) => now_secs.checked_add(parse_duration_secs(delay)?)?,
+        (None, Some(at)) => u64::try_from(parse_wall_clock(at, &now)?.timestamp())
+            .ok()
+            .filter(|at| *at >= now_secs)?,
+        (None, None) => now_secs.checked_add(interval?)?,
+    };
+
+    Some((start_at, interval))
+}
+
+fn non_empty(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|s| !s.is_empty())
+}
+
+/// Schedule timing from the `delay`, `at` and `every` args
+fn parse_schedule_timing(
+    request: &LegacyRequest,
+    now: DateTime<Local>,
+) -> Option<(u64, Option<u64>)> {
+    let arg = |key: &str| request.args.get(key).map(String::as_str);
+    schedule_timing(arg("delay"), arg("at"), arg("every"), now)
+}
+
+/// Empty labels are treated as no label
+fn non_empty_label(label: Option<&str>) -> Option<String> {
+    label
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+/// Build the executable for a typed command. Arguments were already checked
+/// by deserialization, so only file paths and schedule times are validated.
+pub fn command_executable(command: Command) -> Box<dyn Executable + Send> {
+    match command {
+        Command::Hello { client } => Box::new(HelloCommand { client }),
+        Command::Subscribe => Box::new(SubscribeCommand {}),
+        Command::Batch { commands } => Box::new(BatchCommand { commands }),
+        Command::Ping => Box::new(PingCommand {}),
+        Command::Pause => Box::new(PublishPlayerState(PauseCommand {})),
+        Command::Resume => Box::new(PublishPlayerState(ResumeCommand {})),
+        Command::TogglePause => Box::new(PublishPlayerState(TogglePauseCommand {})),
+        Command::Stop => Box::new(PublishPlayerState(StopCommand {})),
+        Command::Play {
+            file_path,
+            priority,
+        } => Box::new(PublishPlayerState(PlayCommand {
+            file_path: validate_audio_path(&file_path),
+            priority: Some(priority),
+        })),
+        Command::Preview { file_path } => Box::new(PublishPlayerState(PreviewCommand {
+            file_path: validate_audio_path(&file_path),
+        })),
+        Command::IsPaused => Box::new(IsPausedCommand {}),
+        Command::GetVolume => Box::new(GetVolumeCommand {}),
+        Command::SetVolume { volume } => Box::new(PublishPlayerState(SetVolumeCommand {
+            volume: Some(volume),
+        })),
+        Command::GetGain => Box::new(GetGainCommand {}),
+        Command::SetGain { gain } => {
+            Box::new(PublishPlayerState(SetGainCommand { gain: Some(gain) }))
+        }
+        Command::GetMicGain => Box::new(GetMicGainCommand {}),
+        Command::SetMicGain { mic_gain } => Box::new(PublishPlayerState(SetMicGainCommand {
+            mic_gain: Some(mic_gain),
+        })),
+        Command::GetPosition => Box::new(GetPositionCommand {}),
+        Command::Seek { position } => Box::new(PublishPlayerState(SeekCommand {
+            position: Some(position),
+        })),
+        Command::GetDuration => Box::new(GetDurationCommand {}),
+        Command::GetState => Box::new(GetStateCommand {}),
+        Command::GetCurrentFilePath => Box::new(GetCurrentFilePathCommand {}),
+        Command::GetInput => Box::new(GetCurrentInputCommand {}),
+        Command::GetInputs => Box::new(GetAllInputsCommand {}),
+        Command::SetInput { input_name } => Box::new(PublishDevices(SetCurrentInputCommand {
+            name: Some(input_name),
+        })),
+        Command::GetOutput => Box::new(GetCurrentOutputCommand {}),
+        Command::GetOutputs => Box::new(GetAllOutputsCommand {}),
+        Command::SetOutput { output_name } => Box::new(PublishDevices(SetCurrentOutputCommand {
+            name: Some(output_name),
+        })),
+        Command::GetLoop => Box::new(GetLoopCommand {}),
+        Command::SetLoop { enabled } => Box::new(PublishPlayerState(SetLoopCommand {
+            enabled: Some(enabled),
+        })),
+        Command::ToggleLoop => Box::new(PublishPlayerState(ToggleLoopCommand {})),
+        Command::SetFollowDefaultInput { enabled } => {
+            Box::new(PublishDevices(SetFollowDefaultInputCommand {
+                enabled: Some(enabled),
+            }))
+        }
+        Command::SetFollowDefaultOutput { enabled } => {
+            Box::new(PublishDevices(SetFollowDefaultOutputCommand {
+                enabled: Some(enabled),
+            }))
+        }
+        Command::PlayOnLayer {
+            layer_index,
+            file_path,
+            priority,
+        } => Box::new(PublishPlayerState(PlayOnLayerCommand {
+            layer_index: Some(layer_index),
+            file_path: validate_audio_path(&file_path),
+            priority: Some(priority),
+        })),
+        Command::StopLayer { layer_index } => Box::new(PublishPlayerState(StopLayerCommand {
+            layer_index: Some(layer_index),
+        })),
+        Command::StopAllLayers => Box::new(PublishPlayerState(StopAllLayersCommand {})),
+        Command::SetLayerVolume {
+            layer_index,
+            volume,
+        } => Box::new(PublishPlayerState(SetLayerVolumeCommand {
+            layer_index: Some(layer_index),
+            volume: Some(volume),
+        })),
+        Command::GetLayersInfo => Box::new(GetLayersInfoCommand {}),
+        Command::GetAppStreams => Box::new(GetAppStreamsCommand {}),
+        Command::GetAppRoutes => Box::new(GetAppRoutesCommand {}),
+        Command::AddAppRoute {
+            application_name,
+            process_binary,
+        } => Box::new(AddAppRouteCommand {
+            rule: app_route_rule(application_name.as_deref(), process_binary.as_deref()),
+        }),
+        Command::RemoveAppRoute {
+            application_name,
+            process_binary,
+        } => Box::new(RemoveAppRouteCommand {
+            rule: app_route_rule(application_name.as_deref(), process_binary.as_deref()),
+        }),
+        Command::RunMacro { name } => Box::new(RunMacroCommand { name: Some(name) }),
+        Command::CancelMacro { name } => Box::new(CancelMacroCommand { name: Some(name) }),
+        Command::GetRunningMacros => Box::new(GetRunningMacrosCommand {}),
+        Command::SchedulePlay {
+            file_path,
+            layer_index,
+            delay,
+            at,
+            every,
+            label,
+        } => Box::new(SchedulePlayCommand {
+            file_path: validate_audio_path(&file_path),
+            layer_index: Some(layer_index),
+            timing: schedule_timing(
+                delay.as_deref(),
+                at.as_deref(),
+                every.as_deref(),
+                Local::now(),
+            ),
+            label: non_empty_label(label.as_deref()),
+        }),
+        Command::ListSchedules => Box::new(ListSchedulesCommand {}),
+        Command::CancelSchedule { id } => Box::new(CancelScheduleCommand { id: Some(id) }),
+        Command::PlayPlaylist { name, mode } => Box::new(PublishPlayerState(PlayPlaylistCommand {
+            name: Some(name),
+            mode: Some(mode),
+        })),
+        Command::GetPlaylist => Box::new(GetActivePlaylistCommand {}),
+        Command::GetLibrary => Box::new(GetLibraryCommand {}),
+        Command::GetMidiMappings => Box::new(GetMidiMappingsCommand {}),
+        Command::AddMidiMapping { mapping } => Box::new(AddMidiMappingCommand { mapping }),
+        Command::RemoveMidiMapping { channel, trigger } => {
+            Box::new(RemoveMidiMappingCommand { channel, trigger })
+        }
+        Command::MidiLearn => Box::new(MidiLearnCommand {}),
+    }
 }
 
-pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
+/// Parse a request in the legacy `{name, args}` shape
+pub fn parse_command(request: &LegacyRequest) -> Option<Box<dyn Executable + Send>> {
     match request.name.as_str() {
         "ping" => Some(Box::new(PingCommand {})),
-        "pause" => Some(Box::new(PauseCommand {})),
-        "resume" => Some(Box::new(ResumeCommand {})),
-        "toggle_pause" => Some(Box::new(TogglePauseCommand {})),
-        "stop" => Some(Box::new(StopCommand {})),
+        "pause" => Some(Box::new(PublishPlayerState(PauseCommand {}))),
+        "resume" => Some(Box::new(PublishPlayerState(ResumeCommand {}))),
+        "toggle_pause" => Some(Box::new(PublishPlayerState(TogglePauseCommand {}))),
+        "stop" => Some(Box::new(PublishPlayerState(StopCommand {}))),
         "is_paused" => Some(Box::new(IsPausedCommand {})),
         "get_state" => Some(Box::new(GetStateCommand {})),
         "get_volume" => Some(Box::new(GetVolumeCommand {})),
@@ -71,7 +328,7 @@ pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
                 .unwrap_or(&String::new())
                 .parse::<f32>()
                 .ok();
-            Some(Box::new(SetVolumeCommand { volume }))
+            Some(Box::new(PublishPlayerState(SetVolumeCommand { volume })))
         }
         "get_gain" => Some(Box::new(GetGainCommand {})),
         "set_gain" => {
@@ -81,7 +338,7 @@ pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
                 .unwrap_or(&String::new())
                 .parse::<f32>()
                 .ok();
-            Some(Box::new(SetGainCommand { gain }))
+            Some(Box::new(PublishPlayerState(SetGainCommand { gain })))
         }
         "get_mic_gain" => Some(Box::new(GetMicGainCommand {})),
         "set_mic_gain" => {
@@ -91,7 +348,7 @@ pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
                 .unwrap_or(&String::new())
                 .parse::<f32>()
                 .ok();
-            Some(Box::new(SetMicGainCommand { mic_gain }))
+            Some(Box::new(PublishPlayerState(SetMicGainCommand { mic_gain })))
         }
         "get_position" => Some(Box::new(GetPositionCommand {})),
         "seek" => {
@@ -101,35 +358,33 @@ pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
                 .unwrap_or(&String::new())
                 .parse::<f32>()
                 .ok();
-            Some(Box::new(SeekCommand { position }))
+            Some(Box::new(PublishPlayerState(SeekCommand { position })))
         }
         "get_duration" => Some(Box::new(GetDurationCommand {})),
         "play" => {
-            let file_path = request
-                .args
-                .get("file_path")
-                .and_then(|s| validate_audio_path(s));
-            Some(Box::new(PlayCommand { file_path }))
+            let file_path = parse_audio_path(request);
+            let priority = parse_priority(request);
+            Some(Box::new(PublishPlayerState(PlayCommand {
+                file_path,
+                priority,
+            })))
         }
         "preview" => {
-            let file_path = request
-                .args
-                .get("file_path")
-                .and_then(|s| validate_audio_path(s));
-            Some(Box::new(PreviewCommand { file_path }))
+            let file_path = parse_audio_path(request);
+            Some(Box::new(PublishPlayerState(PreviewCommand { file_path })))
         }
         "get_current_file_path" => Some(Box::new(GetCurrentFilePathCommand {})),
         "get_input" => Some(Box::new(GetCurrentInputCommand {})),
         "get_inputs" => Some(Box::new(GetAllInputsCommand {})),
         "set_input" => {
             let name = request.args.get("input_name").cloned();
-            Some(Box::new(SetCurrentInputCommand { name }))
+            Some(Box::new(PublishDevices(SetCurrentInputCommand { name })))
         }
         "get_output" => Some(Box::new(GetCurrentOutputCommand {})),
         "get_outputs" => Some(Box::new(GetAllOutputsCommand {})),
         "set_output" => {
             let name = request.args.get("output_name").cloned();
-            Some(Box::new(SetCurrentOutputCommand { name }))
+            Some(Box::new(PublishDevices(SetCurrentOutputCommand { name })))
         }
         "get_loop" => Some(Box::new(GetLoopCommand {})),
         "set_loop" => {
@@ -139,25 +394,46 @@ pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
                 .unwrap_or(&String::new())
                 .parse::<bool>()
                 .ok();
-            Some(Box::new(SetLoopCommand { enabled }))
+            Some(Box::new(PublishPlayerState(SetLoopCommand { enabled })))
         }
-        "toggle_loop" => Some(Box::new(ToggleLoopCommand {})),
-        // Layer commands
-        "play_on_layer" => {
-            let layer_index = request
+        "toggle_loop" => Some(Box::new(PublishPlayerState(ToggleLoopCommand {}))),
+        "set_follow_default_input" => {
+            let enabled = request
                 .args
-                .get("layer_index")
+                .get("enabled")
                 .unwrap_or(&String::new())
-                .parse::<usize>()
+                .parse::<bool>()
                 .ok();
-            let file_path = request
+            Some(Box::new(PublishDevices(SetFollowDefaultInputCommand {
+                enabled,
+            })))
+        }
+        "set_follow_default_output" => {
+            let enabled = request
                 .args
-                .get("file_path")
-                .and_then(|s| validate_audio_path(s));
-            Some(Box::new(PlayOnLayerCommand {
+                .get("enabled")
+                .unwrap_or(&String::new())
+                .parse::<bool>()
+                .ok();
+            Some(Box::new(PublishDevices(SetFollowDefaultOutputCommand {
+                enabled,
+            })))
+        }
+        // Layer commands
+        "play_on_layer" => {
+            // "auto" lets the daemon pick a free layer, or steal one
+            let layer_index = match request.args.get("layer_index").map(|s| s.trim()) {
+                Some("auto") => Some(None),
+                Some(s) => s.parse::<usize>().ok().map(Some),
+                None => None,
+            };
+            let file_path = parse_audio_path(request);
+            let priority = parse_priority(request);
+            Some(Box::new(PublishPlayerState(PlayOnLayerCommand {
                 layer_index,
                 file_path,
-            }))
+                priority,
+            })))
         }
         "stop_layer" => {
             let layer_index = request
@@ -166,9 +442,11 @@ pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
                 .unwrap_or(&String::new())
                 .parse::<usize>()
                 .ok();
-            Some(Box::new(StopLayerCommand { layer_index }))
+            Some(Box::new(PublishPlayerState(StopLayerCommand {
+                layer_index,
+            })))
         }
-        "stop_all_layers" => Some(Box::new(StopAllLayersCommand {})),
+        "stop_all_layers" => Some(Box::new(PublishPlayerState(StopAllLayersCommand {}))),
         "set_layer_volume" => {
             let layer_index = request
                 .args
@@ -182,12 +460,72 @@ pub fn parse_command(request: &Request) -> Option<Box<dyn Executable + Send>> {
                 .unwrap_or(&String::new())
                 .parse::<f32>()
                 .ok();
-            Some(Box::new(SetLayerVolumeCommand {
+            Some(Box::new(PublishPlayerState(SetLayerVolumeCommand {
                 layer_index,
                 volume,
-            }))
+            })))
         }
         "get_layers_info" => Some(Box::new(GetLayersInfoCommand {})),
+        // App routing commands
+        "get_app_streams" => Some(Box::new(GetAppStreamsCommand {})),
+        "get_app_routes" => Some(Box::new(GetAppRoutesCommand {})),
+        "add_app_route" => {
+            let rule = parse_app_route_rule(request);
+            Some(Box::new(AddAppRouteCommand { rule }))
+        }
+        "remove_app_route" => {
+            let rule = parse_app_route_rule(request);
+            Some(Box::new(RemoveAppRouteCommand { rule }))
+        }
+        // Macro commands
+        "run_macro" => {
+            let name = request.args.get("name").cloned();
+            Some(Box::new(RunMacroCommand { name }))
+        }
+        "cancel_macro" => {
+            let name = request.args.get("name").cloned();
+            Some(Box::new(CancelMacroCommand { name }))
+        }
+        "get_running_macros" => Some(Box::new(GetRunningMacrosCommand {})),
+        // Schedule commands
+        "schedule_play" => {
+            let file_path = parse_audio_path(request);
+            let layer_index = match request.args.get("layer_index").map(|s| s.trim()) {
+                None | Some("") => Some(None),
+                Some(s) => s.parse::<usize>().ok().map(Some),
+            };
+            let timing = parse_schedule_timing(request, Local::now());
+            let label = non_empty_label(request.args.get("label").map(String::as_str));
+            Some(Box::new(SchedulePlayCommand {
+                file_path,
+                layer_index,
+                timing,
+                label,
+            }))
+        }
+        "list_schedules" => Some(Box::new(ListSchedulesCommand {})),
+        "cancel_schedule" => {
+            let id = request
+                .args
+                .get("id")
+                .unwrap_or(&String::new())
+                .parse::<u64>()
+                .ok();
+            Some(Box::new(CancelScheduleCommand { id }))
+        }
+        // Playlist commands
+        "play_playlist" => {
+            let name = request.args.get("name").cloned();
+            let mode = match request.args.get("mode").map(|s| s.trim()) {
+                None | Some("") => Some(None),
+                Some(s) => s.parse::<PlaylistMode>().ok().map(Some),
+            };
+            Some(Box::new(PublishPlayerState(PlayPlaylistCommand {
+                name,
+                mode,
+            })))
+        }
+        "get_playlist" => Some(Box::new(GetActivePlaylistCommand {})),
         _ => None,
     }
 }
@@ -200,18 +538,30 @@ mod tests {
 
     #[test]
     fn test_validate_audio_path_empty_string() {
-        assert!(validate_audio_path("").is_none());
+        assert!(validate_audio_path("").is_err());
     }
 
     #[test]
     fn test_validate_audio_path_null_byte() {
-        assert!(validate_audio_path("/path/to/file\0.mp3").is_none());
-        assert!(validate_audio_path("file\0name.wav").is_none());
+        assert!(validate_audio_path("/path/to/file\0.mp3").is_err());
+        assert!(validate_audio_path("file\0name.wav").is_err());
     }
 
     #[test]
     fn test_validate_audio_path_nonexistent_file() {
-        assert!(validate_audio_path("/nonexistent/path/to/audio.mp3").is_none());
+        assert!(validate_audio_path("/nonexistent/path/to/audio.mp3").is_err());
+    }
+
+    #[test]
+    fn test_validate_audio_path_error_codes() {
+        let code = |path: &str| validate_audio_path(path).unwrap_err().code;
+        assert_eq!(code(""), ErrorCode::InvalidArg);
+        assert_eq!(code("/nonexistent/path/to/audio.mp3"), ErrorCode::NotFound);
+
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("notes.txt");
+        File::create(&file_path).unwrap();
+        assert_eq!(code(file_path.to_str().unwrap()), ErrorCode::InvalidArg);
     }
 
     #[test]
@@ -223,7 +573,7 @@ mod tests {
             File::create(&file_path).unwrap();
 
             let result = validate_audio_path(file_path.to_str().unwrap());
-            assert!(result.is_some(), "Extension {} should be valid", ext);
+            assert!(result.is_ok(), "Extension {} should be valid", ext);
         }
     }
 
@@ -238,7 +588,7 @@ mod tests {
             File::create(&file_path).unwrap();
 
             let result = validate_audio_path(file_path.to_str().unwrap());
-            assert!(result.is_none(), "Extension {} should be rejected", ext);
+            assert!(result.is_err(), "Extension {} should be rejected", ext);
         }
     }
 
@@ -250,7 +600,7 @@ mod tests {
 
         let result = validate_audio_path(dir_path.to_str().unwrap());
         assert!(
-            result.is_none(),
+            result.is_err(),
             "Directories should be rejected even with audio extension"
         );
     }
@@ -266,7 +616,7 @@ mod tests {
 
             let result = validate_audio_path(file_path.to_str().unwrap());
             assert!(
-                result.is_some(),
+                result.is_ok(),
                 "Extension {} should be valid (case insensitive)",
                 ext
             );
@@ -283,7 +633,7 @@ mod tests {
         let redundant_path = temp_dir.path().join("./test.mp3");
         let result = validate_audio_path(redundant_path.to_str().unwrap());
 
-        assert!(result.is_some());
+        assert!(result.is_ok());
         // The result should be canonicalized (absolute path)
         assert!(result.unwrap().is_absolute());
     }
@@ -296,18 +646,18 @@ mod tests {
 
         let result = validate_audio_path(file_path.to_str().unwrap());
         assert!(
-            result.is_none(),
+            result.is_err(),
             "Files without extension should be rejected"
         );
     }
 
     // Tests for parse_command function
-    use crate::types::socket::Request;
+    use crate::types::socket::LegacyRequest;
     use std::collections::HashMap;
 
     #[test]
     fn test_parse_command_ping() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "ping".to_string(),
             args: HashMap::new(),
         };
@@ -317,7 +667,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_pause() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "pause".to_string(),
             args: HashMap::new(),
         };
@@ -327,7 +677,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_resume() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "resume".to_string(),
             args: HashMap::new(),
         };
@@ -337,7 +687,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_toggle_pause() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "toggle_pause".to_string(),
             args: HashMap::new(),
         };
@@ -347,7 +697,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_stop() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "stop".to_string(),
             args: HashMap::new(),
         };
@@ -357,7 +707,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_is_paused() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "is_paused".to_string(),
             args: HashMap::new(),
         };
@@ -367,7 +717,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_get_state() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "get_state".to_string(),
             args: HashMap::new(),
         };
@@ -377,7 +727,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_get_volume() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "get_volume".to_string(),
             args: HashMap::new(),
         };
@@ -389,7 +739,7 @@ mod tests {
     fn test_parse_command_set_volume_with_valid_args() {
         let mut args = HashMap::new();
         args.insert("volume".to_string(), "0.5".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "set_volume".to_string(),
             args,
         };
@@ -399,7 +749,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_set_volume_without_args() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "set_volume".to_string(),
             args: HashMap::new(),
         };
@@ -412,7 +762,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_get_gain() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "get_gain".to_string(),
             args: HashMap::new(),
         };
@@ -424,7 +774,7 @@ mod tests {
     fn test_parse_command_set_gain() {
         let mut args = HashMap::new();
         args.insert("gain".to_string(), "1.5".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "set_gain".to_string(),
             args,
         };
@@ -434,7 +784,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_get_mic_gain() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "get_mic_gain".to_string(),
             args: HashMap::new(),
         };
@@ -446,7 +796,7 @@ mod tests {
     fn test_parse_command_set_mic_gain() {
         let mut args = HashMap::new();
         args.insert("mic_gain".to_string(), "2.0".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "set_mic_gain".to_string(),
             args,
         };
@@ -456,7 +806,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_get_position() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "get_position".to_string(),
             args: HashMap::new(),
         };
@@ -468,7 +818,7 @@ mod tests {
     fn test_parse_command_seek() {
         let mut args = HashMap::new();
         args.insert("position".to_string(), "30.5".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "seek".to_string(),
             args,
         };
@@ -478,7 +828,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_get_duration() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "get_duration".to_string(),
             args: HashMap::new(),
         };
@@ -488,7 +838,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_get_current_file_path() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "get_current_file_path".to_string(),
             args: HashMap::new(),
         };
@@ -501,7 +851,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_get_input() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "get_input".to_string(),
             args: HashMap::new(),
         };
@@ -511,7 +861,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_get_inputs() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "get_inputs".to_string(),
             args: HashMap::new(),
         };
@@ -523,7 +873,7 @@ mod tests {
     fn test_parse_command_set_input() {
         let mut args = HashMap::new();
         args.insert("input_name".to_string(), "mic1".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "set_input".to_string(),
             args,
         };
@@ -533,7 +883,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_get_output() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "get_output".to_string(),
             args: HashMap::new(),
         };
@@ -543,7 +893,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_get_outputs() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "get_outputs".to_string(),
             args: HashMap::new(),
         };
@@ -555,7 +905,7 @@ mod tests {
     fn test_parse_command_set_output() {
         let mut args = HashMap::new();
         args.insert("output_name".to_string(), "speaker1".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "set_output".to_string(),
             args,
         };
@@ -565,7 +915,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_get_loop() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "get_loop".to_string(),
             args: HashMap::new(),
         };
@@ -577,7 +927,7 @@ mod tests {
     fn test_parse_command_set_loop() {
         let mut args = HashMap::new();
         args.insert("enabled".to_string(), "true".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "set_loop".to_string(),
             args,
         };
@@ -587,7 +937,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_toggle_loop() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "toggle_loop".to_string(),
             args: HashMap::new(),
         };
@@ -599,7 +949,7 @@ mod tests {
     fn test_parse_command_stop_layer() {
         let mut args = HashMap::new();
         args.insert("layer_index".to_string(), "0".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "stop_layer".to_string(),
             args,
         };
@@ -609,7 +959,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_stop_all_layers() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "stop_all_layers".to_string(),
             args: HashMap::new(),
         };
@@ -622,7 +972,7 @@ mod tests {
         let mut args = HashMap::new();
         args.insert("layer_index".to_string(), "1".to_string());
         args.insert("volume".to_string(), "0.8".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "set_layer_volume".to_string(),
             args,
         };
@@ -635,7 +985,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_get_layers_info() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "get_layers_info".to_string(),
             args: HashMap::new(),
         };
@@ -643,9 +993,166 @@ mod tests {
         assert!(result.is_some(), "get_layers_info command should be parsed");
     }
 
+    #[test]
+    fn test_parse_command_follow_default() {
+        for name in ["set_follow_default_input", "set_follow_default_output"] {
+            let mut args = HashMap::new();
+            args.insert("enabled".to_string(), "true".to_string());
+            let request = LegacyRequest {
+                name: name.to_string(),
+                args,
+            };
+            assert!(
+                parse_command(&request).is_some(),
+                "{} command should be parsed",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_command_app_routing() {
+        for name in [
+            "get_app_streams",
+            "get_app_routes",
+            "add_app_route",
+            "remove_app_route",
+        ] {
+            let request = LegacyRequest {
+                name: name.to_string(),
+                args: HashMap::new(),
+            };
+            assert!(
+                parse_command(&request).is_some(),
+                "{} command should be parsed",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_command_macros() {
+        for name in ["run_macro", "cancel_macro", "get_running_macros"] {
+            let request = LegacyRequest {
+                name: name.to_string(),
+                args: HashMap::new(),
+            };
+            assert!(
+                parse_command(&request).is_some(),
+                "{} command should be parsed",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_command_schedules() {
+        for name in ["schedule_play", "list_schedules", "cancel_schedule"] {
+            let request = LegacyRequest {
+                name: name.to_string(),
+                args: HashMap::new(),
+            };
+            assert!(
+                parse_command(&request).is_some(),
+                "{} command should be parsed",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_command_playlists() {
+        for name in ["play_playlist", "get_playlist"] {
+            let request = LegacyRequest {
+                name: name.to_string(),
+                args: HashMap::new(),
+            };
+            assert!(
+                parse_command(&request).is_some(),
+                "{} command should be parsed",
+                name
+            );
+        }
+    }
+
+    fn schedule_request(args: &[(&str, &str)]) -> LegacyRequest {
+        LegacyRequest {
+            name: "schedule_play".to_string(),
+            args: args
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_schedule_timing() {
+        let now = Local::now();
+        let now_secs = now.timestamp() as u64;
+
+        let delayed = parse_schedule_timing(&schedule_request(&[("delay", "90")]), now);
+        assert_eq!(delayed, Some((now_secs + 90, None)));
+
+        let recurring = parse_schedule_timing(&schedule_request(&[("every", "30m")]), now);
+        assert_eq!(recurring, Some((now_secs + 1800, Some(1800))));
+
+        let delayed_recurring =
+            parse_schedule_timing(&schedule_request(&[("delay", "10s"), ("every", "1h")]), now);
+        assert_eq!(delayed_recurring, Some((now_secs + 10, Some(3600))));
+
+        let at = parse_schedule_timing(&schedule_request(&[("at", "23:59:59")]), now);
+        assert!(at.is_some_and(|(start, interval)| start >= now_secs && interval.is_none()));
+    }
+
+    #[test]
+    fn test_parse_schedule_timing_invalid() {
+        let now = Local::now();
+
+        assert_eq!(parse_schedule_timing(&schedule_request(&[]), now), None);
+        assert_eq!(
+            parse_schedule_timing(&schedule_request(&[("delay", "10"), ("at", "12:00")]), now),
+            None
+        );
+        assert_eq!(
+            parse_schedule_timing(&schedule_request(&[("every", "0")]), now),
+            None
+        );
+        assert_eq!(
+            parse_schedule_timing(&schedule_request(&[("at", "2000-01-01 00:00")]), now),
+            None
+        );
+        assert_eq!(
+            parse_schedule_timing(&schedule_request(&[("delay", "soon")]), now),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_app_route_rule() {
+        let mut args = HashMap::new();
+        args.insert("application_name".to_string(), " Discord ".to_string());
+        args.insert("process_binary".to_string(), "".to_string());
+        let request = LegacyRequest {
+            name: "add_app_route".to_string(),
+            args,
+        };
+        let rule = parse_app_route_rule(&request).expect("rule should parse");
+        assert_eq!(rule.application_name.as_deref(), Some("Discord"));
+        assert!(rule.process_binary.is_none());
+    }
+
+    #[test]
+    fn test_parse_app_route_rule_empty_is_none() {
+        let request = LegacyRequest {
+            name: "add_app_route".to_string(),
+            args: HashMap::new(),
+        };
+        assert!(parse_app_route_rule(&request).is_none());
+    }
+
     #[test]
     fn test_parse_command_unknown_returns_none() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "unknown_command".to_string(),
             args: HashMap::new(),
         };
@@ -655,7 +1162,7 @@ mod tests {
 
     #[test]
     fn test_parse_command_empty_name_returns_none() {
-        let request = Request {
+        let request = LegacyRequest {
             name: "".to_string(),
             args: HashMap::new(),
         };
@@ -667,7 +1174,7 @@ mod tests {
     fn test_parse_command_invalid_volume_string() {
         let mut args = HashMap::new();
         args.insert("volume".to_string(), "not_a_number".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "set_volume".to_string(),
             args,
         };
@@ -685,7 +1192,7 @@ mod tests {
     fn test_parse_command_set_volume_negative() {
         let mut args = HashMap::new();
         args.insert("volume".to_string(), "-0.5".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "set_volume".to_string(),
             args,
         };
@@ -700,7 +1207,7 @@ mod tests {
     fn test_parse_command_set_gain_exceeds_max() {
         let mut args = HashMap::new();
         args.insert("gain".to_string(), "999.0".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "set_gain".to_string(),
             args,
         };
@@ -711,7 +1218,7 @@ mod tests {
     fn test_parse_command_seek_negative_position() {
         let mut args = HashMap::new();
         args.insert("position".to_string(), "-10.0".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "seek".to_string(),
             args,
         };
@@ -723,7 +1230,7 @@ mod tests {
         let mut args = HashMap::new();
         args.insert("volume".to_string(), "0.5".to_string());
         // No layer_index provided
-        let request = Request {
+        let request = LegacyRequest {
             name: "set_layer_volume".to_string(),
             args,
         };
@@ -738,7 +1245,7 @@ mod tests {
         let mut args = HashMap::new();
         args.insert("layer_index".to_string(), "abc".to_string());
         args.insert("volume".to_string(), "0.5".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "set_layer_volume".to_string(),
             args,
         };
@@ -753,18 +1260,30 @@ mod tests {
         let mut args = HashMap::new();
         args.insert("layer_index".to_string(), "0".to_string());
         // No file_path
-        let request = Request {
+        let request = LegacyRequest {
             name: "play_on_layer".to_string(),
             args,
         };
         assert!(parse_command(&request).is_some());
     }
 
+    #[test]
+    fn test_parse_priority() {
+        let request = |priority: &str| LegacyRequest {
+            name: "play".to_string(),
+            args: HashMap::from([("priority".to_string(), priority.to_string())]),
+        };
+        assert_eq!(parse_priority(&request("")), Some(None));
+        assert_eq!(parse_priority(&request(" 7 ")), Some(Some(7)));
+        assert_eq!(parse_priority(&request("256")), None);
+        assert_eq!(parse_priority(&request("high")), None);
+    }
+
     #[test]
     fn test_parse_command_set_loop_invalid_bool() {
         let mut args = HashMap::new();
         args.insert("enabled".to_string(), "maybe".to_string());
-        let request = Request {
+        let request = LegacyRequest {
             name: "set_loop".to_string(),
             args,
         };
@@ -778,8 +1297,8 @@ mod tests {
 
     #[test]
     fn test_validate_audio_path_traversal_rejected() {
-        assert!(validate_audio_path("../../../etc/passwd").is_none());
-        assert!(validate_audio_path("/tmp/../etc/shadow.mp3").is_none());
+        assert!(validate_audio_path("../../../etc/passwd").is_err());
+        assert!(validate_audio_path("/tmp/../etc/shadow.mp3").is_err());
     }
 
     #[test]
@@ -788,7 +1307,7 @@ mod tests {
         let file_path = temp_dir.path().join("file.exe.mp3");
         File::create(&file_path).unwrap();
         // Should pass since the final extension is .mp3 and it's a real file
-        assert!(validate_audio_path(file_path.to_str().unwrap()).is_some());
+        assert!(validate_audio_path(file_path.to_str().unwrap()).is_ok());
     }
 
     #[test]
@@ -797,7 +1316,7 @@ mod tests {
         let file_path = temp_dir.path().join(".hidden.mp3");
         File::create(&file_path).unwrap();
         // Hidden files with valid extensions should still pass (they're real audio files)
-        assert!(validate_audio_path(file_path.to_str().unwrap()).is_some());
+        assert!(validate_audio_path(file_path.to_str().unwrap()).is_ok());
     }
 
     #[cfg(target_os = "linux")]
@@ -809,11 +1328,58 @@ mod tests {
         let link = temp_dir.path().join("trick.mp3");
         std::os::unix::fs::symlink(&target, &link).unwrap();
         // After canonicalization, extension is .txt -> rejected
-        assert!(validate_audio_path(link.to_str().unwrap()).is_none());
+        assert!(validate_audio_path(link.to_str().unwrap()).is_err());
     }
 
     #[test]
     fn test_validate_audio_path_whitespace_only() {
-        assert!(validate_audio_path("   ").is_none());
+        assert!(validate_audio_path("   ").is_err());
+    }
+
+    #[test]
+    fn test_legacy_shape_covers_every_command() {
+        // Until the legacy shape is dropped, every typed command needs a
+        // legacy name too. Hello, subscribe, batch, the library and MIDI
+        // commands are new in the typed protocol.
+        let typed_only = [
+            "hello",
+            "subscribe",
+            "batch",
+            "get_library",
+            "get_midi_mappings",
+            "add_midi_mapping",
+            "remove_midi_mapping",
+            "midi_learn",
+        ];
+        for name in Command::NAMES
+            .iter()
+            .filter(|name| !typed_only.contains(*name))
+        {
+            let request = LegacyRequest::new(*name, vec![]);
+            assert!(
+                parse_command(&request).is_some(),
+                "{} has no legacy form",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_schedule_timing_typed() {
+        let now = Local::now();
+        let now_secs = now.timestamp() as u64;
+
+        assert_eq!(
+            schedule_timing(Some("90"), None, Some("1h"), now),
+            Some((now_secs + 90, Some(3600)))
+        );
+        assert_eq!(schedule_timing(Some(" "), None, None, now), None);
+    }
+
+    #[test]
+    fn test_app_route_rule_typed() {
+        let rule = app_route_rule(None, Some("firefox")).expect("rule should build");
+        assert_eq!(rule.process_binary.as_deref(), Some("firefox"));
+        assert!(app_route_rule(Some(""), None).is_none());
     }
 }
@@ -1,19 +1,38 @@
 use crate::{
+    MutexExt,
     types::{
-        audio_player::AudioPlayer,
-        config::DaemonConfig,
+        audio_player::{AudioPlayer, SystemDefaults, get_system_defaults},
+        config::{AudioBackend, DaemonConfig, GuiConfig, SoundMetadata},
+        events::Event,
+        payload::{DaemonError, ErrorCode},
         socket::{Request, Response},
     },
+    utils::events::publish_devices,
 };
 #[cfg(target_os = "linux")]
 use crate::{DAEMON_OUTPUT_NAME, VIRTUAL_MIC_NAME};
 #[cfg(target_os = "linux")]
-use crate::utils::pipewire::{create_link, get_all_devices};
-use std::path::PathBuf;
-use std::{error::Error, fs};
+use crate::{
+    types::pipewire::LinkHandle,
+    utils::pipewire::{create_link, get_all_devices, roundtrip, subscribe_graph_changes},
+};
+use serde::de::DeserializeOwned;
+use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, fs,
+    future::Future,
+    ops::{Deref, DerefMut},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    sync::{Mutex, OnceCell},
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf},
+    sync::{Mutex, MutexGuard, OnceCell, OwnedMutexGuard, oneshot},
+    task::JoinHandle,
     time::{Duration, sleep},
 };
 
@@ -21,15 +40,19 @@ use tokio::{
 #[cfg(target_os = "windows")]
 pub const DAEMON_TCP_PORT: u16 = 19735;
 
+/// How often default devices are polled on Windows (no change notifications there)
+#[cfg(target_os = "windows")]
+const DEFAULT_DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
 static AUDIO_PLAYER: OnceCell<Mutex<AudioPlayer>> = OnceCell::const_new();
 
 /// Initialize the audio player. Must be called before get_audio_player().
-pub async fn init_audio_player() -> Result<(), Box<dyn Error + Send + Sync>> {
+pub async fn init_audio_player(backend: &AudioBackend) -> Result<(), Box<dyn Error + Send + Sync>> {
     if AUDIO_PLAYER.get().is_some() {
         return Ok(());
     }
 
-    let player = AudioPlayer::new()
+    let player = AudioPlayer::new(backend)
         .await
         .map_err(|e| -> Box<dyn Error + Send + Sync> { e.to_string().into() })?;
     AUDIO_PLAYER
@@ -50,6 +73,59 @@ pub fn try_get_audio_player() -> Option<&'static Mutex<AudioPlayer>> {
     AUDIO_PLAYER.get()
 }
 
+tokio::task_local! {
+    // Set while a batch holds the player lock, so its commands reuse it
+    static HELD_AUDIO_PLAYER: Arc<Mutex<MutexGuard<'static, AudioPlayer>>>;
+}
+
+/// A locked audio player, either locked by the caller or by the batch it's
+/// running in
+pub enum AudioPlayerGuard {
+    Locked(MutexGuard<'static, AudioPlayer>),
+    Held(OwnedMutexGuard<MutexGuard<'static, AudioPlayer>>),
+}
+
+impl Deref for AudioPlayerGuard {
+    type Target = AudioPlayer;
+
+    fn deref(&self) -> &AudioPlayer {
+        match self {
+            AudioPlayerGuard::Locked(guard) => guard,
+            AudioPlayerGuard::Held(guard) => guard,
+        }
+    }
+}
+
+impl DerefMut for AudioPlayerGuard {
+    fn deref_mut(&mut self) -> &mut AudioPlayer {
+        match self {
+            AudioPlayerGuard::Locked(guard) => guard,
+            AudioPlayerGuard::Held(guard) => guard,
+        }
+    }
+}
+
+/// Lock the audio player. Inside `with_audio_player_held` this reuses the
+/// held lock instead of waiting for it forever.
+pub async fn lock_audio_player() -> AudioPlayerGuard {
+    match HELD_AUDIO_PLAYER.try_with(Arc::clone) {
+        Ok(held) => AudioPlayerGuard::Held(held.lock_owned().await),
+        Err(_) => AudioPlayerGuard::Locked(get_audio_player().lock().await),
+    }
+}
+
+/// Run `future` while holding the audio player lock, so nothing else touches
+/// the player until it's done
+pub async fn with_audio_player_held<F: Future>(future: F) -> F::Output {
+    if HELD_AUDIO_PLAYER.try_with(|_| ()).is_ok() {
+        return future.await;
+    }
+    let guard = get_audio_player().lock().await;
+    HELD_AUDIO_PLAYER
+        .scope(Arc::new(Mutex::new(guard)), future)
+        .await
+}
+
 pub fn get_daemon_config() -> DaemonConfig {
     DaemonConfig::load_from_file().unwrap_or_else(|e| {
         tracing::error!("Failed to load daemon config ({}), using defaults", e);
@@ -59,8 +135,40 @@ pub fn get_daemon_config() -> DaemonConfig {
     })
 }
 
+/// Edit daemon.json. Updates run one at a time, so two commands editing it
+/// at once can't drop each other's change. Nothing is saved if `update`
+/// fails.
+pub fn update_daemon_config<T>(
+    update: impl FnOnce(&mut DaemonConfig) -> Result<T, DaemonError>,
+) -> Result<T, DaemonError> {
+    static UPDATE_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    let _update = UPDATE_LOCK.lock_or_recover();
+
+    let mut config = get_daemon_config();
+    let result = update(&mut config)?;
+    config.save_to_file().map_err(|e| {
+        DaemonError::new(
+            ErrorCode::ConfigError,
+            format!("Failed to save daemon config: {}", e),
+        )
+    })?;
+    Ok(result)
+}
+
+/// Metadata the GUI saved for a sound (cooldown, priority, ...). Read fresh
+/// on every play since it is edited in the GUI.
+pub fn get_sound_metadata(file_path: &Path) -> SoundMetadata {
+    GuiConfig::load_from_file()
+        .ok()
+        .and_then(|mut config| config.sound_metadata.remove(file_path))
+        .unwrap_or_default()
+}
+
+/// Link the daemon's playback stream into the virtual mic.
+/// The link lives as long as the returned handle.
 #[cfg(target_os = "linux")]
-pub async fn link_player_to_virtual_mic() -> Result<(), Box<dyn Error>> {
+pub async fn link_player_to_virtual_mic() -> Result<Option<LinkHandle>, Box<dyn Error>> {
+    roundtrip().await?;
     let (input_devices, output_devices) = get_all_devices().await?;
 
     let soundboard_daemon_output = match output_devices
@@ -70,7 +178,7 @@ pub async fn link_player_to_virtual_mic() -> Result<(), Box<dyn Error>> {
         Some(device) => device,
         None => {
             tracing::error!("Could not find soundboard-daemon output device, skipping device linking");
-            return Ok(());
+            return Ok(None);
         }
     };
 
@@ -81,7 +189,7 @@ pub async fn link_player_to_virtual_mic() -> Result<(), Box<dyn Error>> {
         Some(device) => device,
         None => {
             tracing::error!("Could not find soundboard-daemon input device, skipping device linking");
-            return Ok(());
+            return Ok(None);
         }
     };
 
@@ -92,12 +200,14 @@ pub async fn link_player_to_virtual_mic() -> Result<(), Box<dyn Error>> {
         &soundboard_daemon_input.input_fr,
     ) {
         (Some(output_fl), Some(output_fr), Some(input_fl), Some(input_fr)) => {
-            create_link(
+            let link = create_link(
                 output_fl.clone(),
                 output_fr.clone(),
                 input_fl.clone(),
                 input_fr.clone(),
-            )?;
+            )
+            .await?;
+            Ok(Some(link))
         }
         (out_fl, out_fr, in_fl, in_fr) => {
             tracing::error!(
@@ -107,10 +217,56 @@ pub async fn link_player_to_virtual_mic() -> Result<(), Box<dyn Error>> {
                 in_fl.is_some(),
                 in_fr.is_some()
             );
+            Ok(None)
         }
     }
+}
 
-    Ok(())
+/// Keep the player on the system default input/output while it is set to
+/// follow them. Runs for the lifetime of the daemon.
+pub async fn follow_default_devices_loop() {
+    #[cfg(target_os = "linux")]
+    let mut graph_changes = subscribe_graph_changes();
+
+    // Most registry changes leave the defaults alone, so the player is only
+    // locked when they actually moved. Turning following on syncs by itself.
+    let mut applied: Option<SystemDefaults> = None;
+    loop {
+        let defaults = get_system_defaults().await;
+        if applied.as_ref() != Some(&defaults) {
+            let followed = match get_audio_player()
+                .lock()
+                .await
+                .apply_system_defaults(defaults.clone())
+                .await
+            {
+                Ok(followed) => {
+                    applied = Some(defaults);
+                    followed
+                }
+                Err(e) => {
+                    tracing::error!("Failed to follow system default devices: {}", e);
+                    false
+                }
+            };
+            if followed {
+                publish_devices().await;
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            if graph_changes.changed().await.is_err() {
+                tracing::error!("PipeWire manager stopped, no longer following default devices");
+                return;
+            }
+            // A device may have come or gone
+            publish_devices().await;
+        }
+
+        #[cfg(target_os = "windows")]
+        sleep(DEFAULT_DEVICE_POLL_INTERVAL).await;
+    }
 }
 
 pub fn get_runtime_dir() -> PathBuf {
@@ -286,25 +442,203 @@ async fn send_and_receive(
     Ok(serde_json::from_slice(&buffer)?)
 }
 
-pub async fn make_request(request: Request) -> Result<Response, Box<dyn Error + Send + Sync>> {
+#[cfg(target_os = "linux")]
+type DaemonStream = tokio::net::UnixStream;
+#[cfg(target_os = "windows")]
+type DaemonStream = tokio::net::TcpStream;
+
+async fn connect() -> Result<DaemonStream, Box<dyn Error + Send + Sync>> {
     use tokio::time::timeout;
 
     #[cfg(target_os = "linux")]
-    {
-        let socket_path = get_runtime_dir().join("daemon.sock");
-        let stream = timeout(Duration::from_secs(2), tokio::net::UnixStream::connect(socket_path))
-            .await
-            .map_err(|_| "Connection timeout")?
-            .map_err(|e| -> Box<dyn Error + Send + Sync> { e.to_string().into() })?;
-        send_and_receive(stream, request).await
-    }
+    let connecting = tokio::net::UnixStream::connect(get_runtime_dir().join("daemon.sock"));
     #[cfg(target_os = "windows")]
-    {
-        let addr = format!("127.0.0.1:{}", DAEMON_TCP_PORT);
-        let stream = timeout(Duration::from_secs(2), tokio::net::TcpStream::connect(&addr))
-            .await
-            .map_err(|_| "Connection timeout")?
-            .map_err(|e| -> Box<dyn Error + Send + Sync> { e.to_string().into() })?;
-        send_and_receive(stream, request).await
+    let connecting = tokio::net::TcpStream::connect(format!("127.0.0.1:{}", DAEMON_TCP_PORT));
+
+    timeout(Duration::from_secs(2), connecting)
+        .await
+        .map_err(|_| "Connection timeout")?
+        .map_err(|e| -> Box<dyn Error + Send + Sync> { e.to_string().into() })
+}
+
+// Read one length-prefixed JSON message, waiting as long as it takes
+async fn read_frame<T: DeserializeOwned>(
+    reader: &mut (impl AsyncRead + Unpin),
+) -> Result<T, Box<dyn Error + Send + Sync>> {
+    const MAX_FRAME_SIZE: usize = 10 * 1024 * 1024;
+
+    let mut len_bytes = [0u8; 4];
+    reader
+        .read_exact(&mut len_bytes)
+        .await
+        .map_err(|_| "Connection closed")?;
+
+    let frame_len = u32::from_le_bytes(len_bytes) as usize;
+    if frame_len > MAX_FRAME_SIZE {
+        return Err(format!(
+            "Message too large: {} bytes (max {})",
+            frame_len, MAX_FRAME_SIZE
+        )
+        .into());
+    }
+
+    let mut buffer = vec![0u8; frame_len];
+    reader
+        .read_exact(&mut buffer)
+        .await
+        .map_err(|_| "Connection closed")?;
+
+    Ok(serde_json::from_slice(&buffer)?)
+}
+
+/// The request never reached the daemon, so sending it again is safe
+#[derive(Debug)]
+pub(crate) struct NotSent(String);
+
+impl fmt::Display for NotSent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to send request: {}", self.0)
+    }
+}
+
+impl Error for NotSent {}
+
+type PendingResponses = Arc<std::sync::Mutex<HashMap<u64, oneshot::Sender<Response>>>>;
+
+/// A connection that stays open for many requests. Each request gets an id,
+/// so several can be in flight at once and are matched to their responses
+/// in whatever order the daemon answers.
+pub struct DaemonConnection {
+    writer: Mutex<WriteHalf<DaemonStream>>,
+    pending: PendingResponses,
+    next_id: AtomicU64,
+    reader: JoinHandle<()>,
+}
+
+impl DaemonConnection {
+    pub async fn open() -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let (reader, writer) = tokio::io::split(connect().await?);
+        let pending = PendingResponses::default();
+        let reader = tokio::spawn(read_responses(reader, pending.clone()));
+        Ok(DaemonConnection {
+            writer: Mutex::new(writer),
+            pending,
+            next_id: AtomicU64::new(1),
+            reader,
+        })
+    }
+
+    /// False once the daemon has hung up
+    pub fn is_open(&self) -> bool {
+        !self.reader.is_finished()
+    }
+
+    /// Send a request and wait up to `response_timeout` for its response
+    pub async fn request(
+        &self,
+        request: Request,
+        response_timeout: Duration,
+    ) -> Result<Response, Box<dyn Error + Send + Sync>> {
+        use tokio::time::timeout;
+
+        if !self.is_open() {
+            return Err(NotSent("connection closed".to_string()).into());
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock_or_recover().insert(id, sender);
+
+        if let Err(e) = self.send(&request.with_id(id)).await {
+            self.pending.lock_or_recover().remove(&id);
+            return Err(NotSent(e.to_string()).into());
+        }
+
+        let response = timeout(response_timeout, receiver).await;
+        self.pending.lock_or_recover().remove(&id);
+        response
+            .map_err(|_| "Read timeout")?
+            .map_err(|_| "Connection closed".into())
+    }
+
+    async fn send(&self, request: &Request) -> Result<(), Box<dyn Error + Send + Sync>> {
+        use tokio::time::timeout;
+
+        let request_data = serde_json::to_vec(request)?;
+        let request_len = request_data.len() as u32;
+
+        let mut writer = self.writer.lock().await;
+        timeout(Duration::from_secs(2), async {
+            writer.write_all(&request_len.to_le_bytes()).await?;
+            writer.write_all(&request_data).await
+        })
+        .await
+        .map_err(|_| "Send timeout")??;
+        Ok(())
+    }
+}
+
+impl Drop for DaemonConnection {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+async fn read_responses(mut reader: ReadHalf<DaemonStream>, pending: PendingResponses) {
+    loop {
+        let response: Response = match read_frame(&mut reader).await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::debug!("Daemon connection closed: {}", e);
+                break;
+            }
+        };
+
+        let sender = {
+            let mut pending = pending.lock_or_recover();
+            match response.id {
+                Some(id) => pending.remove(&id),
+                // Daemons that predate request ids answer one request per
+                // connection
+                None if pending.len() == 1 => {
+                    let id = pending.keys().next().copied();
+                    id.and_then(|id| pending.remove(&id))
+                }
+                None => None,
+            }
+        };
+        match sender {
+            Some(sender) => {
+                let _ = sender.send(response);
+            }
+            None => tracing::warn!("Dropped a daemon response nobody was waiting for"),
+        }
+    }
+
+    // Fail the requests still waiting
+    pending.lock_or_recover().clear();
+}
+
+/// Events pushed by the daemon on a `subscribe` connection
+pub struct EventStream {
+    stream: DaemonStream,
+}
+
+impl EventStream {
+    /// Wait for the next event. Fails once the daemon goes away.
+    pub async fn next_event(&mut self) -> Result<Event, Box<dyn Error + Send + Sync>> {
+        read_frame(&mut self.stream).await
+    }
+}
+
+/// Open a connection that receives the daemon's events, starting with its
+/// current state. Daemons that predate subscriptions answer with a
+/// `DaemonError`.
+pub async fn subscribe_events() -> Result<EventStream, Box<dyn Error + Send + Sync>> {
+    let mut stream = connect().await?;
+    let response = send_and_receive(&mut stream, Request::subscribe()).await?;
+    if let Some(error) = response.daemon_error() {
+        return Err(error.into());
     }
+    Ok(EventStream { stream })
 }
@@ -0,0 +1,248 @@
+use crate::{
+    types::{
+        audio_player::AudioPlayer,
+        events::{Event, Snapshot},
+        payload::{CurrentDevice, DaemonError, Device, ErrorCode},
+        socket::Command,
+    },
+    utils::{
+        commands::command_executable,
+        daemon::{lock_audio_player, with_audio_player_held},
+    },
+    MutexExt,
+};
+use serde::de::DeserializeOwned;
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Events a subscriber may fall behind before it's sent a fresh snapshot
+const EVENT_BUFFER: usize = 256;
+
+fn events() -> &'static broadcast::Sender<Event> {
+    static EVENTS: OnceLock<broadcast::Sender<Event>> = OnceLock::new();
+    EVENTS.get_or_init(|| broadcast::channel(EVENT_BUFFER).0)
+}
+
+// The last snapshot sent to subscribers, dropped while nobody is subscribed.
+// Events are sent while holding the lock so a new subscriber never misses
+// the change between the two.
+fn latest_snapshot() -> &'static Mutex<Option<Snapshot>> {
+    static LATEST: OnceLock<Mutex<Option<Snapshot>>> = OnceLock::new();
+    LATEST.get_or_init(|| Mutex::new(None))
+}
+
+// The sound the main player was last said to start, so every play is
+// followed by exactly one stop
+fn announced_play() -> &'static Mutex<Option<PathBuf>> {
+    static ANNOUNCED: OnceLock<Mutex<Option<PathBuf>>> = OnceLock::new();
+    ANNOUNCED.get_or_init(|| Mutex::new(None))
+}
+
+/// Tell subscribers that something failed in the background
+pub fn publish_error(error: DaemonError) {
+    // Nobody listening is fine
+    let _ = events().send(Event::Error { error });
+}
+
+/// Tell subscribers that `audio_player` just started `file_path` on the
+/// main player, or on a layer
+pub fn publish_play(audio_player: &AudioPlayer, layer_index: Option<usize>, file_path: &Path) {
+    let event = match layer_index {
+        Some(layer_index) => Event::LayerPlay {
+            layer_index,
+            file_path: file_path.to_path_buf(),
+        },
+        None => {
+            *announced_play().lock_or_recover() = Some(file_path.to_path_buf());
+            Event::Play {
+                file_path: file_path.to_path_buf(),
+                duration: audio_player.duration.unwrap_or(0.0),
+                looped: audio_player.looped,
+            }
+        }
+    };
+    let _ = events().send(event);
+}
+
+/// Tell subscribers that the main player stopped, unless they already know
+pub fn publish_stop() {
+    if let Some(file_path) = announced_play().lock_or_recover().take() {
+        let _ = events().send(Event::Stop { file_path });
+    }
+}
+
+/// Tell subscribers that a looping sound started over
+pub fn publish_loop_end(file_path: &Path) {
+    let _ = events().send(Event::LoopEnd {
+        file_path: file_path.to_path_buf(),
+    });
+}
+
+/// Publish whatever changed on the player since the last snapshot. Called
+/// with the player locked by whatever changed it; does nothing while nobody
+/// is subscribed.
+pub fn publish_player_state(audio_player: &mut AudioPlayer) {
+    let mut latest = latest_snapshot().lock_or_recover();
+    if events().receiver_count() == 0 {
+        *latest = None;
+        return;
+    }
+    // Subscribing takes the first snapshot, devices included
+    let Some(devices) = latest.as_ref().map(|snapshot| snapshot.devices.clone()) else {
+        return;
+    };
+    let snapshot = player_snapshot(audio_player, devices);
+    send_changes(&mut latest, snapshot);
+}
+
+/// List the devices again and publish them if they changed, e.g. after the
+/// selection changed or a device was plugged in
+pub async fn publish_devices() {
+    if events().receiver_count() > 0 {
+        take_snapshot().await;
+    }
+}
+
+/// One subscriber's view of the events: the current state first, then each
+/// change. A subscriber that falls behind is handed the current state again
+/// instead of the events it missed.
+pub struct EventFollower {
+    receiver: broadcast::Receiver<Event>,
+    pending: VecDeque<Event>,
+}
+
+impl EventFollower {
+    /// Follow `receiver`, handing out `initial` first
+    pub fn new(receiver: broadcast::Receiver<Event>, initial: Vec<Event>) -> Self {
+        Self {
+            receiver,
+            pending: initial.into(),
+        }
+    }
+
+    /// The next event, or None once events stop. Cancel safe, so it can be
+    /// raced in `select!`.
+    pub async fn next(&mut self) -> Option<Event> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            match self.receiver.recv().await {
+                Ok(event) => return Some(event),
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(
+                        "Event subscriber fell {} events behind, resending state",
+                        skipped
+                    );
+                    self.resend_state();
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Take the events not handed out yet, e.g. the state at subscription
+    pub fn take_pending(&mut self) -> Vec<Event> {
+        self.pending.drain(..).collect()
+    }
+
+    /// Hand out the current state next, in place of anything not handed out
+    /// yet
+    pub fn resend_state(&mut self) {
+        self.pending = current_events().into();
+    }
+}
+
+/// Receive events from now on, starting with the events describing the
+/// current state
+pub async fn follow_events() -> EventFollower {
+    // Subscribe first so the snapshot isn't dropped for lack of subscribers
+    let receiver = events().subscribe();
+    let has_snapshot = latest_snapshot().lock_or_recover().is_some();
+    if !has_snapshot {
+        take_snapshot().await;
+    }
+
+    let latest = latest_snapshot().lock_or_recover();
+    // Start after the snapshot; anything queued before it is already in it
+    EventFollower::new(receiver.resubscribe(), current_events_locked(&latest))
+}
+
+/// Events describing the current state
+pub fn current_events() -> Vec<Event> {
+    current_events_locked(&latest_snapshot().lock_or_recover())
+}
+
+fn current_events_locked(latest: &Option<Snapshot>) -> Vec<Event> {
+    latest
+        .as_ref()
+        .map(|snapshot| snapshot.events_since(None))
+        .unwrap_or_default()
+}
+
+fn send_changes(latest: &mut Option<Snapshot>, snapshot: Snapshot) {
+    for event in snapshot.events_since(latest.as_ref()) {
+        let _ = events().send(event);
+    }
+    *latest = Some(snapshot);
+}
+
+fn player_snapshot(audio_player: &mut AudioPlayer, devices: Event) -> Snapshot {
+    Snapshot {
+        state: Event::State {
+            state: audio_player.get_state(),
+            file_path: audio_player.get_current_file_path().clone(),
+            duration: audio_player.get_duration().unwrap_or(0.0),
+            looped: audio_player.looped,
+        },
+        position: Event::Position {
+            position: audio_player.get_position(),
+        },
+        volume: Event::Volume {
+            volume: audio_player.volume,
+            gain: audio_player.get_gain(),
+            mic_gain: audio_player.get_mic_gain(),
+        },
+        layers: Event::Layers {
+            layers: audio_player.get_all_layers_info(),
+        },
+        devices,
+    }
+}
+
+// The device lists are slow to query, so only the selection and the player
+// are read under one lock
+async fn take_snapshot() {
+    let inputs = query::<Vec<Device>>(Command::GetInputs).await;
+    let outputs = query::<Vec<Device>>(Command::GetOutputs).await;
+    // No device selected isn't an error; failing to list devices is
+    if let Some(error) = inputs.as_ref().err().or(outputs.as_ref().err()) {
+        publish_error(error.clone());
+    }
+
+    with_audio_player_held(async {
+        let devices = Event::Devices {
+            input: query::<CurrentDevice>(Command::GetInput).await.ok(),
+            inputs: inputs.unwrap_or_default(),
+            output: query::<CurrentDevice>(Command::GetOutput).await.ok(),
+            outputs: outputs.unwrap_or_default(),
+        };
+        let snapshot = player_snapshot(&mut *lock_audio_player().await, devices);
+        send_changes(&mut latest_snapshot().lock_or_recover(), snapshot);
+    })
+    .await
+}
+
+async fn query<T: DeserializeOwned>(command: Command) -> Result<T, DaemonError> {
+    let response = command_executable(command).execute().await;
+    if let Some(error) = response.daemon_error() {
+        return Err(error);
+    }
+    response
+        .data_as::<T>()
+        .ok_or_else(|| DaemonError::new(ErrorCode::Internal, "Unexpected response data"))
+}
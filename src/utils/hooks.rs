@@ -0,0 +1,320 @@
+// Shell hooks, run by the daemon when `hooks.enabled` is set in daemon.json.
+// Hooks follow the event stream that subscribers get: plays and stops come
+// from the daemon as they happen, device changes from the device state.
+
+use crate::{
+    types::{config::HooksConfig, events::Event, hooks::HookData},
+    utils::events::follow_events,
+};
+use std::{process::Stdio, sync::Arc, time::Duration};
+use tokio::{io::AsyncWriteExt, process::Command, sync::Semaphore, time::timeout};
+
+/// Turns events into hook events; only device changes need what came before
+#[derive(Default)]
+struct HookWatcher {
+    devices: Option<(Option<String>, Option<String>)>,
+}
+
+impl HookWatcher {
+    fn apply(&mut self, event: &Event) -> Vec<HookData> {
+        match event {
+            Event::Play {
+                file_path,
+                duration,
+                looped,
+            } => vec![HookData::Play {
+                file_path: file_path.clone(),
+                duration: *duration,
+                looped: *looped,
+            }],
+            Event::Stop { file_path } => vec![HookData::Stop {
+                file_path: Some(file_path.clone()),
+            }],
+            Event::LayerPlay {
+                layer_index,
+                file_path,
+            } => vec![HookData::LayerPlay {
+                layer_index: *layer_index,
+                file_path: file_path.clone(),
+            }],
+            Event::LoopEnd { file_path } => vec![HookData::LoopEnd {
+                file_path: file_path.clone(),
+            }],
+            Event::Devices { input, output, .. } => {
+                let current = (
+                    input.as_ref().map(|device| device.name.clone()),
+                    output.as_ref().map(|device| device.name.clone()),
+                );
+                match self.devices.replace(current.clone()) {
+                    Some(previous) if previous != current => vec![HookData::DeviceChanged {
+                        input: current.0,
+                        output: current.1,
+                    }],
+                    _ => Vec::new(),
+                }
+            }
+            Event::Error { error } => vec![HookData::Error {
+                error_code: error.code,
+                error_message: error.message.clone(),
+            }],
+            Event::State { .. }
+            | Event::Position { .. }
+            | Event::Volume { .. }
+            | Event::Layers { .. } => Vec::new(),
+        }
+    }
+}
+
+/// Run the configured hooks as events come in
+pub async fn hooks_loop(config: HooksConfig) {
+    let running = Arc::new(Semaphore::new(config.max_concurrent.max(1)));
+    let mut events = follow_events().await;
+    let mut watcher = HookWatcher::default();
+    // What's already going on when the daemon starts didn't just happen
+    for event in events.take_pending() {
+        watcher.apply(&event);
+    }
+
+    while let Some(event) = events.next().await {
+        for data in watcher.apply(&event) {
+            for hook in config
+                .commands
+                .iter()
+                .filter(|hook| hook.on == data.event())
+            {
+                let Ok(permit) = running.clone().try_acquire_owned() else {
+                    tracing::warn!(
+                        "Skipping hook `{}`, {} hooks are already running",
+                        hook.command,
+                        config.max_concurrent
+                    );
+                    continue;
+                };
+                let command = hook.command.clone();
+                let data = data.clone();
+                let limit = Duration::from_secs(config.timeout);
+                tokio::spawn(async move {
+                    if let Err(e) = run_hook(&command, &data, limit).await {
+                        tracing::warn!("Hook `{}` failed: {}", command, e);
+                    }
+                    drop(permit);
+                });
+            }
+        }
+    }
+}
+
+/// Run one hook to completion, killing it after `limit`
+async fn run_hook(command: &str, data: &HookData, limit: Duration) -> Result<(), String> {
+    let json = serde_json::to_string(data).map_err(|e| e.to_string())?;
+    let mut child = shell(command)
+        .envs(env_vars(data))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| e.to_string())?;
+
+    let finished = timeout(limit, async move {
+        if let Some(mut stdin) = child.stdin.take() {
+            // Hooks that don't read stdin close it early; that's fine
+            let _ = stdin.write_all(json.as_bytes()).await;
+        }
+        child.wait_with_output().await
+    })
+    .await;
+    match finished {
+        Ok(Ok(output)) if output.status.success() => Ok(()),
+        Ok(Ok(output)) => Err(format!(
+            "{}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!("killed after {}s", limit.as_secs_f32())),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(target_os = "windows"))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+/// `SOUNDBOARD_EVENT` plus one `SOUNDBOARD_<FIELD>` per field of the data
+fn env_vars(data: &HookData) -> Vec<(String, String)> {
+    let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(data) else {
+        return Vec::new();
+    };
+    fields
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                serde_json::Value::String(text) => text,
+                serde_json::Value::Null => String::new(),
+                other => other.to_string(),
+            };
+            (format!("SOUNDBOARD_{}", name.to_uppercase()), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        audio_player::PlayerState,
+        hooks::HookEvent,
+        payload::{CurrentDevice, DaemonError, ErrorCode},
+    };
+    use std::path::PathBuf;
+
+    fn events(watcher: &mut HookWatcher, event: Event) -> Vec<HookEvent> {
+        watcher.apply(&event).iter().map(HookData::event).collect()
+    }
+
+    #[test]
+    fn test_plays_and_stops() {
+        let mut watcher = HookWatcher::default();
+        let rain = PathBuf::from("/sounds/rain.ogg");
+        let play = Event::Play {
+            file_path: rain.clone(),
+            duration: 10.0,
+            looped: true,
+        };
+        assert_eq!(
+            watcher.apply(&play),
+            vec![HookData::Play {
+                file_path: rain.clone(),
+                duration: 10.0,
+                looped: true,
+            }]
+        );
+        // Replaying the same sound is another play
+        assert_eq!(events(&mut watcher, play), vec![HookEvent::Play]);
+        assert_eq!(
+            events(
+                &mut watcher,
+                Event::LoopEnd {
+                    file_path: rain.clone()
+                }
+            ),
+            vec![HookEvent::LoopEnd]
+        );
+        assert_eq!(
+            watcher.apply(&Event::Stop {
+                file_path: rain.clone()
+            }),
+            vec![HookData::Stop {
+                file_path: Some(rain),
+            }]
+        );
+        assert_eq!(
+            watcher.apply(&Event::LayerPlay {
+                layer_index: 1,
+                file_path: PathBuf::from("/b.ogg"),
+            }),
+            vec![HookData::LayerPlay {
+                layer_index: 1,
+                file_path: PathBuf::from("/b.ogg"),
+            }]
+        );
+        // State snapshots fire nothing by themselves
+        assert!(events(
+            &mut watcher,
+            Event::State {
+                state: PlayerState::Playing,
+                file_path: Some(PathBuf::from("/sounds/rain.ogg")),
+                duration: 10.0,
+                looped: true,
+            }
+        )
+        .is_empty());
+        assert!(events(&mut watcher, Event::Position { position: 0.1 }).is_empty());
+    }
+
+    #[test]
+    fn test_devices_and_errors() {
+        let mut watcher = HookWatcher::default();
+        let devices = |output: &str| Event::Devices {
+            input: None,
+            inputs: Vec::new(),
+            output: Some(CurrentDevice {
+                id: output.to_string(),
+                name: output.to_string(),
+                follows_default: false,
+            }),
+            outputs: Vec::new(),
+        };
+        assert!(events(&mut watcher, devices("Speakers")).is_empty());
+        assert!(events(&mut watcher, devices("Speakers")).is_empty());
+        assert_eq!(
+            watcher.apply(&devices("Headset")),
+            vec![HookData::DeviceChanged {
+                input: None,
+                output: Some("Headset".to_string()),
+            }]
+        );
+
+        let error = Event::Error {
+            error: DaemonError::new(ErrorCode::NotFound, "No such macro: intro"),
+        };
+        assert_eq!(events(&mut watcher, error), vec![HookEvent::Error]);
+    }
+
+    #[test]
+    fn test_env_vars() {
+        let vars = env_vars(&HookData::LayerPlay {
+            layer_index: 2,
+            file_path: PathBuf::from("/sounds/a.ogg"),
+        });
+        assert!(vars.contains(&("SOUNDBOARD_EVENT".to_string(), "layer_play".to_string())));
+        assert!(vars.contains(&("SOUNDBOARD_LAYER_INDEX".to_string(), "2".to_string())));
+        assert!(vars.contains(&(
+            "SOUNDBOARD_FILE_PATH".to_string(),
+            "/sounds/a.ogg".to_string()
+        )));
+
+        let vars = env_vars(&HookData::Stop { file_path: None });
+        assert!(vars.contains(&("SOUNDBOARD_FILE_PATH".to_string(), String::new())));
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test]
+    async fn test_run_hook_passes_data_and_times_out() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let out = dir.path().join("out");
+        let data = HookData::Play {
+            file_path: PathBuf::from("/sounds/rain.ogg"),
+            duration: 10.0,
+            looped: false,
+        };
+        let command = format!(
+            "echo \"$SOUNDBOARD_EVENT $SOUNDBOARD_FILE_PATH\" > '{0}'; cat >> '{0}'",
+            out.display()
+        );
+        run_hook(&command, &data, Duration::from_secs(5))
+            .await
+            .expect("hook runs");
+        let written = std::fs::read_to_string(&out).expect("output");
+        let (env_line, stdin) = written.split_once('\n').expect("two parts");
+        assert_eq!(env_line, "play /sounds/rain.ogg");
+        let json: serde_json::Value = serde_json::from_str(stdin).expect("json on stdin");
+        assert_eq!(json["event"], "play");
+        assert_eq!(json["duration"], 10.0);
+
+        let failed = run_hook("echo oops >&2; exit 3", &data, Duration::from_secs(5)).await;
+        assert!(failed.expect_err("non-zero exit").contains("oops"));
+        let slow = run_hook("sleep 5", &data, Duration::from_millis(100)).await;
+        assert!(slow.expect_err("timed out").contains("killed"));
+    }
+}
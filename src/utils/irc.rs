@@ -0,0 +1,500 @@
+// IRC chat commands, used by the daemon when `irc.enabled` is set in
+// daemon.json. Viewers type `!name` in a joined channel to play the sound or
+// playlist configured for it; Twitch chat speaks IRC too. Cooldowns and the
+// allow and deny lists keep a busy chat from taking over the soundboard.
+
+use crate::{
+    types::{
+        chat::ChatCommand,
+        config::IrcConfig,
+        payload::{DaemonError, ErrorCode},
+        socket::Response,
+    },
+    utils::{commands::command_executable, events::publish_error},
+};
+use std::{
+    collections::HashMap,
+    error::Error,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+// A busy chat hitting cooldowns gets at most one reply this often
+const COOLDOWN_REPLY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// One line of the IRC protocol, without IRCv3 tags
+#[derive(Debug, PartialEq)]
+struct IrcMessage {
+    prefix: Option<String>,
+    command: String,
+    params: Vec<String>,
+}
+
+impl IrcMessage {
+    fn parse(line: &str) -> Option<IrcMessage> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+        // Twitch adds tags; nothing here needs them
+        if rest.starts_with('@') {
+            rest = rest.split_once(' ')?.1;
+        }
+        let prefix = match rest.strip_prefix(':') {
+            Some(prefixed) => {
+                let (prefix, after) = prefixed.split_once(' ')?;
+                rest = after;
+                Some(prefix.to_string())
+            }
+            None => None,
+        };
+        let (middle, trailing) = match rest.split_once(" :") {
+            Some((middle, trailing)) => (middle, Some(trailing)),
+            None => (rest, None),
+        };
+        let mut words = middle.split(' ').filter(|word| !word.is_empty());
+        let command = words.next()?.to_uppercase();
+        let mut params: Vec<String> = words.map(str::to_string).collect();
+        params.extend(trailing.map(str::to_string));
+        Some(IrcMessage {
+            prefix,
+            command,
+            params,
+        })
+    }
+
+    /// The sender's nick, from `nick!user@host`
+    fn nick(&self) -> Option<&str> {
+        let prefix = self.prefix.as_deref()?;
+        prefix.split('!').next()
+    }
+}
+
+/// When sounds were last triggered, overall and by each user
+struct Cooldowns {
+    user: Duration,
+    global: Duration,
+    last_global: Option<Instant>,
+    last_by_user: HashMap<String, Instant>,
+}
+
+impl Cooldowns {
+    fn new(config: &IrcConfig) -> Self {
+        Self {
+            user: Duration::from_secs(config.user_cooldown),
+            global: Duration::from_secs(config.global_cooldown),
+            last_global: None,
+            last_by_user: HashMap::new(),
+        }
+    }
+
+    /// How long `user` still has to wait, or None if they may trigger now
+    fn remaining(&self, user: &str, now: Instant) -> Option<Duration> {
+        let left = |last: Option<&Instant>, cooldown: Duration| {
+            last.and_then(|last| (*last + cooldown).checked_duration_since(now))
+                .filter(|left| !left.is_zero())
+        };
+        let global = left(self.last_global.as_ref(), self.global);
+        let user = left(self.last_by_user.get(user), self.user);
+        global.max(user)
+    }
+
+    fn record(&mut self, user: &str, now: Instant) {
+        self.last_global = Some(now);
+        self.last_by_user.insert(user.to_string(), now);
+        let cooldown = self.user;
+        self.last_by_user
+            .retain(|_, last| now.duration_since(*last) < cooldown);
+    }
+}
+
+/// What a chat message leads to
+#[derive(Debug, PartialEq)]
+enum ChatOutcome<'a> {
+    Ignore,
+    Run(&'a ChatCommand),
+    Cooldown(&'a ChatCommand, Duration),
+}
+
+/// Stay connected to the IRC server, reconnecting whenever it drops
+pub async fn irc_loop(config: IrcConfig) {
+    // Kept across reconnects, so reconnecting doesn't reset them
+    let mut cooldowns = Cooldowns::new(&config);
+    loop {
+        match TcpStream::connect((config.server.as_str(), config.port)).await {
+            Ok(stream) => {
+                if let Err(e) = run_session(stream, &config, &mut cooldowns).await {
+                    tracing::warn!("IRC connection ended: {}", e);
+                }
+            }
+            Err(e) => tracing::debug!(
+                "IRC server {}:{} unreachable: {}",
+                config.server,
+                config.port,
+                e
+            ),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn run_session(
+    stream: TcpStream,
+    config: &IrcConfig,
+    cooldowns: &mut Cooldowns,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    if let Some(password) = &config.password {
+        send(&mut writer, &format!("PASS {}", password)).await?;
+    }
+    send(&mut writer, &format!("NICK {}", config.nick)).await?;
+    send(
+        &mut writer,
+        &format!("USER {} 0 * :Soundboard", config.nick),
+    )
+    .await?;
+
+    let mut last_reply: Option<Instant> = None;
+    while let Some(line) = lines.next_line().await? {
+        let Some(message) = IrcMessage::parse(&line) else {
+            continue;
+        };
+        match message.command.as_str() {
+            "PING" => {
+                let token = message
+                    .params
+                    .last()
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                send(&mut writer, &format!("PONG :{}", token)).await?;
+            }
+            // Welcome; registration is done
+            "001" => {
+                tracing::info!("Connected to IRC server {}:{}", config.server, config.port);
+                for channel in &config.channels {
+                    send(&mut writer, &format!("JOIN {}", channel_name(channel))).await?;
+                }
+            }
+            "433" => return Err(format!("Nick {} is already in use", config.nick).into()),
+            // Twitch reports failed logins this way
+            "NOTICE" => {
+                if let Some(text) = message.params.last() {
+                    tracing::info!("IRC notice: {}", text);
+                }
+            }
+            "ERROR" => {
+                let reason = message
+                    .params
+                    .last()
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                return Err(format!("IRC server closed the connection: {}", reason).into());
+            }
+            "PRIVMSG" => {
+                let (Some(user), [channel, text]) = (message.nick(), message.params.as_slice())
+                else {
+                    continue;
+                };
+                // Private messages aren't for the whole chat
+                if !channel.starts_with('#') {
+                    continue;
+                }
+                let now = Instant::now();
+                match chat_outcome(config, cooldowns, user, text, now) {
+                    ChatOutcome::Ignore => {}
+                    ChatOutcome::Run(command) => {
+                        tracing::debug!(
+                            "{} triggered {}{} in {}",
+                            user,
+                            config.prefix,
+                            command.name,
+                            channel
+                        );
+                        let response = run_command(config, command).await;
+                        let Some(error) = response.daemon_error() else {
+                            // Only plays that happened start a cooldown
+                            cooldowns.record(user, now);
+                            continue;
+                        };
+                        if !matches!(error.code, ErrorCode::RateLimited | ErrorCode::Busy)
+                            || !may_reply(&mut last_reply, now)
+                        {
+                            continue;
+                        }
+                        let reply = format!("PRIVMSG {} :@{} {}", channel, user, error.message);
+                        send(&mut writer, &reply).await?;
+                    }
+                    ChatOutcome::Cooldown(command, left) => {
+                        if !may_reply(&mut last_reply, now) {
+                            continue;
+                        }
+                        let reply = format!(
+                            "PRIVMSG {} :@{} {}{} is on cooldown, try again in {}s",
+                            channel,
+                            user,
+                            config.prefix,
+                            command.name,
+                            left.as_secs_f32().ceil()
+                        );
+                        send(&mut writer, &reply).await?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Err("IRC server closed the connection".into())
+}
+
+async fn send(
+    writer: &mut (impl AsyncWrite + Unpin),
+    line: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // A line break inside would start a second command
+    let line = line.replace(['\r', '\n'], " ");
+    writer.write_all(format!("{}\r\n", line).as_bytes()).await?;
+    Ok(())
+}
+
+/// Whether a refusal may be answered in chat now, so a busy chat gets at most
+/// one reply per `COOLDOWN_REPLY_INTERVAL`
+fn may_reply(last_reply: &mut Option<Instant>, now: Instant) -> bool {
+    if last_reply.is_some_and(|last| now.duration_since(last) < COOLDOWN_REPLY_INTERVAL) {
+        return false;
+    }
+    *last_reply = Some(now);
+    true
+}
+
+fn channel_name(channel: &str) -> String {
+    let channel = channel.trim().to_lowercase();
+    if channel.starts_with('#') {
+        channel
+    } else {
+        format!("#{}", channel)
+    }
+}
+
+fn chat_outcome<'a>(
+    config: &'a IrcConfig,
+    cooldowns: &Cooldowns,
+    user: &str,
+    text: &str,
+    now: Instant,
+) -> ChatOutcome<'a> {
+    let Some(command) = find_command(config, text) else {
+        return ChatOutcome::Ignore;
+    };
+    if !is_allowed(config, user) {
+        return ChatOutcome::Ignore;
+    }
+    match cooldowns.remaining(user, now) {
+        Some(left) => ChatOutcome::Cooldown(command, left),
+        None => ChatOutcome::Run(command),
+    }
+}
+
+/// The command typed at the start of `text`, like `!airhorn` or `!AirHorn now`
+fn find_command<'a>(config: &'a IrcConfig, text: &str) -> Option<&'a ChatCommand> {
+    let typed = text.trim().strip_prefix(config.prefix.as_str())?;
+    let name = typed.split_whitespace().next()?;
+    config
+        .commands
+        .iter()
+        .find(|command| command.name.eq_ignore_ascii_case(name))
+}
+
+fn is_allowed(config: &IrcConfig, user: &str) -> bool {
+    let listed = |users: &[String]| users.iter().any(|name| name.eq_ignore_ascii_case(user));
+    !listed(&config.deny) && (config.allow.is_empty() || listed(&config.allow))
+}
+
+async fn run_command(config: &IrcConfig, command: &ChatCommand) -> Response {
+    let response = command_executable(command.action.command()).execute().await;
+    if let Some(error) = response.daemon_error() {
+        tracing::warn!(
+            "Chat command {}{} failed: {}",
+            config.prefix,
+            command.name,
+            error
+        );
+        publish_error(DaemonError::new(
+            error.code,
+            format!(
+                "Chat command {}{} failed: {}",
+                config.prefix, command.name, error.message
+            ),
+        ));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::chat::ChatAction;
+    use tokio::net::TcpListener;
+
+    fn config() -> IrcConfig {
+        IrcConfig {
+            enabled: true,
+            channels: vec!["Stream".to_string()],
+            commands: vec![ChatCommand {
+                name: "airhorn".to_string(),
+                action: ChatAction::Playlist {
+                    name: "Memes".to_string(),
+                    mode: None,
+                },
+            }],
+            user_cooldown: 30,
+            global_cooldown: 5,
+            ..IrcConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_messages() {
+        assert_eq!(
+            IrcMessage::parse(
+                ":alice!alice@alice.tmi.twitch.tv PRIVMSG #stream :!airhorn please\r\n"
+            ),
+            Some(IrcMessage {
+                prefix: Some("alice!alice@alice.tmi.twitch.tv".to_string()),
+                command: "PRIVMSG".to_string(),
+                params: vec!["#stream".to_string(), "!airhorn please".to_string()],
+            })
+        );
+        let tagged = IrcMessage::parse("@badges=;color=#FF0000 :bob!bob@host PRIVMSG #stream :hi")
+            .expect("parse");
+        assert_eq!(tagged.nick(), Some("bob"));
+        assert_eq!(tagged.params[1], "hi");
+
+        let ping = IrcMessage::parse("PING :tmi.twitch.tv").expect("parse");
+        assert_eq!(ping.prefix, None);
+        assert_eq!(ping.params, vec!["tmi.twitch.tv"]);
+        assert_eq!(
+            IrcMessage::parse(":server 001 soundboard :Welcome").map(|m| m.command),
+            Some("001".to_string())
+        );
+        assert_eq!(IrcMessage::parse(""), None);
+    }
+
+    #[test]
+    fn test_find_command_and_access() {
+        let mut config = config();
+        assert!(find_command(&config, "!AirHorn now").is_some());
+        assert!(find_command(&config, "airhorn").is_none());
+        assert!(find_command(&config, "!unknown").is_none());
+        assert!(find_command(&config, "!").is_none());
+
+        assert!(is_allowed(&config, "alice"));
+        config.deny = vec!["Alice".to_string()];
+        assert!(!is_allowed(&config, "alice"));
+        config.deny.clear();
+        config.allow = vec!["bob".to_string()];
+        assert!(!is_allowed(&config, "alice"));
+        assert!(is_allowed(&config, "BOB"));
+        assert_eq!(channel_name("Stream"), "#stream");
+        assert_eq!(channel_name("#stream"), "#stream");
+    }
+
+    #[test]
+    fn test_cooldowns() {
+        let config = config();
+        let mut cooldowns = Cooldowns::new(&config);
+        let start = Instant::now();
+        assert!(matches!(
+            chat_outcome(&config, &cooldowns, "alice", "!airhorn", start),
+            ChatOutcome::Run(_)
+        ));
+        cooldowns.record("alice", start);
+
+        // Everyone waits out the global cooldown, and alice the user cooldown
+        let later = start + Duration::from_secs(2);
+        assert_eq!(
+            cooldowns.remaining("bob", later),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            cooldowns.remaining("alice", later),
+            Some(Duration::from_secs(28))
+        );
+        let after_global = start + Duration::from_secs(5);
+        assert_eq!(cooldowns.remaining("bob", after_global), None);
+        assert!(matches!(
+            chat_outcome(&config, &cooldowns, "alice", "!airhorn", after_global),
+            ChatOutcome::Cooldown(_, _)
+        ));
+        assert_eq!(
+            cooldowns.remaining("alice", start + Duration::from_secs(30)),
+            None
+        );
+        // Chat that isn't a command doesn't count
+        assert_eq!(
+            chat_outcome(&config, &cooldowns, "alice", "hello", later),
+            ChatOutcome::Ignore
+        );
+    }
+
+    #[test]
+    fn test_may_reply() {
+        let start = Instant::now();
+        let mut last_reply = None;
+        assert!(may_reply(&mut last_reply, start));
+        assert!(!may_reply(&mut last_reply, start + Duration::from_secs(1)));
+        assert!(may_reply(&mut last_reply, start + COOLDOWN_REPLY_INTERVAL));
+    }
+
+    #[tokio::test]
+    async fn test_session_against_local_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let address = listener.local_addr().expect("address");
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("accept");
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut received = Vec::new();
+
+            received.push(lines.next_line().await.expect("read").expect("line"));
+            received.push(lines.next_line().await.expect("read").expect("line"));
+            writer
+                .write_all(b":server 001 soundboard :Welcome\r\n")
+                .await
+                .expect("write");
+            received.push(lines.next_line().await.expect("read").expect("line"));
+            writer.write_all(b"PING :server\r\n").await.expect("write");
+            received.push(lines.next_line().await.expect("read").expect("line"));
+            writer
+                .write_all(b":alice!alice@host PRIVMSG #stream :!airhorn\r\n")
+                .await
+                .expect("write");
+            received.push(lines.next_line().await.expect("read").expect("line"));
+            received
+        });
+
+        let config = IrcConfig {
+            server: address.ip().to_string(),
+            port: address.port(),
+            ..config()
+        };
+        // alice just played something, so the next try hits the cooldown
+        // instead of the audio player
+        let mut cooldowns = Cooldowns::new(&config);
+        cooldowns.record("alice", Instant::now());
+        let stream = TcpStream::connect(address).await.expect("connect");
+        let session = run_session(stream, &config, &mut cooldowns).await;
+        assert!(session.is_err(), "ends when the server hangs up");
+
+        let received = server.await.expect("server");
+        assert_eq!(received[0], "NICK soundboard");
+        assert_eq!(received[1], "USER soundboard 0 * :Soundboard");
+        assert_eq!(received[2], "JOIN #stream");
+        assert_eq!(received[3], "PONG :server");
+        assert_eq!(
+            received[4],
+            "PRIVMSG #stream :@alice !airhorn is on cooldown, try again in 30s"
+        );
+    }
+}
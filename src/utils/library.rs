@@ -0,0 +1,174 @@
+use crate::types::{
+    config::GuiConfig,
+    library::{Library, LibraryPlaylist, LibrarySound},
+};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+pub const SUPPORTED_EXTENSIONS: [&str; 13] = [
+    "mp3", "wav", "ogg", "flac", "mp4", "m4a", "aac", "mov", "mkv", "webm", "avi", "opus", "wma",
+];
+
+/// Virtual playlist with every sound in the sounds folder
+pub const ALL_SOUNDS: &str = "All Sounds";
+/// Virtual playlist with the favorited sounds
+pub const FAVOURITES: &str = "Favourites";
+
+pub fn is_supported_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Audio files directly inside `folder`, sorted by path
+pub fn sounds_in_folder(folder: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = folder.read_dir() else {
+        return Vec::new();
+    };
+    let mut sounds: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && is_supported_audio(path))
+        .collect();
+    sounds.sort();
+    sounds
+}
+
+/// Playlist names in `playlist_order`, then the rest alphabetically
+pub fn ordered_playlists(config: &GuiConfig) -> Vec<String> {
+    let mut ordered: Vec<String> = config
+        .playlist_order
+        .iter()
+        .filter(|name| config.categories.contains_key(*name))
+        .cloned()
+        .collect();
+
+    let ordered_set: HashSet<&String> = config.playlist_order.iter().collect();
+    let mut remaining: Vec<String> = config
+        .categories
+        .keys()
+        .filter(|name| !ordered_set.contains(name))
+        .cloned()
+        .collect();
+    remaining.sort();
+    ordered.extend(remaining);
+    ordered
+}
+
+/// The sound's custom name, or its file name
+pub fn sound_display_name(config: &GuiConfig, path: &Path) -> String {
+    config
+        .sound_metadata
+        .get(path)
+        .and_then(|metadata| metadata.custom_name.clone())
+        .unwrap_or_else(|| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default()
+        })
+}
+
+/// Every playlist the GUI shows, with the sounds that still exist
+pub fn build_library(config: &GuiConfig) -> Library {
+    let playlist = |name: &str, paths: Vec<PathBuf>| LibraryPlaylist {
+        name: name.to_string(),
+        sounds: paths
+            .into_iter()
+            .filter(|path| path.exists())
+            .map(|path| LibrarySound {
+                name: sound_display_name(config, &path),
+                favorite: config.favorites.contains(&path),
+                file_path: path,
+            })
+            .collect(),
+    };
+
+    let all_sounds = config
+        .sounds_folder
+        .as_deref()
+        .map(sounds_in_folder)
+        .unwrap_or_default();
+    let mut favourites: Vec<PathBuf> = config.favorites.iter().cloned().collect();
+    favourites.sort();
+
+    let mut playlists = vec![
+        playlist(ALL_SOUNDS, all_sounds),
+        playlist(FAVOURITES, favourites),
+    ];
+    for name in ordered_playlists(config) {
+        if let Some(category) = config.categories.get(&name) {
+            playlists.push(playlist(&name, category.sounds.clone()));
+        }
+    }
+    Library { playlists }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::config::{SoundCategory, SoundMetadata};
+    use std::fs;
+
+    #[test]
+    fn test_playlist_order() {
+        let mut config = GuiConfig::default();
+        for name in ["Memes", "Ambience", "Alerts"] {
+            config
+                .categories
+                .insert(name.to_string(), SoundCategory::new(name));
+        }
+        config.playlist_order = vec!["Memes".to_string(), "Gone".to_string()];
+
+        assert_eq!(
+            ordered_playlists(&config),
+            vec!["Memes", "Alerts", "Ambience"]
+        );
+    }
+
+    #[test]
+    fn test_library_matches_gui_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let horn = dir.path().join("horn.ogg");
+        let rain = dir.path().join("rain.wav");
+        fs::write(&horn, b"").unwrap();
+        fs::write(&rain, b"").unwrap();
+        fs::write(dir.path().join("notes.txt"), b"").unwrap();
+
+        let mut config = GuiConfig {
+            sounds_folder: Some(dir.path().to_path_buf()),
+            ..GuiConfig::default()
+        };
+        config.favorites.insert(horn.clone());
+        config.sound_metadata.insert(
+            horn.clone(),
+            SoundMetadata {
+                custom_name: Some("Airhorn".to_string()),
+                ..SoundMetadata::default()
+            },
+        );
+        let mut memes = SoundCategory::new("Memes");
+        memes.add_sound(rain.clone());
+        memes.add_sound(dir.path().join("deleted.mp3"));
+        config.categories.insert("Memes".to_string(), memes);
+
+        let library = build_library(&config);
+        let names: Vec<&str> = library
+            .playlists
+            .iter()
+            .map(|playlist| playlist.name.as_str())
+            .collect();
+        assert_eq!(names, vec![ALL_SOUNDS, FAVOURITES, "Memes"]);
+
+        let all_sounds = &library.playlists[0].sounds;
+        assert_eq!(all_sounds.len(), 2);
+        assert_eq!(all_sounds[0].name, "Airhorn");
+        assert!(all_sounds[0].favorite);
+        assert_eq!(all_sounds[1].name, "rain.wav");
+
+        assert_eq!(library.playlists[1].sounds[0].file_path, horn);
+        // Missing files are left out
+        assert_eq!(library.playlists[2].sounds.len(), 1);
+    }
+}
@@ -0,0 +1,275 @@
+use crate::{
+    types::{
+        audio_player::{AudioPlayer, PlayerState},
+        config::{RateLimitConfig, SoundMetadata},
+        payload::{DaemonError, ErrorCode},
+    },
+    utils::{daemon::get_daemon_config, events::publish_play},
+    MutexExt,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+/// Why a play request was turned down
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayBlocked {
+    /// The sound itself was played too recently
+    Cooldown { remaining: Duration },
+    /// Too many plays in the rate limit window
+    RateLimited { remaining: Duration },
+    /// Too many sounds are already playing
+    TooManySounds { max: usize },
+}
+
+impl fmt::Display for PlayBlocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayBlocked::Cooldown { remaining } => write!(
+                f,
+                "sound is on cooldown, it can play again in {:.1}s",
+                remaining.as_secs_f32()
+            ),
+            PlayBlocked::RateLimited { remaining } => write!(
+                f,
+                "too many plays, try again in {:.1}s",
+                remaining.as_secs_f32()
+            ),
+            PlayBlocked::TooManySounds { max } => {
+                write!(f, "{} sounds are already playing, stop one first", max)
+            }
+        }
+    }
+}
+
+/// Play history used to enforce cooldowns and rate limits
+#[derive(Debug, Default)]
+pub struct PlayLimiter {
+    /// Last play of each sound that has a cooldown
+    last_played: HashMap<PathBuf, Instant>,
+    /// Plays inside the current rate limit window, oldest first
+    recent: VecDeque<Instant>,
+}
+
+impl PlayLimiter {
+    /// Check whether `file_path` may start playing at `now`. `others_playing`
+    /// is the number of sounds that keep playing alongside it.
+    pub fn check(
+        &mut self,
+        file_path: &Path,
+        cooldown: Option<Duration>,
+        limits: &RateLimitConfig,
+        others_playing: usize,
+        now: Instant,
+    ) -> Result<(), PlayBlocked> {
+        if let (Some(cooldown), Some(last)) = (cooldown, self.last_played.get(file_path)) {
+            let elapsed = now.saturating_duration_since(*last);
+            if elapsed < cooldown {
+                return Err(PlayBlocked::Cooldown {
+                    remaining: cooldown - elapsed,
+                });
+            }
+        }
+
+        if let Some(max_plays) = limits.max_plays {
+            let window = Duration::try_from_secs_f32(limits.window_secs).unwrap_or_default();
+            while self
+                .recent
+                .front()
+                .is_some_and(|t| now.saturating_duration_since(*t) >= window)
+            {
+                self.recent.pop_front();
+            }
+            if self.recent.len() >= max_plays as usize {
+                let oldest = self.recent.front().copied().unwrap_or(now);
+                return Err(PlayBlocked::RateLimited {
+                    remaining: window.saturating_sub(now.saturating_duration_since(oldest)),
+                });
+            }
+        }
+
+        if let Some(max) = limits.max_concurrent {
+            if others_playing >= max {
+                return Err(PlayBlocked::TooManySounds { max });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remember a play that went through
+    pub fn record(
+        &mut self,
+        file_path: &Path,
+        cooldown: Option<Duration>,
+        limits: &RateLimitConfig,
+        now: Instant,
+    ) {
+        if cooldown.is_some() {
+            self.last_played.insert(file_path.to_path_buf(), now);
+        }
+        if limits.max_plays.is_some() {
+            self.recent.push_back(now);
+        }
+    }
+}
+
+fn play_limiter() -> &'static Mutex<PlayLimiter> {
+    static PLAY_LIMITER: OnceLock<Mutex<PlayLimiter>> = OnceLock::new();
+    PLAY_LIMITER.get_or_init(Default::default)
+}
+
+/// Cooldown set on the sound in the GUI, if any
+fn sound_cooldown(metadata: &SoundMetadata) -> Option<Duration> {
+    Duration::try_from_secs_f32(metadata.cooldown_secs?)
+        .ok()
+        .filter(|cooldown| !cooldown.is_zero())
+}
+
+/// Sounds playing on tracks other than `layer_index` (None = main player),
+/// which would be replaced by the new sound
+fn others_playing(audio_player: &AudioPlayer, layer_index: Option<usize>) -> usize {
+    let main_playing = audio_player.get_state() == PlayerState::Playing;
+    let layers = (0..audio_player.get_layer_count())
+        .filter(|i| Some(*i) != layer_index && audio_player.is_layer_playing(*i))
+        .count();
+    layers + usize::from(layer_index.is_some() && main_playing)
+}
+
+/// Play `file_path` on the main player or a layer, unless a cooldown or
+/// rate limit blocks it. Only plays that start count toward the limits.
+pub async fn play_limited(
+    audio_player: &mut AudioPlayer,
+    layer_index: Option<usize>,
+    file_path: &Path,
+    metadata: &SoundMetadata,
+) -> Result<(), DaemonError> {
+    let cooldown = sound_cooldown(metadata);
+    let limits = get_daemon_config().rate_limit;
+    let others = others_playing(audio_player, layer_index);
+
+    play_limiter()
+        .lock_or_recover()
+        .check(file_path, cooldown, &limits, others, Instant::now())
+        .map_err(|blocked| {
+            let code = match blocked {
+                PlayBlocked::TooManySounds { .. } => ErrorCode::Busy,
+                _ => ErrorCode::RateLimited,
+            };
+            DaemonError::new(
+                code,
+                format!("Can't play {}: {}", file_path.display(), blocked),
+            )
+        })?;
+
+    let result = match layer_index {
+        Some(layer_index) => audio_player.play_on_layer(layer_index, file_path).await,
+        None => audio_player.play(file_path).await,
+    };
+    result.map_err(|e| DaemonError::new(ErrorCode::PlaybackError, e.to_string()))?;
+    publish_play(audio_player, layer_index, file_path);
+
+    play_limiter()
+        .lock_or_recover()
+        .record(file_path, cooldown, &limits, Instant::now());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_plays: Option<u32>, max_concurrent: Option<usize>) -> RateLimitConfig {
+        RateLimitConfig {
+            max_plays,
+            window_secs: 10.0,
+            max_concurrent,
+        }
+    }
+
+    #[test]
+    fn test_cooldown_blocks_same_sound() {
+        let mut limiter = PlayLimiter::default();
+        let airhorn = Path::new("/sounds/airhorn.ogg");
+        let cooldown = Some(Duration::from_secs(5));
+        let no_limits = limits(None, None);
+        let start = Instant::now();
+
+        assert!(limiter
+            .check(airhorn, cooldown, &no_limits, 0, start)
+            .is_ok());
+        limiter.record(airhorn, cooldown, &no_limits, start);
+
+        let later = start + Duration::from_secs(2);
+        assert_eq!(
+            limiter.check(airhorn, cooldown, &no_limits, 0, later),
+            Err(PlayBlocked::Cooldown {
+                remaining: Duration::from_secs(3)
+            })
+        );
+
+        // Other sounds are unaffected
+        let other = Path::new("/sounds/rimshot.ogg");
+        assert!(limiter.check(other, None, &no_limits, 0, later).is_ok());
+
+        let after = start + Duration::from_secs(5);
+        assert!(limiter
+            .check(airhorn, cooldown, &no_limits, 0, after)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_rate_limit_window() {
+        let mut limiter = PlayLimiter::default();
+        let sound = Path::new("/sounds/a.ogg");
+        let limits = limits(Some(2), None);
+        let start = Instant::now();
+
+        for secs in [0, 1] {
+            let now = start + Duration::from_secs(secs);
+            assert!(limiter.check(sound, None, &limits, 0, now).is_ok());
+            limiter.record(sound, None, &limits, now);
+        }
+
+        let now = start + Duration::from_secs(4);
+        assert_eq!(
+            limiter.check(sound, None, &limits, 0, now),
+            Err(PlayBlocked::RateLimited {
+                remaining: Duration::from_secs(6)
+            })
+        );
+
+        // The first play has left the window
+        let now = start + Duration::from_secs(10);
+        assert!(limiter.check(sound, None, &limits, 0, now).is_ok());
+    }
+
+    #[test]
+    fn test_max_concurrent() {
+        let mut limiter = PlayLimiter::default();
+        let sound = Path::new("/sounds/a.ogg");
+        let limits = limits(None, Some(2));
+        let now = Instant::now();
+
+        assert!(limiter.check(sound, None, &limits, 1, now).is_ok());
+        assert_eq!(
+            limiter.check(sound, None, &limits, 2, now),
+            Err(PlayBlocked::TooManySounds { max: 2 })
+        );
+    }
+
+    #[test]
+    fn test_blocked_message_says_when() {
+        let blocked = PlayBlocked::Cooldown {
+            remaining: Duration::from_millis(2500),
+        };
+        assert_eq!(
+            blocked.to_string(),
+            "sound is on cooldown, it can play again in 2.5s"
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{
+    types::{
+        audio_player::AudioPlayer,
+        macros::{MacroStep, SoundMacro},
+        payload::{DaemonError, ErrorCode},
+    },
+    utils::{
+        daemon::get_audio_player,
+        events::{publish_error, publish_play, publish_stop},
+        playlist::stop_playlist,
+    },
+    MutexExt,
+};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+};
+use tokio::{
+    task::JoinHandle,
+    time::{sleep, Duration},
+};
+
+/// How often the volume is updated during a fade
+const FADE_TICK: Duration = Duration::from_millis(50);
+
+struct RunningMacro {
+    /// Distinguishes a restarted macro from the run it replaced
+    run_id: u64,
+    handle: JoinHandle<()>,
+}
+
+static NEXT_RUN_ID: AtomicU64 = AtomicU64::new(0);
+
+fn running_macros() -> &'static Mutex<HashMap<String, RunningMacro>> {
+    static RUNNING_MACROS: OnceLock<Mutex<HashMap<String, RunningMacro>>> = OnceLock::new();
+    RUNNING_MACROS.get_or_init(Default::default)
+}
+
+/// Run a macro in the background of the daemon. A macro that is already
+/// running under the same name is restarted. Returns true if it was restarted.
+pub fn run_macro(name: &str, sound_macro: SoundMacro) -> bool {
+    let run_id = NEXT_RUN_ID.fetch_add(1, Ordering::Relaxed);
+    let mut running = running_macros().lock_or_recover();
+
+    let handle = tokio::spawn({
+        let name = name.to_string();
+        async move {
+            execute_steps(&name, &sound_macro.steps).await;
+
+            let mut running = running_macros().lock_or_recover();
+            if running.get(&name).is_some_and(|r| r.run_id == run_id) {
+                running.remove(&name);
+            }
+        }
+    });
+
+    match running.insert(name.to_string(), RunningMacro { run_id, handle }) {
+        Some(previous) => {
+            previous.handle.abort();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Stop a running macro. Returns false if it wasn't running.
+pub fn cancel_macro(name: &str) -> bool {
+    match running_macros().lock_or_recover().remove(name) {
+        Some(running) => {
+            running.handle.abort();
+            tracing::info!("Cancelled macro '{}'", name);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Names of the macros that are currently running
+pub fn get_running_macros() -> Vec<String> {
+    let mut names: Vec<String> = running_macros().lock_or_recover().keys().cloned().collect();
+    names.sort();
+    names
+}
+
+async fn execute_steps(name: &str, steps: &[MacroStep]) {
+    tracing::info!("Running macro '{}' ({} steps)", name, steps.len());
+
+    // A failing step (e.g. a missing file) is logged and the rest still run,
+    // so a macro's timing doesn't fall apart because of one bad sound
+    for (i, step) in steps.iter().enumerate() {
+        if let Err(e) = execute_step(step).await {
+            tracing::error!("Macro '{}' step {} failed: {}", name, i + 1, e);
+            publish_error(DaemonError::new(
+                ErrorCode::PlaybackError,
+                format!("Macro '{}' step {} failed: {}", name, i + 1, e),
+            ));
+        }
+    }
+
+    tracing::info!("Macro '{}' finished", name);
+}
+
+async fn execute_step(step: &MacroStep) -> Result<(), String> {
+    match step {
+        MacroStep::Play { file_path } => {
+            let mut audio_player = get_audio_player().lock().await;
+            audio_player
+                .play(file_path)
+                .await
+                .map_err(|e| e.to_string())?;
+            publish_play(&audio_player, None, file_path);
+            Ok(())
+        }
+        MacroStep::PlayOnLayer {
+            layer_index,
+            file_path,
+        } => {
+            let mut audio_player = get_audio_player().lock().await;
+            audio_player
+                .play_on_layer(*layer_index, file_path)
+                .await
+                .map_err(|e| e.to_string())?;
+            publish_play(&audio_player, Some(*layer_index), file_path);
+            Ok(())
+        }
+        MacroStep::Wait { seconds } => {
+            sleep(Duration::from_secs_f32(seconds.max(0.0))).await;
+            Ok(())
+        }
+        MacroStep::Stop { layer_index } => {
+            let mut audio_player = get_audio_player().lock().await;
+            match layer_index {
+                Some(layer_index) => audio_player
+                    .stop_layer(*layer_index)
+                    .map_err(|e| e.to_string()),
+                None => {
+                    stop_playlist();
+                    audio_player.stop();
+                    publish_stop();
+                    Ok(())
+                }
+            }
+        }
+        MacroStep::SetVolume {
+            layer_index,
+            volume,
+        } => {
+            let mut audio_player = get_audio_player().lock().await;
+            set_track_volume(&mut audio_player, *layer_index, *volume)
+        }
+        MacroStep::Fade {
+            layer_index,
+            volume,
+            seconds,
+        } => fade(*layer_index, *volume, *seconds).await,
+    }
+}
+
+/// Ramp linearly to `target`, releasing the player between ticks so
+/// commands from clients aren't blocked for the length of the fade
+async fn fade(layer_index: Option<usize>, target: f32, seconds: f32) -> Result<(), String> {
+    let start = {
+        let audio_player = get_audio_player().lock().await;
+        get_track_volume(&audio_player, layer_index)?
+    };
+    let ticks = fade_ticks(seconds);
+
+    for tick in 1..=ticks {
+        sleep(FADE_TICK).await;
+        let volume = fade_volume(start, target, tick, ticks);
+        let mut audio_player = get_audio_player().lock().await;
+        set_track_volume(&mut audio_player, layer_index, volume)?;
+    }
+
+    if ticks == 0 {
+        let mut audio_player = get_audio_player().lock().await;
+        set_track_volume(&mut audio_player, layer_index, target)?;
+    }
+    Ok(())
+}
+
+fn fade_ticks(seconds: f32) -> u32 {
+    (seconds.max(0.0) / FADE_TICK.as_secs_f32()).round() as u32
+}
+
+fn fade_volume(start: f32, target: f32, tick: u32, ticks: u32) -> f32 {
+    // The last tick sets the target exactly; interpolating can be a rounding
+    // error off
+    if tick >= ticks {
+        return target;
+    }
+    start + (target - start) * tick as f32 / ticks as f32
+}
+
+fn get_track_volume(audio_player: &AudioPlayer, layer_index: Option<usize>) -> Result<f32, String> {
+    match layer_index {
+        Some(layer_index) => audio_player
+            .get_layer_volume(layer_index)
+            .map_err(|e| e.to_string()),
+        None => Ok(audio_player.volume),
+    }
+}
+
+fn set_track_volume(
+    audio_player: &mut AudioPlayer,
+    layer_index: Option<usize>,
+    volume: f32,
+) -> Result<(), String> {
+    match layer_index {
+        Some(layer_index) => audio_player
+            .set_layer_volume(layer_index, volume)
+            .map_err(|e| e.to_string()),
+        None => {
+            audio_player.set_volume(volume);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fade_ticks() {
+        assert_eq!(fade_ticks(0.0), 0);
+        assert_eq!(fade_ticks(0.01), 0);
+        assert_eq!(fade_ticks(1.0), 20);
+        assert_eq!(fade_ticks(-1.0), 0);
+    }
+
+    #[test]
+    fn test_fade_volume() {
+        assert_eq!(fade_volume(1.0, 0.2, 0, 4), 1.0);
+        assert!((fade_volume(1.0, 0.2, 2, 4) - 0.6).abs() < 1e-6);
+        assert_eq!(fade_volume(1.0, 0.2, 4, 4), 0.2);
+        assert_eq!(fade_volume(0.0, 1.0, 1, 4), 0.25);
+    }
+
+    #[tokio::test]
+    async fn test_run_and_cancel_macro() {
+        // Wait steps never touch the audio player
+        let sound_macro = SoundMacro {
+            steps: vec![MacroStep::Wait { seconds: 60.0 }],
+            hotkey: None,
+        };
+
+        assert!(!run_macro("test-wait", sound_macro.clone()));
+        assert!(get_running_macros().contains(&"test-wait".to_string()));
+        assert!(run_macro("test-wait", sound_macro));
+
+        assert!(cancel_macro("test-wait"));
+        assert!(!get_running_macros().contains(&"test-wait".to_string()));
+        assert!(!cancel_macro("test-wait"));
+    }
+}
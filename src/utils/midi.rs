@@ -0,0 +1,570 @@
+// MIDI controllers as a physical soundboard. Note and control change messages
+// run the mappings in daemon.json's `midi` section, and pads mapped to sounds
+// light up while those sounds play. Ports come from midir, which uses the
+// ALSA sequencer on Linux, and are rescanned so controllers can be plugged in
+// while the daemon runs.
+
+use crate::{
+    types::{
+        audio_player::{LayerInfo, PlayerState},
+        config::MidiConfig,
+        events::Event,
+        midi::{MidiAction, MidiMapping, MidiMessage, MidiMessageKind, MidiTrigger},
+        socket::Command,
+    },
+    utils::{commands::command_executable, daemon::get_daemon_config, events::follow_events},
+    MutexExt,
+};
+use midir::{Ignore, MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self as std_mpsc, RecvTimeoutError},
+        Mutex, OnceLock,
+    },
+    thread,
+    time::Instant,
+};
+use tokio::{
+    sync::{mpsc, oneshot, Notify},
+    time::{timeout, Duration},
+};
+
+/// How long `midi_learn` waits for a control to be touched
+pub const LEARN_TIMEOUT: Duration = Duration::from_secs(10);
+const PORT_RESCAN: Duration = Duration::from_secs(2);
+const CLIENT_NAME: &str = "soundboard";
+// Same range as the GUI's gain sliders
+const GAIN_RANGE: (f32, f32) = (0.5, 3.0);
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+fn mappings() -> &'static Mutex<Vec<MidiMapping>> {
+    static MAPPINGS: OnceLock<Mutex<Vec<MidiMapping>>> = OnceLock::new();
+    MAPPINGS.get_or_init(|| Mutex::new(get_daemon_config().midi.mappings))
+}
+
+fn mappings_changed() -> &'static Notify {
+    static MAPPINGS_CHANGED: OnceLock<Notify> = OnceLock::new();
+    MAPPINGS_CHANGED.get_or_init(Notify::new)
+}
+
+fn learners() -> &'static Mutex<Vec<oneshot::Sender<MidiMessage>>> {
+    static LEARNERS: OnceLock<Mutex<Vec<oneshot::Sender<MidiMessage>>>> = OnceLock::new();
+    LEARNERS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+/// Whether the daemon is listening to MIDI controllers
+pub fn midi_running() -> bool {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+/// Reload `midi.mappings` from the daemon config after it was edited
+pub fn reload_midi_mappings() {
+    *mappings().lock_or_recover() = get_daemon_config().midi.mappings;
+    mappings_changed().notify_one();
+}
+
+/// The next pad press or control change from any controller, or None if
+/// nothing arrives within `LEARN_TIMEOUT`. The message doesn't run its
+/// mapping.
+pub async fn learn_next_message() -> Option<MidiMessage> {
+    let (sender, receiver) = oneshot::channel();
+    learners().lock_or_recover().push(sender);
+    timeout(LEARN_TIMEOUT, receiver).await.ok()?.ok()
+}
+
+// Hands a press or control change to whoever waits in `learn_next_message`.
+// Returns false when nobody does.
+fn deliver_to_learners(message: MidiMessage) -> bool {
+    if matches!(message.kind, MidiMessageKind::NoteOff { .. }) {
+        return false;
+    }
+    let mut learners = learners().lock_or_recover();
+    learners.retain(|learner| !learner.is_closed());
+    if learners.is_empty() {
+        return false;
+    }
+    for learner in learners.drain(..) {
+        learner.send(message).ok();
+    }
+    true
+}
+
+/// Run mappings for incoming MIDI messages and drive pad LEDs until the
+/// event stream ends
+pub async fn midi_loop(config: MidiConfig) {
+    let (message_sender, mut messages) = mpsc::unbounded_channel();
+    let (led_sender, led_receiver) = std_mpsc::channel();
+    let filter = config.port.clone();
+    // midir's connections live on their own thread; it owns every port
+    let spawned = thread::Builder::new()
+        .name("midi-ports".to_string())
+        .spawn(move || port_thread(filter, message_sender, led_receiver));
+    if let Err(e) = spawned {
+        tracing::error!("Failed to start the MIDI port thread: {}", e);
+        return;
+    }
+    RUNNING.store(true, Ordering::Relaxed);
+    tracing::info!("Listening for MIDI controllers");
+
+    let mut events = follow_events().await;
+    let mut playing = PlayingSounds::default();
+    events
+        .take_pending()
+        .iter()
+        .for_each(|event| playing.apply(event));
+    let mut lit = HashMap::new();
+
+    loop {
+        if config.feedback {
+            let wanted = led_states(&mappings().lock_or_recover(), &playing);
+            for led in led_changes(&lit, &wanted) {
+                led_sender.send(led).ok();
+            }
+            lit = wanted;
+        }
+
+        tokio::select! {
+            Some(bytes) = messages.recv() => {
+                if let Some(message) = MidiMessage::parse(&bytes) {
+                    handle_message(message).await;
+                }
+            }
+            event = events.next() => match event {
+                Some(event) => playing.apply(&event),
+                None => break,
+            },
+            _ = mappings_changed().notified() => {}
+        }
+    }
+    RUNNING.store(false, Ordering::Relaxed);
+}
+
+async fn handle_message(message: MidiMessage) {
+    if deliver_to_learners(message) {
+        return;
+    }
+
+    let actions: Vec<MidiAction> = mappings()
+        .lock_or_recover()
+        .iter()
+        .filter(|mapping| mapping.matches(&message))
+        .map(|mapping| mapping.action.clone())
+        .collect();
+    for action in actions {
+        let playing_layer = match &action {
+            MidiAction::Play {
+                file_path,
+                toggle: true,
+                ..
+            } => layer_playing(file_path).await,
+            _ => None,
+        };
+        for command in action_commands(&action, &message, playing_layer) {
+            let response = command_executable(command).execute().await;
+            if let Some(error) = response.daemon_error() {
+                tracing::debug!("MIDI {} ({}) failed: {}", message.trigger(), action, error);
+            }
+        }
+    }
+}
+
+// The layer currently playing `file_path`, if any
+async fn layer_playing(file_path: &Path) -> Option<usize> {
+    let response = command_executable(Command::GetLayersInfo).execute().await;
+    let layers: Vec<LayerInfo> = response.data_as()?;
+    layers
+        .iter()
+        .find(|layer| layer.is_playing && layer.current_file.as_deref() == Some(file_path))
+        .map(|layer| layer.index)
+}
+
+// What `action` runs for `message`. Releases run nothing, and buttons only
+// fire on press; levels follow the value. `playing_layer` is where a toggled
+// sound is playing.
+fn action_commands(
+    action: &MidiAction,
+    message: &MidiMessage,
+    playing_layer: Option<usize>,
+) -> Vec<Command> {
+    if matches!(message.kind, MidiMessageKind::NoteOff { .. })
+        || (!action.is_level() && message.value() == 0)
+    {
+        return Vec::new();
+    }
+    let level = message.value() as f32 / 127.0;
+    let gain = GAIN_RANGE.0 + level * (GAIN_RANGE.1 - GAIN_RANGE.0);
+
+    match action {
+        MidiAction::Play {
+            file_path,
+            layer_index,
+            toggle,
+        } => match playing_layer {
+            Some(layer_index) if *toggle => vec![Command::StopLayer { layer_index }],
+            _ => vec![Command::PlayOnLayer {
+                layer_index: *layer_index,
+                file_path: file_path.to_string_lossy().into_owned(),
+                priority: None,
+            }],
+        },
+        MidiAction::StopLayer { layer_index } => vec![Command::StopLayer {
+            layer_index: *layer_index,
+        }],
+        // Separately, so an idle main player doesn't keep the layers going
+        MidiAction::StopAll => vec![Command::Stop, Command::StopAllLayers],
+        MidiAction::LayerVolume { layer_index } => vec![Command::SetLayerVolume {
+            layer_index: *layer_index,
+            volume: level,
+        }],
+        MidiAction::Volume => vec![Command::SetVolume { volume: level }],
+        MidiAction::Gain => vec![Command::SetGain { gain }],
+        MidiAction::MicGain => vec![Command::SetMicGain { mic_gain: gain }],
+    }
+}
+
+/// Sounds playing on the main player or any layer
+#[derive(Debug, Default)]
+struct PlayingSounds {
+    main: Option<PathBuf>,
+    layers: HashSet<PathBuf>,
+}
+
+impl PlayingSounds {
+    fn apply(&mut self, event: &Event) {
+        match event {
+            Event::State {
+                state, file_path, ..
+            } => {
+                self.main = file_path.clone().filter(|_| *state == PlayerState::Playing);
+            }
+            Event::Layers { layers } => {
+                self.layers = layers
+                    .iter()
+                    .filter(|layer| layer.is_playing)
+                    .filter_map(|layer| layer.current_file.clone())
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+
+    fn contains(&self, file_path: &Path) -> bool {
+        self.main.as_deref() == Some(file_path) || self.layers.contains(file_path)
+    }
+}
+
+/// A pad LED to turn on or off
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Led {
+    channel: u8,
+    note: u8,
+    on: bool,
+}
+
+// Whether each (channel, note) pad mapped to a sound should be lit.
+// Mappings on every channel light the pad on channel 0.
+fn led_states(mappings: &[MidiMapping], playing: &PlayingSounds) -> HashMap<(u8, u8), bool> {
+    let mut states = HashMap::new();
+    for mapping in mappings {
+        if let (MidiTrigger::Note { note }, MidiAction::Play { file_path, .. }) =
+            (mapping.trigger, &mapping.action)
+        {
+            let lit = states
+                .entry((mapping.channel.unwrap_or(0), note))
+                .or_insert(false);
+            *lit |= playing.contains(file_path);
+        }
+    }
+    states
+}
+
+// LEDs that differ between `lit` and `wanted`; pads no longer mapped go dark
+fn led_changes(lit: &HashMap<(u8, u8), bool>, wanted: &HashMap<(u8, u8), bool>) -> Vec<Led> {
+    let mut changes: Vec<Led> = wanted
+        .iter()
+        .filter(|(pad, on)| lit.get(pad) != Some(on))
+        .map(|(&(channel, note), &on)| Led { channel, note, on })
+        .collect();
+    changes.extend(
+        lit.iter()
+            .filter(|(pad, on)| **on && !wanted.contains_key(pad))
+            .map(|(&(channel, note), _)| Led {
+                channel,
+                note,
+                on: false,
+            }),
+    );
+    changes
+}
+
+// Our own ports and ALSA's "Midi Through" are never controllers. Without a
+// filter every other port is used.
+fn port_wanted(name: &str, filter: Option<&str>) -> bool {
+    if name.starts_with(CLIENT_NAME) {
+        return false;
+    }
+    match filter {
+        Some(filter) => name.to_lowercase().contains(&filter.to_lowercase()),
+        None => !name.contains("Through"),
+    }
+}
+
+// Owns every midir connection: forwards input to `messages`, sends LED
+// changes to every output and rescans ports between them
+fn port_thread(
+    filter: Option<String>,
+    messages: mpsc::UnboundedSender<Vec<u8>>,
+    leds: std_mpsc::Receiver<Led>,
+) {
+    let mut inputs: HashMap<String, MidiInputConnection<()>> = HashMap::new();
+    let mut outputs: HashMap<String, MidiOutputConnection> = HashMap::new();
+    let mut lit: HashMap<(u8, u8), bool> = HashMap::new();
+
+    loop {
+        scan_inputs(filter.as_deref(), &mut inputs, &messages);
+        scan_outputs(filter.as_deref(), &mut outputs, &lit);
+
+        let rescan_at = Instant::now() + PORT_RESCAN;
+        loop {
+            match leds.recv_timeout(rescan_at.saturating_duration_since(Instant::now())) {
+                Ok(led) => {
+                    lit.insert((led.channel, led.note), led.on);
+                    let bytes = MidiMessage::note_on(led.channel, led.note, velocity(led.on));
+                    outputs.retain(|name, output| match output.send(&bytes) {
+                        Ok(()) => true,
+                        Err(e) => {
+                            tracing::debug!("MIDI output {} went away: {}", name, e);
+                            false
+                        }
+                    });
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+}
+
+fn velocity(on: bool) -> u8 {
+    if on {
+        127
+    } else {
+        0
+    }
+}
+
+fn scan_inputs(
+    filter: Option<&str>,
+    inputs: &mut HashMap<String, MidiInputConnection<()>>,
+    messages: &mpsc::UnboundedSender<Vec<u8>>,
+) {
+    let Ok(listing) = MidiInput::new(CLIENT_NAME) else {
+        return;
+    };
+    let ports: Vec<_> = listing
+        .ports()
+        .into_iter()
+        .filter_map(|port| listing.port_name(&port).ok().map(|name| (name, port)))
+        .filter(|(name, _)| port_wanted(name, filter))
+        .collect();
+    // Unplugged controllers
+    inputs.retain(|name, _| ports.iter().any(|(port_name, _)| port_name == name));
+
+    for (name, port) in ports {
+        if inputs.contains_key(&name) {
+            continue;
+        }
+        let Ok(mut input) = MidiInput::new(CLIENT_NAME) else {
+            return;
+        };
+        input.ignore(Ignore::All);
+        let sender = messages.clone();
+        let forward = move |_: u64, bytes: &[u8], _: &mut ()| {
+            sender.send(bytes.to_vec()).ok();
+        };
+        match input.connect(&port, "soundboard-input", forward, ()) {
+            Ok(connection) => {
+                tracing::info!("MIDI input connected: {}", name);
+                inputs.insert(name, connection);
+            }
+            Err(e) => tracing::warn!("Failed to open MIDI input {}: {}", name, e),
+        }
+    }
+}
+
+// New outputs get every lit LED, since the pads don't know what's playing
+fn scan_outputs(
+    filter: Option<&str>,
+    outputs: &mut HashMap<String, MidiOutputConnection>,
+    lit: &HashMap<(u8, u8), bool>,
+) {
+    let Ok(listing) = MidiOutput::new(CLIENT_NAME) else {
+        return;
+    };
+    let ports: Vec<_> = listing
+        .ports()
+        .into_iter()
+        .filter_map(|port| listing.port_name(&port).ok().map(|name| (name, port)))
+        .filter(|(name, _)| port_wanted(name, filter))
+        .collect();
+    outputs.retain(|name, _| ports.iter().any(|(port_name, _)| port_name == name));
+
+    for (name, port) in ports {
+        if outputs.contains_key(&name) {
+            continue;
+        }
+        let Ok(output) = MidiOutput::new(CLIENT_NAME) else {
+            return;
+        };
+        match output.connect(&port, "soundboard-feedback") {
+            Ok(mut connection) => {
+                for (&(channel, note), &on) in lit.iter().filter(|(_, on)| **on) {
+                    connection
+                        .send(&MidiMessage::note_on(channel, note, velocity(on)))
+                        .ok();
+                }
+                tracing::info!("MIDI output connected: {}", name);
+                outputs.insert(name, connection);
+            }
+            Err(e) => tracing::warn!("Failed to open MIDI output {}: {}", name, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pad(velocity: u8) -> MidiMessage {
+        MidiMessage {
+            channel: 0,
+            kind: if velocity == 0 {
+                MidiMessageKind::NoteOff { note: 36 }
+            } else {
+                MidiMessageKind::NoteOn { note: 36, velocity }
+            },
+        }
+    }
+
+    fn knob(value: u8) -> MidiMessage {
+        MidiMessage {
+            channel: 0,
+            kind: MidiMessageKind::Control {
+                controller: 7,
+                value,
+            },
+        }
+    }
+
+    fn play(toggle: bool) -> MidiAction {
+        MidiAction::Play {
+            file_path: PathBuf::from("/s/a.ogg"),
+            layer_index: None,
+            toggle,
+        }
+    }
+
+    #[test]
+    fn test_pads_fire_on_press_only() {
+        let started = vec![Command::PlayOnLayer {
+            layer_index: None,
+            file_path: "/s/a.ogg".to_string(),
+            priority: None,
+        }];
+        assert_eq!(action_commands(&play(false), &pad(100), None), started);
+        assert!(action_commands(&play(false), &pad(0), None).is_empty());
+        // CC buttons send 0 on release
+        assert!(action_commands(&MidiAction::StopAll, &knob(0), None).is_empty());
+        assert_eq!(
+            action_commands(&MidiAction::StopAll, &knob(127), None),
+            vec![Command::Stop, Command::StopAllLayers]
+        );
+    }
+
+    #[test]
+    fn test_toggle_stops_the_playing_layer() {
+        assert_eq!(
+            action_commands(&play(true), &pad(100), Some(3)),
+            vec![Command::StopLayer { layer_index: 3 }]
+        );
+        // Without toggle it plays again
+        assert!(matches!(
+            action_commands(&play(false), &pad(100), Some(3))[..],
+            [Command::PlayOnLayer { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_levels_follow_the_value() {
+        assert_eq!(
+            action_commands(&MidiAction::Volume, &knob(0), None),
+            vec![Command::SetVolume { volume: 0.0 }]
+        );
+        assert_eq!(
+            action_commands(&MidiAction::Gain, &knob(127), None),
+            vec![Command::SetGain { gain: 3.0 }]
+        );
+        assert_eq!(
+            action_commands(
+                &MidiAction::LayerVolume { layer_index: 1 },
+                &knob(127),
+                None
+            ),
+            vec![Command::SetLayerVolume {
+                layer_index: 1,
+                volume: 1.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_leds_follow_playing_sounds() {
+        let mappings = vec![MidiMapping {
+            channel: Some(9),
+            trigger: MidiTrigger::Note { note: 36 },
+            action: play(true),
+        }];
+        let mut playing = PlayingSounds::default();
+        let off = led_states(&mappings, &playing);
+        assert_eq!(off.get(&(9, 36)), Some(&false));
+
+        playing.apply(&Event::State {
+            state: PlayerState::Playing,
+            file_path: Some(PathBuf::from("/s/a.ogg")),
+            duration: 1.0,
+            looped: false,
+        });
+        let on = led_states(&mappings, &playing);
+        assert_eq!(
+            led_changes(&off, &on),
+            vec![Led {
+                channel: 9,
+                note: 36,
+                on: true,
+            }]
+        );
+        assert!(led_changes(&on, &on).is_empty());
+        // Removing the mapping turns its pad off
+        assert_eq!(
+            led_changes(&on, &HashMap::new()),
+            vec![Led {
+                channel: 9,
+                note: 36,
+                on: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_port_filter() {
+        assert!(port_wanted("LPD8:LPD8 MIDI 1 20:0", None));
+        assert!(!port_wanted("Midi Through:Midi Through Port-0 14:0", None));
+        assert!(!port_wanted("soundboard:soundboard-input 128:0", None));
+        assert!(port_wanted("LPD8:LPD8 MIDI 1 20:0", Some("lpd8")));
+        assert!(!port_wanted("nanoPAD2:nanoPAD2 MIDI 1 24:0", Some("lpd8")));
+    }
+}
@@ -1,8 +1,29 @@
 pub mod commands;
 pub mod config;
 pub mod daemon;
+pub mod events;
+pub mod flac;
 pub mod gui;
+pub mod hooks;
+pub mod irc;
+pub mod library;
+pub mod limits;
 pub mod logging;
+pub mod macros;
+pub mod midi;
+#[cfg(target_os = "linux")]
+pub mod mpris;
+pub mod mqtt;
+pub mod obs;
+pub mod osc;
 #[cfg(target_os = "linux")]
 pub mod pipewire;
+pub mod playlist;
+pub mod remote;
+pub mod render;
+#[cfg(target_os = "linux")]
+pub mod routing;
+pub mod schedule;
 pub mod updater;
+pub mod voices;
+pub mod wav;
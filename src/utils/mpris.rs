@@ -0,0 +1,511 @@
+// MPRIS player on the session bus, so media keys, `playerctl` and desktop
+// widgets control the main player. Everything goes through the daemon's
+// commands, and the exported state follows the event stream.
+
+use crate::{
+    types::{audio_player::PlayerState, config::GuiConfig, events::Event, socket::Command},
+    utils::{commands::command_executable, events::follow_events, library::sound_display_name},
+};
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::{Path, PathBuf},
+};
+use zbus::{
+    connection, fdo, interface,
+    object_server::{InterfaceRef, SignalEmitter},
+    zvariant::{ObjectPath, OwnedValue, Value},
+};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.soundboard";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const TRACK_PATH: &str = "/org/soundboard/track";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+// Position ticks closer than this to the last one are normal playback;
+// anything else is reported as a seek
+const SEEK_TOLERANCE: f32 = 1.0;
+
+/// Register the player on the session bus and keep it in sync until the
+/// event stream ends
+pub async fn serve_mpris() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let connection = connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, MprisRoot)?
+        .serve_at(OBJECT_PATH, MprisPlayer::default())?
+        .build()
+        .await?;
+    let player = connection
+        .object_server()
+        .interface::<_, MprisPlayer>(OBJECT_PATH)
+        .await?;
+    tracing::info!("MPRIS player registered as {}", BUS_NAME);
+
+    let mut events = follow_events().await;
+    while let Some(event) = events.next().await {
+        let changes = player.get_mut().await.apply(&event);
+        for change in changes {
+            if let Err(e) = emit(&player, change).await {
+                tracing::debug!("Failed to emit MPRIS change: {}", e);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn emit(player: &InterfaceRef<MprisPlayer>, change: Change) -> zbus::Result<()> {
+    let emitter = player.signal_emitter();
+    let player = player.get().await;
+    match change {
+        Change::PlaybackStatus => player.playback_status_changed(emitter).await,
+        Change::Metadata => player.metadata_changed(emitter).await,
+        Change::LoopStatus => player.loop_status_changed(emitter).await,
+        Change::Volume => player.volume_changed(emitter).await,
+        Change::Seeked(position) => MprisPlayer::seeked(emitter, position).await,
+    }
+}
+
+async fn run(command: Command) -> fdo::Result<()> {
+    match command_executable(command).execute().await.daemon_error() {
+        Some(error) => Err(fdo::Error::Failed(error.message)),
+        None => Ok(()),
+    }
+}
+
+fn micros(seconds: f32) -> i64 {
+    (seconds as f64 * 1_000_000.0) as i64
+}
+
+// Custom name from the GUI's config, read when the track changes so renames
+// show up without restarting the daemon
+fn track_title(path: &Path) -> String {
+    let config = GuiConfig::load_from_file().unwrap_or_default();
+    sound_display_name(&config, path)
+}
+
+/// `file://` URI with everything but unreserved characters and `/` escaped
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+/// Path of a `file://` URI, or `None` for other schemes and bad escapes
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        if encoded[i] == b'%' {
+            let hex = std::str::from_utf8(encoded.get(i + 1..i + 3)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            bytes.push(encoded[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+fn owned<'a>(value: impl Into<Value<'a>>) -> OwnedValue {
+    // Only file descriptors can fail to convert, and metadata holds none
+    OwnedValue::try_from(value.into()).expect("metadata values are plain data")
+}
+
+/// `org.mpris.MediaPlayer2`: who we are
+struct MprisRoot;
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl MprisRoot {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        "Soundboard".to_string()
+    }
+
+    #[zbus(property)]
+    fn desktop_entry(&self) -> String {
+        "soundboard-gui".to_string()
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        vec!["file".to_string()]
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// What changed after an event, as MPRIS signals
+#[derive(Debug, PartialEq)]
+enum Change {
+    PlaybackStatus,
+    Metadata,
+    LoopStatus,
+    Volume,
+    /// New position in microseconds
+    Seeked(i64),
+}
+
+/// `org.mpris.MediaPlayer2.Player`, mirroring the main player
+struct MprisPlayer {
+    state: PlayerState,
+    file_path: Option<PathBuf>,
+    title: String,
+    duration: f32,
+    looped: bool,
+    position: f32,
+    volume: f32,
+    /// What play replays once stopped; the daemon forgets the file on stop
+    last_file_path: Option<PathBuf>,
+    /// Bumped for every new file so clients see a new track id
+    track: u64,
+}
+
+impl Default for MprisPlayer {
+    fn default() -> Self {
+        Self {
+            state: PlayerState::Stopped,
+            file_path: None,
+            title: String::new(),
+            duration: 0.0,
+            looped: false,
+            position: 0.0,
+            volume: 1.0,
+            last_file_path: None,
+            track: 0,
+        }
+    }
+}
+
+impl MprisPlayer {
+    fn apply(&mut self, event: &Event) -> Vec<Change> {
+        let mut changes = Vec::new();
+        match event {
+            Event::State {
+                state,
+                file_path,
+                duration,
+                looped,
+            } => {
+                if *state != self.state {
+                    self.state = *state;
+                    changes.push(Change::PlaybackStatus);
+                }
+                if *file_path != self.file_path {
+                    if file_path.is_some() {
+                        self.last_file_path = file_path.clone();
+                    }
+                    self.file_path = file_path.clone();
+                    self.title = file_path.as_deref().map(track_title).unwrap_or_default();
+                    self.track += 1;
+                    self.position = 0.0;
+                    self.duration = *duration;
+                    changes.push(Change::Metadata);
+                } else if *duration != self.duration {
+                    self.duration = *duration;
+                    changes.push(Change::Metadata);
+                }
+                if *looped != self.looped {
+                    self.looped = *looped;
+                    changes.push(Change::LoopStatus);
+                }
+            }
+            Event::Position { position } => {
+                let jumped = *position < self.position - SEEK_TOLERANCE
+                    || *position > self.position + SEEK_TOLERANCE;
+                self.position = *position;
+                if jumped && self.state != PlayerState::Stopped {
+                    changes.push(Change::Seeked(micros(*position)));
+                }
+            }
+            Event::Volume { volume, .. } => {
+                if *volume != self.volume {
+                    self.volume = *volume;
+                    changes.push(Change::Volume);
+                }
+            }
+            Event::Layers { .. }
+            | Event::Devices { .. }
+            | Event::Play { .. }
+            | Event::Stop { .. }
+            | Event::LayerPlay { .. }
+            | Event::LoopEnd { .. }
+            | Event::Error { .. } => {}
+        }
+        changes
+    }
+
+    fn track_id(&self) -> ObjectPath<'static> {
+        match self.file_path {
+            Some(_) => ObjectPath::from_string_unchecked(format!("{}/{}", TRACK_PATH, self.track)),
+            None => ObjectPath::from_static_str_unchecked(NO_TRACK),
+        }
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl MprisPlayer {
+    // There is only ever one sound, so next and previous do nothing
+    fn next(&self) {}
+
+    fn previous(&self) {}
+
+    async fn pause(&self) -> fdo::Result<()> {
+        run(Command::Pause).await
+    }
+
+    async fn play_pause(&self) -> fdo::Result<()> {
+        match self.state {
+            PlayerState::Stopped => self.play().await,
+            _ => run(Command::TogglePause).await,
+        }
+    }
+
+    async fn stop(&self) -> fdo::Result<()> {
+        run(Command::Stop).await
+    }
+
+    /// Resumes, or replays the last sound when stopped
+    async fn play(&self) -> fdo::Result<()> {
+        match (self.state, &self.last_file_path) {
+            (PlayerState::Paused, _) => run(Command::Resume).await,
+            (PlayerState::Stopped, Some(file_path)) => {
+                run(Command::Play {
+                    file_path: file_path.to_string_lossy().into_owned(),
+                    priority: None,
+                })
+                .await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn seek(&self, offset: i64) -> fdo::Result<()> {
+        if self.state == PlayerState::Stopped {
+            return Ok(());
+        }
+        let position = (self.position + offset as f32 / 1_000_000.0).clamp(0.0, self.duration);
+        run(Command::Seek { position }).await
+    }
+
+    async fn set_position(&self, track_id: ObjectPath<'_>, position: i64) -> fdo::Result<()> {
+        // Requests for a track that already ended are ignored, as the spec asks
+        if track_id != self.track_id() || position < 0 || position > micros(self.duration) {
+            return Ok(());
+        }
+        run(Command::Seek {
+            position: position as f32 / 1_000_000.0,
+        })
+        .await
+    }
+
+    async fn open_uri(&self, uri: String) -> fdo::Result<()> {
+        let path = uri_path(&uri)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("Not a file URI: {}", uri)))?;
+        run(Command::Play {
+            file_path: path.to_string_lossy().into_owned(),
+            priority: None,
+        })
+        .await
+    }
+
+    #[zbus(signal)]
+    async fn seeked(emitter: &SignalEmitter<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        match self.state {
+            PlayerState::Playing => "Playing",
+            PlayerState::Paused => "Paused",
+            PlayerState::Stopped => "Stopped",
+        }
+        .to_string()
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> String {
+        if self.looped { "Track" } else { "None" }.to_string()
+    }
+
+    #[zbus(property)]
+    async fn set_loop_status(&mut self, status: String) {
+        let enabled = status != "None";
+        if let Err(e) = run(Command::SetLoop { enabled }).await {
+            tracing::warn!("MPRIS failed to set loop: {}", e);
+        }
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn set_rate(&mut self, _rate: f64) {}
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let mut metadata = HashMap::new();
+        metadata.insert("mpris:trackid".to_string(), owned(self.track_id()));
+        if let Some(file_path) = &self.file_path {
+            metadata.insert("mpris:length".to_string(), owned(micros(self.duration)));
+            metadata.insert("xesam:title".to_string(), owned(self.title.clone()));
+            metadata.insert("xesam:url".to_string(), owned(file_uri(file_path)));
+        }
+        metadata
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.volume as f64
+    }
+
+    #[zbus(property)]
+    async fn set_volume(&mut self, volume: f64) {
+        let volume = volume.clamp(0.0, 1.0) as f32;
+        if let Err(e) = run(Command::SetVolume { volume }).await {
+            tracing::warn!("MPRIS failed to set volume: {}", e);
+        }
+    }
+
+    // Clients poll this; the spec says it must not emit change signals
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        micros(self.position)
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        self.last_file_path.is_some()
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        self.state != PlayerState::Stopped
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        self.state != PlayerState::Stopped
+    }
+
+    #[zbus(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(state: PlayerState, file_path: Option<&str>, duration: f32) -> Event {
+        Event::State {
+            state,
+            file_path: file_path.map(PathBuf::from),
+            duration,
+            looped: false,
+        }
+    }
+
+    #[test]
+    fn test_state_changes() {
+        let mut player = MprisPlayer::default();
+        assert_eq!(
+            player.apply(&state(PlayerState::Playing, Some("/s/a.ogg"), 2.0)),
+            vec![Change::PlaybackStatus, Change::Metadata]
+        );
+        assert_eq!(player.track_id().as_str(), "/org/soundboard/track/1");
+        // Same event again changes nothing
+        assert!(player
+            .apply(&state(PlayerState::Playing, Some("/s/a.ogg"), 2.0))
+            .is_empty());
+        assert_eq!(
+            player.apply(&state(PlayerState::Stopped, None, 0.0)),
+            vec![Change::PlaybackStatus, Change::Metadata]
+        );
+        assert_eq!(player.track_id().as_str(), NO_TRACK);
+        // Play still has something to replay
+        assert_eq!(player.last_file_path, Some(PathBuf::from("/s/a.ogg")));
+        assert_eq!(
+            player.apply(&Event::Volume {
+                volume: 0.5,
+                gain: 1.0,
+                mic_gain: 1.0,
+            }),
+            vec![Change::Volume]
+        );
+    }
+
+    #[test]
+    fn test_position_jumps_are_seeks() {
+        let mut player = MprisPlayer::default();
+        player.apply(&state(PlayerState::Playing, Some("/s/a.ogg"), 60.0));
+        assert!(player.apply(&Event::Position { position: 0.03 }).is_empty());
+        assert_eq!(
+            player.apply(&Event::Position { position: 30.0 }),
+            vec![Change::Seeked(30_000_000)]
+        );
+        // A new file starts from zero without looking like a seek
+        player.apply(&state(PlayerState::Playing, Some("/s/b.ogg"), 60.0));
+        assert!(player.apply(&Event::Position { position: 0.03 }).is_empty());
+    }
+
+    #[test]
+    fn test_file_uri_roundtrip() {
+        let path = Path::new("/sounds/air horn #2.ogg");
+        let uri = file_uri(path);
+        assert_eq!(uri, "file:///sounds/air%20horn%20%232.ogg");
+        assert_eq!(uri_path(&uri).as_deref(), Some(path));
+        assert_eq!(uri_path("http://example.com/a.ogg"), None);
+        assert_eq!(uri_path("file:///bad%2"), None);
+    }
+}
@@ -0,0 +1,526 @@
+// MQTT client for home automation, run by the daemon when `mqtt.enabled` is
+// set in daemon.json. With the default `soundboard` prefix:
+//
+//   soundboard/command/<command>    runs a daemon command; the payload is its
+//                                   fields as JSON, e.g. {"file_path": "..."},
+//                                   and may be empty for commands without any
+//   soundboard/result/<command>     the command's response
+//   soundboard/availability         online/offline, retained
+//   soundboard/state                playing/paused/stopped, retained
+//   soundboard/current_file         path of the main player's sound, retained
+//   soundboard/volume, gain, mic_gain                             retained
+//   soundboard/layers               JSON array of the layers, retained
+//   soundboard/error                background failures
+
+use crate::{
+    types::{
+        audio_player::LayerInfo,
+        config::MqttConfig,
+        events::Event,
+        payload::ErrorCode,
+        socket::{Command, Response},
+    },
+    utils::{
+        commands::command_executable,
+        events::{follow_events, EventFollower},
+        remote::parse_command,
+    },
+};
+use rumqttc::{AsyncClient, Event as MqttEvent, EventLoop, LastWill, MqttOptions, Packet, QoS};
+use serde::Serialize;
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+use tokio::sync::{
+    mpsc::{self, error::TrySendError},
+    Notify,
+};
+
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+// Requests queued for the broker before publishing waits
+const REQUEST_CAPACITY: usize = 64;
+// Commands waiting to run; a client flooding the command topics loses the
+// rest instead of growing the queue
+const COMMAND_CAPACITY: usize = 32;
+
+/// Topic names under the configured prefix
+#[derive(Clone)]
+struct Topics {
+    prefix: String,
+}
+
+impl Topics {
+    fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn topic(&self, name: &str) -> String {
+        format!("{}/{}", self.prefix, name)
+    }
+
+    fn commands(&self) -> String {
+        self.topic("command/+")
+    }
+
+    /// The command named by a topic under `<prefix>/command/`
+    fn command_name<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        topic
+            .strip_prefix(self.prefix.as_str())?
+            .strip_prefix("/command/")
+            .filter(|name| !name.is_empty() && !name.contains('/'))
+    }
+}
+
+/// A layer as published on `<prefix>/layers`; positions are left out so the
+/// retained message only changes when something is started, stopped or faded
+#[derive(Serialize)]
+struct LayerState<'a> {
+    index: usize,
+    is_playing: bool,
+    is_paused: bool,
+    volume: f32,
+    current_file: &'a Option<PathBuf>,
+}
+
+/// Keep an MQTT connection to the broker, reconnecting whenever it drops
+pub async fn mqtt_loop(config: MqttConfig) {
+    let topics = Topics::new(&config.topic_prefix);
+    let (client, eventloop) = AsyncClient::new(mqtt_options(&config, &topics), REQUEST_CAPACITY);
+    let (command_sender, command_receiver) = mpsc::channel(COMMAND_CAPACITY);
+    let connected = Arc::new(Notify::new());
+    tokio::spawn(run_commands(
+        client.clone(),
+        topics.clone(),
+        command_receiver,
+    ));
+    tokio::spawn({
+        let topics = topics.clone();
+        let connected = connected.clone();
+        async move {
+            publish_state(client, topics, connected, follow_events().await).await;
+        }
+    });
+
+    poll_broker(eventloop, &config, &topics, &connected, command_sender).await;
+}
+
+fn mqtt_options(config: &MqttConfig, topics: &Topics) -> MqttOptions {
+    let mut options = MqttOptions::new(&config.client_id, &config.broker, config.port);
+    options.set_keep_alive(KEEP_ALIVE);
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+    options.set_last_will(LastWill::new(
+        topics.topic("availability"),
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    options
+}
+
+/// Drive the connection: requests from the client only go out while this
+/// polls. Incoming commands are handed to `command_sender`.
+async fn poll_broker(
+    mut eventloop: EventLoop,
+    config: &MqttConfig,
+    topics: &Topics,
+    connected: &Notify,
+    command_sender: mpsc::Sender<(String, Vec<u8>)>,
+) {
+    let mut was_connected = false;
+    loop {
+        match eventloop.poll().await {
+            Ok(MqttEvent::Incoming(Packet::ConnAck(_))) => {
+                tracing::info!("Connected to MQTT broker {}:{}", config.broker, config.port);
+                was_connected = true;
+                connected.notify_one();
+            }
+            Ok(MqttEvent::Incoming(Packet::Publish(publish))) => {
+                if let Some(name) = topics.command_name(&publish.topic) {
+                    // Run elsewhere so a slow command doesn't stall keep-alives
+                    let command = (name.to_string(), publish.payload.to_vec());
+                    if let Err(TrySendError::Full(_)) = command_sender.try_send(command) {
+                        tracing::warn!(
+                            "Skipping MQTT command `{}`, {} commands are already queued",
+                            name,
+                            COMMAND_CAPACITY
+                        );
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                if was_connected {
+                    tracing::warn!("MQTT connection lost: {}", e);
+                } else {
+                    tracing::debug!("MQTT broker unreachable: {}", e);
+                }
+                was_connected = false;
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+// A single task drains the queue, so a Home Assistant slider's last volume
+// wins over the ones it sent while dragging
+async fn run_commands(
+    client: AsyncClient,
+    topics: Topics,
+    mut commands: mpsc::Receiver<(String, Vec<u8>)>,
+) {
+    while let Some((name, payload)) = commands.recv().await {
+        let response = match parse_command(&name, &payload) {
+            Ok(Command::Subscribe) => Response::error(
+                ErrorCode::InvalidRequest,
+                "Events are published on the state topics instead",
+            ),
+            Ok(command) => command_executable(command).execute().await,
+            Err(error) => error.into(),
+        };
+        if let Some(error) = response.daemon_error() {
+            tracing::debug!("MQTT command {} failed: {}", name, error);
+        }
+        let topic = topics.topic(&format!("result/{}", name));
+        match serde_json::to_vec(&response) {
+            Ok(json) => {
+                if client
+                    .publish(topic, QoS::AtMostOnce, false, json)
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            Err(e) => tracing::warn!("Failed to serialize MQTT response: {}", e),
+        }
+    }
+}
+
+// Retained topics are only published when their payload changes, since the
+// player reports its layers many times a second. Requests wait in the client
+// while the broker is away, so subscribing happens here rather than in the
+// connection loop, which must keep polling for them to go out.
+async fn publish_state(
+    client: AsyncClient,
+    topics: Topics,
+    connected: Arc<Notify>,
+    mut events: EventFollower,
+) {
+    let mut published: HashMap<String, String> = HashMap::new();
+    loop {
+        let event = tokio::select! {
+            event = events.next() => match event {
+                Some(event) => event,
+                None => return,
+            },
+            // The subscription and retained messages don't survive a clean
+            // session, so everything is sent again after a reconnect
+            _ = connected.notified() => {
+                let subscribed = client.subscribe(topics.commands(), QoS::AtLeastOnce).await;
+                let online = client
+                    .publish(topics.topic("availability"), QoS::AtLeastOnce, true, "online")
+                    .await;
+                if subscribed.and(online).is_err() {
+                    return;
+                }
+                published.clear();
+                events.resend_state();
+                continue;
+            }
+        };
+
+        for (topic, payload, retain) in event_messages(&topics, &event) {
+            if retain && published.get(&topic) == Some(&payload) {
+                continue;
+            }
+            if client
+                .publish(&topic, QoS::AtLeastOnce, retain, payload.clone())
+                .await
+                .is_err()
+            {
+                return;
+            }
+            if retain {
+                published.insert(topic, payload);
+            }
+        }
+    }
+}
+
+/// Topic, payload and retain flag for each message an event turns into
+fn event_messages(topics: &Topics, event: &Event) -> Vec<(String, String, bool)> {
+    match event {
+        Event::State {
+            state, file_path, ..
+        } => vec![
+            (topics.topic("state"), state.as_str().to_string(), true),
+            (
+                topics.topic("current_file"),
+                file_path
+                    .as_ref()
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                true,
+            ),
+        ],
+        Event::Volume {
+            volume,
+            gain,
+            mic_gain,
+        } => vec![
+            (topics.topic("volume"), volume.to_string(), true),
+            (topics.topic("gain"), gain.to_string(), true),
+            (topics.topic("mic_gain"), mic_gain.to_string(), true),
+        ],
+        Event::Layers { layers } => match serde_json::to_string(&layer_states(layers)) {
+            Ok(json) => vec![(topics.topic("layers"), json, true)],
+            Err(_) => Vec::new(),
+        },
+        Event::Error { error } => match serde_json::to_string(error) {
+            Ok(json) => vec![(topics.topic("error"), json, false)],
+            Err(_) => Vec::new(),
+        },
+        Event::Position { .. }
+        | Event::Devices { .. }
+        | Event::Play { .. }
+        | Event::Stop { .. }
+        | Event::LayerPlay { .. }
+        | Event::LoopEnd { .. } => Vec::new(),
+    }
+}
+
+fn layer_states(layers: &[LayerInfo]) -> Vec<LayerState<'_>> {
+    layers
+        .iter()
+        .map(|layer| LayerState {
+            index: layer.index,
+            is_playing: layer.is_playing,
+            is_paused: layer.is_paused,
+            volume: layer.volume,
+            current_file: &layer.current_file,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{audio_player::PlayerState, payload::DaemonError};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::broadcast,
+        time::timeout,
+    };
+
+    /// Read one MQTT packet: its first header byte and the rest after the
+    /// length
+    async fn read_packet(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+        let header = stream.read_u8().await.expect("header");
+        let mut length = 0usize;
+        for shift in (0..28).step_by(7) {
+            let byte = stream.read_u8().await.expect("length");
+            length |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).await.expect("body");
+        (header, body)
+    }
+
+    fn read_string(body: &[u8]) -> (String, &[u8]) {
+        let length = u16::from_be_bytes([body[0], body[1]]) as usize;
+        let string = String::from_utf8(body[2..2 + length].to_vec()).expect("utf-8");
+        (string, &body[2 + length..])
+    }
+
+    fn layer(index: usize, file: Option<&str>, position: f32) -> LayerInfo {
+        LayerInfo {
+            index,
+            is_playing: file.is_some(),
+            is_paused: false,
+            is_empty: file.is_none(),
+            volume: 0.5,
+            current_file: file.map(PathBuf::from),
+            position,
+            duration: file.map(|_| 10.0),
+            priority: 0,
+            age: file.map(|_| position),
+        }
+    }
+
+    #[test]
+    fn test_command_topics() {
+        let topics = Topics::new("home/soundboard/");
+        assert_eq!(topics.commands(), "home/soundboard/command/+");
+        assert_eq!(
+            topics.command_name("home/soundboard/command/play"),
+            Some("play")
+        );
+        assert_eq!(topics.command_name("home/soundboard/command/"), None);
+        assert_eq!(topics.command_name("home/soundboard/command/a/b"), None);
+        assert_eq!(topics.command_name("home/soundboard/state"), None);
+        assert_eq!(topics.command_name("home/soundboardx/command/play"), None);
+    }
+
+    #[test]
+    fn test_state_messages() {
+        let topics = Topics::new("soundboard");
+        let state = Event::State {
+            state: PlayerState::Playing,
+            file_path: Some(PathBuf::from("/sounds/rain.ogg")),
+            duration: 12.0,
+            looped: false,
+        };
+        assert_eq!(
+            event_messages(&topics, &state),
+            vec![
+                ("soundboard/state".to_string(), "playing".to_string(), true),
+                (
+                    "soundboard/current_file".to_string(),
+                    "/sounds/rain.ogg".to_string(),
+                    true
+                ),
+            ]
+        );
+
+        let volume = Event::Volume {
+            volume: 0.5,
+            gain: 1.0,
+            mic_gain: 2.0,
+        };
+        let messages = event_messages(&topics, &volume);
+        assert_eq!(
+            messages[0],
+            ("soundboard/volume".to_string(), "0.5".to_string(), true)
+        );
+        assert_eq!(messages.len(), 3);
+
+        assert!(event_messages(&topics, &Event::Position { position: 1.0 }).is_empty());
+
+        let error = Event::Error {
+            error: DaemonError::new(ErrorCode::PlaybackError, "Failed to play rain.ogg"),
+        };
+        let messages = event_messages(&topics, &error);
+        assert_eq!(messages[0].0, "soundboard/error");
+        assert!(!messages[0].2);
+    }
+
+    #[tokio::test]
+    async fn test_session_against_local_broker() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let address = listener.local_addr().expect("address");
+        let broker = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.expect("accept");
+
+            let (header, connect) = read_packet(&mut stream).await;
+            assert_eq!(header, 0x10, "CONNECT comes first");
+            stream.write_all(&[0x20, 2, 0, 0]).await.expect("write");
+
+            // Collect until the daemon is online and has published its state
+            let mut subscriptions = Vec::new();
+            let mut retained = HashMap::new();
+            while !(retained.contains_key("soundboard/availability")
+                && retained.contains_key("soundboard/state"))
+            {
+                let (header, body) = read_packet(&mut stream).await;
+                match header >> 4 {
+                    // SUBSCRIBE
+                    8 => {
+                        let (topic, _) = read_string(&body[2..]);
+                        subscriptions.push(topic);
+                        stream
+                            .write_all(&[0x90, 3, body[0], body[1], 1])
+                            .await
+                            .expect("write");
+                    }
+                    // PUBLISH
+                    3 => {
+                        let qos = (header >> 1) & 0x03;
+                        let (topic, mut rest) = read_string(&body);
+                        if qos > 0 {
+                            stream
+                                .write_all(&[0x40, 2, rest[0], rest[1]])
+                                .await
+                                .expect("write");
+                            rest = &rest[2..];
+                        }
+                        if header & 0x01 == 1 {
+                            retained.insert(topic, String::from_utf8_lossy(rest).into_owned());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            (connect, subscriptions, retained)
+        });
+
+        let config = MqttConfig {
+            broker: address.ip().to_string(),
+            port: address.port(),
+            ..MqttConfig::default()
+        };
+        let topics = Topics::new(&config.topic_prefix);
+        let (client, eventloop) =
+            AsyncClient::new(mqtt_options(&config, &topics), REQUEST_CAPACITY);
+        let (command_sender, _command_receiver) = mpsc::channel(COMMAND_CAPACITY);
+        let connected = Arc::new(Notify::new());
+        let (event_sender, events) = broadcast::channel(16);
+        let publisher = tokio::spawn(publish_state(
+            client,
+            topics.clone(),
+            connected.clone(),
+            EventFollower::new(events, Vec::new()),
+        ));
+        let connection = tokio::spawn(async move {
+            poll_broker(eventloop, &config, &topics, &connected, command_sender).await
+        });
+        event_sender
+            .send(Event::State {
+                state: PlayerState::Playing,
+                file_path: Some(PathBuf::from("/sounds/rain.ogg")),
+                duration: 12.0,
+                looped: false,
+            })
+            .expect("send");
+
+        let (connect, subscriptions, retained) = timeout(Duration::from_secs(5), broker)
+            .await
+            .expect("broker saw the whole session")
+            .expect("broker");
+        publisher.abort();
+        connection.abort();
+
+        // The last will marks the daemon offline if it goes away
+        let connect = String::from_utf8_lossy(&connect);
+        assert!(connect.contains("soundboard/availability"));
+        assert!(connect.contains("offline"));
+        assert_eq!(subscriptions, vec!["soundboard/command/+"]);
+        assert_eq!(retained["soundboard/availability"], "online");
+        assert_eq!(retained["soundboard/state"], "playing");
+        assert_eq!(retained["soundboard/current_file"], "/sounds/rain.ogg");
+    }
+
+    #[test]
+    fn test_layers_payload_ignores_position() {
+        let topics = Topics::new("soundboard");
+        let at = |position| Event::Layers {
+            layers: vec![
+                layer(0, Some("/sounds/a.ogg"), position),
+                layer(1, None, 0.0),
+            ],
+        };
+        let first = event_messages(&topics, &at(1.0));
+        assert_eq!(first, event_messages(&topics, &at(2.5)));
+
+        let layers: serde_json::Value = serde_json::from_str(&first[0].1).unwrap();
+        assert_eq!(layers[0]["current_file"], "/sounds/a.ogg");
+        assert_eq!(layers[1]["is_playing"], false);
+        assert!(layers[0].get("position").is_none());
+    }
+}
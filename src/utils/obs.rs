@@ -0,0 +1,350 @@
+// OBS Studio's WebSocket server (protocol v5, built into OBS 28 and later),
+// used by the daemon when `obs.enabled` is set in daemon.json. Scene changes
+// and stream or recording starts and stops run the configured rules, and the
+// sound on the main player can be shown in a text source.
+
+use crate::{
+    types::{
+        audio_player::PlayerState,
+        config::{GuiConfig, ObsConfig},
+        events::Event,
+        obs::{ObsEvent, ObsRule},
+        payload::DaemonError,
+    },
+    utils::{
+        commands::command_executable,
+        events::{follow_events, publish_error},
+        library::sound_display_name,
+    },
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{error::Error, path::Path, time::Duration};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+const OP_HELLO: u64 = 0;
+const OP_IDENTIFY: u64 = 1;
+const OP_IDENTIFIED: u64 = 2;
+const OP_EVENT: u64 = 5;
+const OP_REQUEST: u64 = 6;
+const OP_REQUEST_RESPONSE: u64 = 7;
+const RPC_VERSION: u64 = 1;
+// The Scenes and Outputs event categories
+const EVENT_SUBSCRIPTIONS: u64 = (1 << 2) | (1 << 6);
+// OBS is often started after the daemon, or restarted during a session
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+type ObsSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// A message of the obs-websocket protocol
+#[derive(Debug, Deserialize)]
+struct ObsMessage {
+    op: u64,
+    #[serde(default)]
+    d: Value,
+}
+
+/// Stay connected to OBS, reconnecting whenever it goes away
+pub async fn obs_loop(config: ObsConfig) {
+    let url = format!("ws://{}:{}", config.host, config.port);
+    loop {
+        match connect_async(url.as_str()).await {
+            Ok((socket, _)) => {
+                if let Err(e) = run_session(socket, &config).await {
+                    tracing::warn!("OBS connection ended: {}", e);
+                }
+            }
+            Err(e) => tracing::debug!("OBS unreachable at {}: {}", url, e),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn run_session(
+    mut socket: ObsSocket,
+    config: &ObsConfig,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let hello = expect_message(&mut socket, OP_HELLO).await?;
+    let identify = identify_message(&hello.d, config.password.as_deref())?;
+    socket
+        .send(Message::Text(identify.to_string().into()))
+        .await?;
+    expect_message(&mut socket, OP_IDENTIFIED).await?;
+    tracing::info!("Connected to OBS at {}:{}", config.host, config.port);
+
+    let mut events = follow_events().await;
+    let mut shown = None;
+
+    loop {
+        tokio::select! {
+            message = next_message(&mut socket) => {
+                let message = message?;
+                match message.op {
+                    OP_EVENT => {
+                        if let Some(event) = parse_event(&message.d) {
+                            run_rules(&config.rules, &event).await;
+                        }
+                    }
+                    OP_REQUEST_RESPONSE => {
+                        let status = &message.d["requestStatus"];
+                        if status["result"] == false {
+                            tracing::warn!(
+                                "OBS {} failed: {}",
+                                message.d["requestType"].as_str().unwrap_or_default(),
+                                status["comment"].as_str().unwrap_or_default()
+                            );
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            event = events.next() => match event {
+                Some(event) => show_now_playing(&mut socket, config, &event, &mut shown).await?,
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+// The next protocol message; tungstenite answers pings by itself
+async fn next_message(socket: &mut ObsSocket) -> Result<ObsMessage, Box<dyn Error + Send + Sync>> {
+    loop {
+        match socket.next().await {
+            Some(Ok(Message::Text(text))) => return Ok(serde_json::from_str(text.as_str())?),
+            // OBS explains refusals, like a wrong password, in the close frame
+            Some(Ok(Message::Close(Some(frame)))) if !frame.reason.is_empty() => {
+                return Err(format!("OBS closed the connection: {}", frame.reason.as_str()).into())
+            }
+            Some(Ok(Message::Close(_))) | None => return Err("OBS closed the connection".into()),
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(e.into()),
+        }
+    }
+}
+
+async fn expect_message(
+    socket: &mut ObsSocket,
+    op: u64,
+) -> Result<ObsMessage, Box<dyn Error + Send + Sync>> {
+    let message = next_message(socket).await?;
+    if message.op != op {
+        return Err(format!("Expected OBS message {}, got {}", op, message.op).into());
+    }
+    Ok(message)
+}
+
+/// The reply to OBS's hello, authenticating when it asks for it
+fn identify_message(
+    hello: &Value,
+    password: Option<&str>,
+) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    let mut identify = json!({
+        "rpcVersion": RPC_VERSION,
+        "eventSubscriptions": EVENT_SUBSCRIPTIONS,
+    });
+    if let Some(auth) = hello.get("authentication") {
+        let password =
+            password.ok_or("OBS asks for a password, set obs.password in daemon.json")?;
+        identify["authentication"] = authentication(
+            password,
+            auth["salt"].as_str().unwrap_or_default(),
+            auth["challenge"].as_str().unwrap_or_default(),
+        )
+        .into();
+    }
+    Ok(json!({ "op": OP_IDENTIFY, "d": identify }))
+}
+
+// base64(sha256(base64(sha256(password + salt)) + challenge))
+fn authentication(password: &str, salt: &str, challenge: &str) -> String {
+    let secret = BASE64.encode(Sha256::digest(format!("{}{}", password, salt)));
+    BASE64.encode(Sha256::digest(format!("{}{}", secret, challenge)))
+}
+
+fn parse_event(data: &Value) -> Option<ObsEvent> {
+    let event_data = &data["eventData"];
+    match data["eventType"].as_str()? {
+        "CurrentProgramSceneChanged" => Some(ObsEvent::SceneChanged {
+            scene: event_data["sceneName"].as_str().map(str::to_string),
+        }),
+        "StreamStateChanged" => {
+            output_event(event_data, ObsEvent::StreamStarted, ObsEvent::StreamStopped)
+        }
+        "RecordStateChanged" => output_event(
+            event_data,
+            ObsEvent::RecordingStarted,
+            ObsEvent::RecordingStopped,
+        ),
+        _ => None,
+    }
+}
+
+// Outputs also report starting and stopping, which rules don't react to
+fn output_event(event_data: &Value, started: ObsEvent, stopped: ObsEvent) -> Option<ObsEvent> {
+    match event_data["outputState"].as_str()? {
+        "OBS_WEBSOCKET_OUTPUT_STARTED" => Some(started),
+        "OBS_WEBSOCKET_OUTPUT_STOPPED" => Some(stopped),
+        _ => None,
+    }
+}
+
+async fn run_rules(rules: &[ObsRule], event: &ObsEvent) {
+    for rule in rules.iter().filter(|rule| rule.matches(event)) {
+        let response = command_executable(rule.action.command()).execute().await;
+        if let Some(error) = response.daemon_error() {
+            tracing::warn!("OBS rule for {:?} failed: {}", event, error);
+            publish_error(DaemonError::new(
+                error.code,
+                format!("OBS rule failed: {}", error.message),
+            ));
+        }
+    }
+}
+
+// Only sends when the text changes; `shown` is what the source shows now
+async fn show_now_playing(
+    socket: &mut ObsSocket,
+    config: &ObsConfig,
+    event: &Event,
+    shown: &mut Option<String>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let Some(source) = &config.now_playing_source else {
+        return Ok(());
+    };
+    let Some(text) = now_playing_text(event, |path| {
+        sound_display_name(&GuiConfig::load_from_file().unwrap_or_default(), path)
+    }) else {
+        return Ok(());
+    };
+    if shown.as_ref() == Some(&text) {
+        return Ok(());
+    }
+
+    let request = json!({
+        "op": OP_REQUEST,
+        "d": {
+            "requestType": "SetInputSettings",
+            "requestId": "now_playing",
+            "requestData": {
+                "inputName": source,
+                "inputSettings": { "text": text },
+                "overlay": true,
+            },
+        },
+    });
+    socket
+        .send(Message::Text(request.to_string().into()))
+        .await?;
+    *shown = Some(text);
+    Ok(())
+}
+
+/// The text source's new text when `event` changes the main player's sound
+fn now_playing_text(event: &Event, display_name: impl Fn(&Path) -> String) -> Option<String> {
+    match event {
+        Event::State {
+            state: PlayerState::Playing | PlayerState::Paused,
+            file_path: Some(path),
+            ..
+        } => Some(display_name(path)),
+        Event::State { .. } => Some(String::new()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_identify_message() {
+        let hello = json!({ "obsWebSocketVersion": "5.5.0", "rpcVersion": 1 });
+        let identify = identify_message(&hello, None).expect("no auth needed");
+        assert_eq!(identify["op"], OP_IDENTIFY);
+        assert_eq!(identify["d"]["eventSubscriptions"], EVENT_SUBSCRIPTIONS);
+        assert!(identify["d"].get("authentication").is_none());
+
+        let hello = json!({
+            "rpcVersion": 1,
+            "authentication": { "challenge": "c", "salt": "s" },
+        });
+        assert!(identify_message(&hello, None).is_err());
+        let identify = identify_message(&hello, Some("pw")).expect("auth");
+        assert_eq!(
+            identify["d"]["authentication"],
+            authentication("pw", "s", "c")
+        );
+        // Base64 of a SHA-256 digest
+        assert_eq!(authentication("pw", "s", "c").len(), 44);
+        assert_ne!(
+            authentication("pw", "s", "c"),
+            authentication("pw", "s", "d")
+        );
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let event = |event_type: &str, event_data: Value| {
+            parse_event(&json!({ "eventType": event_type, "eventData": event_data }))
+        };
+        assert_eq!(
+            event(
+                "CurrentProgramSceneChanged",
+                json!({ "sceneName": "BRB", "sceneUuid": "x" })
+            ),
+            Some(ObsEvent::SceneChanged {
+                scene: Some("BRB".to_string())
+            })
+        );
+        assert_eq!(
+            event(
+                "StreamStateChanged",
+                json!({ "outputActive": true, "outputState": "OBS_WEBSOCKET_OUTPUT_STARTED" })
+            ),
+            Some(ObsEvent::StreamStarted)
+        );
+        assert_eq!(
+            event(
+                "RecordStateChanged",
+                json!({ "outputActive": false, "outputState": "OBS_WEBSOCKET_OUTPUT_STOPPED" })
+            ),
+            Some(ObsEvent::RecordingStopped)
+        );
+        assert_eq!(
+            event(
+                "RecordStateChanged",
+                json!({ "outputActive": false, "outputState": "OBS_WEBSOCKET_OUTPUT_STARTING" })
+            ),
+            None
+        );
+        assert_eq!(event("InputMuteStateChanged", json!({})), None);
+    }
+
+    #[test]
+    fn test_now_playing_text() {
+        let name = |path: &Path| path.file_stem().unwrap().to_string_lossy().into_owned();
+        let state = |state, file_path: Option<&str>| Event::State {
+            state,
+            file_path: file_path.map(PathBuf::from),
+            duration: 10.0,
+            looped: false,
+        };
+        assert_eq!(
+            now_playing_text(&state(PlayerState::Playing, Some("/sounds/rain.ogg")), name),
+            Some("rain".to_string())
+        );
+        assert_eq!(
+            now_playing_text(&state(PlayerState::Stopped, Some("/sounds/rain.ogg")), name),
+            Some(String::new())
+        );
+        assert_eq!(
+            now_playing_text(&Event::Position { position: 1.0 }, name),
+            None
+        );
+    }
+}
@@ -0,0 +1,618 @@
+// Open Sound Control over UDP, served by the daemon when `osc.enabled` is set
+// in daemon.json. Addresses map onto daemon commands:
+//
+//   /soundboard/play <path> [priority]     play on the main player
+//   /soundboard/layer/play <path>           play on any free layer
+//   /soundboard/layer/<n>/play <path>       play on layer n
+//   /soundboard/layer/<n>/stop
+//   /soundboard/layer/<n>/volume <0..1>
+//   /soundboard/layers/stop                 stop every layer
+//   /soundboard/stop, pause, resume, toggle_pause, toggle_loop
+//   /soundboard/loop <0|1>
+//   /soundboard/volume <0..1>, gain <f>, mic_gain <f>, seek <seconds>
+//   /soundboard/macro <name>, macro/cancel <name>, playlist <name>
+//   /soundboard/register, unregister        start or stop feedback to the sender;
+//                                           registrations lapse when it goes quiet
+//
+// Feedback reuses the input addresses where it can, so faders bound to
+// `/soundboard/volume` follow changes made elsewhere:
+//
+//   /soundboard/state <playing|paused|stopped> <path> <duration>
+//   /soundboard/position <seconds>
+//   /soundboard/volume, gain, mic_gain <f>
+//   /soundboard/layer/<n>/playing <0|1>, volume <f>, file <path>
+//   /soundboard/error <message>             also sent to whoever sent a bad message
+
+use crate::{
+    types::{
+        config::OscConfig,
+        events::Event,
+        osc::{OscMessage, OscPacket, OscType},
+        payload::{DaemonError, ErrorCode},
+        socket::Command,
+    },
+    utils::{
+        commands::command_executable,
+        events::{current_events, follow_events},
+    },
+};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use tokio::net::{lookup_host, UdpSocket};
+
+const ADDRESS_PREFIX: &str = "/soundboard/";
+// Largest UDP payload; OSC packets are one datagram each
+const MAX_PACKET_SIZE: usize = 65_507;
+// Anyone who can reach the port can register, so the set is capped and
+// senders that go quiet are dropped
+const MAX_REGISTERED_CLIENTS: usize = 16;
+const REGISTRATION_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// What a received OSC message asks for
+#[derive(Debug, PartialEq)]
+enum OscAction {
+    Run(Command),
+    Register,
+    Unregister,
+    /// Button releases, which controllers send as a second message
+    Ignore,
+}
+
+/// Where feedback goes: the configured addresses, plus senders that
+/// registered and have sent something within `REGISTRATION_TIMEOUT`
+#[derive(Default)]
+struct FeedbackClients {
+    fixed: HashSet<SocketAddr>,
+    registered: HashMap<SocketAddr, Instant>,
+}
+
+impl FeedbackClients {
+    /// Keep `sender`'s registration alive, if it has one
+    fn heard(&mut self, sender: SocketAddr, now: Instant) {
+        if let Some(last) = self.registered.get_mut(&sender) {
+            *last = now;
+        }
+    }
+
+    /// Whether `sender` is newly registered, or an error once the set is full
+    fn register(&mut self, sender: SocketAddr, now: Instant) -> Result<bool, DaemonError> {
+        self.expire(now);
+        if self.fixed.contains(&sender) || self.registered.contains_key(&sender) {
+            self.heard(sender, now);
+            return Ok(false);
+        }
+        if self.registered.len() >= MAX_REGISTERED_CLIENTS {
+            return Err(DaemonError::new(
+                ErrorCode::Busy,
+                format!(
+                    "Too many OSC feedback clients; at most {} can register",
+                    MAX_REGISTERED_CLIENTS
+                ),
+            ));
+        }
+        self.registered.insert(sender, now);
+        Ok(true)
+    }
+
+    fn unregister(&mut self, sender: SocketAddr) {
+        self.registered.remove(&sender);
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.registered.retain(|client, last| {
+            let alive = now.duration_since(*last) < REGISTRATION_TIMEOUT;
+            if !alive {
+                tracing::info!("OSC feedback for {} expired", client);
+            }
+            alive
+        });
+    }
+
+    /// Everyone who should get feedback now
+    fn targets(&mut self, now: Instant) -> Vec<SocketAddr> {
+        self.expire(now);
+        self.fixed
+            .iter()
+            .chain(self.registered.keys())
+            .copied()
+            .collect()
+    }
+}
+
+/// Serve OSC until the socket fails
+pub async fn serve_osc(config: OscConfig) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let socket = UdpSocket::bind(&config.bind).await?;
+    tracing::info!("OSC server listening on udp://{}", config.bind);
+
+    let mut clients = FeedbackClients::default();
+    for target in &config.feedback {
+        match lookup_host(target).await.map(|mut addrs| addrs.next()) {
+            Ok(Some(addr)) => {
+                clients.fixed.insert(addr);
+            }
+            Ok(None) => tracing::warn!("OSC feedback address {} resolved to nothing", target),
+            Err(e) => tracing::warn!("Invalid OSC feedback address {}: {}", target, e),
+        }
+    }
+
+    let mut events = follow_events().await;
+    let mut buffer = vec![0; MAX_PACKET_SIZE];
+    loop {
+        tokio::select! {
+            received = socket.recv_from(&mut buffer) => {
+                let (len, sender) = match received {
+                    Ok(received) => received,
+                    // Windows reports an unreachable feedback client here;
+                    // that must not stop the server
+                    Err(e) => {
+                        tracing::debug!("OSC receive failed: {}", e);
+                        continue;
+                    }
+                };
+                clients.heard(sender, Instant::now());
+                let packet = match OscPacket::decode(&buffer[..len]) {
+                    Ok(packet) => packet,
+                    Err(e) => {
+                        tracing::debug!("Ignoring malformed OSC packet from {}: {}", sender, e);
+                        continue;
+                    }
+                };
+                for message in flatten(packet) {
+                    handle_message(&socket, &mut clients, sender, &message).await;
+                }
+            }
+            event = events.next() => match event {
+                Some(event) => send_event(&socket, &mut clients, &event).await,
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+// Awaited by the receive loop before it reads the next packet
+async fn handle_message(
+    socket: &UdpSocket,
+    clients: &mut FeedbackClients,
+    sender: SocketAddr,
+    message: &OscMessage,
+) {
+    match parse_message(message) {
+        Ok(OscAction::Run(command)) => {
+            let response = command_executable(command).execute().await;
+            if let Some(error) = response.daemon_error() {
+                tracing::debug!("OSC {} failed: {}", message.addr, error.message);
+                send(socket, sender, error_message(&error)).await;
+            }
+        }
+        Ok(OscAction::Register) => match clients.register(sender, Instant::now()) {
+            Ok(true) => {
+                tracing::info!("OSC feedback registered for {}", sender);
+                for event in current_events() {
+                    for message in event_messages(&event) {
+                        send(socket, sender, message).await;
+                    }
+                }
+            }
+            Ok(false) => {}
+            Err(error) => {
+                tracing::warn!("Refused OSC feedback for {}: {}", sender, error.message);
+                send(socket, sender, error_message(&error)).await;
+            }
+        },
+        Ok(OscAction::Unregister) => clients.unregister(sender),
+        Ok(OscAction::Ignore) => {}
+        Err(error) => send(socket, sender, error_message(&error)).await,
+    }
+}
+
+// Messages in the order they appear, with bundles unpacked
+fn flatten(packet: OscPacket) -> Vec<OscMessage> {
+    match packet {
+        OscPacket::Message(message) => vec![message],
+        OscPacket::Bundle(content) => content.into_iter().flat_map(flatten).collect(),
+    }
+}
+
+fn parse_message(message: &OscMessage) -> Result<OscAction, DaemonError> {
+    let unknown = || {
+        DaemonError::new(
+            ErrorCode::UnknownCommand,
+            format!("Unknown OSC address: {}", message.addr),
+        )
+    };
+    let path = message
+        .addr
+        .strip_prefix(ADDRESS_PREFIX)
+        .ok_or_else(unknown)?;
+    let segments: Vec<&str> = path.split('/').collect();
+    let args = &message.args;
+
+    let command = match segments.as_slice() {
+        ["register"] => return Ok(OscAction::Register),
+        ["unregister"] => return Ok(OscAction::Unregister),
+        ["play"] => Command::Play {
+            file_path: string_arg(args, 0)?,
+            priority: priority_arg(args, 1)?,
+        },
+        ["stop"] => return Ok(trigger(args, Command::Stop)),
+        ["pause"] => return Ok(trigger(args, Command::Pause)),
+        ["resume"] => return Ok(trigger(args, Command::Resume)),
+        ["toggle_pause"] => return Ok(trigger(args, Command::TogglePause)),
+        ["toggle_loop"] => return Ok(trigger(args, Command::ToggleLoop)),
+        ["loop"] => Command::SetLoop {
+            enabled: number_arg(args, 0)? != 0.0,
+        },
+        ["volume"] => Command::SetVolume {
+            volume: number_arg(args, 0)? as f32,
+        },
+        ["gain"] => Command::SetGain {
+            gain: number_arg(args, 0)? as f32,
+        },
+        ["mic_gain"] => Command::SetMicGain {
+            mic_gain: number_arg(args, 0)? as f32,
+        },
+        ["seek"] => Command::Seek {
+            position: number_arg(args, 0)? as f32,
+        },
+        ["layer", "play"] => Command::PlayOnLayer {
+            layer_index: None,
+            file_path: string_arg(args, 0)?,
+            priority: priority_arg(args, 1)?,
+        },
+        ["layer", index, "play"] => Command::PlayOnLayer {
+            layer_index: Some(layer_index(index)?),
+            file_path: string_arg(args, 0)?,
+            priority: priority_arg(args, 1)?,
+        },
+        ["layer", index, "stop"] => {
+            let layer_index = layer_index(index)?;
+            return Ok(trigger(args, Command::StopLayer { layer_index }));
+        }
+        ["layer", index, "volume"] => Command::SetLayerVolume {
+            layer_index: layer_index(index)?,
+            volume: number_arg(args, 0)? as f32,
+        },
+        ["layers", "stop"] => return Ok(trigger(args, Command::StopAllLayers)),
+        ["macro"] => Command::RunMacro {
+            name: string_arg(args, 0)?,
+        },
+        ["macro", "cancel"] => Command::CancelMacro {
+            name: string_arg(args, 0)?,
+        },
+        ["playlist"] => Command::PlayPlaylist {
+            name: string_arg(args, 0)?,
+            mode: None,
+        },
+        _ => return Err(unknown()),
+    };
+    Ok(OscAction::Run(command))
+}
+
+// Controllers send buttons as 1 on press and 0 on release; only the press
+// should do anything
+fn trigger(args: &[OscType], command: Command) -> OscAction {
+    match args.first().and_then(as_number) {
+        Some(0.0) => OscAction::Ignore,
+        _ => OscAction::Run(command),
+    }
+}
+
+fn as_number(arg: &OscType) -> Option<f64> {
+    match arg {
+        OscType::Int(value) => Some(*value as f64),
+        OscType::Long(value) => Some(*value as f64),
+        OscType::Float(value) => Some(*value as f64),
+        OscType::Double(value) => Some(*value),
+        OscType::Bool(value) => Some(if *value { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+fn number_arg(args: &[OscType], index: usize) -> Result<f64, DaemonError> {
+    args.get(index).and_then(as_number).ok_or_else(|| {
+        DaemonError::new(
+            ErrorCode::InvalidArg,
+            format!("Argument {} must be a number", index + 1),
+        )
+    })
+}
+
+fn string_arg(args: &[OscType], index: usize) -> Result<String, DaemonError> {
+    match args.get(index) {
+        Some(OscType::String(value)) => Ok(value.clone()),
+        _ => Err(DaemonError::new(
+            ErrorCode::InvalidArg,
+            format!("Argument {} must be a string", index + 1),
+        )),
+    }
+}
+
+fn priority_arg(args: &[OscType], index: usize) -> Result<Option<u8>, DaemonError> {
+    match args.get(index) {
+        None => Ok(None),
+        Some(_) => {
+            let priority = number_arg(args, index)?;
+            u8::try_from(priority as i64).map(Some).map_err(|_| {
+                DaemonError::new(ErrorCode::InvalidArg, "Priority must be between 0 and 255")
+            })
+        }
+    }
+}
+
+fn layer_index(segment: &str) -> Result<usize, DaemonError> {
+    segment.parse().map_err(|_| {
+        DaemonError::new(
+            ErrorCode::InvalidArg,
+            format!("Invalid layer index: {}", segment),
+        )
+    })
+}
+
+fn message(addr: impl Into<String>, args: Vec<OscType>) -> OscMessage {
+    OscMessage {
+        addr: format!("{}{}", ADDRESS_PREFIX, addr.into()),
+        args,
+    }
+}
+
+fn error_message(error: &DaemonError) -> OscMessage {
+    message("error", vec![OscType::String(error.message.clone())])
+}
+
+// Device changes have no OSC feedback
+fn event_messages(event: &Event) -> Vec<OscMessage> {
+    match event {
+        Event::State {
+            state,
+            file_path,
+            duration,
+            ..
+        } => {
+            let file_path = file_path
+                .as_ref()
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            vec![message(
+                "state",
+                vec![
+                    OscType::String(state.as_str().to_string()),
+                    OscType::String(file_path),
+                    OscType::Float(*duration),
+                ],
+            )]
+        }
+        Event::Position { position } => {
+            vec![message("position", vec![OscType::Float(*position)])]
+        }
+        Event::Volume {
+            volume,
+            gain,
+            mic_gain,
+        } => vec![
+            message("volume", vec![OscType::Float(*volume)]),
+            message("gain", vec![OscType::Float(*gain)]),
+            message("mic_gain", vec![OscType::Float(*mic_gain)]),
+        ],
+        Event::Layers { layers } => layers
+            .iter()
+            .flat_map(|layer| {
+                let file = layer
+                    .current_file
+                    .as_ref()
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                [
+                    message(
+                        format!("layer/{}/playing", layer.index),
+                        vec![OscType::Int(layer.is_playing as i32)],
+                    ),
+                    message(
+                        format!("layer/{}/volume", layer.index),
+                        vec![OscType::Float(layer.volume)],
+                    ),
+                    message(
+                        format!("layer/{}/file", layer.index),
+                        vec![OscType::String(file)],
+                    ),
+                ]
+            })
+            .collect(),
+        Event::Devices { .. }
+        | Event::Play { .. }
+        | Event::Stop { .. }
+        | Event::LayerPlay { .. }
+        | Event::LoopEnd { .. } => Vec::new(),
+        Event::Error { error } => vec![error_message(error)],
+    }
+}
+
+async fn send_event(socket: &UdpSocket, clients: &mut FeedbackClients, event: &Event) {
+    let targets = clients.targets(Instant::now());
+    if targets.is_empty() {
+        return;
+    }
+    for message in event_messages(event) {
+        for client in &targets {
+            send(socket, *client, message.clone()).await;
+        }
+    }
+}
+
+// Feedback is best effort: a controller that went away just misses it
+async fn send(socket: &UdpSocket, target: SocketAddr, message: OscMessage) {
+    if let Err(e) = socket.send_to(&message.encode(), target).await {
+        tracing::debug!("OSC feedback to {} failed: {}", target, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::audio_player::LayerInfo;
+
+    fn osc(addr: &str, args: Vec<OscType>) -> OscMessage {
+        OscMessage {
+            addr: addr.to_string(),
+            args,
+        }
+    }
+
+    fn run(command: Command) -> Result<OscAction, DaemonError> {
+        Ok(OscAction::Run(command))
+    }
+
+    #[test]
+    fn test_parse_playback_addresses() {
+        assert_eq!(
+            parse_message(&osc(
+                "/soundboard/play",
+                vec![OscType::String("/sounds/a.ogg".into())]
+            )),
+            run(Command::Play {
+                file_path: "/sounds/a.ogg".to_string(),
+                priority: None,
+            })
+        );
+        assert_eq!(
+            parse_message(&osc(
+                "/soundboard/layer/2/play",
+                vec![OscType::String("/sounds/a.ogg".into()), OscType::Int(7)]
+            )),
+            run(Command::PlayOnLayer {
+                layer_index: Some(2),
+                file_path: "/sounds/a.ogg".to_string(),
+                priority: Some(7),
+            })
+        );
+        assert_eq!(
+            parse_message(&osc(
+                "/soundboard/layer/1/volume",
+                vec![OscType::Double(0.25)]
+            )),
+            run(Command::SetLayerVolume {
+                layer_index: 1,
+                volume: 0.25,
+            })
+        );
+        assert_eq!(
+            parse_message(&osc("/soundboard/volume", vec![OscType::Float(0.5)])),
+            run(Command::SetVolume { volume: 0.5 })
+        );
+        assert_eq!(
+            parse_message(&osc("/soundboard/register", vec![])),
+            Ok(OscAction::Register)
+        );
+    }
+
+    #[test]
+    fn test_button_release_is_ignored() {
+        assert_eq!(
+            parse_message(&osc("/soundboard/stop", vec![])),
+            run(Command::Stop)
+        );
+        assert_eq!(
+            parse_message(&osc("/soundboard/stop", vec![OscType::Float(1.0)])),
+            run(Command::Stop)
+        );
+        assert_eq!(
+            parse_message(&osc("/soundboard/layers/stop", vec![OscType::Float(0.0)])),
+            Ok(OscAction::Ignore)
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let code = |message: OscMessage| parse_message(&message).unwrap_err().code;
+        assert_eq!(code(osc("/other/play", vec![])), ErrorCode::UnknownCommand);
+        assert_eq!(
+            code(osc("/soundboard/explode", vec![])),
+            ErrorCode::UnknownCommand
+        );
+        assert_eq!(
+            code(osc("/soundboard/play", vec![OscType::Int(1)])),
+            ErrorCode::InvalidArg
+        );
+        assert_eq!(
+            code(osc("/soundboard/layer/x/stop", vec![])),
+            ErrorCode::InvalidArg
+        );
+        assert_eq!(
+            code(osc(
+                "/soundboard/play",
+                vec![OscType::String("a.ogg".into()), OscType::Int(300)]
+            )),
+            ErrorCode::InvalidArg
+        );
+    }
+
+    #[test]
+    fn test_bundles_are_flattened_in_order() {
+        let packet = OscPacket::Bundle(vec![
+            OscPacket::Message(osc("/soundboard/stop", vec![])),
+            OscPacket::Bundle(vec![OscPacket::Message(osc("/soundboard/pause", vec![]))]),
+        ]);
+        let addrs: Vec<String> = flatten(packet).into_iter().map(|m| m.addr).collect();
+        assert_eq!(addrs, ["/soundboard/stop", "/soundboard/pause"]);
+    }
+
+    #[test]
+    fn test_feedback_clients_are_capped_and_expire() {
+        let start = Instant::now();
+        let fixed: SocketAddr = "192.168.1.20:9000".parse().unwrap();
+        let mut clients = FeedbackClients::default();
+        clients.fixed.insert(fixed);
+        let client = |port: u16| SocketAddr::from(([192, 168, 1, 30], port));
+
+        for port in 0..MAX_REGISTERED_CLIENTS as u16 {
+            assert_eq!(clients.register(client(port), start), Ok(true));
+        }
+        assert_eq!(clients.register(client(0), start), Ok(false));
+        assert_eq!(clients.register(fixed, start), Ok(false));
+        let refused = clients.register(client(100), start).unwrap_err();
+        assert_eq!(refused.code, ErrorCode::Busy);
+        assert_eq!(clients.targets(start).len(), MAX_REGISTERED_CLIENTS + 1);
+
+        // Only the client that kept talking outlives the timeout, and its
+        // place frees up for someone else
+        clients.heard(client(1), start + REGISTRATION_TIMEOUT / 2);
+        let later = start + REGISTRATION_TIMEOUT;
+        let mut targets = clients.targets(later);
+        targets.sort();
+        assert_eq!(targets, vec![fixed, client(1)]);
+        assert_eq!(clients.register(client(100), later), Ok(true));
+    }
+
+    #[test]
+    fn test_layer_feedback_messages() {
+        let layer = LayerInfo {
+            index: 3,
+            is_playing: true,
+            is_paused: false,
+            is_empty: false,
+            volume: 0.8,
+            current_file: Some("/sounds/a.ogg".into()),
+            position: 1.0,
+            duration: Some(2.0),
+            priority: 0,
+            age: None,
+        };
+        let messages = event_messages(&Event::Layers {
+            layers: vec![layer],
+        });
+        assert_eq!(
+            messages,
+            vec![
+                osc("/soundboard/layer/3/playing", vec![OscType::Int(1)]),
+                osc("/soundboard/layer/3/volume", vec![OscType::Float(0.8)]),
+                osc(
+                    "/soundboard/layer/3/file",
+                    vec![OscType::String("/sounds/a.ogg".into())]
+                ),
+            ]
+        );
+    }
+}
@@ -1,254 +1,664 @@
-use crate::VIRTUAL_MIC_NAME;
-use crate::types::pipewire::{AudioDevice, DeviceType, Port, Terminate};
+use crate::types::pipewire::{
+    AppStream, AudioDevice, DefaultDevices, DeviceType, LinkHandle, LinkId, Port, PwCommand,
+    PwReply,
+};
+use crate::{MutexExt, VIRTUAL_MIC_NAME};
 use pipewire::{
-    context::ContextRc, link::Link, main_loop::MainLoopRc, properties::properties,
-    registry::GlobalObject, spa::utils::dict::DictRef,
+    context::ContextRc,
+    core::{CoreRc, PW_ID_CORE},
+    link::Link,
+    main_loop::MainLoopRc,
+    metadata::{Metadata, MetadataListener},
+    node::Node,
+    properties::properties,
+    registry::GlobalObject,
+    spa::utils::dict::DictRef,
+    types::ObjectType,
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    error::Error,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    thread,
 };
-use std::{collections::HashMap, error::Error, sync::OnceLock, thread, time::Instant};
 use tokio::{
-    sync::{Mutex, mpsc},
-    time::{Duration, timeout},
+    sync::{oneshot, watch},
+    time::{timeout, Duration},
 };
 
 type DeviceSnapshot = (Vec<AudioDevice>, Vec<AudioDevice>);
-const DEVICE_CACHE_TTL: Duration = Duration::from_secs(2);
 
-fn device_cache() -> &'static Mutex<Option<(Instant, DeviceSnapshot)>> {
-    static CACHE: OnceLock<Mutex<Option<(Instant, DeviceSnapshot)>>> = OnceLock::new();
-    CACHE.get_or_init(|| Mutex::new(None))
+/// How long device queries wait for the manager's initial registry roundtrip
+const INITIAL_SYNC_TIMEOUT: Duration = Duration::from_secs(2);
+/// Upper bound for a single command round-trip to the manager thread
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Registry globals the manager keeps track of
+enum RegistryObject {
+    Device(AudioDevice),
+    Stream(AppStream),
+    Port(Port),
+    /// `Audio/Sink` node, tracked by name so the default sink can be described
+    Sink {
+        name: String,
+        description: String,
+    },
 }
 
-/// Drop the cached device snapshot so the next `get_all_devices()` call re-enumerates.
-/// Call after operations that change the device graph (virtual mic creation, link changes).
-pub async fn invalidate_device_cache() {
-    *device_cache().lock().await = None;
+/// Live copy of the PipeWire registry, updated from registry events
+#[derive(Default)]
+struct RegistryMirror {
+    devices: HashMap<u32, AudioDevice>,
+    streams: HashMap<u32, AppStream>,
+    /// Ports keyed by their global id so `global_remove` can find them
+    ports: HashMap<u32, Port>,
+    /// Sink node name -> description, keyed by global id
+    sinks: HashMap<u32, (String, String)>,
+    /// Default source/sink node names from the `default` metadata
+    default_source: Option<String>,
+    default_sink: Option<String>,
 }
 
-/// Assign a PipeWire port to the appropriate field on an AudioDevice
-fn assign_port_to_device(device: &mut AudioDevice, port: Port) {
-    match port.name.as_str() {
-        "input_FL" => device.input_fl = Some(port),
-        "input_FR" => device.input_fr = Some(port),
-        "output_FL" => device.output_fl = Some(port),
-        "output_FR" => device.output_fr = Some(port),
-        "capture_FL" => device.output_fl = Some(port),
-        "capture_FR" => device.output_fr = Some(port),
-        "input_MONO" => {
-            device.input_fl = Some(port.clone());
-            device.input_fr = Some(port);
-        }
-        "output_MONO" | "capture_MONO" => {
-            device.output_fl = Some(port.clone());
-            device.output_fr = Some(port);
+impl RegistryMirror {
+    fn insert(&mut self, global_id: u32, object: RegistryObject) {
+        match object {
+            RegistryObject::Device(device) => {
+                self.devices.insert(global_id, device);
+            }
+            RegistryObject::Stream(stream) => {
+                self.streams.insert(global_id, stream);
+            }
+            RegistryObject::Port(port) => {
+                self.ports.insert(global_id, port);
+            }
+            RegistryObject::Sink { name, description } => {
+                self.sinks.insert(global_id, (name, description));
+            }
         }
-        _ => {}
     }
-}
 
-fn parse_global_object(
-    global_object: &GlobalObject<&DictRef>,
-) -> (Option<AudioDevice>, Option<Port>) {
-    if let Some(props) = global_object.props {
-        if let Some(media_class) = props.get("media.class") {
-            let node_id = global_object.id;
-            let node_nick = props.get("node.nick");
-            let node_name = props.get("node.name");
-            let node_description = props.get("node.description");
-
-            return if media_class.starts_with("Audio/Source") {
-                let input_device = AudioDevice {
-                    id: node_id,
-                    nick: node_nick
-                        .unwrap_or(node_description.unwrap_or(node_name.unwrap_or_default()))
-                        .to_string(),
-                    name: node_name.unwrap_or_default().to_string(),
-                    device_type: DeviceType::Input,
-
-                    input_fl: None,
-                    input_fr: None,
-                    output_fl: None,
-                    output_fr: None,
-                };
-                (Some(input_device), None)
-            } else if media_class.starts_with("Stream/Output/Audio") {
-                let output_device = AudioDevice {
-                    id: node_id,
-                    nick: node_nick
-                        .unwrap_or(node_description.unwrap_or(node_name.unwrap_or_default()))
-                        .to_string(),
-                    name: node_name.unwrap_or_default().to_string(),
-                    device_type: DeviceType::Output,
-
-                    input_fl: None,
-                    input_fr: None,
-                    output_fl: None,
-                    output_fr: None,
-                };
-                (Some(output_device), None)
-            } else {
-                (None, None)
-            };
-        } else if props.get("port.direction").is_some() {
-            let node_id = match props.get("node.id").and_then(|s| s.parse::<u32>().ok()) {
-                Some(id) => id,
-                None => return (None, None),
-            };
-            let port_id = match props.get("port.id").and_then(|s| s.parse::<u32>().ok()) {
-                Some(id) => id,
-                None => return (None, None),
-            };
-            let port_name = match props.get("port.name") {
-                Some(name) => name,
-                None => return (None, None),
-            };
+    /// Apply a property event from the `default` metadata. Returns true if
+    /// the default source or sink changed.
+    fn set_default(&mut self, key: Option<&str>, value: Option<&str>) -> bool {
+        let previous = (self.default_source.clone(), self.default_sink.clone());
 
-            let port = Port {
-                node_id,
-                port_id,
-                name: port_name.to_string(),
-            };
-
-            return (None, Some(port));
+        match key {
+            Some("default.audio.source") => {
+                self.default_source = value.and_then(parse_default_node_name);
+            }
+            Some("default.audio.sink") => {
+                self.default_sink = value.and_then(parse_default_node_name);
+            }
+            // A missing key means every property was cleared
+            None => {
+                self.default_source = None;
+                self.default_sink = None;
+            }
+            _ => {}
         }
+
+        previous != (self.default_source.clone(), self.default_sink.clone())
     }
-    (None, None)
-}
 
-async fn pw_get_global_objects_thread(
-    main_sender: mpsc::Sender<(Option<AudioDevice>, Option<Port>)>,
-    pw_receiver: pipewire::channel::Receiver<Terminate>,
-) {
-    pipewire::init();
+    fn default_devices(&self) -> DefaultDevices {
+        let sink_description = self.default_sink.as_ref().and_then(|default_sink| {
+            self.sinks
+                .values()
+                .find(|(name, _)| name == default_sink)
+                .map(|(_, description)| description.clone())
+        });
 
-    let main_loop = match MainLoopRc::new(None) {
-        Ok(ml) => ml,
-        Err(e) => {
-            tracing::error!("Failed to initialize pipewire main loop: {}", e);
-            return;
+        DefaultDevices {
+            source: self.default_source.clone(),
+            sink: self.default_sink.clone(),
+            sink_description,
         }
-    };
+    }
 
-    let _receiver = pw_receiver.attach(main_loop.loop_(), {
-        let _main_loop = main_loop.clone();
-        move |_| _main_loop.quit()
-    });
+    /// Returns true if the id belonged to a tracked object
+    fn remove(&mut self, global_id: u32) -> bool {
+        let removed_device = self.devices.remove(&global_id).is_some();
+        let removed_stream = self.streams.remove(&global_id).is_some();
+        let removed_port = self.ports.remove(&global_id).is_some();
+        let removed_sink = self.sinks.remove(&global_id).is_some();
+        removed_device || removed_stream || removed_port || removed_sink
+    }
 
-    let context = match ContextRc::new(&main_loop, None) {
-        Ok(ctx) => ctx,
-        Err(e) => {
-            tracing::error!("Failed to create pipewire context: {}", e);
-            return;
+    fn app_streams(&self) -> Vec<AppStream> {
+        let mut streams: HashMap<u32, AppStream> = self.streams.clone();
+
+        for port in self.ports.values() {
+            if let Some(stream) = streams.get_mut(&port.node_id) {
+                match port.name.as_str() {
+                    "input_FL" => stream.input_fl = Some(port.clone()),
+                    "input_FR" => stream.input_fr = Some(port.clone()),
+                    "input_MONO" => {
+                        stream.input_fl = Some(port.clone());
+                        stream.input_fr = Some(port.clone());
+                    }
+                    _ => {}
+                }
+            }
         }
-    };
-    let core = match context.connect(None) {
-        Ok(c) => c,
-        Err(e) => {
-            tracing::error!("Failed to connect to pipewire context: {}", e);
-            return;
+
+        let mut streams: Vec<AppStream> = streams.into_values().collect();
+        streams.sort_by_key(|s| s.id);
+        streams
+    }
+
+    fn snapshot(&self) -> DeviceSnapshot {
+        let mut input_devices: HashMap<u32, AudioDevice> = HashMap::new();
+        let mut output_devices: HashMap<u32, AudioDevice> = HashMap::new();
+
+        for device in self.devices.values() {
+            match device.device_type {
+                DeviceType::Input => input_devices.insert(device.id, device.clone()),
+                DeviceType::Output => output_devices.insert(device.id, device.clone()),
+            };
         }
-    };
-    let registry = match core.get_registry() {
-        Ok(r) => r,
-        Err(e) => {
-            tracing::error!("Failed to get registry from pipewire context: {}", e);
-            return;
+
+        for port in self.ports.values() {
+            let device = input_devices
+                .get_mut(&port.node_id)
+                .or_else(|| output_devices.get_mut(&port.node_id));
+
+            if let Some(device) = device {
+                assign_port_to_device(device, port.clone());
+            }
         }
-    };
 
-    let _listener = registry
-        .add_listener_local()
-        .global(move |global| {
-            let (device, port) = parse_global_object(global);
-            let sender_clone = main_sender.clone();
-            tokio::task::spawn(async move {
-                sender_clone.send((device, port)).await.ok();
-            });
-        })
-        .register();
+        let mut input_devices: Vec<AudioDevice> = input_devices.into_values().collect();
+        let mut output_devices: Vec<AudioDevice> = output_devices.into_values().collect();
 
-    main_loop.run();
+        input_devices.sort_by_key(|a| a.id);
+        output_devices.sort_by_key(|a| a.id);
+
+        (input_devices, output_devices)
+    }
 }
 
-pub async fn get_all_devices() -> Result<DeviceSnapshot, Box<dyn Error>> {
-    {
-        let guard = device_cache().lock().await;
-        if let Some((fetched_at, snapshot)) = guard.as_ref() {
-            if fetched_at.elapsed() < DEVICE_CACHE_TTL {
-                return Ok(snapshot.clone());
+/// Handle to the single long-lived PipeWire connection.
+///
+/// The manager thread owns the MainLoop, Context, Core and every object the
+/// daemon creates (virtual mic, links). Other threads talk to it through
+/// `sender` and read the registry through `mirror`.
+struct PwManager {
+    sender: pipewire::channel::Sender<PwCommand>,
+    mirror: Arc<Mutex<RegistryMirror>>,
+    synced: watch::Receiver<bool>,
+    /// Bumped whenever a tracked device, stream or port appears or disappears,
+    /// or the default source/sink changes
+    graph_changes: watch::Receiver<u64>,
+    next_link_id: AtomicU64,
+}
+
+static MANAGER: OnceLock<PwManager> = OnceLock::new();
+
+fn manager() -> &'static PwManager {
+    MANAGER.get_or_init(start_manager)
+}
+
+impl PwManager {
+    /// Whether the manager thread has exited, which drops its end of `synced`
+    fn is_stopped(&self) -> bool {
+        self.synced.has_changed().is_err()
+    }
+}
+
+fn start_manager() -> PwManager {
+    let (pw_sender, pw_receiver) = pipewire::channel::channel::<PwCommand>();
+    let mirror = Arc::new(Mutex::new(RegistryMirror::default()));
+    let (synced_sender, synced_receiver) = watch::channel(false);
+    let (changes_sender, changes_receiver) = watch::channel(0u64);
+
+    let thread_mirror = mirror.clone();
+    let spawn_result = thread::Builder::new()
+        .name("pipewire-manager".to_string())
+        .spawn(move || {
+            if let Err(e) = run_manager(pw_receiver, thread_mirror, synced_sender, changes_sender) {
+                tracing::error!("PipeWire manager stopped: {}", e);
             }
-        }
+        });
+
+    if let Err(e) = spawn_result {
+        tracing::error!("Failed to spawn PipeWire manager thread: {}", e);
     }
 
-    let snapshot = enumerate_devices().await?;
-    *device_cache().lock().await = Some((Instant::now(), snapshot.clone()));
-    Ok(snapshot)
+    PwManager {
+        sender: pw_sender,
+        mirror,
+        synced: synced_receiver,
+        graph_changes: changes_receiver,
+        next_link_id: AtomicU64::new(1),
+    }
 }
 
-async fn enumerate_devices() -> Result<DeviceSnapshot, Box<dyn Error>> {
-    let (main_sender, mut main_receiver) = mpsc::channel(10);
-    let (pw_sender, pw_receiver) = pipewire::channel::channel();
-
-    let _pw_thread =
-        tokio::spawn(async move { pw_get_global_objects_thread(main_sender, pw_receiver).await });
-
-    let mut input_devices: HashMap<u32, AudioDevice> = HashMap::new();
-    let mut output_devices: HashMap<u32, AudioDevice> = HashMap::new();
-    let mut ports: Vec<Port> = vec![];
-
-    loop {
-        // If no message arrives within 100ms, assume the pipewire thread finished enumerating
-        match timeout(Duration::from_millis(100), main_receiver.recv()).await {
-            Ok(Some((device, port))) => {
-                if let Some(device) = device {
-                    match device.device_type {
-                        DeviceType::Input => {
-                            input_devices.insert(device.id, device);
+fn run_manager(
+    pw_receiver: pipewire::channel::Receiver<PwCommand>,
+    mirror: Arc<Mutex<RegistryMirror>>,
+    synced: watch::Sender<bool>,
+    graph_changes: watch::Sender<u64>,
+) -> Result<(), Box<dyn Error>> {
+    pipewire::init();
+
+    let main_loop = MainLoopRc::new(None)?;
+    let context = ContextRc::new(&main_loop, None)?;
+    let core = context.connect_rc(None)?;
+    let registry = core.get_registry_rc()?;
+    let graph_changes = Rc::new(graph_changes);
+
+    // The `default` metadata object, bound so its property events keep the
+    // default source/sink in the mirror up to date
+    let default_metadata: Rc<RefCell<Option<(u32, Metadata, MetadataListener)>>> =
+        Rc::new(RefCell::new(None));
+
+    let _registry_listener = registry
+        .add_listener_local()
+        .global({
+            let mirror = mirror.clone();
+            let graph_changes = graph_changes.clone();
+            let registry = registry.clone();
+            let default_metadata = default_metadata.clone();
+            move |global| {
+                if is_default_metadata(global) {
+                    match registry.bind::<Metadata, _>(global) {
+                        Ok(metadata) => {
+                            let listener = metadata
+                                .add_listener_local()
+                                .property({
+                                    let mirror = mirror.clone();
+                                    let graph_changes = graph_changes.clone();
+                                    move |subject, key, _type, value| {
+                                        if subject == PW_ID_CORE
+                                            && mirror.lock_or_recover().set_default(key, value)
+                                        {
+                                            graph_changes.send_modify(|generation| {
+                                                *generation = generation.wrapping_add(1)
+                                            });
+                                        }
+                                        0
+                                    }
+                                })
+                                .register();
+                            *default_metadata.borrow_mut() = Some((global.id, metadata, listener));
                         }
-                        DeviceType::Output => {
-                            output_devices.insert(device.id, device);
+                        Err(e) => {
+                            tracing::error!("Failed to bind default metadata: {}", e);
                         }
                     }
-                } else if let Some(port) = port {
-                    ports.push(port);
+                    return;
+                }
+
+                if let Some(object) = parse_global_object(global) {
+                    mirror.lock_or_recover().insert(global.id, object);
+                    graph_changes
+                        .send_modify(|generation| *generation = generation.wrapping_add(1));
+                }
+            }
+        })
+        .global_remove({
+            let mirror = mirror.clone();
+            let graph_changes = graph_changes.clone();
+            let default_metadata = default_metadata.clone();
+            move |id| {
+                let is_default_metadata = matches!(
+                    &*default_metadata.borrow(),
+                    Some((metadata_id, _, _)) if *metadata_id == id
+                );
+                if is_default_metadata {
+                    *default_metadata.borrow_mut() = None;
+                    if mirror.lock_or_recover().set_default(None, None) {
+                        graph_changes
+                            .send_modify(|generation| *generation = generation.wrapping_add(1));
+                    }
+                    return;
+                }
+
+                if mirror.lock_or_recover().remove(id) {
+                    graph_changes
+                        .send_modify(|generation| *generation = generation.wrapping_add(1));
                 }
             }
-            Ok(None) | Err(_) => {
-                let _ = pw_sender.send(Terminate {});
+        })
+        .register();
 
-                for port in ports {
-                    let node_id = port.node_id;
+    // The server replays every existing global before answering this sync, so
+    // its `done` event marks the point where the mirror is complete.
+    let initial_sync = core.sync(0)?.seq();
+    let pending_syncs: Rc<RefCell<HashMap<i32, PwReply<()>>>> =
+        Rc::new(RefCell::new(HashMap::new()));
+
+    let _core_listener = core
+        .add_listener_local()
+        .done({
+            let pending_syncs = pending_syncs.clone();
+            move |id, seq| {
+                if id != PW_ID_CORE {
+                    return;
+                }
+                if seq.seq() == initial_sync {
+                    synced.send_replace(true);
+                }
+                if let Some(reply) = pending_syncs.borrow_mut().remove(&seq.seq()) {
+                    reply.send(Ok(())).ok();
+                }
+            }
+        })
+        .error(|id, seq, res, message| {
+            tracing::error!(
+                "PipeWire error (id: {}, seq: {}, res: {}): {}",
+                id,
+                seq,
+                res,
+                message
+            );
+        })
+        .register();
 
-                    let device = input_devices
-                        .get_mut(&node_id)
-                        .or_else(|| output_devices.get_mut(&node_id));
+    let virtual_mic: RefCell<Option<Node>> = RefCell::new(None);
+    let links: RefCell<HashMap<LinkId, (Link, Link)>> = RefCell::new(HashMap::new());
 
-                    if let Some(device) = device {
-                        assign_port_to_device(device, port);
+    let _receiver = pw_receiver.attach(main_loop.loop_(), {
+        let core = core.clone();
+        let main_loop = main_loop.clone();
+        let default_metadata = default_metadata.clone();
+        move |command| match command {
+            PwCommand::CreateVirtualMic { reply } => {
+                let result = if virtual_mic.borrow().is_some() {
+                    Ok(())
+                } else {
+                    create_virtual_mic_node(&core).map(|node| {
+                        *virtual_mic.borrow_mut() = Some(node);
+                    })
+                };
+                reply.send(result).ok();
+            }
+            PwCommand::CreateLink {
+                id,
+                output_fl,
+                output_fr,
+                input_fl,
+                input_fr,
+                reply,
+            } => {
+                let result = create_link_object(&core, &output_fl, &input_fl)
+                    .map_err(|e| format!("Failed to create link FL: {}", e))
+                    .and_then(|link_fl| {
+                        create_link_object(&core, &output_fr, &input_fr)
+                            .map_err(|e| format!("Failed to create link FR: {}", e))
+                            .map(|link_fr| (link_fl, link_fr))
+                    });
+                match result {
+                    Ok(pair) => {
+                        links.borrow_mut().insert(id, pair);
+                        reply.send(Ok(())).ok();
+                    }
+                    Err(e) => {
+                        reply.send(Err(e)).ok();
+                    }
+                }
+            }
+            PwCommand::DestroyLink(id) => {
+                // Dropping the proxies destroys the links on the server
+                links.borrow_mut().remove(&id);
+            }
+            PwCommand::SetStreamTarget {
+                node_id,
+                target,
+                reply,
+            } => {
+                let result = match &*default_metadata.borrow() {
+                    Some((_, metadata, _)) => {
+                        metadata.set_property(node_id, "target.object", None, target.as_deref());
+                        Ok(())
                     }
+                    None => Err("PipeWire has no default metadata".to_string()),
+                };
+                reply.send(result).ok();
+            }
+            PwCommand::Sync { reply } => match core.sync(0) {
+                Ok(seq) => {
+                    pending_syncs.borrow_mut().insert(seq.seq(), reply);
                 }
+                Err(e) => {
+                    reply
+                        .send(Err(format!("Failed to sync with PipeWire: {}", e)))
+                        .ok();
+                }
+            },
+            PwCommand::Terminate => main_loop.quit(),
+        }
+    });
 
-                let mut input_devices: Vec<AudioDevice> = input_devices.values().cloned().collect();
-                let mut output_devices: Vec<AudioDevice> =
-                    output_devices.values().cloned().collect();
+    main_loop.run();
 
-                input_devices.sort_by_key(|a| a.id);
-                output_devices.sort_by_key(|a| a.id);
+    Ok(())
+}
 
-                return Ok((input_devices, output_devices));
-            }
+fn create_virtual_mic_node(core: &CoreRc) -> Result<Node, String> {
+    let props = properties!(
+        "factory.name" => "support.null-audio-sink",
+        "node.name" => VIRTUAL_MIC_NAME,
+        "node.description" => "Soundboard Virtual Mic",
+        "media.class" => "Audio/Source/Virtual",
+        "audio.position" => "[ FL FR ]",
+        "audio.channels" => "2",
+        "object.linger" => "false",
+    );
+
+    core.create_object::<Node>("adapter", &props)
+        .map_err(|e| format!("Failed to create virtual mic: {}", e))
+}
+
+fn create_link_object(core: &CoreRc, output: &Port, input: &Port) -> Result<Link, pipewire::Error> {
+    let props = properties! {
+        "link.output.node" => format!("{}", output.node_id).as_str(),
+        "link.output.port" => format!("{}", output.port_id).as_str(),
+        "link.input.node"  => format!("{}", input.node_id).as_str(),
+        "link.input.port"  => format!("{}", input.port_id).as_str(),
+    };
+    core.create_object::<Link>("link-factory", &props)
+}
+
+/// Send a command that carries a reply channel and wait for the answer
+async fn send_command<T>(
+    make_command: impl FnOnce(oneshot::Sender<Result<T, String>>) -> PwCommand,
+) -> Result<T, Box<dyn Error>> {
+    let manager = manager();
+    if manager.is_stopped() {
+        return Err("PipeWire manager is not running".into());
+    }
+    let (reply_sender, reply_receiver) = oneshot::channel();
+    manager
+        .sender
+        .send(make_command(reply_sender))
+        .map_err(|_| "Failed to send command to PipeWire manager")?;
+
+    // A command queued as the thread died is never answered or dropped, so
+    // stop waiting as soon as the thread is gone
+    let mut synced = manager.synced.clone();
+    tokio::select! {
+        reply = timeout(COMMAND_TIMEOUT, reply_receiver) => match reply {
+            Ok(Ok(result)) => result.map_err(|e| e.into()),
+            Ok(Err(_)) => Err("PipeWire manager is not running".into()),
+            Err(_) => Err("Timed out waiting for PipeWire manager".into()),
+        },
+        _ = synced.wait_for(|_| false) => Err("PipeWire manager is not running".into()),
+    }
+}
+
+/// Wait until the server has processed everything sent on the manager's
+/// connection, so objects it created are visible in the registry mirror.
+pub async fn roundtrip() -> Result<(), Box<dyn Error>> {
+    send_command(|reply| PwCommand::Sync { reply }).await
+}
+
+/// Stop the manager thread, if it was started. Every link and the virtual mic
+/// go away with it.
+pub fn shutdown_manager() {
+    let Some(manager) = MANAGER.get() else {
+        return;
+    };
+    if manager.is_stopped() {
+        return;
+    }
+    if manager.sender.send(PwCommand::Terminate).is_err() {
+        tracing::error!("Failed to send terminate signal to PipeWire manager");
+    }
+}
+
+/// Assign a PipeWire port to the appropriate field on an AudioDevice
+fn assign_port_to_device(device: &mut AudioDevice, port: Port) {
+    match port.name.as_str() {
+        "input_FL" => device.input_fl = Some(port),
+        "input_FR" => device.input_fr = Some(port),
+        "output_FL" => device.output_fl = Some(port),
+        "output_FR" => device.output_fr = Some(port),
+        "capture_FL" => device.output_fl = Some(port),
+        "capture_FR" => device.output_fr = Some(port),
+        "input_MONO" => {
+            device.input_fl = Some(port.clone());
+            device.input_fr = Some(port);
+        }
+        "output_MONO" | "capture_MONO" => {
+            device.output_fl = Some(port.clone());
+            device.output_fr = Some(port);
         }
+        _ => {}
     }
 }
 
-pub async fn get_device(device_name: &str) -> Result<AudioDevice, Box<dyn Error>> {
-    if let Ok(device) = get_device_impl(device_name).await {
-        return Ok(device);
+fn is_default_metadata(global_object: &GlobalObject<&DictRef>) -> bool {
+    global_object.type_ == ObjectType::Metadata
+        && global_object
+            .props
+            .and_then(|props| props.get("metadata.name"))
+            == Some("default")
+}
+
+/// Default node values look like `{ "name": "alsa_input.pci-0000_00_1f.3.analog-stereo" }`
+fn parse_default_node_name(value: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(value).ok()?;
+    value.get("name")?.as_str().map(str::to_string)
+}
+
+fn parse_global_object(global_object: &GlobalObject<&DictRef>) -> Option<RegistryObject> {
+    let props = global_object.props?;
+
+    if let Some(media_class) = props.get("media.class") {
+        let node_id = global_object.id;
+        let node_nick = props.get("node.nick");
+        let node_name = props.get("node.name");
+        let node_description = props.get("node.description");
+
+        if media_class == "Audio/Sink" {
+            return Some(RegistryObject::Sink {
+                name: node_name.unwrap_or_default().to_string(),
+                description: node_description
+                    .unwrap_or(node_nick.unwrap_or(node_name.unwrap_or_default()))
+                    .to_string(),
+            });
+        }
+
+        let device_type = if media_class.starts_with("Audio/Source") {
+            DeviceType::Input
+        } else if media_class.starts_with("Stream/Output/Audio") {
+            DeviceType::Output
+        } else if media_class.starts_with("Stream/Input/Audio") {
+            return Some(RegistryObject::Stream(AppStream {
+                id: node_id,
+                node_name: node_name.unwrap_or_default().to_string(),
+                application_name: props.get("application.name").map(str::to_string),
+                process_binary: props.get("application.process.binary").map(str::to_string),
+
+                input_fl: None,
+                input_fr: None,
+            }));
+        } else {
+            return None;
+        };
+
+        return Some(RegistryObject::Device(AudioDevice {
+            id: node_id,
+            nick: node_nick
+                .unwrap_or(node_description.unwrap_or(node_name.unwrap_or_default()))
+                .to_string(),
+            name: node_name.unwrap_or_default().to_string(),
+            device_type,
+
+            input_fl: None,
+            input_fr: None,
+            output_fl: None,
+            output_fr: None,
+        }));
+    }
+
+    props.get("port.direction")?;
+
+    let node_id = props.get("node.id").and_then(|s| s.parse::<u32>().ok())?;
+    let port_id = props.get("port.id").and_then(|s| s.parse::<u32>().ok())?;
+    let port_name = props.get("port.name")?;
+
+    Some(RegistryObject::Port(Port {
+        node_id,
+        port_id,
+        name: port_name.to_string(),
+    }))
+}
+
+/// Return the current input and output devices from the registry mirror.
+///
+/// Waits for the manager's initial sync on first use; afterwards this is a
+/// lock-and-clone and never talks to the server.
+pub async fn get_all_devices() -> Result<DeviceSnapshot, Box<dyn Error>> {
+    let manager = manager();
+    wait_for_initial_sync(manager).await?;
+    Ok(manager.mirror.lock_or_recover().snapshot())
+}
+
+/// Wait for the initial registry sync so the mirror isn't empty on first use
+async fn wait_for_initial_sync(manager: &PwManager) -> Result<(), Box<dyn Error>> {
+    if *manager.synced.borrow() {
+        return Ok(());
+    }
+
+    let mut synced = manager.synced.clone();
+    match timeout(INITIAL_SYNC_TIMEOUT, synced.wait_for(|s| *s)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(_)) => Err("PipeWire manager is not running".into()),
+        Err(_) => {
+            tracing::warn!("PipeWire registry not synced yet, device list may be incomplete");
+            Ok(())
+        }
     }
-    invalidate_device_cache().await;
-    get_device_impl(device_name).await
 }
 
-async fn get_device_impl(device_name: &str) -> Result<AudioDevice, Box<dyn Error>> {
+/// Return the capture streams of running applications, with their input ports
+pub async fn get_app_streams() -> Result<Vec<AppStream>, Box<dyn Error>> {
+    let manager = manager();
+    wait_for_initial_sync(manager).await?;
+    Ok(manager.mirror.lock_or_recover().app_streams())
+}
+
+/// Return the system default source and sink
+pub async fn get_default_devices() -> Result<DefaultDevices, Box<dyn Error>> {
+    let manager = manager();
+    wait_for_initial_sync(manager).await?;
+    Ok(manager.mirror.lock_or_recover().default_devices())
+}
+
+/// Subscribe to registry changes. The value is a generation counter; only
+/// `changed()` notifications are meaningful.
+pub fn subscribe_graph_changes() -> watch::Receiver<u64> {
+    manager().graph_changes.clone()
+}
+
+pub async fn get_device(device_name: &str) -> Result<AudioDevice, Box<dyn Error>> {
     let (mut input_devices, output_devices) = get_all_devices().await?;
     input_devices.extend(output_devices);
 
@@ -261,131 +671,206 @@ async fn get_device_impl(device_name: &str) -> Result<AudioDevice, Box<dyn Error
     Err("Device not found".into())
 }
 
-pub fn create_virtual_mic() -> Result<pipewire::channel::Sender<Terminate>, Box<dyn Error>> {
-    let (pw_sender, pw_receiver) = pipewire::channel::channel::<Terminate>();
+/// Keep the stream node named `stream_name` on the node named `target`, or
+/// let the session manager move it with the system default for `None`
+pub async fn set_stream_target(
+    stream_name: &str,
+    target: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let manager = manager();
+    wait_for_initial_sync(manager).await?;
+    let node_id = manager
+        .mirror
+        .lock_or_recover()
+        .devices
+        .values()
+        .find(|device| device.name == stream_name)
+        .map(|device| device.id)
+        .ok_or_else(|| format!("Stream '{}' not found", stream_name))?;
+
+    send_command(|reply| PwCommand::SetStreamTarget {
+        node_id,
+        target: target.map(str::to_string),
+        reply,
+    })
+    .await?;
+    roundtrip().await
+}
 
-    let _pw_thread = thread::spawn(move || {
-        pipewire::init();
+/// Create the virtual mic node. It lives as long as the manager thread.
+pub async fn create_virtual_mic() -> Result<(), Box<dyn Error>> {
+    send_command(|reply| PwCommand::CreateVirtualMic { reply }).await?;
+    roundtrip().await
+}
 
-        let main_loop = match MainLoopRc::new(None) {
-            Ok(ml) => ml,
-            Err(e) => {
-                tracing::error!("Failed to initialize pipewire main loop: {}", e);
-                return;
-            }
-        };
-        let context = match ContextRc::new(&main_loop, None) {
-            Ok(ctx) => ctx,
-            Err(e) => {
-                tracing::error!("Failed to create pipewire context: {}", e);
-                return;
-            }
-        };
-        let core = match context.connect(None) {
-            Ok(c) => c,
-            Err(e) => {
-                tracing::error!("Failed to connect to pipewire context: {}", e);
-                return;
-            }
-        };
+/// Link two stereo port pairs. The links are removed when the returned
+/// handle is dropped.
+pub async fn create_link(
+    output_fl: Port,
+    output_fr: Port,
+    input_fl: Port,
+    input_fr: Port,
+) -> Result<LinkHandle, Box<dyn Error>> {
+    let manager = manager();
+    let id = manager.next_link_id.fetch_add(1, Ordering::Relaxed);
+
+    send_command(|reply| PwCommand::CreateLink {
+        id,
+        output_fl,
+        output_fr,
+        input_fl,
+        input_fr,
+        reply,
+    })
+    .await?;
+
+    Ok(LinkHandle {
+        id,
+        sender: manager.sender.clone(),
+    })
+}
 
-        let props = properties!(
-            "factory.name" => "support.null-audio-sink",
-            "node.name" => VIRTUAL_MIC_NAME,
-            "node.description" => "Soundboard Virtual Mic",
-            "media.class" => "Audio/Source/Virtual",
-            "audio.position" => "[ FL FR ]",
-            "audio.channels" => "2",
-            "object.linger" => "false",
-        );
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(id: u32, name: &str, device_type: DeviceType) -> AudioDevice {
+        AudioDevice {
+            id,
+            nick: name.to_string(),
+            name: name.to_string(),
+            device_type,
+            input_fl: None,
+            input_fr: None,
+            output_fl: None,
+            output_fr: None,
+        }
+    }
 
-        let _node = match core.create_object::<pipewire::node::Node>("adapter", &props) {
-            Ok(node) => node,
-            Err(e) => {
-                tracing::error!("Failed to create virtual mic: {}", e);
-                return;
-            }
-        };
+    fn port(node_id: u32, port_id: u32, name: &str) -> Port {
+        Port {
+            node_id,
+            port_id,
+            name: name.to_string(),
+        }
+    }
 
-        let _receiver = pw_receiver.attach(main_loop.loop_(), {
-            let _main_loop = main_loop.clone();
-            move |_| _main_loop.quit()
-        });
+    #[test]
+    fn test_snapshot_assigns_ports_to_devices() {
+        let mut mirror = RegistryMirror::default();
+        mirror
+            .devices
+            .insert(10, device(10, "mic", DeviceType::Input));
+        mirror
+            .devices
+            .insert(20, device(20, "player", DeviceType::Output));
+        mirror.ports.insert(100, port(10, 0, "capture_FL"));
+        mirror.ports.insert(101, port(10, 1, "capture_FR"));
+        mirror.ports.insert(102, port(20, 0, "output_MONO"));
+
+        let (inputs, outputs) = mirror.snapshot();
+
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].output_fl.as_ref().map(|p| p.port_id), Some(0));
+        assert_eq!(inputs[0].output_fr.as_ref().map(|p| p.port_id), Some(1));
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].output_fl, outputs[0].output_fr);
+    }
 
-        main_loop.run();
-    });
+    #[test]
+    fn test_snapshot_ignores_orphan_ports_and_sorts() {
+        let mut mirror = RegistryMirror::default();
+        mirror
+            .devices
+            .insert(30, device(30, "b", DeviceType::Input));
+        mirror.devices.insert(5, device(5, "a", DeviceType::Input));
+        mirror.ports.insert(200, port(99, 0, "capture_FL"));
 
-    Ok(pw_sender)
-}
+        let (inputs, outputs) = mirror.snapshot();
 
-pub fn create_link(
-    output_fl: Port,
-    output_fr: Port,
-    input_fl: Port,
-    input_fr: Port,
-) -> Result<pipewire::channel::Sender<Terminate>, Box<dyn Error>> {
-    let (pw_sender, pw_receiver) = pipewire::channel::channel::<Terminate>();
+        assert_eq!(inputs.iter().map(|d| d.id).collect::<Vec<_>>(), vec![5, 30]);
+        assert!(inputs.iter().all(|d| d.output_fl.is_none()));
+        assert!(outputs.is_empty());
+    }
 
-    let _pw_thread = thread::spawn(move || {
-        pipewire::init();
+    #[test]
+    fn test_app_streams_collect_input_ports() {
+        let mut mirror = RegistryMirror::default();
+        mirror.insert(
+            40,
+            RegistryObject::Stream(AppStream {
+                id: 40,
+                node_name: "WEBRTC VoiceEngine".to_string(),
+                application_name: Some("Discord".to_string()),
+                process_binary: Some("Discord".to_string()),
+                input_fl: None,
+                input_fr: None,
+            }),
+        );
+        mirror.insert(300, RegistryObject::Port(port(40, 0, "input_MONO")));
 
-        let main_loop = match MainLoopRc::new(None) {
-            Ok(ml) => ml,
-            Err(e) => {
-                tracing::error!("Failed to initialize pipewire main loop: {}", e);
-                return;
-            }
-        };
-        let context = match ContextRc::new(&main_loop, None) {
-            Ok(ctx) => ctx,
-            Err(e) => {
-                tracing::error!("Failed to create pipewire context: {}", e);
-                return;
-            }
-        };
-        let core = match context.connect(None) {
-            Ok(c) => c,
-            Err(e) => {
-                tracing::error!("Failed to connect to pipewire context: {}", e);
-                return;
-            }
-        };
+        let streams = mirror.app_streams();
 
-        let props_fl = properties! {
-            "link.output.node" => format!("{}", output_fl.node_id).as_str(),
-            "link.output.port" => format!("{}", output_fl.port_id).as_str(),
-            "link.input.node"  => format!("{}", input_fl.node_id).as_str(),
-            "link.input.port"  => format!("{}", input_fl.port_id).as_str(),
-        };
-        let props_fr = properties! {
-            "link.output.node" => format!("{}", output_fr.node_id).as_str(),
-            "link.output.port" => format!("{}", output_fr.port_id).as_str(),
-            "link.input.node"  => format!("{}", input_fr.node_id).as_str(),
-            "link.input.port"  => format!("{}", input_fr.port_id).as_str(),
-        };
+        assert_eq!(streams.len(), 1);
+        assert!(streams[0].input_fl.is_some());
+        assert_eq!(streams[0].input_fl, streams[0].input_fr);
+    }
 
-        let _link_fl = match core.create_object::<Link>("link-factory", &props_fl) {
-            Ok(link) => link,
-            Err(e) => {
-                tracing::error!("Failed to create link FL: {}", e);
-                return;
-            }
-        };
-        let _link_fr = match core.create_object::<Link>("link-factory", &props_fr) {
-            Ok(link) => link,
-            Err(e) => {
-                tracing::error!("Failed to create link FR: {}", e);
-                return;
-            }
-        };
+    #[test]
+    fn test_mirror_remove_reports_tracked_ids() {
+        let mut mirror = RegistryMirror::default();
+        mirror.insert(
+            10,
+            RegistryObject::Device(device(10, "mic", DeviceType::Input)),
+        );
 
-        let _receiver = pw_receiver.attach(main_loop.loop_(), {
-            let _main_loop = main_loop.clone();
-            move |_| _main_loop.quit()
-        });
+        assert!(mirror.remove(10));
+        assert!(!mirror.remove(10));
+        assert!(mirror.snapshot().0.is_empty());
+    }
 
-        main_loop.run();
-    });
+    #[test]
+    fn test_parse_default_node_name() {
+        assert_eq!(
+            parse_default_node_name(r#"{ "name": "alsa_input.usb-mic" }"#),
+            Some("alsa_input.usb-mic".to_string())
+        );
+        assert_eq!(parse_default_node_name(r#"{ "other": 1 }"#), None);
+        assert_eq!(parse_default_node_name("not json"), None);
+    }
 
-    Ok(pw_sender)
+    #[test]
+    fn test_mirror_tracks_default_devices() {
+        let mut mirror = RegistryMirror::default();
+        mirror.insert(
+            40,
+            RegistryObject::Sink {
+                name: "alsa_output.speakers".to_string(),
+                description: "Speakers".to_string(),
+            },
+        );
+
+        assert!(mirror.set_default(
+            Some("default.audio.sink"),
+            Some(r#"{ "name": "alsa_output.speakers" }"#)
+        ));
+        assert!(mirror.set_default(
+            Some("default.audio.source"),
+            Some(r#"{ "name": "alsa_input.usb-mic" }"#)
+        ));
+        // Same value again and unrelated keys are not changes
+        assert!(!mirror.set_default(
+            Some("default.audio.sink"),
+            Some(r#"{ "name": "alsa_output.speakers" }"#)
+        ));
+        assert!(!mirror.set_default(Some("default.video.source"), Some("{}")));
+
+        let defaults = mirror.default_devices();
+        assert_eq!(defaults.source.as_deref(), Some("alsa_input.usb-mic"));
+        assert_eq!(defaults.sink.as_deref(), Some("alsa_output.speakers"));
+        assert_eq!(defaults.sink_description.as_deref(), Some("Speakers"));
+
+        assert!(mirror.set_default(None, None));
+        assert_eq!(mirror.default_devices(), DefaultDevices::default());
+    }
 }
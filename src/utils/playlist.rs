@@ -0,0 +1,185 @@
+use crate::{
+    types::{
+        audio_player::AudioPlayer,
+        config::GuiConfig,
+        payload::{DaemonError, ErrorCode},
+        playlist::{PlaylistCursor, PlaylistMode},
+    },
+    utils::{
+        daemon::lock_audio_player,
+        events::{publish_error, publish_play},
+    },
+    MutexExt,
+};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+};
+
+/// A playlist the player loop moves through as sounds finish
+struct ActivePlaylist {
+    name: String,
+    mode: PlaylistMode,
+    sounds: Vec<PathBuf>,
+    /// The sound that was started last; if the player moves on to anything
+    /// else, the playlist was interrupted
+    current: PathBuf,
+}
+
+/// Playlist cursors live in the daemon so every client (GUI, CLI, hotkeys,
+/// tray) advances the same one
+struct PlaylistState {
+    cursors: HashMap<String, PlaylistCursor>,
+    active: Option<ActivePlaylist>,
+    rng: fastrand::Rng,
+}
+
+fn playlist_state() -> &'static Mutex<PlaylistState> {
+    static PLAYLIST_STATE: OnceLock<Mutex<PlaylistState>> = OnceLock::new();
+    PLAYLIST_STATE.get_or_init(|| {
+        Mutex::new(PlaylistState {
+            cursors: HashMap::new(),
+            active: None,
+            rng: fastrand::Rng::new(),
+        })
+    })
+}
+
+/// Pick the next sound of a playlist
+fn advance_cursor(name: &str, mode: PlaylistMode, len: usize, restart: bool) -> Option<usize> {
+    let mut state = playlist_state().lock_or_recover();
+    let state = &mut *state;
+    let cursor = state.cursors.entry(name.to_string()).or_default();
+    if restart {
+        cursor.restart(mode, len, &mut state.rng);
+    }
+    cursor.advance(mode, len, &mut state.rng)
+}
+
+/// Playlists are edited in the GUI, so read them fresh on every trigger
+fn load_playlist(name: &str) -> Result<(Vec<PathBuf>, PlaylistMode), DaemonError> {
+    let config = GuiConfig::load_from_file().map_err(|e| {
+        DaemonError::new(
+            ErrorCode::ConfigError,
+            format!("Failed to load playlists: {}", e),
+        )
+    })?;
+    let playlist = config.categories.get(name).ok_or_else(|| {
+        DaemonError::new(
+            ErrorCode::NotFound,
+            format!("Playlist '{}' not found", name),
+        )
+    })?;
+    Ok((playlist.sounds.clone(), playlist.play_mode))
+}
+
+/// Play a playlist on the main player. Continuous modes start over from the
+/// top; round-robin and random play their next sound. `mode` overrides the
+/// playlist's own mode. Returns the sound that was started and the mode used.
+pub async fn play_playlist(
+    name: &str,
+    mode: Option<PlaylistMode>,
+) -> Result<(PathBuf, PlaylistMode), DaemonError> {
+    let (sounds, playlist_mode) = load_playlist(name)?;
+    let mode = mode.unwrap_or(playlist_mode);
+    if sounds.is_empty() {
+        return Err(DaemonError::new(
+            ErrorCode::InvalidArg,
+            format!("Playlist '{}' is empty", name),
+        ));
+    }
+
+    let mut audio_player = lock_audio_player().await;
+
+    // Skip sounds that fail to play (e.g. deleted files)
+    for attempt in 0..sounds.len() {
+        let restart = attempt == 0 && mode.is_continuous();
+        let Some(index) = advance_cursor(name, mode, sounds.len(), restart) else {
+            break;
+        };
+        let sound = &sounds[index];
+
+        match audio_player.play(sound).await {
+            Ok(()) => {
+                publish_play(&audio_player, None, sound);
+                let active = mode.is_continuous().then(|| ActivePlaylist {
+                    name: name.to_string(),
+                    mode,
+                    sounds: sounds.clone(),
+                    current: sound.clone(),
+                });
+                playlist_state().lock_or_recover().active = active;
+                return Ok((sound.clone(), mode));
+            }
+            Err(e) => tracing::error!("Skipping {} in playlist '{}': {}", sound.display(), name, e),
+        }
+    }
+
+    Err(DaemonError::new(
+        ErrorCode::PlaybackError,
+        format!("No playable sounds in playlist '{}'", name),
+    ))
+}
+
+/// Called by the player loop when the main player has stopped. Starts the
+/// next sound of the active playlist and returns true if a playlist was
+/// playing.
+pub async fn continue_playlist(audio_player: &mut AudioPlayer) -> bool {
+    let (name, mode, sounds) = {
+        let mut state = playlist_state().lock_or_recover();
+        match &state.active {
+            Some(active) if audio_player.current_file_path.as_ref() == Some(&active.current) => {
+                (active.name.clone(), active.mode, active.sounds.clone())
+            }
+            Some(_) => {
+                // Something else was played over the playlist
+                state.active = None;
+                return false;
+            }
+            None => return false,
+        }
+    };
+
+    for _ in 0..sounds.len() {
+        let Some(index) = advance_cursor(&name, mode, sounds.len(), false) else {
+            break;
+        };
+        let sound = &sounds[index];
+
+        match audio_player.play(sound).await {
+            Ok(()) => {
+                publish_play(audio_player, None, sound);
+                if let Some(active) = playlist_state().lock_or_recover().active.as_mut() {
+                    active.current = sound.clone();
+                }
+                return true;
+            }
+            Err(e) => {
+                tracing::error!("Skipping {} in playlist '{}': {}", sound.display(), name, e);
+                publish_error(DaemonError::new(
+                    ErrorCode::PlaybackError,
+                    format!("Skipping {} in playlist '{}': {}", sound.display(), name, e),
+                ));
+            }
+        }
+    }
+
+    tracing::info!("Playlist '{}' finished", name);
+    playlist_state().lock_or_recover().active = None;
+    true
+}
+
+/// Stop moving through the active playlist. Returns false if none was active.
+pub fn stop_playlist() -> bool {
+    playlist_state().lock_or_recover().active.take().is_some()
+}
+
+/// Name and mode of the playlist being played
+pub fn get_active_playlist() -> Option<(String, PlaylistMode)> {
+    playlist_state()
+        .lock_or_recover()
+        .active
+        .as_ref()
+        .map(|active| (active.name.clone(), active.mode))
+}
@@ -0,0 +1,327 @@
+// HTTP and WebSocket remote control, served by the daemon when
+// `remote.enabled` is set in daemon.json. Every request except the control
+// panel page needs the configured bearer token.
+//
+//   GET  /                        the web control panel (assets/panel.html)
+//   POST /api/commands/{command}  run any command; the JSON body holds its fields
+//   GET  /api/commands/{command}  run a command that only reads state
+//   GET  /api/events              WebSocket pushing `types::events::Event`s
+
+use crate::{
+    types::{
+        config::RemoteConfig,
+        payload::{DaemonError, ErrorCode},
+        socket::{Command, Response},
+    },
+    utils::{commands::command_executable, events::follow_events},
+};
+use axum::{
+    body::Bytes,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        DefaultBodyLimit, Path, Query, Request, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse},
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use std::{error::Error, sync::Arc};
+use tokio::time::{timeout, Duration};
+
+// Same cap as requests on the local socket
+const MAX_BODY_SIZE: usize = 64 * 1024;
+// The page itself holds no secrets; it asks for the token and calls the API
+const PANEL_HTML: &str = include_str!("../../assets/panel.html");
+// A WebSocket client that stops reading is dropped instead of holding events back
+const WS_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serve the remote control API until it fails. Refuses to start without a
+/// token.
+pub async fn serve_remote(config: RemoteConfig) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let token = config
+        .token
+        .filter(|token| !token.is_empty())
+        .ok_or("remote.token must be set to enable the remote API")?;
+
+    let listener = tokio::net::TcpListener::bind(&config.bind).await?;
+    tracing::info!("Remote control API listening on http://{}", config.bind);
+    axum::serve(listener, router(token)).await?;
+    Ok(())
+}
+
+fn router(token: String) -> Router {
+    let token = Arc::new(token);
+    Router::new()
+        .route(
+            "/api/commands/{command}",
+            get(query_command).post(run_command),
+        )
+        .route("/api/events", get(events))
+        .layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
+        .layer(middleware::from_fn_with_state(token, require_token))
+        .route("/", get(|| async { Html(PANEL_HTML) }))
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+// Browsers can't set headers on WebSockets, so `?token=` works too
+async fn require_token(
+    State(token): State<Arc<String>>,
+    Query(query): Query<TokenQuery>,
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> axum::response::Response {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let given = bearer.or(query.token.as_deref());
+
+    if given.is_some_and(|given| token_matches(&token, given)) {
+        next.run(request).await
+    } else {
+        let response = Response::error(ErrorCode::InvalidRequest, "Missing or wrong token");
+        (StatusCode::UNAUTHORIZED, Json(response)).into_response()
+    }
+}
+
+// Compares every byte so the time taken doesn't leak how much matched
+fn token_matches(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn run_command(Path(name): Path<String>, body: Bytes) -> impl IntoResponse {
+    match parse_command(&name, &body) {
+        Ok(Command::Subscribe) => reply(Response::error(
+            ErrorCode::InvalidRequest,
+            "Use the /api/events WebSocket to subscribe",
+        )),
+        Ok(command) => reply(command_executable(command).execute().await),
+        Err(error) => reply(error.into()),
+    }
+}
+
+async fn query_command(Path(name): Path<String>) -> impl IntoResponse {
+    match parse_command(&name, &[]) {
+        Ok(command) if is_read_only(&command) => reply(command_executable(command).execute().await),
+        Ok(_) => reply(Response::error(
+            ErrorCode::InvalidRequest,
+            format!("'{}' changes state, send it with POST", name),
+        )),
+        Err(error) => reply(error.into()),
+    }
+}
+
+/// The command named in the path, with its fields from the JSON body
+pub(crate) fn parse_command(name: &str, body: &[u8]) -> Result<Command, DaemonError> {
+    if !Command::NAMES.contains(&name) {
+        return Err(DaemonError::new(
+            ErrorCode::UnknownCommand,
+            format!("Unknown command: {}", name),
+        ));
+    }
+
+    let mut fields = if body.iter().all(u8::is_ascii_whitespace) {
+        serde_json::Map::new()
+    } else {
+        match serde_json::from_slice(body) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            Ok(_) => {
+                return Err(DaemonError::new(
+                    ErrorCode::InvalidRequest,
+                    "The body must be a JSON object",
+                ))
+            }
+            Err(e) => {
+                return Err(DaemonError::new(
+                    ErrorCode::InvalidRequest,
+                    format!("Invalid JSON body: {}", e),
+                ))
+            }
+        }
+    };
+    fields.insert("command".to_string(), name.into());
+
+    serde_json::from_value(serde_json::Value::Object(fields)).map_err(|e| {
+        DaemonError::new(
+            ErrorCode::InvalidArg,
+            format!("Invalid arguments for {}: {}", name, e),
+        )
+    })
+}
+
+// Commands that are safe to run from a GET
+fn is_read_only(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::Hello { .. }
+            | Command::Ping
+            | Command::IsPaused
+            | Command::GetVolume
+            | Command::GetGain
+            | Command::GetMicGain
+            | Command::GetPosition
+            | Command::GetDuration
+            | Command::GetState
+            | Command::GetCurrentFilePath
+            | Command::GetInput
+            | Command::GetInputs
+            | Command::GetOutput
+            | Command::GetOutputs
+            | Command::GetLoop
+            | Command::GetLayersInfo
+            | Command::GetAppStreams
+            | Command::GetAppRoutes
+            | Command::GetRunningMacros
+            | Command::ListSchedules
+            | Command::GetPlaylist
+            | Command::GetLibrary
+            | Command::GetMidiMappings
+    )
+}
+
+// The daemon's response as JSON, with an HTTP status matching its error code
+fn reply(response: Response) -> (StatusCode, Json<Response>) {
+    let status = response
+        .daemon_error()
+        .map_or(StatusCode::OK, |error| http_status(error.code));
+    (status, Json(response))
+}
+
+fn http_status(code: ErrorCode) -> StatusCode {
+    match code {
+        ErrorCode::InvalidArg | ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+        ErrorCode::NotFound | ErrorCode::UnknownCommand => StatusCode::NOT_FOUND,
+        ErrorCode::NotPlaying | ErrorCode::Busy => StatusCode::CONFLICT,
+        ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+        ErrorCode::Unsupported => StatusCode::NOT_IMPLEMENTED,
+        ErrorCode::DeviceError
+        | ErrorCode::PlaybackError
+        | ErrorCode::ConfigError
+        | ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+async fn events(upgrade: WebSocketUpgrade) -> impl IntoResponse {
+    upgrade.on_upgrade(stream_events)
+}
+
+// Push events as JSON text messages, starting with the current state, until
+// the client closes the socket
+async fn stream_events(mut socket: WebSocket) {
+    let mut events = follow_events().await;
+    loop {
+        let event = tokio::select! {
+            message = socket.recv() => match message {
+                // Pings are answered for us; anything else is ignored
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => continue,
+            },
+            event = events.next() => match event {
+                Some(event) => event,
+                None => return,
+            },
+        };
+
+        let text = match serde_json::to_string(&event) {
+            Ok(text) => text,
+            Err(e) => {
+                tracing::error!("Failed to serialize event: {}", e);
+                continue;
+            }
+        };
+        let sent = timeout(WS_WRITE_TIMEOUT, socket.send(Message::Text(text.into()))).await;
+        if !matches!(sent, Ok(Ok(()))) {
+            tracing::debug!("WebSocket event subscriber went away");
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command_from_path_and_body() {
+        assert_eq!(
+            parse_command("set_volume", br#"{"volume": 0.5}"#),
+            Ok(Command::SetVolume { volume: 0.5 })
+        );
+        assert_eq!(parse_command("stop", b""), Ok(Command::Stop));
+        // The path names the command, not the body
+        assert_eq!(
+            parse_command("ping", br#"{"command": "stop"}"#),
+            Ok(Command::Ping)
+        );
+    }
+
+    #[test]
+    fn test_parse_command_errors() {
+        let code = |result: Result<Command, DaemonError>| result.unwrap_err().code;
+        assert_eq!(
+            code(parse_command("format_disk", b"")),
+            ErrorCode::UnknownCommand
+        );
+        assert_eq!(
+            code(parse_command("seek", b"[1]")),
+            ErrorCode::InvalidRequest
+        );
+        assert_eq!(code(parse_command("seek", b"{")), ErrorCode::InvalidRequest);
+        assert_eq!(
+            code(parse_command("seek", br#"{"position": "soon"}"#)),
+            ErrorCode::InvalidArg
+        );
+    }
+
+    #[test]
+    fn test_only_getters_are_read_only() {
+        assert!(is_read_only(&Command::GetLayersInfo));
+        assert!(!is_read_only(&Command::Stop));
+        assert!(!is_read_only(&Command::Batch { commands: vec![] }));
+    }
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("s3cret", "s3cret"));
+        assert!(!token_matches("s3cret", "s3creT"));
+        assert!(!token_matches("s3cret", "s3cre"));
+        assert!(!token_matches("s3cret", ""));
+    }
+
+    #[test]
+    fn test_error_codes_map_to_http_statuses() {
+        assert_eq!(http_status(ErrorCode::NotFound), StatusCode::NOT_FOUND);
+        assert_eq!(
+            http_status(ErrorCode::RateLimited),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(reply(Response::new(true, "pong")).0, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_panel_uses_existing_commands() {
+        for command in [
+            "get_library",
+            "play_on_layer",
+            "stop_layer",
+            "set_layer_volume",
+            "set_volume",
+        ] {
+            assert!(PANEL_HTML.contains(&format!("\"{}\"", command)));
+            assert!(Command::NAMES.contains(&command));
+        }
+    }
+}
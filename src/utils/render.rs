@@ -0,0 +1,391 @@
+use crate::{
+    types::timeline::{Timeline, TimelineClip},
+    utils::{flac::write_flac_file, wav::create_wav_file},
+};
+use rodio::{
+    buffer::SamplesBuffer,
+    source::{UniformSourceIterator, Zero},
+    Decoder, Player, Source,
+};
+use std::{error::Error, fs, num::NonZero, path::Path, time::Duration};
+
+/// Bit depth of rendered FLAC files
+const FLAC_BITS_PER_SAMPLE: u32 = 24;
+
+/// File formats a timeline can be rendered to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderFormat {
+    /// 32-bit float WAV
+    Wav,
+    /// 24-bit FLAC
+    Flac,
+}
+
+impl RenderFormat {
+    /// Pick the format from the output file extension
+    pub fn from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("wav") => Ok(RenderFormat::Wav),
+            Some("flac") => Ok(RenderFormat::Flac),
+            _ => Err(format!(
+                "Unsupported output format '{}', expected .wav or .flac",
+                path.display()
+            )
+            .into()),
+        }
+    }
+}
+
+/// Mix `timeline` into `output` as fast as the files can be decoded.
+///
+/// Every clip gets its own `Player` on a rodio mixer, with its volume set the
+/// way the AudioPlayer sets its sinks, and the mix is pulled from the mixer
+/// instead of a sound card. Relative clip paths are resolved against
+/// `base_dir`. Returns the length of the rendered audio.
+pub fn render_timeline(
+    timeline: &Timeline,
+    base_dir: &Path,
+    output: &Path,
+) -> Result<Duration, Box<dyn Error>> {
+    timeline.validate()?;
+    let format = RenderFormat::from_path(output)?;
+
+    let channels = NonZero::new(timeline.channels).ok_or("Channel count must be greater than 0")?;
+    let sample_rate =
+        NonZero::new(timeline.sample_rate).ok_or("Sample rate must be greater than 0")?;
+    let (mixer, mut source) = rodio::mixer::mixer(channels, sample_rate);
+
+    // Players stop their sounds when dropped, so keep them until the end
+    let mut players = Vec::with_capacity(timeline.clips.len());
+    let mut total_frames = 0;
+    for (clip, limit) in timeline.clips.iter().zip(clip_play_limits(timeline)) {
+        let path = base_dir.join(&clip.file);
+        let samples = decode_clip(&path, channels, sample_rate, limit)?;
+        let offset = seconds_to_frames(clip.start, sample_rate.get());
+        total_frames = total_frames.max(offset + samples.len() / channels.get() as usize);
+
+        let (player, queue) = Player::new();
+        player.set_volume(timeline.track_volume(clip.layer) * clip.volume.clamp(0.0, 1.0));
+        if offset > 0 {
+            player.append(Zero::new_samples(
+                channels,
+                sample_rate,
+                offset * channels.get() as usize,
+            ));
+        }
+        append_with_fades(&player, clip, samples, channels, sample_rate);
+        // The mixer picks its conversion from the queue's first sound, so
+        // only hand the queue over once the clip is in it
+        mixer.add(queue);
+        players.push(player);
+    }
+
+    // Players play silence once their clip is over, so the mixer never
+    // ends on its own
+    let mix: Vec<f32> = source
+        .by_ref()
+        .take(total_frames * channels.get() as usize)
+        .collect();
+    drop(players);
+
+    match format {
+        RenderFormat::Wav => write_wav(output, &mix, timeline.channels, sample_rate.get())?,
+        RenderFormat::Flac => write_flac(output, &mix, timeline.channels, sample_rate.get())?,
+    }
+
+    let rendered = Duration::from_secs_f64(total_frames as f64 / sample_rate.get() as f64);
+    tracing::info!(
+        "Rendered {} clips ({:.2}s) to {}",
+        timeline.clips.len(),
+        rendered.as_secs_f64(),
+        output.display()
+    );
+    Ok(rendered)
+}
+
+/// Queue a decoded clip on `player` with rodio's fades. The fade-out covers
+/// the last `fade_out` seconds and starts from wherever the fade-in got to.
+fn append_with_fades(
+    player: &Player,
+    clip: &TimelineClip,
+    mut samples: Vec<f32>,
+    channels: NonZero<u16>,
+    sample_rate: NonZero<u32>,
+) {
+    let frames = samples.len() / channels.get() as usize;
+    let fade_out_frames = seconds_to_frames(clip.fade_out, sample_rate.get()).min(frames);
+    let tail = samples.split_off((frames - fade_out_frames) * channels.get() as usize);
+    let head_length = (frames - fade_out_frames) as f32 / sample_rate.get() as f32;
+
+    let head = SamplesBuffer::new(channels, sample_rate, samples);
+    let fade_in = Duration::from_secs_f32(clip.fade_in);
+    if fade_in.is_zero() {
+        player.append(head);
+    } else {
+        player.append(head.fade_in(fade_in));
+    }
+
+    if !tail.is_empty() {
+        let start_gain = if clip.fade_in > head_length {
+            head_length / clip.fade_in
+        } else {
+            1.0
+        };
+        let tail = SamplesBuffer::new(channels, sample_rate, tail);
+        let length = tail.total_duration().unwrap_or_default();
+        player.append(tail.linear_gain_ramp(length, start_gain, 0.0, true));
+    }
+}
+
+/// Longest time in seconds each clip may play, if limited. A clip stops at
+/// its own `duration` or when the next clip on the same layer starts,
+/// whichever comes first. Clips starting at the same time replace each other
+/// in list order.
+fn clip_play_limits(timeline: &Timeline) -> Vec<Option<f32>> {
+    let clips = &timeline.clips;
+
+    clips
+        .iter()
+        .enumerate()
+        .map(|(i, clip)| {
+            let cut = clips
+                .iter()
+                .enumerate()
+                .filter(|(j, other)| {
+                    *j != i
+                        && other.layer == clip.layer
+                        && (other.start > clip.start || (other.start == clip.start && *j > i))
+                })
+                .map(|(_, other)| other.start - clip.start)
+                .reduce(f32::min);
+
+            match (clip.duration, cut) {
+                (Some(duration), Some(cut)) => Some(duration.min(cut)),
+                (duration, cut) => duration.or(cut),
+            }
+        })
+        .collect()
+}
+
+fn seconds_to_frames(seconds: f32, sample_rate: u32) -> usize {
+    (seconds as f64 * sample_rate as f64).round() as usize
+}
+
+/// Decode a file, converted to the output channel count and sample rate
+fn decode_clip(
+    path: &Path,
+    channels: NonZero<u16>,
+    sample_rate: NonZero<u32>,
+    limit: Option<f32>,
+) -> Result<Vec<f32>, Box<dyn Error>> {
+    if !path.exists() {
+        return Err(format!("File does not exist: {}", path.display()).into());
+    }
+
+    let file = fs::File::open(path)?;
+    let source = Decoder::try_from(file)
+        .map_err(|e| format!("Failed to decode '{}': {}", path.display(), e))?;
+
+    let samples = UniformSourceIterator::new(source, channels, sample_rate);
+
+    Ok(match limit {
+        Some(limit) => samples
+            .take(seconds_to_frames(limit, sample_rate.get()) * channels.get() as usize)
+            .collect(),
+        None => samples.collect(),
+    })
+}
+
+fn write_wav(
+    path: &Path,
+    mix: &[f32],
+    channels: u16,
+    sample_rate: u32,
+) -> Result<(), Box<dyn Error>> {
+    let mut writer = create_wav_file(path, channels, sample_rate)?;
+    for sample in mix {
+        writer.write_sample(*sample)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+fn write_flac(
+    path: &Path,
+    mix: &[f32],
+    channels: u16,
+    sample_rate: u32,
+) -> Result<(), Box<dyn Error>> {
+    let scale = ((1 << (FLAC_BITS_PER_SAMPLE - 1)) - 1) as f32;
+    let samples: Vec<i32> = mix
+        .iter()
+        .map(|sample| (sample.clamp(-1.0, 1.0) * scale).round() as i32)
+        .collect();
+    write_flac_file(path, &samples, channels, sample_rate, FLAC_BITS_PER_SAMPLE)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::timeline::TimelineClip;
+    use std::path::PathBuf;
+
+    fn clip(start: f32, layer: Option<usize>) -> TimelineClip {
+        TimelineClip {
+            file: PathBuf::from("a.wav"),
+            start,
+            layer,
+            volume: 1.0,
+            fade_in: 0.0,
+            fade_out: 0.0,
+            duration: None,
+        }
+    }
+
+    fn timeline(clips: Vec<TimelineClip>) -> Timeline {
+        Timeline {
+            sample_rate: 8000,
+            channels: 1,
+            volume: 1.0,
+            gain: 1.0,
+            layer_volumes: Default::default(),
+            clips,
+        }
+    }
+
+    // One second of constant 0.25 at the output rate
+    fn write_tone(path: &Path) {
+        let mut input = create_wav_file(path, 1, 8000).unwrap();
+        for _ in 0..8000 {
+            input.write_sample(0.25).unwrap();
+        }
+        input.finalize().unwrap();
+    }
+
+    // --- Format tests ---
+
+    #[test]
+    fn test_render_format_from_path() {
+        assert_eq!(
+            RenderFormat::from_path(Path::new("out.wav")).unwrap(),
+            RenderFormat::Wav
+        );
+        assert_eq!(
+            RenderFormat::from_path(Path::new("out.FLAC")).unwrap(),
+            RenderFormat::Flac
+        );
+        assert!(RenderFormat::from_path(Path::new("out.mp3")).is_err());
+        assert!(RenderFormat::from_path(Path::new("out")).is_err());
+    }
+
+    // --- Mixing tests ---
+
+    #[test]
+    fn test_clip_play_limits_cut_on_same_layer() {
+        let mut clips = vec![
+            clip(0.0, None),
+            clip(1.0, Some(0)),
+            clip(2.5, None),
+            clip(3.0, Some(0)),
+        ];
+        clips[3].duration = Some(0.5);
+
+        let limits = clip_play_limits(&timeline(clips));
+        assert_eq!(limits, vec![Some(2.5), Some(2.0), None, Some(0.5)]);
+    }
+
+    #[test]
+    fn test_clip_play_limits_duration_and_ties() {
+        let mut clips = vec![clip(0.0, Some(1)), clip(0.0, Some(1)), clip(4.0, Some(1))];
+        clips[1].duration = Some(10.0);
+
+        let limits = clip_play_limits(&timeline(clips));
+        assert_eq!(limits, vec![Some(0.0), Some(4.0), None]);
+    }
+
+    #[test]
+    fn test_render_timeline_to_wav() {
+        let dir = tempfile::tempdir().unwrap();
+
+        write_tone(&dir.path().join("tone.wav"));
+
+        let mut first = clip(0.0, Some(0));
+        first.file = PathBuf::from("tone.wav");
+        let mut second = first.clone();
+        second.start = 0.5;
+        second.layer = Some(1);
+        let mut timeline = timeline(vec![first, second]);
+        timeline.gain = 2.0;
+
+        let output = dir.path().join("out.wav");
+        let rendered = render_timeline(&timeline, dir.path(), &output).unwrap();
+        assert_eq!(rendered, Duration::from_millis(1500));
+
+        let bytes = fs::read(&output).unwrap();
+        let sample_at = |frame: usize| {
+            let offset = 58 + frame * 4;
+            f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+        };
+        assert_eq!(bytes.len(), 58 + 12000 * 4);
+        assert!((sample_at(100) - 0.5).abs() < 1e-4);
+        assert!((sample_at(6000) - 1.0).abs() < 1e-4);
+        assert!((sample_at(11000) - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_render_timeline_fades() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tone(&dir.path().join("tone.wav"));
+
+        let mut faded = clip(0.0, None);
+        faded.file = PathBuf::from("tone.wav");
+        faded.fade_in = 0.5;
+        faded.fade_out = 0.25;
+
+        let output = dir.path().join("out.wav");
+        render_timeline(&timeline(vec![faded]), dir.path(), &output).unwrap();
+
+        let bytes = fs::read(&output).unwrap();
+        let sample_at = |frame: usize| {
+            let offset = 58 + frame * 4;
+            f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+        };
+        assert!(sample_at(0).abs() < 1e-3);
+        assert!((sample_at(2000) - 0.125).abs() < 1e-3);
+        assert!((sample_at(5000) - 0.25).abs() < 1e-3);
+        assert!((sample_at(7000) - 0.125).abs() < 1e-3);
+        assert!(sample_at(7999).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_render_timeline_to_flac() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tone(&dir.path().join("tone.wav"));
+
+        let mut tone = clip(0.0, None);
+        tone.file = PathBuf::from("tone.wav");
+
+        let output = dir.path().join("out.flac");
+        let rendered = render_timeline(&timeline(vec![tone]), dir.path(), &output).unwrap();
+        assert_eq!(rendered, Duration::from_secs(1));
+
+        let decoded: Vec<f32> = Decoder::try_from(fs::File::open(&output).unwrap())
+            .unwrap()
+            .collect();
+        assert_eq!(decoded.len(), 8000);
+        assert!(decoded.iter().all(|sample| (sample - 0.25).abs() < 1e-4));
+    }
+
+    #[test]
+    fn test_render_timeline_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let timeline = timeline(vec![clip(0.0, None)]);
+        assert!(render_timeline(&timeline, dir.path(), &dir.path().join("out.wav")).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use crate::{
+    types::{
+        config::AppRouteRule,
+        pipewire::{AppStream, LinkHandle},
+    },
+    utils::{
+        daemon::get_daemon_config,
+        pipewire::{create_link, get_all_devices, get_app_streams, subscribe_graph_changes},
+    },
+    DAEMON_OUTPUT_NAME,
+};
+use std::{collections::HashMap, error::Error, sync::OnceLock};
+use tokio::{
+    sync::Notify,
+    time::{sleep, Duration},
+};
+
+/// A new stream announces its node and then its ports; wait for the burst
+/// of registry events to settle before re-evaluating the rules.
+const ROUTE_DEBOUNCE: Duration = Duration::from_millis(100);
+
+fn rules_changed() -> &'static Notify {
+    static RULES_CHANGED: OnceLock<Notify> = OnceLock::new();
+    RULES_CHANGED.get_or_init(Notify::new)
+}
+
+/// Ask the routing loop to reload `app_routes` from the daemon config
+pub fn notify_app_routes_changed() {
+    rules_changed().notify_one();
+}
+
+/// Returns true if any rule matches the stream
+pub fn stream_matches_rules(stream: &AppStream, rules: &[AppRouteRule]) -> bool {
+    rules.iter().any(|rule| {
+        rule.matches(
+            stream.application_name.as_deref(),
+            stream.process_binary.as_deref(),
+        )
+    })
+}
+
+/// Links from the daemon's playback stream into application capture streams
+#[derive(Default)]
+struct AppRouter {
+    /// Node id of the daemon playback stream the current links start from
+    player_id: Option<u32>,
+    /// Links keyed by the capture stream's node id
+    links: HashMap<u32, LinkHandle>,
+}
+
+impl AppRouter {
+    async fn sync(&mut self, rules: &[AppRouteRule]) -> Result<(), Box<dyn Error>> {
+        let (_, output_devices) = get_all_devices().await?;
+        let player = output_devices
+            .into_iter()
+            .find(|d| d.name == DAEMON_OUTPUT_NAME);
+
+        let (player_id, output_fl, output_fr) = match player {
+            Some(player) => match (player.output_fl, player.output_fr) {
+                (Some(fl), Some(fr)) => (player.id, fl, fr),
+                _ => {
+                    self.links.clear();
+                    return Ok(());
+                }
+            },
+            None => {
+                self.links.clear();
+                return Ok(());
+            }
+        };
+
+        // Links from a previous playback stream are stale
+        if self.player_id != Some(player_id) {
+            self.links.clear();
+            self.player_id = Some(player_id);
+        }
+
+        let wanted: Vec<AppStream> = get_app_streams()
+            .await?
+            .into_iter()
+            .filter(|stream| stream_matches_rules(stream, rules))
+            .collect();
+
+        self.links
+            .retain(|id, _| wanted.iter().any(|stream| stream.id == *id));
+
+        for stream in wanted {
+            if self.links.contains_key(&stream.id) {
+                continue;
+            }
+
+            let (Some(input_fl), Some(input_fr)) = (&stream.input_fl, &stream.input_fr) else {
+                // Ports not announced yet, the next registry change retries
+                continue;
+            };
+
+            match create_link(
+                output_fl.clone(),
+                output_fr.clone(),
+                input_fl.clone(),
+                input_fr.clone(),
+            )
+            .await
+            {
+                Ok(link) => {
+                    tracing::info!(
+                        "Routing soundboard output into {} (node {})",
+                        stream
+                            .application_name
+                            .as_deref()
+                            .unwrap_or(&stream.node_name),
+                        stream.id
+                    );
+                    self.links.insert(stream.id, link);
+                }
+                Err(e) => {
+                    tracing::error!("Failed to route into stream {}: {}", stream.id, e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Keep application routes in sync with the PipeWire graph and the configured rules
+pub async fn app_routing_loop() {
+    let mut graph_changes = subscribe_graph_changes();
+    let mut router = AppRouter::default();
+    let mut rules = get_daemon_config().app_routes;
+
+    loop {
+        if let Err(e) = router.sync(&rules).await {
+            tracing::error!("Failed to sync application routes: {}", e);
+        }
+
+        tokio::select! {
+            changed = graph_changes.changed() => {
+                if changed.is_err() {
+                    tracing::error!("PipeWire manager stopped, application routing disabled");
+                    return;
+                }
+            }
+            _ = rules_changed().notified() => {
+                rules = get_daemon_config().app_routes;
+            }
+        }
+
+        sleep(ROUTE_DEBOUNCE).await;
+        graph_changes.borrow_and_update();
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{
+    types::{
+        payload::{DaemonError, ErrorCode},
+        schedule::ScheduledPlay,
+    },
+    utils::{
+        daemon::{get_audio_player, get_daemon_config, update_daemon_config},
+        events::{publish_error, publish_play},
+    },
+};
+use chrono::{DateTime, NaiveDateTime, NaiveTime, TimeZone};
+use std::{
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::Notify,
+    time::{sleep, Duration},
+};
+
+fn schedules_changed() -> &'static Notify {
+    static SCHEDULES_CHANGED: OnceLock<Notify> = OnceLock::new();
+    SCHEDULES_CHANGED.get_or_init(Notify::new)
+}
+
+/// Ask the scheduler to reload `schedules` from the daemon config
+pub fn notify_schedules_changed() {
+    schedules_changed().notify_one();
+}
+
+/// Current Unix time in seconds
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Id for a new schedule, one past the highest in use
+pub fn next_schedule_id(schedules: &[ScheduledPlay]) -> u64 {
+    schedules.iter().map(|s| s.id).max().map_or(1, |id| id + 1)
+}
+
+/// Parse a duration like "90", "45s", "30m", "2h" or "1h30m" into seconds
+pub fn parse_duration_secs(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs);
+    }
+
+    let mut total: u64 = 0;
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let unit = match c.to_ascii_lowercase() {
+            'd' => 86400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        let amount = number.parse::<u64>().ok()?;
+        total = total.checked_add(amount.checked_mul(unit)?)?;
+        number.clear();
+    }
+
+    // Trailing digits without a unit ("1h30") are ambiguous
+    if !number.is_empty() {
+        return None;
+    }
+    Some(total)
+}
+
+/// Parse a wall-clock time relative to `now`: RFC 3339, "YYYY-MM-DD HH:MM[:SS]"
+/// or "HH:MM[:SS]" (the next time that time of day comes around), all in
+/// `now`'s time zone unless the RFC 3339 string carries its own offset.
+pub fn parse_wall_clock<Tz: TimeZone>(value: &str, now: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+    let value = value.trim();
+    let tz = now.timezone();
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&tz));
+    }
+
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(value, format) {
+            return naive.and_local_timezone(tz.clone()).earliest();
+        }
+    }
+
+    for format in ["%H:%M:%S", "%H:%M"] {
+        if let Ok(time_of_day) = NaiveTime::parse_from_str(value, format) {
+            let today = now
+                .date_naive()
+                .and_time(time_of_day)
+                .and_local_timezone(tz.clone())
+                .earliest()?;
+            if today > *now {
+                return Some(today);
+            }
+            let tomorrow = now.date_naive().succ_opt()?.and_time(time_of_day);
+            return tomorrow.and_local_timezone(tz.clone()).earliest();
+        }
+    }
+
+    None
+}
+
+/// Play schedules when they come due. Runs for the lifetime of the daemon.
+pub async fn schedule_loop() {
+    drop_missed_schedules(unix_now());
+
+    loop {
+        let next_run = get_daemon_config()
+            .schedules
+            .iter()
+            .map(|s| s.next_run)
+            .min();
+
+        match next_run {
+            Some(next_run) => {
+                let wait = Duration::from_secs(next_run.saturating_sub(unix_now()));
+                tokio::select! {
+                    _ = sleep(wait) => run_due_schedules().await,
+                    _ = schedules_changed().notified() => {}
+                }
+            }
+            None => schedules_changed().notified().await,
+        }
+    }
+}
+
+/// One-shot schedules that came due while the daemon wasn't running are
+/// dropped rather than all played at once; recurring ones skip ahead.
+fn drop_missed_schedules(now: u64) {
+    let result = update_daemon_config(|config| {
+        config.schedules.retain_mut(|schedule| {
+            if schedule.next_run >= now || schedule.reschedule(now) {
+                return true;
+            }
+            tracing::warn!(
+                "Dropping schedule {} for {}: its time passed while the daemon was stopped",
+                schedule.id,
+                schedule.file_path.display()
+            );
+            false
+        });
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        tracing::error!("Failed to save schedules: {}", e);
+    }
+}
+
+async fn run_due_schedules() {
+    let now = unix_now();
+    let mut due = Vec::new();
+    let result = update_daemon_config(|config| {
+        config.schedules.retain_mut(|schedule| {
+            if !schedule.is_due(now) {
+                return true;
+            }
+            due.push(schedule.clone());
+            schedule.reschedule(now)
+        });
+        Ok(())
+    });
+
+    if let Err(e) = result {
+        tracing::error!("Failed to save schedules: {}", e);
+    }
+    if due.is_empty() {
+        return;
+    }
+
+    let mut audio_player = get_audio_player().lock().await;
+    for schedule in due {
+        tracing::info!(
+            "Playing scheduled sound {} ({})",
+            schedule.id,
+            schedule.file_path.display()
+        );
+        let result = match schedule.layer_index {
+            Some(layer_index) => {
+                audio_player
+                    .play_on_layer(layer_index, &schedule.file_path)
+                    .await
+            }
+            None => audio_player.play(&schedule.file_path).await,
+        };
+        match result {
+            Ok(()) => publish_play(&audio_player, schedule.layer_index, &schedule.file_path),
+            Err(e) => {
+                tracing::error!("Failed to play scheduled sound {}: {}", schedule.id, e);
+                publish_error(DaemonError::new(
+                    ErrorCode::PlaybackError,
+                    format!("Failed to play scheduled sound {}: {}", schedule.id, e),
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_duration_secs() {
+        assert_eq!(parse_duration_secs("90"), Some(90));
+        assert_eq!(parse_duration_secs("45s"), Some(45));
+        assert_eq!(parse_duration_secs("30m"), Some(1800));
+        assert_eq!(parse_duration_secs("2h"), Some(7200));
+        assert_eq!(parse_duration_secs("1h30m"), Some(5400));
+        assert_eq!(parse_duration_secs(" 1D "), Some(86400));
+    }
+
+    #[test]
+    fn test_parse_duration_secs_invalid() {
+        assert_eq!(parse_duration_secs(""), None);
+        assert_eq!(parse_duration_secs("abc"), None);
+        assert_eq!(parse_duration_secs("1h30"), None);
+        assert_eq!(parse_duration_secs("m"), None);
+        assert_eq!(parse_duration_secs("-5"), None);
+    }
+
+    #[test]
+    fn test_parse_wall_clock_time_of_day() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 10, 12, 0, 0).unwrap();
+
+        let later_today = parse_wall_clock("15:30", &now).unwrap();
+        assert_eq!(
+            later_today,
+            Utc.with_ymd_and_hms(2024, 5, 10, 15, 30, 0).unwrap()
+        );
+
+        let tomorrow = parse_wall_clock("08:00:05", &now).unwrap();
+        assert_eq!(
+            tomorrow,
+            Utc.with_ymd_and_hms(2024, 5, 11, 8, 0, 5).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_wall_clock_dates() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 10, 12, 0, 0).unwrap();
+
+        assert_eq!(
+            parse_wall_clock("2024-06-01 09:15", &now),
+            Some(Utc.with_ymd_and_hms(2024, 6, 1, 9, 15, 0).unwrap())
+        );
+        assert_eq!(
+            parse_wall_clock("2024-06-01T09:15:00+02:00", &now),
+            Some(Utc.with_ymd_and_hms(2024, 6, 1, 7, 15, 0).unwrap())
+        );
+        assert_eq!(parse_wall_clock("tomorrow", &now), None);
+    }
+
+    #[test]
+    fn test_next_schedule_id() {
+        assert_eq!(next_schedule_id(&[]), 1);
+
+        let schedules: Vec<ScheduledPlay> = [3, 7]
+            .into_iter()
+            .map(|id| ScheduledPlay {
+                id,
+                file_path: PathBuf::from("/sounds/a.ogg"),
+                layer_index: None,
+                next_run: 0,
+                interval_secs: None,
+                label: None,
+            })
+            .collect();
+        assert_eq!(next_schedule_id(&schedules), 8);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::types::{
+    audio_player::{AudioPlayer, LayerInfo, PlayerState},
+    config::VoiceStealing,
+    payload::{DaemonError, ErrorCode},
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Where a new sound goes when it is played on a layer
+#[derive(Debug, Clone, PartialEq)]
+pub enum VoiceDecision {
+    /// The layer is free
+    Free(usize),
+    /// The sound on this layer is cut off
+    Steal(usize),
+    /// No layer can be used
+    Drop,
+}
+
+/// Layer a sound that may use any layer goes to. Sounds with a higher
+/// priority than `priority` are never stolen.
+pub fn choose_voice(layers: &[LayerInfo], priority: u8, policy: VoiceStealing) -> VoiceDecision {
+    if let Some(free) = layers.iter().find(|layer| layer.is_empty) {
+        return VoiceDecision::Free(free.index);
+    }
+
+    let candidates = layers.iter().filter(|layer| layer.priority <= priority);
+    // A layer without a start time counts as the oldest
+    let age = |layer: &LayerInfo| layer.age.unwrap_or(f32::MAX);
+    let older = |a: &LayerInfo, b: &LayerInfo| age(b).total_cmp(&age(a));
+
+    let victim = match policy {
+        VoiceStealing::Drop => None,
+        VoiceStealing::LowestPriority => {
+            candidates.min_by(|a, b| a.priority.cmp(&b.priority).then_with(|| older(a, b)))
+        }
+        VoiceStealing::Oldest => candidates.min_by(|a, b| older(a, b)),
+        VoiceStealing::Quietest => {
+            candidates.min_by(|a, b| a.volume.total_cmp(&b.volume).then_with(|| older(a, b)))
+        }
+    };
+
+    victim.map_or(VoiceDecision::Drop, |layer| {
+        VoiceDecision::Steal(layer.index)
+    })
+}
+
+/// A sound that was cut off to make room for a new one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StolenVoice {
+    pub file_path: Option<PathBuf>,
+    pub priority: u8,
+}
+
+/// Pick the layer for a new sound of `priority`: `layer_index`, or any
+/// layer if None. Returns the layer and the sound it replaces, if any.
+pub fn place_on_layer(
+    audio_player: &AudioPlayer,
+    layer_index: Option<usize>,
+    priority: u8,
+    policy: VoiceStealing,
+) -> Result<(usize, Option<StolenVoice>), DaemonError> {
+    let layer_index = match layer_index {
+        Some(layer_index) => layer_index,
+        None => {
+            let layers = audio_player.get_all_layers_info();
+            match choose_voice(&layers, priority, policy) {
+                VoiceDecision::Free(layer_index) | VoiceDecision::Steal(layer_index) => layer_index,
+                VoiceDecision::Drop if policy == VoiceStealing::Drop => {
+                    return Err(DaemonError::new(
+                        ErrorCode::Busy,
+                        format!("all {} layers are busy", layers.len()),
+                    ));
+                }
+                VoiceDecision::Drop => {
+                    return Err(DaemonError::new(
+                        ErrorCode::Busy,
+                        format!(
+                            "all {} layers are busy with higher-priority sounds",
+                            layers.len()
+                        ),
+                    ));
+                }
+            }
+        }
+    };
+
+    let layer = audio_player.get_layer_info(layer_index).ok_or_else(|| {
+        DaemonError::new(
+            ErrorCode::InvalidArg,
+            format!("Invalid layer index: {}", layer_index),
+        )
+    })?;
+    if layer.is_empty {
+        return Ok((layer_index, None));
+    }
+    if layer.priority > priority {
+        return Err(DaemonError::new(
+            ErrorCode::Busy,
+            format!(
+                "layer {} is playing a higher-priority sound ({} > {})",
+                layer_index, layer.priority, priority
+            ),
+        ));
+    }
+
+    Ok((
+        layer_index,
+        Some(StolenVoice {
+            file_path: layer.current_file,
+            priority: layer.priority,
+        }),
+    ))
+}
+
+/// Fails if the main player is busy with a sound more important than `priority`
+pub fn check_main_priority(audio_player: &AudioPlayer, priority: u8) -> Result<(), DaemonError> {
+    let busy = audio_player.get_state() != PlayerState::Stopped;
+    if busy && audio_player.priority > priority {
+        return Err(DaemonError::new(
+            ErrorCode::Busy,
+            format!(
+                "the main player is playing a higher-priority sound ({} > {})",
+                audio_player.priority, priority
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn busy(index: usize, priority: u8, age: f32, volume: f32) -> LayerInfo {
+        LayerInfo {
+            index,
+            is_playing: true,
+            priority,
+            age: Some(age),
+            volume,
+            ..Default::default()
+        }
+    }
+
+    fn free(index: usize) -> LayerInfo {
+        LayerInfo {
+            index,
+            is_empty: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_free_layer_is_used_first() {
+        let layers = vec![busy(0, 0, 1.0, 1.0), free(1), free(2)];
+        for policy in [VoiceStealing::Drop, VoiceStealing::Oldest] {
+            assert_eq!(choose_voice(&layers, 0, policy), VoiceDecision::Free(1));
+        }
+    }
+
+    #[test]
+    fn test_drop_policy_never_steals() {
+        let layers = vec![busy(0, 0, 1.0, 1.0), busy(1, 0, 2.0, 1.0)];
+        assert_eq!(
+            choose_voice(&layers, 9, VoiceStealing::Drop),
+            VoiceDecision::Drop
+        );
+    }
+
+    #[test]
+    fn test_lowest_priority_then_oldest() {
+        let layers = vec![
+            busy(0, 2, 9.0, 1.0),
+            busy(1, 1, 3.0, 1.0),
+            busy(2, 1, 5.0, 1.0),
+        ];
+        assert_eq!(
+            choose_voice(&layers, 2, VoiceStealing::LowestPriority),
+            VoiceDecision::Steal(2)
+        );
+    }
+
+    #[test]
+    fn test_oldest_and_quietest() {
+        let layers = vec![
+            busy(0, 0, 9.0, 0.8),
+            busy(1, 0, 3.0, 0.2),
+            busy(2, 0, 5.0, 1.0),
+        ];
+        assert_eq!(
+            choose_voice(&layers, 0, VoiceStealing::Oldest),
+            VoiceDecision::Steal(0)
+        );
+        assert_eq!(
+            choose_voice(&layers, 0, VoiceStealing::Quietest),
+            VoiceDecision::Steal(1)
+        );
+    }
+
+    #[test]
+    fn test_higher_priority_is_never_stolen() {
+        let layers = vec![busy(0, 5, 9.0, 0.1), busy(1, 3, 1.0, 1.0)];
+        for policy in [
+            VoiceStealing::LowestPriority,
+            VoiceStealing::Oldest,
+            VoiceStealing::Quietest,
+        ] {
+            assert_eq!(choose_voice(&layers, 4, policy), VoiceDecision::Steal(1));
+            assert_eq!(choose_voice(&layers, 2, policy), VoiceDecision::Drop);
+        }
+    }
+}
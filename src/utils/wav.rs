@@ -0,0 +1,166 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// WAVE_FORMAT_IEEE_FLOAT
+const FORMAT_IEEE_FLOAT: u16 = 3;
+const BYTES_PER_SAMPLE: u16 = 4;
+/// Offset of the RIFF chunk size field
+const RIFF_SIZE_OFFSET: u64 = 4;
+/// Offset of the sample-frame count in the `fact` chunk
+const FACT_FRAMES_OFFSET: u64 = 46;
+/// Offset of the data chunk size field
+const DATA_SIZE_OFFSET: u64 = 54;
+/// Size of everything before the sample data
+const HEADER_LEN: u32 = 58;
+
+/// Streaming writer for 32-bit float WAV files.
+///
+/// The header is written up front with zero sizes and patched by
+/// `update_header`/`finalize`, so samples can be appended for as long as the
+/// caller needs.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    channels: u16,
+    data_len: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, channels: u16, sample_rate: u32) -> io::Result<Self> {
+        let block_align = channels * BYTES_PER_SAMPLE;
+        let byte_rate = sample_rate * block_align as u32;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&18u32.to_le_bytes())?;
+        writer.write_all(&FORMAT_IEEE_FLOAT.to_le_bytes())?;
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&(BYTES_PER_SAMPLE * 8).to_le_bytes())?;
+        writer.write_all(&0u16.to_le_bytes())?;
+
+        // Non-PCM formats carry a fact chunk with the number of frames
+        writer.write_all(b"fact")?;
+        writer.write_all(&4u32.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            channels,
+            data_len: 0,
+        })
+    }
+
+    pub fn write_sample(&mut self, sample: f32) -> io::Result<()> {
+        let data_len = self
+            .data_len
+            .checked_add(BYTES_PER_SAMPLE as u32)
+            .filter(|len| len.checked_add(HEADER_LEN).is_some())
+            .ok_or_else(|| io::Error::other("WAV file size limit reached"))?;
+
+        self.writer.write_all(&sample.to_le_bytes())?;
+        self.data_len = data_len;
+        Ok(())
+    }
+
+    /// Number of complete sample frames written so far
+    pub fn frames_written(&self) -> u32 {
+        self.data_len / (self.channels as u32 * BYTES_PER_SAMPLE as u32)
+    }
+
+    /// Patch the header sizes for the samples written so far and flush, so
+    /// the file is valid even if the process never gets to `finalize`
+    pub fn update_header(&mut self) -> io::Result<()> {
+        let frames = self.frames_written();
+
+        self.writer.seek(SeekFrom::Start(RIFF_SIZE_OFFSET))?;
+        self.writer
+            .write_all(&(HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(FACT_FRAMES_OFFSET))?;
+        self.writer.write_all(&frames.to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+        self.writer.write_all(&self.data_len.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+
+    /// Patch the header and return the inner writer
+    pub fn finalize(mut self) -> io::Result<W> {
+        self.update_header()?;
+        Ok(self.writer)
+    }
+}
+
+/// Create (or truncate) a WAV file at `path`
+pub fn create_wav_file(
+    path: &Path,
+    channels: u16,
+    sample_rate: u32,
+) -> io::Result<WavWriter<BufWriter<File>>> {
+    WavWriter::new(BufWriter::new(File::create(path)?), channels, sample_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_empty_wav_header() {
+        let writer = WavWriter::new(Cursor::new(Vec::new()), 2, 48000).unwrap();
+        let bytes = writer.finalize().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), HEADER_LEN as usize);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4), HEADER_LEN - 8);
+        assert_eq!(&bytes[8..12], b"WAVE");
+        assert_eq!(u32_at(&bytes, 24), 48000);
+        assert_eq!(u32_at(&bytes, 28), 48000 * 2 * 4);
+        assert_eq!(&bytes[38..42], b"fact");
+        assert_eq!(&bytes[50..54], b"data");
+        assert_eq!(u32_at(&bytes, 54), 0);
+    }
+
+    #[test]
+    fn test_wav_sizes_are_patched() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 2, 44100).unwrap();
+        for sample in [0.5f32, -0.5, 0.25, -0.25, 1.0, -1.0] {
+            writer.write_sample(sample).unwrap();
+        }
+        assert_eq!(writer.frames_written(), 3);
+
+        let bytes = writer.finalize().unwrap().into_inner();
+        assert_eq!(bytes.len(), HEADER_LEN as usize + 24);
+        assert_eq!(u32_at(&bytes, 4), HEADER_LEN - 8 + 24);
+        assert_eq!(u32_at(&bytes, FACT_FRAMES_OFFSET as usize), 3);
+        assert_eq!(u32_at(&bytes, DATA_SIZE_OFFSET as usize), 24);
+        assert_eq!(f32::from_le_bytes(bytes[58..62].try_into().unwrap()), 0.5);
+    }
+
+    #[test]
+    fn test_wav_update_header_keeps_appending() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 1, 8000).unwrap();
+        writer.write_sample(0.1).unwrap();
+        writer.update_header().unwrap();
+        writer.write_sample(0.2).unwrap();
+
+        let bytes = writer.finalize().unwrap().into_inner();
+        assert_eq!(bytes.len(), HEADER_LEN as usize + 8);
+        assert_eq!(u32_at(&bytes, DATA_SIZE_OFFSET as usize), 8);
+        assert_eq!(f32::from_le_bytes(bytes[62..66].try_into().unwrap()), 0.2);
+    }
+}